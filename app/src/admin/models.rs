@@ -0,0 +1,401 @@
+//! Superadmin management of users and their sessions. Every change made here is written to the
+//! `admin_audit` table, together with the names involved so the log outlives deleted accounts.
+//! A superadmin impersonating someone can look around as them, but [`forbid_impersonation`]
+//! keeps them from deleting the account or changing its sessions, sign-in methods and keys.
+
+use leptos::prelude::*;
+use partial_struct::Partial;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::devices::SessionInfo;
+use crate::{Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{
+        cookies::session_token_from,
+        extract::require_superadmin_user,
+        session::{current_session_token, set_session_cookie, AdapterSession, CreateSessionData},
+        user::AdapterUser,
+    },
+    db_init, AppError,
+};
+
+#[cfg(feature = "ssr")]
+use axum_extra::extract::CookieJar;
+#[cfg(feature = "ssr")]
+use chrono::Utc;
+
+/// Users shown per page of the user list.
+pub const ADMIN_USERS_PAGE_SIZE: usize = 25;
+
+/// Audit entries shown per page.
+pub const ADMIN_AUDIT_PAGE_SIZE: usize = 50;
+
+/// How long a superadmin can act as another user before signing in again.
+pub const IMPERSONATION_TTL_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    Impersonate,
+    StopImpersonating,
+    Disable,
+    Enable,
+    Promote,
+    Demote,
+    Delete,
+    RevokeSession,
+    RevokeAllSessions,
+    RotateKeyEncryption,
+}
+
+impl AdminAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AdminAction::Impersonate => "Started impersonating",
+            AdminAction::StopImpersonating => "Stopped impersonating",
+            AdminAction::Disable => "Disabled",
+            AdminAction::Enable => "Enabled",
+            AdminAction::Promote => "Made superadmin",
+            AdminAction::Demote => "Removed superadmin from",
+            AdminAction::Delete => "Deleted",
+            AdminAction::RevokeSession => "Revoked a session of",
+            AdminAction::RevokeAllSessions => "Revoked all sessions of",
+            AdminAction::RotateKeyEncryption => "Rotated the key encryption",
+        }
+    }
+}
+
+/// One recorded admin action.
+#[derive(Debug, Clone, Serialize, Deserialize, Partial)]
+#[partial("CreateAuditEntry", derive(Serialize, Deserialize, Clone), omit(id))]
+pub struct AuditEntry {
+    pub id: RecordId,
+    pub admin_id: RecordId,
+    pub admin_name: String,
+    pub action: AdminAction,
+    pub target_user_id: Option<RecordId>,
+    pub target_name: Option<String>,
+    pub detail: Option<String>,
+    pub created_at: Datetime,
+}
+
+/// A user as listed in the admin console.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdminUserInfo {
+    pub id: RecordId,
+    pub name: String,
+    pub image: Option<String>,
+    pub superadmin: bool,
+    pub disabled: bool,
+    /// Email addresses the user signs in with.
+    pub emails: Vec<String>,
+    /// Live sessions.
+    pub session_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdminUserPage {
+    pub users: Vec<AdminUserInfo>,
+    /// All users matching the search, across pages.
+    pub total: usize,
+}
+
+#[cfg(feature = "ssr")]
+impl AuditEntry {
+    /// Records that `admin` did `action`, to `target` if it concerned a user.
+    pub async fn record(
+        admin: &AdapterUser,
+        action: AdminAction,
+        target: Option<&AdapterUser>,
+        detail: Option<String>,
+    ) -> Result<(), AppError> {
+        let db = db_init().await?;
+        let entry = CreateAuditEntry {
+            admin_id: admin.id.clone(),
+            admin_name: admin.name.clone(),
+            action,
+            target_user_id: target.map(|user| user.id.clone()),
+            target_name: target.map(|user| user.name.clone()),
+            detail,
+            created_at: Datetime::from(Utc::now()),
+        };
+        let _: Option<Self> = db.create("admin_audit").content(entry).await?;
+        Ok(())
+    }
+
+    /// Recorded actions, newest first.
+    pub async fn recent(start: usize, limit: usize) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM admin_audit ORDER BY created_at DESC LIMIT $limit START $start;")
+            .bind(("limit", limit))
+            .bind(("start", start))
+            .await?;
+        let entries: Vec<Self> = result.take(0)?;
+        Ok(entries)
+    }
+}
+
+/// Users whose name or email contains `search`, ignoring case, ordered by name. Emails come
+/// from sign-in link and password accounts; OIDC accounts only store the provider's subject.
+#[cfg(feature = "ssr")]
+async fn search_users(search: String, start: usize) -> Result<AdminUserPage, AppError> {
+    let db = db_init().await?;
+    let mut result = db
+        .query(
+            r#"
+            LET $by_email = (SELECT VALUE user_id FROM account WHERE provider IN ["email", "credentials"] AND string::contains(string::lowercase(provider_account_id), $search));
+            LET $matches = (SELECT VALUE id FROM user WHERE $search = "" OR string::contains(string::lowercase(name), $search) OR id IN $by_email);
+            SELECT
+                id,
+                name,
+                image,
+                superadmin ?? false AS superadmin,
+                disabled ?? false AS disabled,
+                array::distinct(SELECT VALUE provider_account_id FROM account WHERE user_id = $parent.id AND provider IN ["email", "credentials"]) AS emails,
+                count(SELECT id FROM session WHERE user_id = $parent.id AND expires > time::now() AND mfa_pending != true) AS session_count
+            FROM user WHERE id IN $matches ORDER BY name ASC LIMIT $limit START $start;
+            array::len($matches);
+            "#,
+        )
+        .bind(("search", search.trim().to_lowercase()))
+        .bind(("limit", ADMIN_USERS_PAGE_SIZE))
+        .bind(("start", start))
+        .await?;
+    let users: Vec<AdminUserInfo> = result.take(2)?;
+    let total: Option<usize> = result.take(3)?;
+    Ok(AdminUserPage {
+        users,
+        total: total.unwrap_or_default(),
+    })
+}
+
+/// The user a superadmin is about to act on. Superadmins can't act on their own account here,
+/// so they can't lock themselves out.
+#[cfg(feature = "ssr")]
+async fn admin_target(user_id: RecordId) -> Result<(AdapterUser, AdapterUser), ServerFnError> {
+    let admin = require_superadmin_user().await?;
+    if admin.id == user_id {
+        return Err(AppError::ErrorReason("Use your own settings for your account".into()).into());
+    }
+    let target = AdapterUser::get_user(user_id)
+        .await
+        .map_err(|_| AppError::NotFound("User not found".into()))?;
+    Ok((admin, target))
+}
+
+/// One page of users, filtered by name or email. `page` counts from zero.
+#[server]
+pub async fn admin_list_users(search: String, page: usize) -> Result<AdminUserPage, ServerFnError> {
+    require_superadmin_user().await?;
+    let users = search_users(search, page * ADMIN_USERS_PAGE_SIZE).await?;
+    Ok(users)
+}
+
+#[server]
+pub async fn admin_user_sessions(user_id: RecordId) -> Result<Vec<SessionInfo>, ServerFnError> {
+    require_superadmin_user().await?;
+    let sessions = AdapterSession::for_user(user_id).await?;
+    Ok(sessions
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: false,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires: session.expires,
+        })
+        .collect())
+}
+
+#[server]
+pub async fn admin_revoke_session(user_id: RecordId, id: RecordId) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    AdapterSession::revoke(id.clone(), target.id.clone()).await?;
+    AuditEntry::record(
+        &admin,
+        AdminAction::RevokeSession,
+        Some(&target),
+        Some(id.to_string()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[server]
+pub async fn admin_revoke_all_sessions(user_id: RecordId) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    AdapterSession::revoke_all(target.id.clone()).await?;
+    AuditEntry::record(&admin, AdminAction::RevokeAllSessions, Some(&target), None).await?;
+    Ok(())
+}
+
+/// Disables a user, which signs them out everywhere, or enables them again.
+#[server]
+pub async fn admin_set_disabled(user_id: RecordId, disabled: bool) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    target.set_disabled(disabled).await?;
+    let action = if disabled {
+        AdminAction::Disable
+    } else {
+        AdminAction::Enable
+    };
+    AuditEntry::record(&admin, action, Some(&target), None).await?;
+    Ok(())
+}
+
+#[server]
+pub async fn admin_set_superadmin(
+    user_id: RecordId,
+    superadmin: bool,
+) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    target.set_superadmin(superadmin).await?;
+    let action = if superadmin {
+        AdminAction::Promote
+    } else {
+        AdminAction::Demote
+    };
+    AuditEntry::record(&admin, action, Some(&target), None).await?;
+    Ok(())
+}
+
+#[server]
+pub async fn admin_delete_user(user_id: RecordId) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    target.delete_user().await?;
+    AuditEntry::record(&admin, AdminAction::Delete, Some(&target), None).await?;
+    Ok(())
+}
+
+/// Signs the superadmin in as another user until they stop or [`IMPERSONATION_TTL_MINUTES`]
+/// pass. The superadmin's own session ends; stopping starts a new one.
+#[server]
+pub async fn impersonate_user(user_id: RecordId) -> Result<(), ServerFnError> {
+    let (admin, target) = admin_target(user_id).await?;
+    if target.superadmin == Some(true) {
+        return Err(AppError::Forbidden("Superadmins can't be impersonated".into()).into());
+    }
+    target.check_enabled()?;
+
+    let session = AdapterSession::create_session(CreateSessionData {
+        user_id: target.id.clone(),
+        session_token: uuid::Uuid::new_v4().to_string(),
+        expires: Datetime::from(Utc::now() + chrono::Duration::minutes(IMPERSONATION_TTL_MINUTES)),
+        mfa_pending: false,
+        user_agent: None,
+        ip: None,
+        created_at: Some(Datetime::from(Utc::now())),
+        last_seen_at: None,
+        organization_id: None,
+        impersonator_id: Some(admin.id.clone()),
+    })
+    .await?;
+    AuditEntry::record(&admin, AdminAction::Impersonate, Some(&target), None).await?;
+
+    AdapterSession::delete_session(current_session_token().await?).await?;
+    set_session_cookie(&session);
+    Ok(())
+}
+
+/// Ends an impersonation and signs the superadmin back in as themselves. Returns where to go
+/// next, which is the second factor form if they use one.
+#[server]
+pub async fn stop_impersonating() -> Result<String, ServerFnError> {
+    let token = current_session_token().await?;
+    let session = AdapterSession::from_string(token.clone()).await?;
+    let Some(admin_id) = session.impersonator_id else {
+        return Err(AppError::ErrorReason("You are not impersonating anyone".into()).into());
+    };
+    AdapterSession::delete_session(token).await?;
+
+    let admin = AdapterUser::get_user(admin_id).await?;
+    if admin.superadmin != Some(true) {
+        return Err(AppError::Forbidden("Superadmin access required".into()).into());
+    }
+    let target = AdapterUser::get_user(session.user_id).await.ok();
+    AuditEntry::record(
+        &admin,
+        AdminAction::StopImpersonating,
+        target.as_ref(),
+        None,
+    )
+    .await?;
+
+    let session = admin.new_session().await?;
+    set_session_cookie(&session);
+    Ok(session.landing_path().to_string())
+}
+
+/// The superadmin acting through the session in `jar`, if any. For axum handlers, which
+/// can't use [`forbid_impersonation`].
+#[cfg(feature = "ssr")]
+pub async fn impersonator_in(jar: &CookieJar) -> Result<Option<RecordId>, AppError> {
+    let Some(token) = session_token_from(jar) else {
+        return Ok(None);
+    };
+    match AdapterSession::from_string(token).await {
+        Ok(session) => Ok(session.impersonator_id),
+        Err(AppError::AuthError(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The superadmin acting through the current session, if any.
+#[cfg(feature = "ssr")]
+async fn current_impersonator_id() -> Result<Option<RecordId>, AppError> {
+    let Ok(jar) = leptos_axum::extract::<CookieJar>().await else {
+        return Ok(None);
+    };
+    impersonator_in(&jar).await
+}
+
+/// Refuses what only the account holder should do, like deleting the account or changing its
+/// sessions, sign-in methods and keys, while a superadmin is impersonating them.
+#[cfg(feature = "ssr")]
+pub async fn forbid_impersonation() -> Result<(), AppError> {
+    if current_impersonator_id().await?.is_some() {
+        return Err(AppError::Forbidden(
+            "Stop impersonating to make this change".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The name of the superadmin acting through the current session, if any.
+#[server]
+pub async fn get_impersonator() -> Result<Option<String>, ServerFnError> {
+    match current_impersonator_id().await? {
+        Some(id) => Ok(Some(AdapterUser::get_user(id).await?.name)),
+        None => Ok(None),
+    }
+}
+
+/// Recorded admin actions, newest first. `page` counts from zero.
+#[server]
+pub async fn admin_audit_log(page: usize) -> Result<Vec<AuditEntry>, ServerFnError> {
+    require_superadmin_user().await?;
+    let entries = AuditEntry::recent(page * ADMIN_AUDIT_PAGE_SIZE, ADMIN_AUDIT_PAGE_SIZE).await?;
+    Ok(entries)
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_search_finds_password_users_by_email() {
+    use crate::auth::credentials::register_with_password;
+
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    let session = register_with_password("Ada", &email, "correct horse 42".into())
+        .await
+        .unwrap();
+
+    let page = search_users(email.to_uppercase(), 0).await.unwrap();
+    assert_eq!(page.users.len(), 1);
+    assert_eq!(page.users[0].id, session.user_id);
+    assert_eq!(page.users[0].emails, vec![email]);
+}
@@ -0,0 +1,450 @@
+use leptos::prelude::*;
+
+use crate::admin::models::{
+    admin_audit_log, admin_list_users, admin_user_sessions, get_impersonator, AdminDeleteUser,
+    AdminRevokeAllSessions, AdminRevokeSession, AdminSetDisabled, AdminSetSuperadmin,
+    AdminUserInfo, ImpersonateUser, StopImpersonating, ADMIN_AUDIT_PAGE_SIZE,
+    ADMIN_USERS_PAGE_SIZE,
+};
+use crate::auth::devices::describe_user_agent;
+use crate::auth::key_vault::RotateKeyEncryption;
+use crate::auth::ui_auth::reload_to;
+use crate::date_utils::format_datetime;
+use crate::RecordId;
+
+const BUTTON_CLASS: &str = "px-3 py-1 text-sm bg-neutral-200 dark:bg-neutral-700 rounded-md hover:bg-neutral-300 dark:hover:bg-neutral-600 disabled:opacity-50 disabled:cursor-not-allowed";
+const DANGER_BUTTON_CLASS: &str = "px-3 py-1 text-sm bg-red-600 text-white rounded-md hover:bg-red-700 disabled:opacity-50 disabled:cursor-not-allowed";
+
+/// Tells a superadmin acting as someone else who they are, with a way back to their own account.
+/// Renders nothing otherwise.
+#[component]
+pub fn ImpersonationBanner() -> impl IntoView {
+    let impersonator = Resource::new(|| (), |_| get_impersonator());
+    let stop = ServerAction::<StopImpersonating>::new();
+
+    Effect::new(move |_| {
+        if let Some(Ok(path)) = stop.value().get() {
+            reload_to(&path);
+        }
+    });
+
+    view! {
+        <Suspense fallback=|| ()>
+            {move || impersonator.get().and_then(|r| r.ok()).flatten().map(|name| view! {
+                <div class="flex items-center gap-2 px-3 py-1.5 text-sm rounded-md bg-yellow-100 text-yellow-900 dark:bg-yellow-900 dark:text-yellow-100">
+                    <span>{format!("{} is impersonating this account", name)}</span>
+                    <button
+                        class="font-medium underline disabled:opacity-50"
+                        disabled=move || stop.pending().get()
+                        on:click=move |_| {
+                            stop.dispatch(StopImpersonating {});
+                        }
+                    >
+                        "Stop"
+                    </button>
+                </div>
+            })}
+        </Suspense>
+    }
+}
+
+/// The live sessions of one user, each of which can be revoked.
+#[component]
+fn AdminUserSessions(user_id: RecordId, on_change: Callback<()>) -> impl IntoView {
+    let sessions = Resource::new(
+        {
+            let user_id = user_id.clone();
+            move || user_id.clone()
+        },
+        admin_user_sessions,
+    );
+    let revoke = ServerAction::<AdminRevokeSession>::new();
+    let revoke_all = ServerAction::<AdminRevokeAllSessions>::new();
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| {
+        for result in [revoke.value().get(), revoke_all.value().get()] {
+            if let Some(result) = result {
+                set_error_message.set(result.err().map(|e| e.to_string()));
+                sessions.refetch();
+                on_change.run(());
+            }
+        }
+    });
+
+    let revoke_user_id = user_id.clone();
+    view! {
+        <div class="mt-3 ml-4 pl-4 border-l border-neutral-200 dark:border-neutral-700">
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match sessions.get() {
+                    Some(Ok(list)) if list.is_empty() => {
+                        view! { <p class="text-sm text-neutral-600 dark:text-neutral-400">"No active sessions."</p> }.into_any()
+                    }
+                    Some(Ok(list)) => {
+                        let user_id = revoke_user_id.clone();
+                        view! {
+                            <ul class="flex flex-col gap-2">
+                                {list
+                                    .into_iter()
+                                    .map(|session| {
+                                        let user_id = user_id.clone();
+                                        let id = session.id.clone();
+                                        let label = session
+                                            .user_agent
+                                            .as_deref()
+                                            .map(describe_user_agent)
+                                            .unwrap_or_else(|| "Unknown device".to_string());
+                                        view! {
+                                            <li class="flex items-center justify-between gap-4 text-sm">
+                                                <span>
+                                                    {label}
+                                                    {session.ip.clone().map(|ip| view! { <span class="ml-2 font-mono text-xs">{ip}</span> })}
+                                                    <span class="ml-2 text-xs text-neutral-600 dark:text-neutral-400">
+                                                        "Last active: "
+                                                        {session.last_seen_at.as_ref().map(format_datetime).unwrap_or_else(|| "unknown".to_string())}
+                                                    </span>
+                                                </span>
+                                                <button
+                                                    class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 font-medium"
+                                                    on:click=move |_| {
+                                                        revoke.dispatch(AdminRevokeSession { user_id: user_id.clone(), id: id.clone() });
+                                                    }
+                                                >
+                                                    "Revoke"
+                                                </button>
+                                            </li>
+                                        }
+                                    })
+                                    .collect_view()}
+                            </ul>
+                        }.into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! { <p class="text-red-600 dark:text-red-400">"Error loading sessions: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            <button
+                class=format!("mt-2 {}", BUTTON_CLASS)
+                disabled=move || revoke_all.pending().get()
+                on:click=move |_| {
+                    revoke_all.dispatch(AdminRevokeAllSessions { user_id: user_id.clone() });
+                }
+            >
+                "Revoke all"
+            </button>
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+#[component]
+fn AdminUserRow(user: AdminUserInfo, on_change: Callback<()>) -> impl IntoView {
+    let impersonate = ServerAction::<ImpersonateUser>::new();
+    let set_disabled = ServerAction::<AdminSetDisabled>::new();
+    let set_superadmin = ServerAction::<AdminSetSuperadmin>::new();
+    let delete = ServerAction::<AdminDeleteUser>::new();
+    let (show_sessions, set_show_sessions) = signal(false);
+    let (confirm_delete, set_confirm_delete) = signal(false);
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| {
+        if matches!(impersonate.value().get(), Some(Ok(()))) {
+            reload_to("/");
+        }
+    });
+
+    Effect::new(move |_| {
+        for result in [
+            impersonate.value().get(),
+            set_disabled.value().get(),
+            set_superadmin.value().get(),
+            delete.value().get(),
+        ] {
+            if let Some(result) = result {
+                set_error_message.set(result.err().map(|e| e.to_string()));
+                on_change.run(());
+            }
+        }
+    });
+
+    let pending = move || {
+        impersonate.pending().get()
+            || set_disabled.pending().get()
+            || set_superadmin.pending().get()
+            || delete.pending().get()
+    };
+
+    let AdminUserInfo {
+        id,
+        name,
+        superadmin,
+        disabled,
+        emails,
+        session_count,
+        ..
+    } = user;
+    let (impersonate_id, disable_id, promote_id, delete_id) =
+        (id.clone(), id.clone(), id.clone(), id.clone());
+
+    view! {
+        <div class="px-4 py-3 hover:bg-neutral-50 dark:hover:bg-neutral-700">
+            <div class="flex flex-wrap items-center justify-between gap-2">
+                <div class="flex-1 min-w-0">
+                    <h3 class="text-sm font-medium text-neutral-900 dark:text-neutral-100">
+                        {name}
+                        {superadmin.then(|| view! {
+                            <span class="ml-2 px-2 py-0.5 text-xs rounded bg-purple-100 text-purple-800 dark:bg-purple-900 dark:text-purple-200">"Superadmin"</span>
+                        })}
+                        {disabled.then(|| view! {
+                            <span class="ml-2 px-2 py-0.5 text-xs rounded bg-red-100 text-red-800 dark:bg-red-900 dark:text-red-200">"Disabled"</span>
+                        })}
+                    </h3>
+                    <div class="flex flex-wrap items-center gap-4 mt-1 text-xs text-neutral-600 dark:text-neutral-400">
+                        <span class="font-mono">{id.to_string()}</span>
+                        {(!emails.is_empty()).then(|| view! { <span>{emails.join(", ")}</span> })}
+                        <button class="hover:underline" on:click=move |_| set_show_sessions.update(|show| *show = !*show)>
+                            {format!("{} active sessions", session_count)}
+                        </button>
+                    </div>
+                </div>
+                <div class="flex flex-wrap items-center gap-2">
+                    {(!superadmin && !disabled).then(|| view! {
+                        <button
+                            class=BUTTON_CLASS
+                            disabled=pending
+                            on:click=move |_| {
+                                impersonate.dispatch(ImpersonateUser { user_id: impersonate_id.clone() });
+                            }
+                        >
+                            "Impersonate"
+                        </button>
+                    })}
+                    <button
+                        class=BUTTON_CLASS
+                        disabled=pending
+                        on:click=move |_| {
+                            set_disabled.dispatch(AdminSetDisabled { user_id: disable_id.clone(), disabled: !disabled });
+                        }
+                    >
+                        {if disabled { "Enable" } else { "Disable" }}
+                    </button>
+                    <button
+                        class=BUTTON_CLASS
+                        disabled=pending
+                        on:click=move |_| {
+                            set_superadmin.dispatch(AdminSetSuperadmin { user_id: promote_id.clone(), superadmin: !superadmin });
+                        }
+                    >
+                        {if superadmin { "Demote" } else { "Promote" }}
+                    </button>
+                    {move || if confirm_delete.get() {
+                        let delete_id = delete_id.clone();
+                        view! {
+                            <button
+                                class=DANGER_BUTTON_CLASS
+                                disabled=pending
+                                on:click=move |_| {
+                                    delete.dispatch(AdminDeleteUser { user_id: delete_id.clone() });
+                                }
+                            >
+                                "Confirm delete"
+                            </button>
+                            <button class=BUTTON_CLASS on:click=move |_| set_confirm_delete.set(false)>
+                                "Cancel"
+                            </button>
+                        }.into_any()
+                    } else {
+                        view! {
+                            <button class=DANGER_BUTTON_CLASS disabled=pending on:click=move |_| set_confirm_delete.set(true)>
+                                "Delete"
+                            </button>
+                        }.into_any()
+                    }}
+                </div>
+            </div>
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+            {
+                let id = id.clone();
+                move || show_sessions.get().then(|| view! { <AdminUserSessions user_id=id.clone() on_change=on_change /> })
+            }
+        </div>
+    }
+}
+
+/// Every user, searchable by name or email, with the actions a superadmin can take on them.
+#[component]
+pub fn AdminUsers() -> impl IntoView {
+    let search = RwSignal::new(String::new());
+    let page = RwSignal::new(0usize);
+    let (version, set_version) = signal(0usize);
+    let users = Resource::new(
+        move || (search.get(), page.get(), version.get()),
+        |(search, page, _)| admin_list_users(search, page),
+    );
+    let on_change = Callback::new(move |_| set_version.update(|v| *v += 1));
+
+    let page_count = move || {
+        users
+            .get()
+            .and_then(|r| r.ok())
+            .map(|users| users.total.div_ceil(ADMIN_USERS_PAGE_SIZE).max(1))
+            .unwrap_or(1)
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <div class="flex flex-wrap items-center justify-between gap-2 mb-4">
+                <h2 class="text-xl font-semibold">"Users"</h2>
+                <input
+                    type="search"
+                    class="px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md"
+                    placeholder="Search by name or email"
+                    prop:value=move || search.get()
+                    on:input=move |e| {
+                        search.set(event_target_value(&e));
+                        page.set(0);
+                    }
+                />
+            </div>
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match users.get() {
+                    Some(Ok(result)) if result.users.is_empty() => {
+                        view! { <p class="text-sm text-neutral-600 dark:text-neutral-400">"No users found."</p> }.into_any()
+                    }
+                    Some(Ok(result)) => {
+                        view! {
+                            <div class="divide-y divide-neutral-200 dark:divide-neutral-700">
+                                {result
+                                    .users
+                                    .into_iter()
+                                    .map(|user| view! { <AdminUserRow user=user on_change=on_change /> })
+                                    .collect_view()}
+                            </div>
+                        }.into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! { <p class="text-red-600 dark:text-red-400">"Error loading users: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            <div class="flex items-center justify-between mt-4 text-sm">
+                <button
+                    class=BUTTON_CLASS
+                    disabled=move || page.get() == 0
+                    on:click=move |_| page.update(|p| *p = p.saturating_sub(1))
+                >
+                    "Previous"
+                </button>
+                <span>{move || format!("Page {} of {}", page.get() + 1, page_count())}</span>
+                <button
+                    class=BUTTON_CLASS
+                    disabled=move || page.get() + 1 >= page_count()
+                    on:click=move |_| page.update(|p| *p += 1)
+                >
+                    "Next"
+                </button>
+            </div>
+        </div>
+    }
+}
+
+/// Recorded admin actions, newest first.
+#[component]
+pub fn AdminAuditLog() -> impl IntoView {
+    let page = RwSignal::new(0usize);
+    let entries = Resource::new(move || page.get(), admin_audit_log);
+    let full_page = move || {
+        entries
+            .get()
+            .and_then(|r| r.ok())
+            .is_some_and(|entries| entries.len() == ADMIN_AUDIT_PAGE_SIZE)
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-4">"Audit log"</h2>
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match entries.get() {
+                    Some(Ok(list)) if list.is_empty() => {
+                        view! { <p class="text-sm text-neutral-600 dark:text-neutral-400">"Nothing recorded yet."</p> }.into_any()
+                    }
+                    Some(Ok(list)) => {
+                        view! {
+                            <ul class="flex flex-col gap-1 text-sm">
+                                {list
+                                    .into_iter()
+                                    .map(|entry| view! {
+                                        <li>
+                                            <span class="text-xs text-neutral-600 dark:text-neutral-400">{format_datetime(&entry.created_at)}</span>
+                                            " "
+                                            <span class="font-medium">{entry.admin_name}</span>
+                                            " "
+                                            {entry.action.label().to_lowercase()}
+                                            {entry.target_name.map(|name| view! { " " <span class="font-medium">{name}</span> })}
+                                            {entry.detail.map(|detail| view! { <span class="text-neutral-600 dark:text-neutral-400">" ("{detail}")"</span> })}
+                                        </li>
+                                    })
+                                    .collect_view()}
+                            </ul>
+                        }.into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! { <p class="text-red-600 dark:text-red-400">"Error loading the audit log: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            <div class="flex items-center justify-between mt-4 text-sm">
+                <button
+                    class=BUTTON_CLASS
+                    disabled=move || page.get() == 0
+                    on:click=move |_| page.update(|p| *p = p.saturating_sub(1))
+                >
+                    "Newer"
+                </button>
+                <button
+                    class=BUTTON_CLASS
+                    disabled=move || !full_page()
+                    on:click=move |_| page.update(|p| *p += 1)
+                >
+                    "Older"
+                </button>
+            </div>
+        </div>
+    }
+}
+
+/// Re-encrypts stored keys after the master key was rotated.
+#[component]
+pub fn KeyEncryptionControl() -> impl IntoView {
+    let rotate = ServerAction::<RotateKeyEncryption>::new();
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-2">"Key encryption"</h2>
+            <p class="text-sm text-neutral-600 dark:text-neutral-400">
+                "After setting a new KEY_ENCRYPTION_KEY and moving the old one to KEY_ENCRYPTION_PREVIOUS_KEYS, re-encrypt the stored keys so the old master key can be dropped."
+            </p>
+            <button
+                class=format!("mt-4 {}", BUTTON_CLASS)
+                disabled=move || rotate.pending().get()
+                on:click=move |_| {
+                    rotate.dispatch(RotateKeyEncryption {});
+                }
+            >
+                "Re-encrypt keys"
+            </button>
+            {move || rotate.value().get().map(|result| match result {
+                Ok(changed) => view! { <p class="mt-2 text-sm">{format!("{} keys re-encrypted", changed)}</p> }.into_any(),
+                Err(e) => view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{e.to_string()}</p> }.into_any(),
+            })}
+        </div>
+    }
+}
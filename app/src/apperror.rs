@@ -28,6 +28,7 @@ pub enum AppError {
     DatabaseError(String),
     EnvVarError(String),
     NotFound(String),
+    Forbidden(String),
     Provider(String),
     InvalidAddress(String),
     Config(String),
@@ -98,6 +99,13 @@ impl axum::response::IntoResponse for AppError {
                     format!("Not found: {}", text),
                 )
             }
+            AppError::Forbidden(text) => {
+                tracing::warn!(error = %text, "Forbidden");
+                (
+                    axum::http::StatusCode::FORBIDDEN,
+                    format!("Forbidden: {}", text),
+                )
+            }
             AppError::Db => {
                 tracing::error!("Database error occurred");
                 (
@@ -271,6 +279,7 @@ impl Display for AppError {
             AppError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             AppError::EnvVarError(msg) => write!(f, "Environment variable error: {}", msg),
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             AppError::Provider(msg) => write!(f, "Provider error: {}", msg),
             AppError::InvalidAddress(msg) => write!(f, "Invalid address: {}", msg),
             AppError::Config(msg) => write!(f, "Configuration error: {}", msg),
@@ -4,6 +4,9 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "ssr")]
 use surrealdb::RecordId;
 
+#[cfg(feature = "ssr")]
+use crate::{db_init, AppError};
+
 #[derive(Debug, Clone, Serialize, Deserialize, Partial)]
 #[partial(
     "LinkAccountData",
@@ -11,7 +14,7 @@ use surrealdb::RecordId;
     omit(id)
 )]
 pub struct AdapterAccount {
-    pub id: String,
+    pub id: RecordId,
     pub access_token: String,
     pub account_type: AccountType,
     pub expires_at: i64,
@@ -21,6 +24,9 @@ pub struct AdapterAccount {
     pub scope: String,
     pub token_type: String,
     pub user_id: RecordId,
+    /// Argon2id PHC string, only set for credentials accounts.
+    #[serde(default)]
+    pub password_hash: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,3 +36,67 @@ pub enum AccountType {
     Email,
     Credentials,
 }
+
+#[cfg(feature = "ssr")]
+impl AdapterAccount {
+    pub async fn find(provider: &str, provider_account_id: &str) -> Result<Option<Self>, AppError> {
+        let client = db_init().await?;
+
+        let mut result = client
+            .query(
+                "SELECT * FROM ONLY account WHERE provider = $provider AND provider_account_id = $provider_account_id LIMIT 1;",
+            )
+            .bind(("provider", provider.to_string()))
+            .bind(("provider_account_id", provider_account_id.to_string()))
+            .await?;
+
+        let account: Option<Self> = result.take(0)?;
+        Ok(account)
+    }
+
+    /// Links a verified email address to a user, for passwordless sign-in.
+    pub async fn link_email(user_id: RecordId, email: &str) -> Result<Self, AppError> {
+        let client = db_init().await?;
+
+        let data = LinkAccountData {
+            access_token: String::new(),
+            account_type: AccountType::Email,
+            expires_at: 0,
+            provider_account_id: email.to_string(),
+            provider: "email".to_string(),
+            refresh_token: None,
+            scope: String::new(),
+            token_type: String::new(),
+            user_id,
+            password_hash: None,
+        };
+
+        let account: Option<Self> = client.create("account").content(data).await?;
+        account.ok_or_else(|| AppError::AuthError("Could not link email".into()))
+    }
+
+    /// Adds an email and password login to a user. `password_hash` must already be hashed.
+    pub async fn link_credentials(
+        user_id: RecordId,
+        email: &str,
+        password_hash: String,
+    ) -> Result<Self, AppError> {
+        let client = db_init().await?;
+
+        let data = LinkAccountData {
+            access_token: String::new(),
+            account_type: AccountType::Credentials,
+            expires_at: 0,
+            provider_account_id: email.to_string(),
+            provider: "credentials".to_string(),
+            refresh_token: None,
+            scope: String::new(),
+            token_type: String::new(),
+            user_id,
+            password_hash: Some(password_hash),
+        };
+
+        let account: Option<Self> = client.create("account").content(data).await?;
+        account.ok_or_else(|| AppError::AuthError("Could not save password".into()))
+    }
+}
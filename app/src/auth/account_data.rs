@@ -0,0 +1,355 @@
+//! The user's own data: a download of everything stored about them and deleting the account.
+//! The export leaves out secrets such as session tokens, password hashes and private keys.
+
+use leptos::prelude::*;
+use leptos::server_fn::codec::{ByteStream, GetUrl, Streaming};
+use leptos::server_fn::ServerFn;
+
+use crate::auth::ui_auth::reload_to;
+
+#[cfg(feature = "ssr")]
+use serde::Serialize;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        cookies::expired_session_cookie,
+        keys::Key,
+        passkeys::WebauthnCredential,
+        session::{get_user, AdapterSession},
+        totp::TotpFactor,
+        user::AdapterUser,
+        StorageAuthed,
+    },
+    chat::models::{ChatEventDb, ChatEventType},
+    db_init,
+    organization::models::Organization,
+    AppError, Datetime, RecordId,
+};
+
+/// Chat events fetched per query while streaming an export.
+#[cfg(feature = "ssr")]
+const EXPORT_PAGE_SIZE: usize = 500;
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct ProfileExport {
+    id: String,
+    name: String,
+    image: Option<String>,
+    superadmin: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize, serde::Deserialize)]
+struct AccountExport {
+    provider: String,
+    provider_account_id: String,
+    scope: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct SessionExport {
+    id: String,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    created_at: Option<Datetime>,
+    last_seen_at: Option<Datetime>,
+    expires: Datetime,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct PasskeyExport {
+    name: String,
+    created_at: Datetime,
+    last_used_at: Option<Datetime>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct OrganizationExport {
+    id: String,
+    name: String,
+    role: crate::auth::permissions::Role,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct KeyExport {
+    id: String,
+    name: String,
+    description: String,
+    algorithm: Option<crate::auth::keypairs::KeyAlgorithm>,
+    key_public: Option<String>,
+    key_prefix: Option<String>,
+    scopes: Vec<crate::auth::api_keys::ApiScope>,
+    organization_id: Option<String>,
+    created_at: String,
+    expires_at: Option<Datetime>,
+    last_used: Option<Datetime>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct MessageExport {
+    id: String,
+    room_id: Option<String>,
+    event_type: ChatEventType,
+    message: Option<String>,
+    timestamp: Datetime,
+    target_id: Option<String>,
+}
+
+/// Everything about a user except their chat messages, which are streamed after it.
+#[cfg(feature = "ssr")]
+#[derive(Serialize)]
+struct UserDataExport {
+    exported_at: Datetime,
+    profile: ProfileExport,
+    accounts: Vec<AccountExport>,
+    sessions: Vec<SessionExport>,
+    passkeys: Vec<PasskeyExport>,
+    two_factor_enabled: bool,
+    organizations: Vec<OrganizationExport>,
+    keys: Vec<KeyExport>,
+}
+
+#[cfg(feature = "ssr")]
+impl UserDataExport {
+    async fn collect(user: &AdapterUser) -> Result<Self, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT provider, provider_account_id, scope FROM account WHERE user_id = $user_id;")
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let accounts: Vec<AccountExport> = result.take(0)?;
+
+        let sessions = AdapterSession::for_user(user.id.clone())
+            .await?
+            .into_iter()
+            .map(|session| SessionExport {
+                id: session.id.to_string(),
+                user_agent: session.user_agent,
+                ip: session.ip,
+                created_at: session.created_at,
+                last_seen_at: session.last_seen_at,
+                expires: session.expires,
+            })
+            .collect();
+
+        let passkeys = WebauthnCredential::for_user(user.id.clone())
+            .await?
+            .into_iter()
+            .map(|credential| PasskeyExport {
+                name: credential.name,
+                created_at: credential.created_at,
+                last_used_at: credential.last_used_at,
+            })
+            .collect();
+
+        let organizations = Organization::for_user(user)
+            .await?
+            .into_iter()
+            .map(|(organization, role)| OrganizationExport {
+                id: organization.id.to_string(),
+                name: organization.name,
+                role,
+            })
+            .collect();
+
+        let keys = Key::get_by_user(user.clone())
+            .await?
+            .into_iter()
+            .map(|key| KeyExport {
+                id: key.id.to_string(),
+                name: key.name,
+                description: key.description,
+                algorithm: key.algorithm,
+                key_public: key.key_public,
+                key_prefix: key.key_prefix,
+                scopes: key.scopes,
+                organization_id: key.organization_id.map(|id| id.to_string()),
+                created_at: key.created_at,
+                expires_at: key.expires_at,
+                last_used: key.last_used,
+            })
+            .collect();
+
+        Ok(Self {
+            exported_at: Datetime::from(chrono::Utc::now()),
+            profile: ProfileExport {
+                id: user.id.to_string(),
+                name: user.name.clone(),
+                image: user.image.clone(),
+                superadmin: user.superadmin == Some(true),
+            },
+            accounts,
+            sessions,
+            passkeys,
+            two_factor_enabled: TotpFactor::enabled_for(user.id.clone()).await?,
+            organizations,
+            keys,
+        })
+    }
+}
+
+/// One page of the chat events `user_id` wrote, as comma separated JSON objects.
+#[cfg(feature = "ssr")]
+async fn message_page(user_id: RecordId, start: usize) -> Result<(String, usize), AppError> {
+    let db = db_init().await?;
+    let mut result = db
+        .query("SELECT * FROM chat_event WHERE user_id = $user_id ORDER BY timestamp ASC LIMIT $limit START $start;")
+        .bind(("user_id", user_id))
+        .bind(("limit", EXPORT_PAGE_SIZE))
+        .bind(("start", start))
+        .await?;
+    let events: Vec<ChatEventDb> = result.take(0)?;
+
+    let messages = events
+        .into_iter()
+        .map(|event| {
+            serde_json::to_string(&MessageExport {
+                id: event.id.to_string(),
+                room_id: event.room_id.map(|id| id.to_string()),
+                event_type: event.event_type,
+                message: event.message,
+                timestamp: event.timestamp,
+                target_id: event.target_id.map(|id| id.to_string()),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((messages.join(","), messages.len()))
+}
+
+/// Everything stored about the signed-in user as one JSON document. Chat messages are written
+/// page by page, so long histories never have to fit in memory at once.
+#[server(input = GetUrl, output = Streaming)]
+pub async fn download_my_data() -> Result<ByteStream, ServerFnError> {
+    use futures_util::{stream, StreamExt};
+
+    let user = get_user().await?;
+    let export = UserDataExport::collect(&user).await?;
+
+    // Leave the object open for the messages that follow
+    let mut head = serde_json::to_string(&export).map_err(AppError::from)?;
+    head.pop();
+    head.push_str(r#","messages":["#);
+
+    let user_id = user.id;
+    let messages = stream::try_unfold(Some(0), move |start| {
+        let user_id = user_id.clone();
+        async move {
+            let Some(start) = start else {
+                return Ok(None);
+            };
+            let (page, count) = message_page(user_id, start).await?;
+            let chunk = if start > 0 && count > 0 {
+                format!(",{}", page)
+            } else {
+                page
+            };
+            let next = (count == EXPORT_PAGE_SIZE).then_some(start + EXPORT_PAGE_SIZE);
+            Ok::<_, ServerFnError>(Some((chunk, next)))
+        }
+    });
+
+    let body = stream::once(async move { Ok(head) })
+        .chain(messages)
+        .chain(stream::once(async { Ok("]}".to_string()) }));
+    Ok(ByteStream::new(body))
+}
+
+/// Deletes the signed-in user's account for good. `confirmation` has to repeat their name.
+#[server]
+pub async fn delete_my_account(confirmation: String) -> Result<(), ServerFnError> {
+    use http::header::HeaderValue;
+    use leptos_axum::ResponseOptions;
+
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    if confirmation.trim() != user.name.trim() {
+        return Err(AppError::ErrorReason("Type your name to confirm".into()).into());
+    }
+    user.delete_user().await?;
+
+    // The session went with the account
+    if let Some(resp) = use_context::<ResponseOptions>() {
+        resp.insert_header(
+            axum::http::header::SET_COOKIE,
+            HeaderValue::from_str(&expired_session_cookie().to_string()).unwrap(),
+        );
+    }
+    Ok(())
+}
+
+/// Downloading the user's data and deleting their account, shown in settings.
+#[component]
+pub fn AccountDataControl() -> impl IntoView {
+    let delete = ServerAction::<DeleteMyAccount>::new();
+    let (confirmation, set_confirmation) = signal(String::new());
+
+    Effect::new(move |_| {
+        if matches!(delete.value().get(), Some(Ok(()))) {
+            reload_to("/");
+        }
+    });
+
+    let error = move || {
+        delete
+            .value()
+            .get()
+            .and_then(|r| r.err())
+            .map(|e| e.to_string())
+    };
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        delete.dispatch(DeleteMyAccount {
+            confirmation: confirmation.get(),
+        });
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-2">"Your data"</h2>
+            <p class="text-sm text-neutral-600 dark:text-neutral-400">
+                "Download your profile, sign-in methods, devices, keys and chat messages as JSON. Secrets such as passwords and private keys are not included."
+            </p>
+            <a
+                href=DownloadMyData::url()
+                download="netron-data.json"
+                class="inline-block mt-4 px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700"
+            >
+                "Download my data"
+            </a>
+
+            <h3 class="text-lg font-semibold mt-6 mb-2 text-red-600 dark:text-red-400">"Delete account"</h3>
+            <p class="text-sm text-neutral-600 dark:text-neutral-400">
+                "This deletes your sign-in methods, devices, personal keys and organizations you are the only member of. Your chat messages stay for the others but are shown as from a deleted user. Type your name to confirm."
+            </p>
+            <form on:submit=submit class="mt-4 flex items-end gap-2">
+                <input
+                    type="text"
+                    class="flex-1 px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md"
+                    placeholder="Your name"
+                    prop:value=move || confirmation.get()
+                    on:input=move |e| set_confirmation.set(event_target_value(&e))
+                    required
+                />
+                <button
+                    type="submit"
+                    class="px-4 py-2 bg-red-600 text-white rounded-md hover:bg-red-700 disabled:opacity-50 disabled:cursor-not-allowed"
+                    disabled=move || delete.pending().get() || confirmation.get().trim().is_empty()
+                >
+                    "Delete my account"
+                </button>
+            </form>
+            {move || error().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
@@ -25,9 +25,10 @@ impl SurrealAdapter {
 
     pub async fn get_user_by_account(
         &self,
-        provider_account_id: RecordId,
+        provider: &str,
+        provider_account_id: &str,
     ) -> Result<Option<AdapterUser>, AppError> {
-        Ok(AdapterUser::get_user_by_account(provider_account_id).await?)
+        Ok(AdapterUser::get_user_by_account(provider, provider_account_id).await?)
     }
 
     pub async fn update_user(data: UpdateUserData) -> Result<AdapterUser, AppError> {
@@ -66,13 +67,19 @@ impl SurrealAdapter {
         Ok(())
     }
 
-    pub async fn unlink_account(&self, provider_account_id: &str) -> Result<(), AppError> {
+    pub async fn unlink_account(
+        &self,
+        provider: &str,
+        provider_account_id: &str,
+    ) -> Result<(), AppError> {
         let client = crate::db_init().await?;
-        let query = format!(
-            "DELETE account WHERE providerAccountId = '{}';",
-            provider_account_id
-        );
-        client.query(&query).await?;
+        client
+            .query(
+                "DELETE account WHERE provider = $provider AND provider_account_id = $provider_account_id;",
+            )
+            .bind(("provider", provider.to_string()))
+            .bind(("provider_account_id", provider_account_id.to_string()))
+            .await?;
         Ok(())
     }
 
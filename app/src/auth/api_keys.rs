@@ -0,0 +1,555 @@
+//! API keys for scripts and other programs. A request that sends `Authorization: Bearer <key>`
+//! acts as the key's owner, limited to the key's [`ApiScope`]s. Only a salted hash of the
+//! secret is stored, so the full key is shown once, when it is created or rotated.
+
+use leptos::prelude::*;
+use phosphor_leptos::KEY;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::keys::Key;
+use crate::components::{
+    button::{BtnColor, BtnVariant, ButtonIcon},
+    Button, Modal, ModalSize,
+};
+use crate::RecordId;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        keys::KeyCreate,
+        permissions::{Action, Permission},
+        session::get_user,
+        totp::constant_time_eq,
+        user::AdapterUser,
+        StorageAuthed,
+    },
+    db_init, AppError, Datetime,
+};
+
+/// Start of every API key, so leaked keys are easy to recognize.
+pub const API_KEY_PREFIX: &str = "ntk";
+#[cfg(feature = "ssr")]
+const KEY_ID_BYTES: usize = 6;
+#[cfg(feature = "ssr")]
+const KEY_SECRET_BYTES: usize = 32;
+#[cfg(feature = "ssr")]
+const KEY_SALT_BYTES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiScope {
+    /// `GET` and `HEAD` requests.
+    Read,
+    /// Every other method, which includes most server functions.
+    Write,
+    /// Keeps the owner's superadmin rights. Without it the key acts as a regular user.
+    Admin,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [ApiScope::Read, ApiScope::Write, ApiScope::Admin];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Write => "write",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    /// The scope a request with `method` needs.
+    #[cfg(feature = "ssr")]
+    pub fn required_for(method: &http::Method) -> Self {
+        if method.is_safe() {
+            ApiScope::Read
+        } else {
+            ApiScope::Write
+        }
+    }
+}
+
+/// A key that was just created or rotated. `secret` is the full key and is not kept anywhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedApiKey {
+    pub key: Key,
+    pub secret: String,
+}
+
+#[cfg(feature = "ssr")]
+struct GeneratedApiKey {
+    prefix: String,
+    secret: String,
+    hash: String,
+}
+
+#[cfg(feature = "ssr")]
+fn generate_api_key() -> GeneratedApiKey {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let id: [u8; KEY_ID_BYTES] = rng.gen();
+    let secret: [u8; KEY_SECRET_BYTES] = rng.gen();
+    let salt: [u8; KEY_SALT_BYTES] = rng.gen();
+
+    let prefix = format!("{}_{}", API_KEY_PREFIX, hex::encode(id));
+    let secret = URL_SAFE_NO_PAD.encode(secret);
+    GeneratedApiKey {
+        hash: hash_secret(&salt, &secret),
+        secret: format!("{}_{}", prefix, secret),
+        prefix,
+    }
+}
+
+/// API keys carry 256 random bits, so a salted SHA-256 keeps them unreadable at rest without
+/// slowing down every request the way a password hash would.
+#[cfg(feature = "ssr")]
+fn hash_secret(salt: &[u8], secret: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(secret.as_bytes());
+    format!("{}${}", hex::encode(salt), hex::encode(hasher.finalize()))
+}
+
+#[cfg(feature = "ssr")]
+fn verify_secret(stored_hash: &str, secret: &str) -> bool {
+    let Some(salt) = stored_hash
+        .split_once('$')
+        .and_then(|(salt, _)| hex::decode(salt).ok())
+    else {
+        return false;
+    };
+    constant_time_eq(
+        hash_secret(&salt, secret).as_bytes(),
+        stored_hash.as_bytes(),
+    )
+}
+
+/// Splits a full key into the prefix it is stored under and its secret.
+#[cfg(feature = "ssr")]
+fn split_api_key(key: &str) -> Option<(&str, &str)> {
+    let rest = key.strip_prefix(API_KEY_PREFIX)?.strip_prefix('_')?;
+    let (id, secret) = rest.split_once('_')?;
+    if id.len() != KEY_ID_BYTES * 2 || secret.is_empty() {
+        return None;
+    }
+    Some((&key[..API_KEY_PREFIX.len() + 1 + id.len()], secret))
+}
+
+/// The token of an `Authorization: Bearer` header, if the request has one.
+#[cfg(feature = "ssr")]
+pub fn bearer_token(headers: &http::HeaderMap) -> Option<&str> {
+    let value = headers.get(http::header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// The user `api_key` acts for in a request with `method`. Unknown, expired and revoked keys
+/// resolve to `None`; a key without the scope the method needs is forbidden.
+#[cfg(feature = "ssr")]
+pub async fn user_from_api_key(
+    api_key: &str,
+    method: &http::Method,
+) -> Result<Option<AdapterUser>, AppError> {
+    let Some((prefix, secret)) = split_api_key(api_key) else {
+        return Ok(None);
+    };
+
+    let db = db_init().await?;
+    let mut result = db
+        .query("SELECT * FROM key WHERE key_prefix = $prefix AND (expires_at IS NONE OR expires_at > time::now());")
+        .bind(("prefix", prefix.to_string()))
+        .await?;
+    let keys: Vec<Key> = result.take(0)?;
+    let Some(key) = keys.into_iter().find(|key| {
+        key.key_hash
+            .as_deref()
+            .is_some_and(|hash| verify_secret(hash, secret))
+    }) else {
+        return Ok(None);
+    };
+
+    let required = ApiScope::required_for(method);
+    if !key.scopes.contains(&required) {
+        return Err(AppError::Forbidden(format!(
+            "This API key does not have the {} scope",
+            required.label()
+        )));
+    }
+
+    // Once a minute is precise enough to spot unused keys
+    db.query("UPDATE $id SET last_used = time::now() WHERE last_used IS NONE OR last_used < time::now() - 1m RETURN NONE;")
+        .bind(("id", key.id.clone()))
+        .await?;
+
+    let mut user = match AdapterUser::get_user(key.created_by_user_id.clone()).await {
+        Ok(user) => user,
+        Err(AppError::AuthError(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if user.disabled {
+        return Ok(None);
+    }
+    if !key.scopes.contains(&ApiScope::Admin) {
+        user.superadmin = Some(false);
+    }
+    Ok(Some(user))
+}
+
+/// Issues a personal API key for `user`. Only superadmins may grant the admin scope.
+#[cfg(feature = "ssr")]
+pub async fn issue_api_key(
+    user: AdapterUser,
+    name: String,
+    description: String,
+    scopes: Vec<ApiScope>,
+    expires_at: Option<Datetime>,
+) -> Result<IssuedApiKey, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::ErrorReason("Key name is required".into()));
+    }
+    if scopes.is_empty() {
+        return Err(AppError::ErrorReason("Choose at least one scope".into()));
+    }
+    if scopes.contains(&ApiScope::Admin) && user.superadmin != Some(true) {
+        return Err(AppError::Forbidden(
+            "Only superadmins can grant the admin scope".into(),
+        ));
+    }
+
+    let generated = generate_api_key();
+    let key = Key::create_by_user(
+        user,
+        KeyCreate {
+            name,
+            description,
+            key_for: None,
+            key_public: None,
+            key_private: None,
+            key_apikey: None,
+            key_token: None,
+            expires_at,
+            organization_id: None,
+            key_prefix: Some(generated.prefix),
+            key_hash: Some(generated.hash),
+            scopes,
+            algorithm: None,
+            iroh_identity: false,
+            key_dek: None,
+            has_secrets: false,
+        },
+    )
+    .await?;
+
+    Ok(IssuedApiKey {
+        key: key.redacted(),
+        secret: generated.secret,
+    })
+}
+
+#[server]
+pub async fn create_api_key(
+    name: String,
+    description: String,
+    scopes: Vec<ApiScope>,
+    expires_in_days: Option<i64>,
+) -> Result<IssuedApiKey, ServerFnError> {
+    use chrono::{Duration, Utc};
+
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let expires_at = match expires_in_days {
+        Some(days) if days > 0 => Some(Datetime::from(Utc::now() + Duration::days(days))),
+        Some(_) => return Err(AppError::ErrorReason("Invalid expiration days".into()).into()),
+        None => None,
+    };
+    let issued = issue_api_key(user, name, description, scopes, expires_at).await?;
+    Ok(issued)
+}
+
+/// Replaces an API key's secret. The old secret stops working at once.
+#[server]
+pub async fn rotate_api_key(id: RecordId) -> Result<IssuedApiKey, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let key = Key::get_by_id(id.clone()).await?;
+    key.authorize(&user, Action::Update).await?;
+    if !key.is_api_key() {
+        return Err(AppError::ErrorReason("Only API keys can be rotated".into()).into());
+    }
+
+    let generated = generate_api_key();
+    let db = db_init().await?;
+    let mut result = db
+        .query("UPDATE ONLY $id SET key_prefix = $prefix, key_hash = $hash, last_used = NONE, updated_at = time::now() RETURN AFTER;")
+        .bind(("id", id))
+        .bind(("prefix", generated.prefix))
+        .bind(("hash", generated.hash))
+        .await?;
+    let key: Option<Key> = result.take(0)?;
+    let key = key.ok_or_else(|| AppError::NotFound("Key not found".into()))?;
+
+    Ok(IssuedApiKey {
+        key: key.redacted(),
+        secret: generated.secret,
+    })
+}
+
+/// Shows a new API key until the user confirms they copied it.
+#[component]
+pub fn IssuedApiKeySecret(secret: String, on_done: Callback<()>) -> impl IntoView {
+    view! {
+        <div class="mb-4 p-4 rounded-md bg-neutral-100 dark:bg-neutral-700">
+            <p class="text-sm font-medium mb-2">
+                "Copy your new API key now. It will not be shown again."
+            </p>
+            <code class="block p-2 rounded bg-white dark:bg-neutral-800 font-mono text-sm break-all select-all">
+                {secret}
+            </code>
+            <button
+                type="button"
+                class="mt-4 px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700"
+                on:click=move |_| on_done.run(())
+            >
+                "I have copied the key"
+            </button>
+        </div>
+    }
+}
+
+#[component]
+pub fn ApiKeyAdd(#[prop(optional)] on_success: Option<Callback<Key, ()>>) -> impl IntoView {
+    let (show_modal, set_show_modal) = signal(false);
+    let (name, set_name) = signal(String::new());
+    let (description, set_description) = signal(String::new());
+    let (expires_in_days, set_expires_in_days) = signal(String::new());
+    let (scopes, set_scopes) = signal(vec![ApiScope::Read]);
+    let (issued, set_issued) = signal(Option::<String>::None);
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+    let create = ServerAction::<CreateApiKey>::new();
+
+    Effect::new(move |_| match create.value().get() {
+        Some(Ok(created)) => {
+            set_name.set(String::new());
+            set_description.set(String::new());
+            set_expires_in_days.set(String::new());
+            set_scopes.set(vec![ApiScope::Read]);
+            set_error_message.set(None);
+            set_issued.set(Some(created.secret));
+            if let Some(callback) = on_success {
+                callback.run(created.key);
+            }
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let expires_in_days = expires_in_days.get();
+        let expires_in_days = if expires_in_days.trim().is_empty() {
+            None
+        } else {
+            match expires_in_days.trim().parse::<i64>() {
+                Ok(days) => Some(days),
+                Err(_) => {
+                    set_error_message.set(Some("Invalid expiration days".to_string()));
+                    return;
+                }
+            }
+        };
+        create.dispatch(CreateApiKey {
+            name: name.get(),
+            description: description.get(),
+            scopes: scopes.get(),
+            expires_in_days,
+        });
+    };
+
+    let close_modal = Callback::new(move |_| {
+        set_show_modal.set(false);
+        set_issued.set(None);
+        set_error_message.set(None);
+    });
+
+    let toggle_scope = move |scope: ApiScope| {
+        set_scopes.update(|scopes| {
+            if let Some(index) = scopes.iter().position(|s| *s == scope) {
+                scopes.remove(index);
+            } else {
+                scopes.push(scope);
+            }
+        });
+    };
+
+    view! {
+        <>
+            <Button
+                on_click=Callback::new(move |_| set_show_modal.set(true))
+                variant=BtnVariant::CallToAction
+                color=BtnColor::Primary
+                icon=ButtonIcon::Icon(KEY)
+            >
+                "Create API Key"
+            </Button>
+
+            <Modal
+                show=show_modal.into()
+                on_close=close_modal
+                title="Create API Key".to_string()
+                size=ModalSize::Medium
+            >
+                {move || match issued.get() {
+                    Some(secret) => view! {
+                        <IssuedApiKeySecret secret=secret on_done=close_modal />
+                    }.into_any(),
+                    None => view! {
+                        <form on:submit=submit>
+                            <div class="space-y-4">
+                                <div>
+                                    <label for="api-key-name" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                        "Name" <span class="text-red-500">"*"</span>
+                                    </label>
+                                    <input
+                                        type="text"
+                                        id="api-key-name"
+                                        class="w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+                                        prop:value=move || name.get()
+                                        on:input=move |e| set_name.set(event_target_value(&e))
+                                        required
+                                        disabled=move || create.pending().get()
+                                    />
+                                </div>
+
+                                <div>
+                                    <label for="api-key-description" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                        "Description"
+                                    </label>
+                                    <textarea
+                                        id="api-key-description"
+                                        rows=2
+                                        class="w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+                                        prop:value=move || description.get()
+                                        on:input=move |e| set_description.set(event_target_value(&e))
+                                        disabled=move || create.pending().get()
+                                    />
+                                </div>
+
+                                <fieldset>
+                                    <legend class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">"Scopes"</legend>
+                                    <div class="flex gap-4">
+                                        {ApiScope::ALL
+                                            .into_iter()
+                                            .map(|scope| view! {
+                                                <label class="flex items-center gap-2 text-sm">
+                                                    <input
+                                                        type="checkbox"
+                                                        prop:checked=move || scopes.get().contains(&scope)
+                                                        on:change=move |_| toggle_scope(scope)
+                                                    />
+                                                    {scope.label()}
+                                                </label>
+                                            })
+                                            .collect_view()}
+                                    </div>
+                                    <p class="mt-1 text-xs text-neutral-500 dark:text-neutral-400">
+                                        "Read allows GET requests. Server functions are called with POST and need write."
+                                    </p>
+                                </fieldset>
+
+                                <div>
+                                    <label for="api-key-expires" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                        "Expires in (days)"
+                                    </label>
+                                    <input
+                                        type="number"
+                                        id="api-key-expires"
+                                        min="1"
+                                        class="w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+                                        placeholder="Leave empty for no expiration"
+                                        prop:value=move || expires_in_days.get()
+                                        on:input=move |e| set_expires_in_days.set(event_target_value(&e))
+                                        disabled=move || create.pending().get()
+                                    />
+                                </div>
+
+                                {move || error_message.get().map(|msg| {
+                                    view! {
+                                        <div class="text-red-600 dark:text-red-400 text-sm">
+                                            {msg}
+                                        </div>
+                                    }
+                                })}
+
+                                <div class="flex gap-4 pt-2">
+                                    <button
+                                        type="submit"
+                                        class="px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 dark:focus:ring-offset-neutral-800 disabled:opacity-50 disabled:cursor-not-allowed"
+                                        disabled=move || create.pending().get()
+                                    >
+                                        {move || if create.pending().get() { "Creating..." } else { "Create Key" }}
+                                    </button>
+                                    <button
+                                        type="button"
+                                        class="px-4 py-2 bg-neutral-200 dark:bg-neutral-700 text-neutral-700 dark:text-neutral-300 rounded-md hover:bg-neutral-300 dark:hover:bg-neutral-600"
+                                        on:click=move |_| close_modal.run(())
+                                    >
+                                        "Cancel"
+                                    </button>
+                                </div>
+                            </div>
+                        </form>
+                    }.into_any(),
+                }}
+            </Modal>
+        </>
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_api_key_round_trip() {
+    let generated = generate_api_key();
+    assert!(generated.secret.starts_with("ntk_"));
+    assert!(!generated.hash.contains(&generated.secret));
+
+    let (prefix, secret) = split_api_key(&generated.secret).unwrap();
+    assert_eq!(prefix, generated.prefix);
+    assert!(verify_secret(&generated.hash, secret));
+    assert!(!verify_secret(&generated.hash, "not-the-secret"));
+    assert!(!verify_secret("garbage", secret));
+
+    assert!(split_api_key("ntk_short_secret").is_none());
+    assert!(split_api_key("Bearer something").is_none());
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_bearer_token_and_scopes() {
+    let mut headers = http::HeaderMap::new();
+    assert_eq!(bearer_token(&headers), None);
+    headers.insert(
+        http::header::AUTHORIZATION,
+        "Bearer ntk_abc".parse().unwrap(),
+    );
+    assert_eq!(bearer_token(&headers), Some("ntk_abc"));
+    headers.insert(
+        http::header::AUTHORIZATION,
+        "Basic dXNlcg==".parse().unwrap(),
+    );
+    assert_eq!(bearer_token(&headers), None);
+
+    assert_eq!(ApiScope::required_for(&http::Method::GET), ApiScope::Read);
+    assert_eq!(ApiScope::required_for(&http::Method::POST), ApiScope::Write);
+    assert_eq!(
+        ApiScope::required_for(&http::Method::DELETE),
+        ApiScope::Write
+    );
+}
@@ -0,0 +1,128 @@
+//! Session cookie settings. Cookies are `Secure` when the app is served over HTTPS, which is
+//! read from the scheme of `AUTH_URL` unless `COOKIE_SECURE` is set to `true` or `false`.
+//! Secure session cookies get the `__Host-` prefix, so browsers only accept them from this
+//! exact host, over HTTPS and for the whole site.
+
+use std::sync::LazyLock;
+
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CookieSettings {
+    pub secure: bool,
+}
+
+impl CookieSettings {
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        Self::from_values(
+            std::env::var("AUTH_URL").ok().as_deref(),
+            std::env::var("COOKIE_SECURE").ok().as_deref(),
+        )
+    }
+
+    fn from_values(auth_url: Option<&str>, cookie_secure: Option<&str>) -> Self {
+        let secure = match cookie_secure.map(|value| value.trim().to_ascii_lowercase()) {
+            Some(value) if value == "true" || value == "1" => true,
+            Some(value) if value == "false" || value == "0" => false,
+            _ => auth_url.is_some_and(|url| url.trim().starts_with("https://")),
+        };
+        Self { secure }
+    }
+
+    pub fn session_cookie_name(&self) -> &'static str {
+        if self.secure {
+            "__Host-session_token"
+        } else {
+            "session_token"
+        }
+    }
+
+    pub fn oidc_state_cookie_name(&self) -> &'static str {
+        if self.secure {
+            "__Host-oidc_state"
+        } else {
+            "oidc_state"
+        }
+    }
+}
+
+static COOKIE_SETTINGS: LazyLock<CookieSettings> = LazyLock::new(CookieSettings::from_env);
+
+pub fn cookie_settings() -> CookieSettings {
+    *COOKIE_SETTINGS
+}
+
+pub fn session_cookie_name() -> &'static str {
+    cookie_settings().session_cookie_name()
+}
+
+/// The session cookie carrying `value` for `max_age`.
+pub fn session_cookie(value: String, max_age: time::Duration) -> Cookie<'static> {
+    let settings = cookie_settings();
+    Cookie::build((settings.session_cookie_name(), value))
+        .path("/")
+        .secure(settings.secure)
+        .http_only(true) // JS can't read the cookie
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .build()
+}
+
+/// A cookie that makes the browser drop its session cookie.
+pub fn expired_session_cookie() -> Cookie<'static> {
+    let mut cookie = session_cookie(String::new(), time::Duration::ZERO);
+    cookie.set_expires(time::OffsetDateTime::now_utc() - time::Duration::days(1));
+    cookie
+}
+
+/// Ties an OIDC sign-in to the browser that started it, for `max_age`. `Lax` so that it comes
+/// back with the provider's top-level redirect to the callback.
+pub fn oidc_state_cookie(state: String, max_age: time::Duration) -> Cookie<'static> {
+    let settings = cookie_settings();
+    Cookie::build((settings.oidc_state_cookie_name(), state))
+        .path("/")
+        .secure(settings.secure)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .build()
+}
+
+/// A cookie that makes the browser drop its OIDC state cookie.
+pub fn expired_oidc_state_cookie() -> Cookie<'static> {
+    let mut cookie = oidc_state_cookie(String::new(), time::Duration::ZERO);
+    cookie.set_expires(time::OffsetDateTime::now_utc() - time::Duration::days(1));
+    cookie
+}
+
+/// The OIDC state in `jar`, if there is a non-empty one.
+pub fn oidc_state_from(jar: &CookieJar) -> Option<String> {
+    jar.get(cookie_settings().oidc_state_cookie_name())
+        .map(|cookie| cookie.value().to_string())
+        .filter(|state| !state.is_empty())
+}
+
+/// The session token in `jar`, if there is a non-empty one.
+pub fn session_token_from(jar: &CookieJar) -> Option<String> {
+    jar.get(session_cookie_name())
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+#[test]
+fn test_cookie_settings() {
+    let local = CookieSettings::from_values(Some("http://localhost:3000"), None);
+    assert!(!local.secure);
+    assert_eq!(local.session_cookie_name(), "session_token");
+
+    let deployed = CookieSettings::from_values(Some("https://netron.example"), None);
+    assert!(deployed.secure);
+    assert_eq!(deployed.session_cookie_name(), "__Host-session_token");
+    assert_eq!(deployed.oidc_state_cookie_name(), "__Host-oidc_state");
+
+    // An explicit setting wins, e.g. behind a proxy that terminates TLS
+    assert!(CookieSettings::from_values(Some("http://netron.internal"), Some("true")).secure);
+    assert!(!CookieSettings::from_values(Some("https://netron.example"), Some("false")).secure);
+}
@@ -0,0 +1,236 @@
+use leptos::prelude::*;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        account::AdapterAccount,
+        magic_link::normalize_email,
+        session::{set_session_cookie, AdapterSession},
+        user::{AdapterUser, CreateUserData},
+    },
+    theme::Theme,
+    AppError,
+};
+
+#[cfg(feature = "ssr")]
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+pub const PASSWORD_MIN_LEN: usize = 10;
+pub const PASSWORD_MAX_LEN: usize = 128;
+
+/// Passwords that pass the length and character rules but are still guessed first.
+const COMMON_PASSWORDS: [&str; 8] = [
+    "password123",
+    "password1234",
+    "qwertyuiop",
+    "1234567890",
+    "letmein123",
+    "iloveyou123",
+    "welcome123",
+    "administrator",
+];
+
+/// Checks a new password, returning the reason it is too weak. `personal` holds values the
+/// password must not contain, such as the user's name and email.
+pub fn validate_password_strength(password: &str, personal: &[&str]) -> Result<(), String> {
+    let len = password.chars().count();
+    if len < PASSWORD_MIN_LEN {
+        return Err(format!(
+            "Use at least {} characters for your password",
+            PASSWORD_MIN_LEN
+        ));
+    }
+    if len > PASSWORD_MAX_LEN {
+        return Err(format!(
+            "Use at most {} characters for your password",
+            PASSWORD_MAX_LEN
+        ));
+    }
+
+    let has_letter = password.chars().any(char::is_alphabetic);
+    let has_other = password.chars().any(|c| !c.is_alphabetic());
+    if !has_letter || !has_other {
+        return Err("Mix letters with numbers or symbols".to_string());
+    }
+
+    let distinct: std::collections::HashSet<char> = password.chars().collect();
+    if distinct.len() < 4 {
+        return Err("Use more varied characters".to_string());
+    }
+
+    let lowered = password.to_lowercase();
+    if COMMON_PASSWORDS.contains(&lowered.as_str()) {
+        return Err("That password is too common".to_string());
+    }
+    // Compare against each word of a name and the part of an email before the @
+    let words = personal.iter().flat_map(|value| {
+        value
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .split(|c: char| !c.is_alphanumeric())
+    });
+    for word in words {
+        if word.chars().count() >= 3 && lowered.contains(&word.to_lowercase()) {
+            return Err("Don't include your name or email in your password".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+async fn hash_password(password: String) -> Result<String, AppError> {
+    // Argon2 is deliberately slow, so keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::GenericError(format!("Could not hash password: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::GenericError(e.to_string()))?
+}
+
+/// Verifies a password against a PHC hash string. A missing hash is checked against a dummy
+/// so unknown accounts take as long to reject as wrong passwords.
+#[cfg(feature = "ssr")]
+async fn verify_password(password: String, hash: Option<String>) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(b"dummy password", &salt)
+                .map(|hash| hash.to_string())
+                .unwrap_or_default()
+        });
+
+        // Argon2's verifier compares the digests in constant time
+        let matches = PasswordHash::new(&hash).is_ok_and(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        });
+        known && matches
+    })
+    .await
+    .map_err(|e| AppError::GenericError(e.to_string()))
+}
+
+/// Creates a user with a password login and starts a session.
+#[cfg(feature = "ssr")]
+pub async fn register_with_password(
+    name: &str,
+    email: &str,
+    password: String,
+) -> Result<AdapterSession, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::ErrorReason("Enter your name".into()));
+    }
+    let email = normalize_email(email)
+        .ok_or_else(|| AppError::ErrorReason("Enter a valid email address".into()))?;
+    validate_password_strength(&password, &[name, &email]).map_err(AppError::ErrorReason)?;
+
+    // Registering must not attach a password to someone else's passwordless account
+    if AdapterAccount::find("credentials", &email).await?.is_some()
+        || AdapterAccount::find("email", &email).await?.is_some()
+    {
+        return Err(AppError::ErrorReason(
+            "An account with this email already exists".into(),
+        ));
+    }
+
+    let password_hash = hash_password(password).await?;
+    let user = AdapterUser::create_user(CreateUserData {
+        name: name.to_string(),
+        image: None,
+        theme: Theme::default(),
+    })
+    .await?;
+    AdapterAccount::link_credentials(user.id.clone(), &email, password_hash).await?;
+
+    user.new_session().await
+}
+
+/// Checks an email and password and starts a session.
+#[cfg(feature = "ssr")]
+pub async fn login_with_password(
+    email: &str,
+    password: String,
+) -> Result<AdapterSession, AppError> {
+    let invalid = || AppError::AuthError("Invalid email or password".into());
+
+    let account = match normalize_email(email) {
+        Some(email) => AdapterAccount::find("credentials", &email).await?,
+        None => None,
+    };
+    let hash = account.as_ref().and_then(|a| a.password_hash.clone());
+
+    if !verify_password(password, hash).await? {
+        return Err(invalid());
+    }
+    let account = account.ok_or_else(invalid)?;
+
+    let user = AdapterUser::get_user(account.user_id).await?;
+    user.new_session().await
+}
+
+/// Returns the path to continue to, which asks for a code if the user has two-factor on.
+#[server]
+pub async fn register_password(
+    name: String,
+    email: String,
+    password: String,
+) -> Result<String, ServerFnError> {
+    // Signing up would quietly replace the impersonation session
+    forbid_impersonation().await?;
+    let session = register_with_password(&name, &email, password).await?;
+    set_session_cookie(&session);
+    Ok(session.landing_path().to_string())
+}
+
+/// Returns the path to continue to, which asks for a code if the user has two-factor on.
+#[server]
+pub async fn login_password(email: String, password: String) -> Result<String, ServerFnError> {
+    let session = login_with_password(&email, password).await?;
+    set_session_cookie(&session);
+    Ok(session.landing_path().to_string())
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_validate_password_strength() {
+    assert!(validate_password_strength("short1", &[]).is_err());
+    assert!(validate_password_strength("onlyletterslong", &[]).is_err());
+    assert!(validate_password_strength("1212121212", &[]).is_err());
+    assert!(validate_password_strength("Password123", &[]).is_err());
+    assert!(
+        validate_password_strength("ada-rocks-2024", &["Ada Lovelace", "ada@example.com"]).is_err()
+    );
+    assert!(validate_password_strength("ada-rocks-2024", &["Grace", "grace@example.com"]).is_ok());
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+async fn test_password_hash_round_trip() {
+    let hash = hash_password("correct horse 42".to_string()).await.unwrap();
+    assert!(hash.starts_with("$argon2id$"));
+    assert!(
+        verify_password("correct horse 42".to_string(), Some(hash.clone()))
+            .await
+            .unwrap()
+    );
+    assert!(!verify_password("wrong horse 42".to_string(), Some(hash))
+        .await
+        .unwrap());
+    assert!(!verify_password("dummy password".to_string(), None)
+        .await
+        .unwrap());
+}
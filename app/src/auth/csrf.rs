@@ -0,0 +1,125 @@
+//! Cross-site request forgery protection for server functions and other state changing
+//! requests. Browsers send `Origin` with every cross-origin POST, so unsafe requests must come
+//! from the app's own origin or one listed in `CSRF_TRUSTED_ORIGINS`. Requests with neither
+//! `Origin` nor `Referer` come from non-browser clients, which carry no ambient cookies, and are
+//! let through. So are requests authenticated by an API key in the `Authorization` header
+//! without a session cookie: browsers never attach that header on their own.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::auth::{api_keys::bearer_token, cookies::session_token_from};
+use crate::AppError;
+
+#[derive(Debug, Clone, Default)]
+pub struct CsrfConfig {
+    trusted_origins: Vec<String>,
+}
+
+/// The `scheme://host[:port]` part of a URL or origin, lowercased.
+fn origin_of(url: &str) -> String {
+    match reqwest::Url::parse(url.trim()) {
+        Ok(parsed) if parsed.origin().is_tuple() => parsed.origin().ascii_serialization(),
+        _ => url.trim().trim_end_matches('/').to_ascii_lowercase(),
+    }
+}
+
+impl CsrfConfig {
+    pub fn new<I, S>(trusted_origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            trusted_origins: trusted_origins
+                .into_iter()
+                .map(|origin| origin_of(origin.as_ref()))
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Trusts `AUTH_URL` and the comma separated `CSRF_TRUSTED_ORIGINS`.
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        let auth_url = std::env::var("AUTH_URL").ok();
+        let extra = std::env::var("CSRF_TRUSTED_ORIGINS").unwrap_or_default();
+        Self::new(
+            auth_url
+                .iter()
+                .map(String::as_str)
+                .chain(extra.split(','))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Whether a request from `origin` to the server at `host` is same-origin or trusted.
+    pub fn allows(&self, origin: &str, host: Option<&str>) -> bool {
+        let origin = origin_of(origin);
+        if origin == "null" {
+            return false;
+        }
+        let same_host = host.is_some_and(|host| {
+            origin
+                .split_once("://")
+                .is_some_and(|(_, origin_host)| origin_host.eq_ignore_ascii_case(host))
+        });
+        same_host || self.trusted_origins.contains(&origin)
+    }
+}
+
+/// Middleware that rejects unsafe requests sent from other sites with 403.
+pub async fn csrf_guard(
+    State(config): State<CsrfConfig>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    ) {
+        return Ok(next.run(request).await);
+    }
+
+    let headers = request.headers();
+    let cookies = axum_extra::extract::CookieJar::from_headers(headers);
+    if bearer_token(headers).is_some() && session_token_from(&cookies).is_none() {
+        return Ok(next.run(request).await);
+    }
+
+    let origin = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .map(|value| value.to_str().unwrap_or("null").to_string());
+    if let Some(origin) = origin {
+        let host = headers
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok());
+        if !config.allows(&origin, host) {
+            return Err(AppError::Forbidden(format!(
+                "Cross-origin request from {} blocked",
+                origin_of(&origin)
+            )));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[test]
+fn test_csrf_origins() {
+    let config = CsrfConfig::new(["http://localhost:3000/", "tauri://localhost"]);
+
+    assert!(config.allows("http://localhost:3000", None));
+    assert!(config.allows("http://localhost:3000/login?next=/", None));
+    assert!(config.allows("tauri://localhost", None));
+    assert!(config.allows("https://netron.example", Some("netron.example")));
+
+    assert!(!config.allows("http://localhost:3001", None));
+    assert!(!config.allows("https://evil.example", Some("netron.example")));
+    assert!(!config.allows("null", Some("netron.example")));
+}
@@ -0,0 +1,240 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::session::Logout;
+use crate::auth::ui_auth::reload_to;
+use crate::date_utils::format_datetime;
+use crate::{Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use crate::admin::models::forbid_impersonation;
+#[cfg(feature = "ssr")]
+use crate::auth::session::{current_session_token, get_user, AdapterSession};
+
+/// A signed-in device as shown to its owner. Never carries the session token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: RecordId,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: Option<Datetime>,
+    pub last_seen_at: Option<Datetime>,
+    pub expires: Datetime,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+/// A short label such as "Firefox on Linux" for a user agent string.
+pub fn describe_user_agent(user_agent: &str) -> String {
+    // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("Tauri", "Netron app"),
+        ("curl/", "curl"),
+    ]
+    .into_iter()
+    .find(|(needle, _)| user_agent.contains(needle))
+    .map(|(_, name)| name);
+
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .into_iter()
+    .find(|(needle, _)| user_agent.contains(needle))
+    .map(|(_, name)| name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{} on {}", browser, os),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+#[server]
+pub async fn list_sessions() -> Result<Vec<SessionInfo>, ServerFnError> {
+    let user = get_user().await?;
+    let token = current_session_token().await?;
+    let sessions = AdapterSession::for_user(user.id).await?;
+    Ok(sessions
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: session.session_token == token,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires: session.expires,
+        })
+        .collect())
+}
+
+#[server]
+pub async fn revoke_session(id: RecordId) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    AdapterSession::revoke(id, user.id).await?;
+    Ok(())
+}
+
+/// Signs out everywhere except on the device making the request.
+#[server]
+pub async fn revoke_other_sessions() -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let token = current_session_token().await?;
+    AdapterSession::revoke_others(user.id, token).await?;
+    Ok(())
+}
+
+#[component]
+fn SessionItem(
+    session: SessionInfo,
+    on_revoke: Callback<RecordId>,
+    on_sign_out: Callback<()>,
+) -> impl IntoView {
+    let id = session.id.clone();
+    let current = session.current;
+    let label = session
+        .user_agent
+        .as_deref()
+        .map(describe_user_agent)
+        .unwrap_or_else(|| "Unknown device".to_string());
+
+    view! {
+        <div class="px-4 py-3 hover:bg-neutral-50 dark:hover:bg-neutral-700">
+            <div class="flex items-center justify-between">
+                <div class="flex-1">
+                    <h3 class="text-sm font-medium text-neutral-900 dark:text-neutral-100">
+                        {label}
+                        {session.current.then(|| view! {
+                            <span class="ml-2 px-2 py-0.5 text-xs rounded bg-green-100 text-green-800 dark:bg-green-900 dark:text-green-200">"This device"</span>
+                        })}
+                    </h3>
+                    <div class="flex flex-wrap items-center gap-4 mt-2 text-xs text-neutral-600 dark:text-neutral-400">
+                        {session.ip.clone().map(|ip| view! { <span class="font-mono">{ip}</span> })}
+                        <span>
+                            "Last active: "
+                            {session.last_seen_at.as_ref().map(format_datetime).unwrap_or_else(|| "unknown".to_string())}
+                        </span>
+                        {session.created_at.as_ref().map(|created| view! { <span>"Signed in: "{format_datetime(created)}</span> })}
+                        <span>"Expires: "{format_datetime(&session.expires)}</span>
+                    </div>
+                </div>
+                <button
+                    class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 text-sm font-medium"
+                    on:click=move |_| {
+                        if current {
+                            on_sign_out.run(())
+                        } else {
+                            on_revoke.run(id.clone())
+                        }
+                    }
+                >
+                    {if current { "Sign out" } else { "Revoke" }}
+                </button>
+            </div>
+        </div>
+    }
+}
+
+/// The user's signed-in devices, each of which can be signed out.
+#[component]
+pub fn DevicesControl() -> impl IntoView {
+    let sessions = Resource::new(|| (), |_| list_sessions());
+    let revoke = ServerAction::<RevokeSession>::new();
+    let revoke_others = ServerAction::<RevokeOtherSessions>::new();
+    // Revoking the current session is a normal sign out, which also clears the cookie
+    let logout = ServerAction::<Logout>::new();
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| {
+        for result in [revoke.value().get(), revoke_others.value().get()] {
+            if let Some(result) = result {
+                set_error_message.set(result.err().map(|e| e.to_string()));
+                sessions.refetch();
+            }
+        }
+    });
+
+    Effect::new(move |_| match logout.value().get() {
+        Some(Ok(())) => reload_to("/login"),
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    let on_revoke = Callback::new(move |id: RecordId| {
+        revoke.dispatch(RevokeSession { id });
+    });
+    let on_sign_out = Callback::new(move |_| {
+        logout.dispatch(Logout {});
+    });
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <div class="flex items-center justify-between mb-4">
+                <h2 class="text-xl font-semibold">"Your devices"</h2>
+                <button
+                    class="px-4 py-2 bg-neutral-200 dark:bg-neutral-700 rounded-md hover:bg-neutral-300 dark:hover:bg-neutral-600 disabled:opacity-50"
+                    disabled=move || revoke_others.pending().get()
+                    on:click=move |_| {
+                        revoke_others.dispatch(RevokeOtherSessions {});
+                    }
+                >
+                    "Sign out other devices"
+                </button>
+            </div>
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match sessions.get() {
+                    Some(Ok(list)) => {
+                        view! {
+                            <div class="divide-y divide-neutral-200 dark:divide-neutral-700">
+                                {list
+                                    .into_iter()
+                                    .map(|session| view! { <SessionItem session=session on_revoke=on_revoke on_sign_out=on_sign_out /> })
+                                    .collect_view()}
+                            </div>
+                        }.into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! { <p class="text-red-600 dark:text-red-400">"Error loading devices: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_describe_user_agent() {
+    assert_eq!(
+        describe_user_agent(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0"
+        ),
+        "Firefox on Linux"
+    );
+    assert_eq!(
+        describe_user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36 Edg/129.0.0.0"),
+        "Edge on Windows"
+    );
+    assert_eq!(
+        describe_user_agent("Mozilla/5.0 (iPhone; CPU iPhone OS 17_6 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.6 Mobile/15E148 Safari/604.1"),
+        "Safari on iOS"
+    );
+    assert_eq!(describe_user_agent(""), "Unknown device");
+}
@@ -0,0 +1,152 @@
+//! Resolves the signed-in user once per request, from an API key in the `Authorization` header
+//! or else the session cookie. [`resolve_auth`] runs as middleware and stores the result in the
+//! request extensions, where the [`AuthUser`] and [`OptionalAuthUser`] extractors pick it up,
+//! both in axum handlers and through `leptos_axum::extract` in server functions.
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderMap, Method},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::CookieJar;
+
+use crate::auth::{
+    api_keys::{bearer_token, user_from_api_key},
+    cookies::session_token_from,
+    user::AdapterUser,
+};
+use crate::AppError;
+
+/// The outcome of resolving a request's session, as stored in the request extensions.
+#[derive(Debug, Clone)]
+struct ResolvedAuth(Option<AdapterUser>);
+
+/// The signed-in user. Rejects the request with 401 if there is none.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub AdapterUser);
+
+/// The signed-in user, if any.
+#[derive(Debug, Clone)]
+pub struct OptionalAuthUser(pub Option<AdapterUser>);
+
+/// Looks up the user for the API key or session cookie in `headers`. Missing, expired and
+/// pending credentials resolve to `None`; database failures and API keys without the scope
+/// `method` needs are errors. A request with an API key never falls back to the cookie.
+async fn user_from_headers(
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<Option<AdapterUser>, AppError> {
+    if let Some(api_key) = bearer_token(headers) {
+        return user_from_api_key(api_key, method).await;
+    }
+
+    let Some(token) = session_token_from(&CookieJar::from_headers(headers)) else {
+        return Ok(None);
+    };
+
+    match AdapterUser::get_user_from_session(token).await {
+        Ok(user) => Ok(Some(user)),
+        Err(AppError::AuthError(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Middleware that resolves the session cookie into the request extensions. On a database
+/// failure nothing is stored, so the extractors try again and report the error themselves.
+pub async fn resolve_auth(mut request: Request, next: Next) -> Response {
+    match user_from_headers(request.method(), request.headers()).await {
+        Ok(user) => {
+            request.extensions_mut().insert(ResolvedAuth(user));
+        }
+        Err(e) => tracing::warn!("Could not resolve credentials: {}", e),
+    }
+    next.run(request).await
+}
+
+/// Middleware for routes that only superadmins may use. Answers 401 without a user and 403 for
+/// everyone else.
+pub async fn require_superadmin(
+    user: AuthUser,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    user.require_superadmin()?;
+    Ok(next.run(request).await)
+}
+
+impl AuthUser {
+    /// Fails with [`AppError::Forbidden`] unless the user is a superadmin.
+    pub fn require_superadmin(self) -> Result<Self, AppError> {
+        if self.0.superadmin == Some(true) {
+            Ok(self)
+        } else {
+            Err(AppError::Forbidden("Superadmin access required".into()))
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for OptionalAuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Routes outside the layer resolve the cookie themselves
+        if let Some(ResolvedAuth(user)) = parts.extensions.get::<ResolvedAuth>() {
+            return Ok(OptionalAuthUser(user.clone()));
+        }
+        let user = user_from_headers(&parts.method, &parts.headers).await?;
+        parts.extensions.insert(ResolvedAuth(user.clone()));
+        Ok(OptionalAuthUser(user))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let OptionalAuthUser(user) = OptionalAuthUser::from_request_parts(parts, state).await?;
+        user.map(AuthUser)
+            .ok_or_else(|| AppError::AuthError("Not logged in.".into()))
+    }
+}
+
+/// The signed-in user for the current server function, or a "Not logged in." error.
+pub async fn auth_user() -> Result<AdapterUser, leptos::prelude::ServerFnError> {
+    let OptionalAuthUser(user) = leptos_axum::extract::<OptionalAuthUser>().await?;
+    user.ok_or_else(|| leptos::prelude::ServerFnError::new("Not logged in."))
+}
+
+/// The signed-in user for the current server function if they are a superadmin.
+pub async fn require_superadmin_user() -> Result<AdapterUser, leptos::prelude::ServerFnError> {
+    let user = auth_user().await?;
+    Ok(AuthUser(user).require_superadmin()?.0)
+}
+
+#[tokio::test]
+async fn test_require_superadmin() {
+    use crate::theme::Theme;
+
+    let user = |superadmin| {
+        AuthUser(AdapterUser {
+            id: crate::RecordId::from(("user", "ada")),
+            name: "Ada".into(),
+            image: None,
+            superadmin,
+            theme: Theme::System,
+            disabled: false,
+        })
+    };
+
+    assert!(user(Some(true)).require_superadmin().is_ok());
+    assert!(matches!(
+        user(Some(false)).require_superadmin(),
+        Err(AppError::Forbidden(_))
+    ));
+    assert!(user(None).require_superadmin().is_err());
+
+    // No cookie resolves to no user without touching the database
+    assert!(user_from_headers(&Method::GET, &HeaderMap::new())
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,433 @@
+//! Envelope encryption of key material at rest. The private parts of every key are encrypted
+//! with XChaCha20-Poly1305 under a random data key of their own, and the data key is stored
+//! wrapped by the server's master key from [`Settings`](crate::db::Settings).
+//!
+//! Rotating the master key only re-wraps the data keys: set the new `KEY_ENCRYPTION_KEY`, move
+//! the old one to `KEY_ENCRYPTION_PREVIOUS_KEYS` and restart, or let a superadmin call
+//! [`rotate_key_encryption`]. Keys stored before encryption are sealed the same way.
+//!
+//! Decrypted secrets reach the owner only once, through [`reveal_key_secrets`]. The one
+//! exception is an iroh identity: binding it with
+//! [`bind_iroh_identity`](crate::auth::keypairs::bind_iroh_identity) spends that reveal, and
+//! the bound key's private part is then served to the owner's browser on every start.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::RecordId;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        keys::{Key, KeyCreate},
+        session::get_user,
+        StorageAuthed,
+    },
+    db::{get_settings, Settings},
+    db_init, AppError,
+};
+
+#[cfg(feature = "ssr")]
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+#[cfg(feature = "ssr")]
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+#[cfg(feature = "ssr")]
+use sha2::{Digest, Sha256};
+
+/// Stored secrets that are encrypted start with this.
+#[cfg(feature = "ssr")]
+const SEALED_PREFIX: &str = "enc:";
+
+#[cfg(feature = "ssr")]
+const NONCE_LEN: usize = 24;
+
+/// Associated data of wrapped data keys, so they can't pass for a sealed field.
+#[cfg(feature = "ssr")]
+const DATA_KEY_AAD: &[u8] = b"data key";
+
+/// The decrypted secrets of a key, from its one-time reveal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeySecrets {
+    pub key_private: Option<String>,
+    pub key_apikey: Option<String>,
+    pub key_token: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+struct MasterKey {
+    /// Names the master key in wrapped data keys without giving anything away.
+    id: String,
+    cipher: XChaCha20Poly1305,
+}
+
+#[cfg(feature = "ssr")]
+impl MasterKey {
+    fn parse(encoded: &str) -> Result<Self, AppError> {
+        let bytes: [u8; 32] = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                AppError::EnvVarError("Key encryption keys must be base64 of 32 bytes".into())
+            })?;
+        Ok(Self {
+            id: hex::encode(&Sha256::digest(bytes)[..4]),
+            cipher: XChaCha20Poly1305::new(&bytes.into()),
+        })
+    }
+}
+
+/// The secret fields of a key, named in their ciphertexts so one can't be swapped for another.
+#[cfg(feature = "ssr")]
+type SecretFields<'a> = [(&'static str, &'a mut Option<String>); 3];
+
+#[cfg(feature = "ssr")]
+fn undecryptable() -> AppError {
+    AppError::GenericError("Stored key material could not be decrypted".into())
+}
+
+#[cfg(feature = "ssr")]
+fn encrypt(cipher: &XChaCha20Poly1305, msg: &[u8], aad: &[u8]) -> Result<String, AppError> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg, aad })
+        .map_err(|_| AppError::GenericError("Could not encrypt key material".into()))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(STANDARD.encode(sealed))
+}
+
+#[cfg(feature = "ssr")]
+fn decrypt(cipher: &XChaCha20Poly1305, encoded: &str, aad: &[u8]) -> Result<Vec<u8>, AppError> {
+    let sealed = STANDARD.decode(encoded).map_err(|_| undecryptable())?;
+    if sealed.len() < NONCE_LEN {
+        return Err(undecryptable());
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| undecryptable())
+}
+
+/// The configured master keys.
+#[cfg(feature = "ssr")]
+pub struct KeyVault {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+#[cfg(feature = "ssr")]
+impl KeyVault {
+    pub fn from_settings(settings: &Settings) -> Result<Self, AppError> {
+        let current = settings
+            .key_encryption_key
+            .as_deref()
+            .ok_or_else(|| AppError::EnvVarError("KEY_ENCRYPTION_KEY is not set".into()))?;
+        Self::from_keys(current, &settings.key_encryption_previous_keys)
+    }
+
+    fn from_keys(current: &str, previous: &[String]) -> Result<Self, AppError> {
+        Ok(Self {
+            current: MasterKey::parse(current)?,
+            previous: previous
+                .iter()
+                .map(|key| MasterKey::parse(key))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn master_key(&self, id: &str) -> Result<&MasterKey, AppError> {
+        std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|master| master.id == id)
+            .ok_or_else(|| AppError::GenericError(format!("Master key {} is not configured", id)))
+    }
+
+    fn wrap_data_key(&self, data_key: &[u8]) -> Result<String, AppError> {
+        let wrapped = encrypt(&self.current.cipher, data_key, DATA_KEY_AAD)?;
+        Ok(format!("{}${}", self.current.id, wrapped))
+    }
+
+    fn unwrap_data_key(&self, wrapped: &str) -> Result<Vec<u8>, AppError> {
+        let (id, wrapped) = wrapped.split_once('$').ok_or_else(undecryptable)?;
+        decrypt(&self.master_key(id)?.cipher, wrapped, DATA_KEY_AAD)
+    }
+
+    /// Encrypts every present field under a new data key and returns it wrapped, or `None` if
+    /// there is nothing to protect.
+    fn seal_fields(&self, fields: SecretFields) -> Result<Option<String>, AppError> {
+        if fields.iter().all(|(_, value)| value.is_none()) {
+            return Ok(None);
+        }
+        let data_key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let cipher = XChaCha20Poly1305::new(&data_key);
+        for (name, value) in fields {
+            if let Some(plaintext) = value.as_deref() {
+                let sealed = encrypt(&cipher, plaintext.as_bytes(), name.as_bytes())?;
+                *value = Some(format!("{}{}", SEALED_PREFIX, sealed));
+            }
+        }
+        Ok(Some(self.wrap_data_key(&data_key)?))
+    }
+
+    /// Decrypts the fields sealed under `data_key`. Without one they are still plaintext.
+    fn open_fields(&self, fields: SecretFields, data_key: Option<&str>) -> Result<(), AppError> {
+        let Some(data_key) = data_key else {
+            return Ok(());
+        };
+        let cipher = XChaCha20Poly1305::new_from_slice(&self.unwrap_data_key(data_key)?)
+            .map_err(|_| undecryptable())?;
+        for (name, value) in fields {
+            if let Some(stored) = value.as_deref() {
+                let sealed = stored
+                    .strip_prefix(SEALED_PREFIX)
+                    .ok_or_else(undecryptable)?;
+                let plaintext = decrypt(&cipher, sealed, name.as_bytes())?;
+                *value = Some(String::from_utf8(plaintext).map_err(|_| undecryptable())?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The data key wrapped by the current master key, or `None` if it already is.
+    fn rewrap(&self, wrapped: &str) -> Result<Option<String>, AppError> {
+        if wrapped.split_once('$').map(|(id, _)| id) == Some(self.current.id.as_str()) {
+            return Ok(None);
+        }
+        let data_key = self.unwrap_data_key(wrapped)?;
+        Ok(Some(self.wrap_data_key(&data_key)?))
+    }
+
+    /// Encrypts the secrets of a key about to be created.
+    pub fn seal(&self, key: &mut KeyCreate) -> Result<(), AppError> {
+        key.key_dek = self.seal_fields([
+            ("key_private", &mut key.key_private),
+            ("key_apikey", &mut key.key_apikey),
+            ("key_token", &mut key.key_token),
+        ])?;
+        key.has_secrets = key.key_dek.is_some();
+        Ok(())
+    }
+
+    /// Decrypts the secrets of a stored key in place.
+    pub fn open(&self, key: &mut Key) -> Result<(), AppError> {
+        self.open_fields(
+            [
+                ("key_private", &mut key.key_private),
+                ("key_apikey", &mut key.key_apikey),
+                ("key_token", &mut key.key_token),
+            ],
+            key.key_dek.as_deref(),
+        )
+    }
+
+    /// Brings a stored key up to the current master key. Returns whether it changed.
+    fn reencrypt(&self, key: &mut Key) -> Result<bool, AppError> {
+        match key.key_dek.as_deref() {
+            Some(wrapped) => match self.rewrap(wrapped)? {
+                Some(rewrapped) => {
+                    key.key_dek = Some(rewrapped);
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            None => {
+                key.key_dek = self.seal_fields([
+                    ("key_private", &mut key.key_private),
+                    ("key_apikey", &mut key.key_apikey),
+                    ("key_token", &mut key.key_token),
+                ])?;
+                key.has_secrets = key.key_dek.is_some();
+                Ok(key.has_secrets)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn key_vault() -> Result<KeyVault, AppError> {
+    KeyVault::from_settings(&get_settings())
+}
+
+/// The stored key with its secrets decrypted, for use on the server only.
+#[cfg(feature = "ssr")]
+pub fn open_key(mut key: Key) -> Result<Key, AppError> {
+    key_vault()?.open(&mut key)?;
+    Ok(key)
+}
+
+/// Seals keys stored before encryption and re-wraps data keys of previous master keys under
+/// the current one. Returns how many keys changed.
+#[cfg(feature = "ssr")]
+pub async fn reencrypt_keys() -> Result<usize, AppError> {
+    let vault = key_vault()?;
+    let db = db_init().await?;
+    let keys: Vec<Key> = db.select("key").await?;
+
+    let mut changed = 0;
+    for mut key in keys {
+        if !vault.reencrypt(&mut key)? {
+            continue;
+        }
+        db.query(
+            "UPDATE $id SET key_private = $key_private, key_apikey = $key_apikey, key_token = $key_token, key_dek = $key_dek, has_secrets = $has_secrets;",
+        )
+        .bind(("id", key.id))
+        .bind(("key_private", key.key_private))
+        .bind(("key_apikey", key.key_apikey))
+        .bind(("key_token", key.key_token))
+        .bind(("key_dek", key.key_dek))
+        .bind(("has_secrets", key.has_secrets))
+        .await?;
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+/// Re-encrypts stored keys after the master key changed. Returns how many keys changed.
+#[server]
+pub async fn rotate_key_encryption() -> Result<usize, ServerFnError> {
+    use crate::admin::models::{AdminAction, AuditEntry};
+
+    let admin = crate::auth::extract::require_superadmin_user().await?;
+    let changed = reencrypt_keys().await?;
+    AuditEntry::record(
+        &admin,
+        AdminAction::RotateKeyEncryption,
+        None,
+        Some(format!("{} keys re-encrypted", changed)),
+    )
+    .await?;
+    Ok(changed)
+}
+
+/// Shows the secrets of one of the user's own keys, once. Later calls are refused, so a
+/// hijacked session can't read them out again. Binding a key as the iroh identity spends the
+/// reveal too, so such keys are refused from the start.
+#[server]
+pub async fn reveal_key_secrets(id: RecordId) -> Result<KeySecrets, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let key = Key::get_by_id(id.clone()).await?;
+    // Organization members may use a key, but its secrets stay with whoever added it
+    if key.created_by_user_id != user.id {
+        return Err(
+            AppError::Forbidden("Only the owner of a key can reveal its secrets".into()).into(),
+        );
+    }
+    if !key.has_secrets {
+        return Err(AppError::ErrorReason("This key has no secrets to reveal".into()).into());
+    }
+
+    let db = db_init().await?;
+    let mut result = db
+        .query("UPDATE $id SET secrets_revealed_at = time::now() WHERE secrets_revealed_at = NONE RETURN BEFORE;")
+        .bind(("id", id))
+        .await?;
+    let Some(key) = result.take::<Option<Key>>(0)? else {
+        return Err(
+            AppError::Forbidden("The secrets of this key were already revealed".into()).into(),
+        );
+    };
+
+    let key = open_key(key)?;
+    Ok(KeySecrets {
+        key_private: key.key_private,
+        key_apikey: key.key_apikey,
+        key_token: key.key_token,
+    })
+}
+
+#[component]
+pub fn RevealedKeySecrets(secrets: KeySecrets, on_done: Callback<()>) -> impl IntoView {
+    let fields = [
+        ("Private key", secrets.key_private),
+        ("API key", secrets.key_apikey),
+        ("Token", secrets.key_token),
+    ];
+
+    view! {
+        <div class="mb-4 p-4 rounded-md bg-neutral-100 dark:bg-neutral-700">
+            <p class="text-sm font-medium mb-2">
+                "Copy the secrets of this key now. They will not be shown again."
+            </p>
+            {fields
+                .into_iter()
+                .filter_map(|(label, value)| value.map(|value| (label, value)))
+                .map(|(label, value)| view! {
+                    <p class="mt-2 text-xs text-neutral-600 dark:text-neutral-400">{label}</p>
+                    <code class="block p-2 rounded bg-white dark:bg-neutral-800 font-mono text-sm break-all select-all">
+                        {value}
+                    </code>
+                })
+                .collect_view()}
+            <button
+                type="button"
+                class="mt-4 px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700"
+                on:click=move |_| on_done.run(())
+            >
+                "I have copied the secrets"
+            </button>
+        </div>
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_key_vault_round_trip_and_rotation() {
+    let old_master = STANDARD.encode([1u8; 32]);
+    let new_master = STANDARD.encode([2u8; 32]);
+    let vault = KeyVault::from_keys(&old_master, &[]).unwrap();
+
+    let mut private = Some("private".to_string());
+    let mut apikey = None;
+    let mut token = Some("token".to_string());
+    let data_key = vault
+        .seal_fields([
+            ("key_private", &mut private),
+            ("key_apikey", &mut apikey),
+            ("key_token", &mut token),
+        ])
+        .unwrap();
+    assert!(private.as_deref().unwrap().starts_with(SEALED_PREFIX));
+    assert!(!private.as_deref().unwrap().contains("private"));
+    assert!(apikey.is_none());
+
+    // Fields are bound to their names
+    let (mut swapped_private, mut swapped_token) = (token.clone(), private.clone());
+    assert!(vault
+        .open_fields(
+            [
+                ("key_private", &mut swapped_private),
+                ("key_apikey", &mut None),
+                ("key_token", &mut swapped_token),
+            ],
+            data_key.as_deref(),
+        )
+        .is_err());
+
+    let rotated = KeyVault::from_keys(&new_master, &[old_master]).unwrap();
+    let data_key = rotated.rewrap(data_key.as_deref().unwrap()).unwrap();
+    assert!(data_key.is_some());
+    assert_eq!(rotated.rewrap(data_key.as_deref().unwrap()).unwrap(), None);
+
+    let only_new = KeyVault::from_keys(&new_master, &[]).unwrap();
+    only_new
+        .open_fields(
+            [
+                ("key_private", &mut private),
+                ("key_apikey", &mut apikey),
+                ("key_token", &mut token),
+            ],
+            data_key.as_deref(),
+        )
+        .unwrap();
+    assert_eq!(private.as_deref(), Some("private"));
+    assert_eq!(token.as_deref(), Some("token"));
+
+    assert!(KeyVault::from_keys("too short", &[]).is_err());
+}
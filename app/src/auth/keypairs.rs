@@ -0,0 +1,584 @@
+//! Asymmetric keypairs in the key manager. Ed25519 keys sign and verify and can serve as the
+//! user's iroh node identity; X25519 keys are for key agreement. Keys are base64 of their 32
+//! raw bytes, with private keys encrypted at rest by the [key vault](crate::auth::key_vault).
+//! They only leave the server through the owner's one-time reveal or as their iroh identity.
+
+use leptos::prelude::*;
+use phosphor_leptos::KEY;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::keys::Key;
+use crate::components::{
+    button::{BtnColor, BtnVariant, ButtonIcon},
+    Button, Modal, ModalSize,
+};
+use crate::RecordId;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        key_vault::open_key,
+        keys::KeyCreate,
+        permissions::{Action, Permission},
+        session::get_user,
+        user::AdapterUser,
+        StorageAuthed,
+    },
+    db_init, AppError,
+};
+
+#[cfg(feature = "ssr")]
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAlgorithm {
+    Ed25519,
+    X25519,
+}
+
+impl KeyAlgorithm {
+    pub const ALL: [KeyAlgorithm; 2] = [KeyAlgorithm::Ed25519, KeyAlgorithm::X25519];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KeyAlgorithm::Ed25519 => "Ed25519",
+            KeyAlgorithm::X25519 => "X25519",
+        }
+    }
+
+    pub fn can_sign(self) -> bool {
+        self == KeyAlgorithm::Ed25519
+    }
+}
+
+/// A freshly generated keypair, base64 encoded.
+#[cfg(feature = "ssr")]
+pub struct Keypair {
+    pub public: String,
+    pub private: String,
+}
+
+#[cfg(feature = "ssr")]
+pub fn generate_keypair(algorithm: KeyAlgorithm) -> Keypair {
+    match algorithm {
+        KeyAlgorithm::Ed25519 => {
+            let secret = iroh::SecretKey::generate(rand::rngs::OsRng);
+            Keypair {
+                public: STANDARD.encode(secret.public().as_bytes()),
+                private: STANDARD.encode(secret.to_bytes()),
+            }
+        }
+        KeyAlgorithm::X25519 => {
+            let secret = x25519_dalek::StaticSecret::random_from_rng(rand::rngs::OsRng);
+            let public = x25519_dalek::PublicKey::from(&secret);
+            Keypair {
+                public: STANDARD.encode(public.as_bytes()),
+                private: STANDARD.encode(secret.to_bytes()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn decode_key_bytes(encoded: &str) -> Option<[u8; 32]> {
+    let encoded = encoded.trim();
+    let bytes = if encoded.len() == 64 && encoded.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(encoded).ok()?
+    } else {
+        STANDARD.decode(encoded).ok()?
+    };
+    bytes.try_into().ok()
+}
+
+/// Parses a public key given as hex, base64 or, for Ed25519, an iroh node id, and returns it
+/// in the stored base64 form.
+#[cfg(feature = "ssr")]
+pub fn parse_public_key(algorithm: KeyAlgorithm, encoded: &str) -> Result<String, AppError> {
+    let invalid = || AppError::ErrorReason(format!("Not a valid {} public key", algorithm.label()));
+    match algorithm {
+        KeyAlgorithm::Ed25519 => {
+            let public = match decode_key_bytes(encoded) {
+                Some(bytes) => iroh::PublicKey::from_bytes(&bytes).map_err(|_| invalid())?,
+                None => encoded
+                    .trim()
+                    .parse::<iroh::PublicKey>()
+                    .map_err(|_| invalid())?,
+            };
+            Ok(STANDARD.encode(public.as_bytes()))
+        }
+        KeyAlgorithm::X25519 => {
+            let bytes = decode_key_bytes(encoded).ok_or_else(invalid)?;
+            Ok(STANDARD.encode(bytes))
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn ed25519_secret(key: &Key) -> Result<iroh::SecretKey, AppError> {
+    if key.algorithm != Some(KeyAlgorithm::Ed25519) {
+        return Err(AppError::ErrorReason("Only Ed25519 keys can sign".into()));
+    }
+    let bytes = key
+        .key_private
+        .as_deref()
+        .and_then(decode_key_bytes)
+        .ok_or_else(|| AppError::ErrorReason("This key has no private part".into()))?;
+    Ok(iroh::SecretKey::from_bytes(&bytes))
+}
+
+#[cfg(feature = "ssr")]
+fn ed25519_public(key: &Key) -> Result<iroh::PublicKey, AppError> {
+    if key.algorithm != Some(KeyAlgorithm::Ed25519) {
+        return Err(AppError::ErrorReason(
+            "Only Ed25519 keys can verify signatures".into(),
+        ));
+    }
+    key.key_public
+        .as_deref()
+        .and_then(decode_key_bytes)
+        .and_then(|bytes| iroh::PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| AppError::ErrorReason("This key has no valid public part".into()))
+}
+
+/// Signs `message` with an Ed25519 key and returns the base64 signature.
+#[cfg(feature = "ssr")]
+pub fn sign_message(key: &Key, message: &[u8]) -> Result<String, AppError> {
+    let signature = ed25519_secret(key)?.sign(message);
+    Ok(STANDARD.encode(signature.to_bytes()))
+}
+
+/// Whether `signature` is a valid base64 Ed25519 signature of `message` by `key`.
+#[cfg(feature = "ssr")]
+pub fn verify_message(key: &Key, message: &[u8], signature: &str) -> Result<bool, AppError> {
+    let public = ed25519_public(key)?;
+    let Some(bytes) = STANDARD
+        .decode(signature.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+    else {
+        return Ok(false);
+    };
+    let signature = iroh_base::Signature::from_bytes(&bytes);
+    Ok(public.verify(message, &signature).is_ok())
+}
+
+/// Stores a public key someone else holds, e.g. to verify their signatures.
+#[server]
+pub async fn import_public_key(
+    name: String,
+    description: String,
+    algorithm: KeyAlgorithm,
+    public_key: String,
+) -> Result<Key, ServerFnError> {
+    use crate::organization::models::current_organization_id;
+
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::ErrorReason("Key name is required".into()).into());
+    }
+    let key_public = parse_public_key(algorithm, &public_key)?;
+
+    let key = Key::create_by_user(
+        user,
+        KeyCreate {
+            name,
+            description,
+            key_for: None,
+            key_public: Some(key_public),
+            key_private: None,
+            key_apikey: None,
+            key_token: None,
+            expires_at: None,
+            organization_id: current_organization_id().await?,
+            key_prefix: None,
+            key_hash: None,
+            scopes: Vec::new(),
+            algorithm: Some(algorithm),
+            iroh_identity: false,
+            key_dek: None,
+            has_secrets: false,
+        },
+    )
+    .await?;
+    Ok(key.redacted())
+}
+
+#[server]
+pub async fn sign_with_key(id: RecordId, message: String) -> Result<String, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let key = open_key(Key::get_by_id_for(user, id).await?)?;
+    let signature = sign_message(&key, message.as_bytes())?;
+    Ok(signature)
+}
+
+#[server]
+pub async fn verify_with_key(
+    id: RecordId,
+    message: String,
+    signature: String,
+) -> Result<bool, ServerFnError> {
+    let user = get_user().await?;
+    let key = Key::get_by_id_for(user, id).await?;
+    let valid = verify_message(&key, message.as_bytes(), &signature)?;
+    Ok(valid)
+}
+
+/// The user's own Ed25519 key marked as their iroh identity, if any.
+#[cfg(feature = "ssr")]
+pub async fn iroh_identity_of(user: &AdapterUser) -> Result<Option<Key>, AppError> {
+    let db = db_init().await?;
+    let mut result = db
+        .query("SELECT * FROM key WHERE created_by_user_id = $user_id AND iroh_identity = true LIMIT 1;")
+        .bind(("user_id", user.id.clone()))
+        .await?;
+    let keys: Vec<Key> = result.take(0)?;
+    Ok(keys.into_iter().next())
+}
+
+/// Makes one of the user's Ed25519 keys their iroh node identity, or clears it for `None`.
+/// Returns the node id the key gives.
+///
+/// Binding hands the private key to the browser, so it spends the key's one-time reveal: keys
+/// whose secrets were already revealed are refused, and a bound key can't be revealed later.
+#[server]
+pub async fn bind_iroh_identity(id: Option<RecordId>) -> Result<Option<String>, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let node_id = match &id {
+        Some(id) => {
+            let key = Key::get_by_id(id.clone()).await?;
+            // Only the owner holds their identity, not the rest of an organization
+            if key.created_by_user_id != user.id {
+                return Err(
+                    AppError::Forbidden("Only your own keys can be your identity".into()).into(),
+                );
+            }
+            key.authorize(&user, Action::Update).await?;
+            if key.secrets_revealed_at.is_some() && !key.iroh_identity {
+                return Err(AppError::Forbidden(
+                    "The secrets of this key were already revealed, so it can't be your identity"
+                        .into(),
+                )
+                .into());
+            }
+            Some(ed25519_secret(&open_key(key)?)?.public().to_string())
+        }
+        None => None,
+    };
+
+    let db = db_init().await?;
+    // The reveal is spent in the same transaction, so a concurrent reveal can't also succeed
+    db.query(
+        r#"
+        BEGIN TRANSACTION;
+        IF $id != NONE {
+            LET $bound = (UPDATE $id SET iroh_identity = true, secrets_revealed_at = secrets_revealed_at ?? time::now() WHERE secrets_revealed_at = NONE OR iroh_identity = true);
+            IF array::len($bound) = 0 { THROW "The secrets of this key were already revealed" };
+        };
+        UPDATE key SET iroh_identity = false WHERE created_by_user_id = $user_id AND iroh_identity = true AND id != $id;
+        COMMIT TRANSACTION;
+        "#,
+    )
+    .bind(("user_id", user.id))
+    .bind(("id", id))
+    .await?
+    .check()?;
+    Ok(node_id)
+}
+
+/// The base64 secret key the browser's iroh node should use, if the user bound one. Unlike the
+/// one-time reveal this is always available, since the node needs it on every start.
+#[server]
+pub async fn get_iroh_identity() -> Result<Option<String>, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let Some(key) = iroh_identity_of(&user).await? else {
+        return Ok(None);
+    };
+    Ok(open_key(key)?.key_private)
+}
+
+const INPUT_CLASS: &str = "w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500";
+
+#[component]
+pub fn PublicKeyImport(#[prop(optional)] on_success: Option<Callback<Key, ()>>) -> impl IntoView {
+    let (show_modal, set_show_modal) = signal(false);
+    let (name, set_name) = signal(String::new());
+    let (description, set_description) = signal(String::new());
+    let (algorithm, set_algorithm) = signal(KeyAlgorithm::Ed25519);
+    let (public_key, set_public_key) = signal(String::new());
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+    let import = ServerAction::<ImportPublicKey>::new();
+
+    let close_modal = Callback::new(move |_| {
+        set_show_modal.set(false);
+        set_name.set(String::new());
+        set_description.set(String::new());
+        set_public_key.set(String::new());
+        set_error_message.set(None);
+    });
+
+    Effect::new(move |_| match import.value().get() {
+        Some(Ok(key)) => {
+            close_modal.run(());
+            if let Some(callback) = on_success {
+                callback.run(key);
+            }
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        import.dispatch(ImportPublicKey {
+            name: name.get(),
+            description: description.get(),
+            algorithm: algorithm.get(),
+            public_key: public_key.get(),
+        });
+    };
+
+    view! {
+        <>
+            <Button
+                on_click=Callback::new(move |_| set_show_modal.set(true))
+                variant=BtnVariant::CallToAction
+                color=BtnColor::Primary
+                icon=ButtonIcon::Icon(KEY)
+            >
+                "Import Public Key"
+            </Button>
+
+            <Modal
+                show=show_modal.into()
+                on_close=close_modal
+                title="Import Public Key".to_string()
+                size=ModalSize::Medium
+            >
+                <form on:submit=submit>
+                    <div class="space-y-4">
+                        <div>
+                            <label for="import-key-name" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                "Name" <span class="text-red-500">"*"</span>
+                            </label>
+                            <input
+                                type="text"
+                                id="import-key-name"
+                                class=INPUT_CLASS
+                                prop:value=move || name.get()
+                                on:input=move |e| set_name.set(event_target_value(&e))
+                                required
+                                disabled=move || import.pending().get()
+                            />
+                        </div>
+
+                        <div>
+                            <label for="import-key-description" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                "Description"
+                            </label>
+                            <textarea
+                                id="import-key-description"
+                                rows=2
+                                class=INPUT_CLASS
+                                prop:value=move || description.get()
+                                on:input=move |e| set_description.set(event_target_value(&e))
+                                disabled=move || import.pending().get()
+                            />
+                        </div>
+
+                        <div>
+                            <label for="import-key-algorithm" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                "Algorithm"
+                            </label>
+                            <select
+                                id="import-key-algorithm"
+                                class=INPUT_CLASS
+                                on:change=move |e| {
+                                    let value = event_target_value(&e);
+                                    if let Some(algorithm) = KeyAlgorithm::ALL.into_iter().find(|a| a.label() == value) {
+                                        set_algorithm.set(algorithm);
+                                    }
+                                }
+                            >
+                                {KeyAlgorithm::ALL
+                                    .into_iter()
+                                    .map(|a| view! {
+                                        <option value=a.label() selected=move || algorithm.get() == a>{a.label()}</option>
+                                    })
+                                    .collect_view()}
+                            </select>
+                        </div>
+
+                        <div>
+                            <label for="import-key-public" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                "Public key" <span class="text-red-500">"*"</span>
+                            </label>
+                            <textarea
+                                id="import-key-public"
+                                rows=2
+                                class=format!("{INPUT_CLASS} font-mono text-sm")
+                                placeholder="Hex, base64 or an iroh node id"
+                                prop:value=move || public_key.get()
+                                on:input=move |e| set_public_key.set(event_target_value(&e))
+                                required
+                                disabled=move || import.pending().get()
+                            />
+                        </div>
+
+                        {move || error_message.get().map(|msg| {
+                            view! { <div class="text-red-600 dark:text-red-400 text-sm">{msg}</div> }
+                        })}
+
+                        <div class="flex gap-4 pt-2">
+                            <button
+                                type="submit"
+                                class="px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 dark:focus:ring-offset-neutral-800 disabled:opacity-50 disabled:cursor-not-allowed"
+                                disabled=move || import.pending().get()
+                            >
+                                {move || if import.pending().get() { "Importing..." } else { "Import Key" }}
+                            </button>
+                            <button
+                                type="button"
+                                class="px-4 py-2 bg-neutral-200 dark:bg-neutral-700 text-neutral-700 dark:text-neutral-300 rounded-md hover:bg-neutral-300 dark:hover:bg-neutral-600"
+                                on:click=move |_| close_modal.run(())
+                            >
+                                "Cancel"
+                            </button>
+                        </div>
+                    </div>
+                </form>
+            </Modal>
+        </>
+    }
+}
+
+/// Signs a message with an Ed25519 key or checks a signature against it.
+#[component]
+pub fn SignVerifyPanel(id: RecordId) -> impl IntoView {
+    let (message, set_message) = signal(String::new());
+    let (signature, set_signature) = signal(String::new());
+    let sign = ServerAction::<SignWithKey>::new();
+    let verify = ServerAction::<VerifyWithKey>::new();
+
+    // A fresh signature replaces whatever was being verified
+    Effect::new(move |_| {
+        if let Some(Ok(signed)) = sign.value().get() {
+            set_signature.set(signed);
+        }
+    });
+
+    let error = move || {
+        sign.value()
+            .get()
+            .and_then(|r| r.err())
+            .or_else(|| verify.value().get().and_then(|r| r.err()))
+            .map(|e| e.to_string())
+    };
+
+    let sign_id = id.clone();
+    view! {
+        <div class="mt-3 space-y-2">
+            <textarea
+                rows=2
+                class=INPUT_CLASS
+                placeholder="Message"
+                prop:value=move || message.get()
+                on:input=move |e| {
+                    set_message.set(event_target_value(&e));
+                    verify.value().set(None);
+                }
+            />
+            <input
+                type="text"
+                class=format!("{INPUT_CLASS} font-mono text-sm")
+                placeholder="Signature (base64)"
+                prop:value=move || signature.get()
+                on:input=move |e| {
+                    set_signature.set(event_target_value(&e));
+                    verify.value().set(None);
+                }
+            />
+            <div class="flex items-center gap-2">
+                <Button
+                    on_click=Callback::new(move |_| {
+                        sign.dispatch(SignWithKey { id: sign_id.clone(), message: message.get() });
+                    })
+                    variant=BtnVariant::CallToAction
+                    color=BtnColor::Primary
+                >
+                    "Sign"
+                </Button>
+                <Button
+                    on_click=Callback::new(move |_| {
+                        verify.dispatch(VerifyWithKey {
+                            id: id.clone(),
+                            message: message.get(),
+                            signature: signature.get(),
+                        });
+                    })
+                    variant=BtnVariant::CallToAction
+                    color=BtnColor::Primary
+                >
+                    "Verify"
+                </Button>
+                {move || match verify.value().get() {
+                    Some(Ok(true)) => view! { <span class="text-sm text-green-600 dark:text-green-400">"Valid signature"</span> }.into_any(),
+                    Some(Ok(false)) => view! { <span class="text-sm text-red-600 dark:text-red-400">"Invalid signature"</span> }.into_any(),
+                    _ => ().into_any(),
+                }}
+            </div>
+            {move || error().map(|msg| view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> })}
+        </div>
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_keypairs_sign_and_verify() {
+    let keypair = generate_keypair(KeyAlgorithm::Ed25519);
+    let secret = iroh::SecretKey::from_bytes(&decode_key_bytes(&keypair.private).unwrap());
+    assert_eq!(STANDARD.encode(secret.public().as_bytes()), keypair.public);
+
+    let signature = STANDARD.encode(secret.sign(b"hello").to_bytes());
+    let public = iroh::PublicKey::from_bytes(&decode_key_bytes(&keypair.public).unwrap()).unwrap();
+    let bytes: [u8; 64] = STANDARD.decode(&signature).unwrap().try_into().unwrap();
+    assert!(public
+        .verify(b"hello", &iroh_base::Signature::from_bytes(&bytes))
+        .is_ok());
+    assert!(public
+        .verify(b"goodbye", &iroh_base::Signature::from_bytes(&bytes))
+        .is_err());
+
+    let x25519 = generate_keypair(KeyAlgorithm::X25519);
+    let secret = x25519_dalek::StaticSecret::from(decode_key_bytes(&x25519.private).unwrap());
+    assert_eq!(
+        STANDARD.encode(x25519_dalek::PublicKey::from(&secret).as_bytes()),
+        x25519.public
+    );
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_parse_public_key() {
+    let secret = iroh::SecretKey::generate(rand::rngs::OsRng);
+    let base64 = STANDARD.encode(secret.public().as_bytes());
+
+    for encoded in [
+        base64.clone(),
+        hex::encode(secret.public().as_bytes()),
+        secret.public().to_string(),
+    ] {
+        assert_eq!(
+            parse_public_key(KeyAlgorithm::Ed25519, &encoded).unwrap(),
+            base64
+        );
+    }
+    assert!(parse_public_key(KeyAlgorithm::Ed25519, "not a key").is_err());
+    assert!(parse_public_key(KeyAlgorithm::X25519, &STANDARD.encode([7u8; 16])).is_err());
+}
@@ -1,5 +1,14 @@
 #[cfg(feature = "ssr")]
-use crate::auth::StorageAuthed;
+use crate::admin::models::forbid_impersonation;
+#[cfg(all(feature = "ssr", test))]
+use crate::auth::permissions::Action;
+#[cfg(feature = "ssr")]
+use crate::auth::{
+    permissions::{Permission, Role},
+    StorageAuthed,
+};
+#[cfg(feature = "ssr")]
+use crate::organization::models::Membership;
 
 #[cfg(not(feature = "ssr"))]
 use crate::{Datetime, RecordId};
@@ -20,6 +29,10 @@ use surrealdb::{Datetime, RecordId};
 #[cfg(feature = "ssr")]
 use crate::auth::user::AdapterUser;
 
+use crate::auth::api_keys::{ApiKeyAdd, ApiScope, IssuedApiKeySecret, RotateApiKey};
+use crate::auth::key_vault::{KeySecrets, RevealKeySecrets, RevealedKeySecrets};
+use crate::auth::keypairs::{BindIrohIdentity, KeyAlgorithm, PublicKeyImport, SignVerifyPanel};
+use crate::date_utils::format_datetime;
 use leptos::prelude::*;
 
 #[cfg(feature = "ssr")]
@@ -29,7 +42,14 @@ use chrono::Utc;
 #[partial(
     "KeyCreate",
     derive(Debug, Serialize, Deserialize, Clone),
-    omit(id, created_by_user_id, created_at, updated_at, last_used)
+    omit(
+        id,
+        created_by_user_id,
+        created_at,
+        updated_at,
+        last_used,
+        secrets_revealed_at
+    )
 )]
 
 pub struct Key {
@@ -46,6 +66,51 @@ pub struct Key {
     pub created_by_user_id: RecordId,
     pub expires_at: Option<Datetime>,
     pub last_used: Option<Datetime>,
+    /// The organization the key is shared with. Personal keys have none.
+    #[serde(default)]
+    pub organization_id: Option<RecordId>,
+    /// The public start of an API key, which identifies it. Other keys have none.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+    /// Salted hash of the API key's secret. Never sent to the browser.
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    /// What an API key may be used for.
+    #[serde(default)]
+    pub scopes: Vec<ApiScope>,
+    /// The kind of keypair in `key_public` and `key_private`. Older keys hold unrelated random
+    /// bytes and have none.
+    #[serde(default)]
+    pub algorithm: Option<KeyAlgorithm>,
+    /// Whether this Ed25519 key is its owner's iroh node identity.
+    #[serde(default)]
+    pub iroh_identity: bool,
+    /// The data key `key_private`, `key_apikey` and `key_token` are encrypted with, wrapped by
+    /// the server's master key. Never sent to the browser.
+    #[serde(default)]
+    pub key_dek: Option<String>,
+    /// Whether the key holds encrypted secrets its owner may reveal.
+    #[serde(default)]
+    pub has_secrets: bool,
+    /// When the owner used the one-time reveal of the secrets.
+    #[serde(default)]
+    pub secrets_revealed_at: Option<Datetime>,
+}
+
+impl Key {
+    pub fn is_api_key(&self) -> bool {
+        self.key_prefix.is_some()
+    }
+
+    /// The key without its secrets, for sending to the browser.
+    pub fn redacted(mut self) -> Self {
+        self.key_hash = None;
+        self.key_private = None;
+        self.key_apikey = None;
+        self.key_token = None;
+        self.key_dek = None;
+        self
+    }
 }
 
 #[cfg(feature = "ssr")]
@@ -53,6 +118,24 @@ impl StorageAuthed<KeyCreate, Key> for Key {
     const TABLE_NAME: &'static str = "key";
 }
 
+/// Members of the key's organization may use it; only its owner, organization admins and
+/// superadmins may change or delete it.
+#[cfg(feature = "ssr")]
+impl Permission for Key {
+    fn owner_id(&self) -> Option<&RecordId> {
+        Some(&self.created_by_user_id)
+    }
+
+    async fn team_role(&self, user: &AdapterUser) -> Result<Option<Role>, AppError> {
+        match &self.organization_id {
+            Some(organization_id) => {
+                Membership::role_of(organization_id.clone(), user.id.clone()).await
+            }
+            None => Ok(None),
+        }
+    }
+}
+
 #[cfg(feature = "ssr")]
 impl Key {
     pub async fn get_user_keys_for(
@@ -81,25 +164,78 @@ impl Key {
             Ok(keys[0].clone())
         }
     }
+
+    /// Keys shared with an organization, newest first.
+    pub async fn for_organization(organization_id: RecordId) -> Result<Vec<Self>, AppError> {
+        let db = crate::db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM key WHERE organization_id = $organization_id ORDER BY created_at DESC;")
+            .bind(("organization_id", organization_id))
+            .await?;
+        let keys: Vec<Self> = result.take(0)?;
+        Ok(keys)
+    }
 }
 
+/// The keys of the organization the session works in, or the user's personal keys.
 #[server]
 pub async fn get_user_keys() -> Result<Vec<Key>, leptos::server_fn::ServerFnError> {
+    use crate::organization::models::current_organization_id;
+
     let user = crate::auth::session::get_user().await?;
-    let keys = Key::get_by_user(user).await?;
-    Ok(keys)
+    let keys = match current_organization_id().await? {
+        Some(organization_id) => Key::for_organization(organization_id).await?,
+        None => Key::get_by_user(user)
+            .await?
+            .into_iter()
+            .filter(|key| key.organization_id.is_none())
+            .collect(),
+    };
+    Ok(keys.into_iter().map(Key::redacted).collect())
 }
 
-#[component]
-pub fn KeyItem(key: Key) -> impl IntoView {
-    let _key_id = key.id.clone();
-    // let key_public_preview = if key.key_public.len() > 20 {
-    //     format!("{}...", &key.key_public[..20])
-    // } else {
-    //     key.key_public.clone()
-    // };
+/// Deletes a key. For API keys this revokes them at once.
+#[server]
+pub async fn delete_user_key(id: RecordId) -> Result<(), leptos::server_fn::ServerFnError> {
+    let user = crate::auth::session::get_user().await?;
+    forbid_impersonation().await?;
+    Key::delete(user, id).await?;
+    Ok(())
+}
 
-    let key_public_preview = "....".to_string(); // Placeholder for key public preview
+#[component]
+pub fn KeyItem(
+    key: Key,
+    #[prop(optional)] on_delete: Option<Callback<RecordId>>,
+    #[prop(optional)] on_rotate: Option<Callback<RecordId>>,
+    #[prop(optional)] on_bind_identity: Option<Callback<Option<RecordId>>>,
+    #[prop(optional)] on_reveal: Option<Callback<RecordId>>,
+) -> impl IntoView {
+    let is_api_key = key.is_api_key();
+    let can_reveal = key.has_secrets && key.secrets_revealed_at.is_none();
+    let can_sign = key.algorithm.is_some_and(KeyAlgorithm::can_sign);
+    let iroh_identity = key.iroh_identity;
+    // Binding spends the one-time reveal, so revealed keys can't become the identity
+    let can_bind = can_sign && (iroh_identity || key.secrets_revealed_at.is_none());
+    let key_public_preview = match (&key.key_prefix, &key.key_public) {
+        (Some(prefix), _) => format!("{}_…", prefix),
+        (None, Some(public)) if key.algorithm.is_some() => {
+            format!("{}…", public.chars().take(16).collect::<String>())
+        }
+        _ => "....".to_string(),
+    };
+    let (show_sign, set_show_sign) = signal(false);
+    let bind_id = key.id.clone();
+    let sign_id = key.id.clone();
+    let scopes = key
+        .scopes
+        .iter()
+        .map(|scope| scope.label())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let delete_id = key.id.clone();
+    let rotate_id = key.id.clone();
+    let reveal_id = key.id.clone();
 
     view! {
         <div class="px-4 py-3 hover:bg-neutral-50 dark:hover:bg-neutral-700">
@@ -107,38 +243,173 @@ pub fn KeyItem(key: Key) -> impl IntoView {
                 <div class="flex-1">
                     <div class="flex items-center gap-2">
                         <h3 class="text-sm font-medium text-neutral-900 dark:text-neutral-100">{key.name.clone()}</h3>
-                        {key.expires_at.is_some().then(|| {
+                        {key.algorithm.map(|algorithm| view! {
+                            <span class="px-2 py-0.5 text-xs rounded bg-neutral-100 text-neutral-700 dark:bg-neutral-700 dark:text-neutral-300">{algorithm.label()}</span>
+                        })}
+                        {iroh_identity.then(|| view! {
+                            <span class="px-2 py-0.5 text-xs rounded bg-green-100 text-green-800 dark:bg-green-900 dark:text-green-200">"iroh identity"</span>
+                        })}
+                        {key.expires_at.as_ref().map(|expires| {
                             view! {
-                                <span class="text-xs text-yellow-600 dark:text-yellow-400">"(expires)"</span>
+                                <span class="text-xs text-yellow-600 dark:text-yellow-400">"(expires "{format_datetime(expires)}")"</span>
                             }
                         })}
                     </div>
                     <p class="text-xs text-neutral-500 dark:text-neutral-400 mt-1">{key.description.clone()}</p>
                     <div class="flex items-center gap-4 mt-2 text-xs text-neutral-600 dark:text-neutral-400">
                         <span class="font-mono">{key_public_preview}</span>
+                        {is_api_key.then(|| view! { <span>"Scopes: "{scopes}</span> })}
                         <span>"Created: "{key.created_at.clone()}</span>
-                        {key.last_used.is_some().then(|| {
+                        {key.last_used.as_ref().map(|last_used| {
+                            view! {
+                                <span>"Last used: "{format_datetime(last_used)}</span>
+                            }
+                        })}
+                        {key.secrets_revealed_at.as_ref().map(|revealed| {
                             view! {
-                                <span>"Last used: recently"</span>
+                                <span>"Secrets revealed: "{format_datetime(revealed)}</span>
                             }
                         })}
                     </div>
                 </div>
                 <div class="flex items-center gap-2">
-                    <button class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 text-sm font-medium">
-                        "Delete"
+                    {can_sign.then(|| view! {
+                        <button
+                            class="text-blue-600 hover:text-blue-800 dark:text-blue-400 dark:hover:text-blue-300 text-sm font-medium"
+                            on:click=move |_| set_show_sign.update(|show| *show = !*show)
+                        >
+                            "Sign"
+                        </button>
+                    })}
+                    {(can_bind && on_bind_identity.is_some()).then(|| view! {
+                        <button
+                            class="text-blue-600 hover:text-blue-800 dark:text-blue-400 dark:hover:text-blue-300 text-sm font-medium"
+                            on:click=move |_| {
+                                if let Some(on_bind_identity) = on_bind_identity {
+                                    on_bind_identity.run((!iroh_identity).then(|| bind_id.clone()));
+                                }
+                            }
+                        >
+                            {if iroh_identity { "Unset identity" } else { "Use as iroh identity" }}
+                        </button>
+                    })}
+                    {(can_reveal && on_reveal.is_some()).then(|| view! {
+                        <button
+                            class="text-blue-600 hover:text-blue-800 dark:text-blue-400 dark:hover:text-blue-300 text-sm font-medium"
+                            title="The secrets of a key can be shown only once. Using it as your iroh identity also counts"
+                            on:click=move |_| {
+                                if let Some(on_reveal) = on_reveal {
+                                    on_reveal.run(reveal_id.clone());
+                                }
+                            }
+                        >
+                            "Reveal once"
+                        </button>
+                    })}
+                    {(is_api_key && on_rotate.is_some()).then(|| view! {
+                        <button
+                            class="text-blue-600 hover:text-blue-800 dark:text-blue-400 dark:hover:text-blue-300 text-sm font-medium"
+                            on:click=move |_| {
+                                if let Some(on_rotate) = on_rotate {
+                                    on_rotate.run(rotate_id.clone());
+                                }
+                            }
+                        >
+                            "Rotate"
+                        </button>
+                    })}
+                    <button
+                        class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 text-sm font-medium"
+                        on:click=move |_| {
+                            if let Some(on_delete) = on_delete {
+                                on_delete.run(delete_id.clone());
+                            }
+                        }
+                    >
+                        {if is_api_key { "Revoke" } else { "Delete" }}
                     </button>
                 </div>
             </div>
+            {move || show_sign.get().then(|| view! { <SignVerifyPanel id=sign_id.clone() /> })}
         </div>
     }
 }
 
 #[component]
-pub fn KeyList() -> impl IntoView {
-    let keys_resource = Resource::new(|| (), |_| get_user_keys());
+pub fn KeyList(
+    /// Bump to reload the list, e.g. after a key was created elsewhere.
+    #[prop(optional)]
+    refresh: Option<ReadSignal<u32>>,
+) -> impl IntoView {
+    let keys_resource = Resource::new(
+        move || refresh.map(|refresh| refresh.get()),
+        |_| get_user_keys(),
+    );
+    let delete = ServerAction::<DeleteUserKey>::new();
+    let rotate = ServerAction::<RotateApiKey>::new();
+    let bind_identity = ServerAction::<BindIrohIdentity>::new();
+    let reveal = ServerAction::<RevealKeySecrets>::new();
+    let (rotated, set_rotated) = signal(Option::<String>::None);
+    let (revealed, set_revealed) = signal(Option::<KeySecrets>::None);
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| {
+        if let Some(result) = delete.value().get() {
+            set_error_message.set(result.err().map(|e| e.to_string()));
+            keys_resource.refetch();
+        }
+    });
+
+    Effect::new(move |_| {
+        if let Some(result) = bind_identity.value().get() {
+            set_error_message.set(result.err().map(|e| e.to_string()));
+            keys_resource.refetch();
+        }
+    });
+
+    Effect::new(move |_| match reveal.value().get() {
+        Some(Ok(secrets)) => {
+            set_error_message.set(None);
+            set_revealed.set(Some(secrets));
+            keys_resource.refetch();
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    Effect::new(move |_| match rotate.value().get() {
+        Some(Ok(issued)) => {
+            set_error_message.set(None);
+            set_rotated.set(Some(issued.secret));
+            keys_resource.refetch();
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    let on_delete = Callback::new(move |id: RecordId| {
+        delete.dispatch(DeleteUserKey { id });
+    });
+    let on_rotate = Callback::new(move |id: RecordId| {
+        rotate.dispatch(RotateApiKey { id });
+    });
+    let on_bind_identity = Callback::new(move |id: Option<RecordId>| {
+        bind_identity.dispatch(BindIrohIdentity { id });
+    });
+    let on_reveal = Callback::new(move |id: RecordId| {
+        reveal.dispatch(RevealKeySecrets { id });
+    });
 
     view! {
+        {move || rotated.get().map(|secret| view! {
+            <IssuedApiKeySecret secret=secret on_done=Callback::new(move |_| set_rotated.set(None)) />
+        })}
+        {move || revealed.get().map(|secrets| view! {
+            <RevealedKeySecrets secrets=secrets on_done=Callback::new(move |_| set_revealed.set(None)) />
+        })}
+        {move || error_message.get().map(|msg| {
+            view! { <p class="mb-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+        })}
         <Suspense fallback=move || view! {
             <div class="bg-white dark:bg-neutral-800 rounded-lg shadow">
                 <div class="p-6">
@@ -169,7 +440,7 @@ pub fn KeyList() -> impl IntoView {
                                             .into_iter()
                                             .map(|key| {
                                                 view! {
-                                                    <KeyItem key=key />
+                                                    <KeyItem key=key on_delete=on_delete on_rotate=on_rotate on_bind_identity=on_bind_identity on_reveal=on_reveal />
                                                 }
                                             })
                                             .collect_view()}
@@ -208,11 +479,24 @@ pub fn KeyList() -> impl IntoView {
 
 #[component]
 pub fn KeysControl() -> impl IntoView {
+    let (refresh, set_refresh) = signal(0u32);
+
     view! {
         <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
-            <h2 class="text-xl font-semibold mb-4">"Manage Keys"</h2>
-            <p class="text-neutral-600 dark:text-neutral-400 mb-6">"Here you can manage your API keys."</p>
-            <KeyList />
+            <div class="flex items-center justify-between mb-4">
+                <h2 class="text-xl font-semibold">"Manage Keys"</h2>
+                <div class="flex items-center gap-2">
+                    <KeysAdd require_public=true on_success=Callback::new(move |_| set_refresh.update(|n| *n += 1)) />
+                    <PublicKeyImport on_success=Callback::new(move |_| set_refresh.update(|n| *n += 1)) />
+                    <ApiKeyAdd on_success=Callback::new(move |_| set_refresh.update(|n| *n += 1)) />
+                </div>
+            </div>
+            <p class="text-neutral-600 dark:text-neutral-400 mb-6">
+                "Here you can manage your keypairs and API keys. Send an API key as "
+                <code class="font-mono">"Authorization: Bearer <key>"</code>
+                " to act as yourself from scripts and other programs."
+            </p>
+            <KeyList refresh=refresh />
         </div>
     }
 }
@@ -222,36 +506,34 @@ pub async fn create_user_key(
     key_create: KeyCreate,
     key_for: Option<RecordId>,
 ) -> Result<Key, leptos::server_fn::ServerFnError> {
-    use base64::{engine::general_purpose, Engine as _};
-    use rand::Rng;
+    use crate::auth::keypairs::generate_keypair;
 
     let user = crate::auth::session::get_user().await?;
+    forbid_impersonation().await?;
 
-    // Generate random bytes for keys - do this before any await
-    let (key_private, key_public) = {
-        // let mut rng = rand::rng(); //0.9.2
-        let mut rng = rand::thread_rng();
-
-        // let private_bytes: [u8; 32] = rng.random();
-        // let public_bytes: [u8; 32] = rng.random();
-
-        let private_bytes: [u8; 32] = rng.gen();
-        let public_bytes: [u8; 32] = rng.gen();
-
-        // Encode to base64
-        let key_private = general_purpose::STANDARD.encode(&private_bytes);
-        let key_public = general_purpose::STANDARD.encode(&public_bytes);
-        (key_private, key_public)
-    };
-
-    // Create the key with generated values
+    // Create the key with a generated keypair
     let mut key_data = key_create;
-    key_data.key_public = Some(key_public);
-    key_data.key_private = Some(key_private);
+    let algorithm = key_data.algorithm.unwrap_or(KeyAlgorithm::Ed25519);
+    let keypair = generate_keypair(algorithm);
+    key_data.algorithm = Some(algorithm);
+    key_data.key_public = Some(keypair.public);
+    key_data.key_private = Some(keypair.private);
     key_data.key_for = key_for;
+    // Becoming an iroh identity goes through `bind_iroh_identity`
+    key_data.iroh_identity = false;
+    key_data.organization_id = crate::organization::models::current_organization_id().await?;
+
+    // API keys are only issued by `create_api_key`, which keeps their secret to itself
+    key_data.key_prefix = None;
+    key_data.key_hash = None;
+    key_data.scopes = Vec::new();
+
+    // The browser never gets to choose how its secrets are stored
+    key_data.key_dek = None;
+    crate::auth::key_vault::key_vault()?.seal(&mut key_data)?;
 
     let created_key = Key::create_by_user(user, key_data).await?;
-    Ok(created_key)
+    Ok(created_key.redacted())
 }
 
 #[component]
@@ -269,6 +551,7 @@ pub fn KeysAdd(
     let (expires_in_days, set_expires_in_days) = signal(String::new());
     let (apikey, set_apikey) = signal(String::new());
     let (token, set_token) = signal(String::new());
+    let (algorithm, set_algorithm) = signal(KeyAlgorithm::Ed25519);
     let (is_creating, set_is_creating) = signal(false);
     let (error_message, set_error_message) = signal(Option::<String>::None);
 
@@ -278,6 +561,7 @@ pub fn KeysAdd(
         let expires_in_days = expires_in_days.get();
         let apikey = apikey.get();
         let token = token.get();
+        let algorithm = algorithm.get();
         let key_for = key_for.clone();
 
         async move {
@@ -317,6 +601,14 @@ pub fn KeysAdd(
                 },
                 key_token: if token.is_empty() { None } else { Some(token) },
                 expires_at,
+                organization_id: None,
+                key_prefix: None,
+                key_hash: None,
+                scopes: Vec::new(),
+                algorithm: Some(algorithm),
+                iroh_identity: false,
+                key_dek: None,
+                has_secrets: false,
             };
 
             create_user_key(key_create, key_for)
@@ -467,9 +759,30 @@ pub fn KeysAdd(
 
                         {move || if show_public_private {
                             view! {
-                                <div class="p-3 bg-neutral-100 dark:bg-neutral-700 rounded-md">
-                                    <p class="text-sm text-neutral-600 dark:text-neutral-400">
-                                        "Public and private keys will be automatically generated when you create this key."
+                                <div>
+                                    <label for="key-algorithm" class="block text-sm font-medium text-neutral-700 dark:text-neutral-300 mb-1">
+                                        "Algorithm"
+                                    </label>
+                                    <select
+                                        id="key-algorithm"
+                                        class="w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+                                        on:change=move |e| {
+                                            let value = event_target_value(&e);
+                                            if let Some(selected) = KeyAlgorithm::ALL.into_iter().find(|a| a.label() == value) {
+                                                set_algorithm.set(selected);
+                                            }
+                                        }
+                                        disabled=move || is_creating.get()
+                                    >
+                                        {KeyAlgorithm::ALL
+                                            .into_iter()
+                                            .map(|a| view! {
+                                                <option value=a.label() selected=move || algorithm.get() == a>{a.label()}</option>
+                                            })
+                                            .collect_view()}
+                                    </select>
+                                    <p class="mt-1 text-sm text-neutral-600 dark:text-neutral-400">
+                                        "A new keypair is generated when you create this key. Ed25519 keys sign messages and can be your iroh identity; X25519 keys are for encryption."
                                     </p>
                                 </div>
                             }.into_any()
@@ -524,3 +837,140 @@ pub fn KeysAdd(
         </>
     }
 }
+
+#[cfg(all(feature = "ssr", test))]
+pub(crate) fn test_key(owner: &AdapterUser) -> Key {
+    Key {
+        id: RecordId::from(("key", "signing")),
+        name: "Signing key".into(),
+        key_for: None,
+        key_public: None,
+        key_private: Some("secret".into()),
+        key_apikey: None,
+        key_token: None,
+        description: String::new(),
+        created_at: String::new(),
+        updated_at: String::new(),
+        created_by_user_id: owner.id.clone(),
+        expires_at: None,
+        last_used: None,
+        organization_id: None,
+        key_prefix: None,
+        key_hash: None,
+        scopes: Vec::new(),
+        algorithm: None,
+        iroh_identity: false,
+        key_dek: None,
+        has_secrets: false,
+        secrets_revealed_at: None,
+    }
+}
+
+#[cfg(all(feature = "ssr", test))]
+pub(crate) fn test_user(id: &str, superadmin: Option<bool>) -> AdapterUser {
+    AdapterUser {
+        id: RecordId::from(("user", id)),
+        name: id.into(),
+        image: None,
+        superadmin,
+        theme: crate::theme::Theme::System,
+        disabled: false,
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+async fn test_key_permissions() {
+    let ada = test_user("ada", None);
+    let grace = test_user("grace", Some(false));
+    let root = test_user("root", Some(true));
+    let key = test_key(&ada);
+
+    for action in [Action::Read, Action::Update, Action::Delete] {
+        assert!(key.authorize(&ada, action).await.is_ok());
+        assert!(key.authorize(&root, action).await.is_ok());
+        assert!(matches!(
+            key.authorize(&grace, action).await,
+            Err(AppError::Forbidden(_))
+        ));
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_key_update_and_delete_by_other_user() -> Result<(), AppError> {
+    let ada = AdapterUser::create_test_user().await?;
+    let grace = AdapterUser::create_test_user().await?;
+
+    let key = Key::create_by_user(
+        ada.clone(),
+        KeyCreate {
+            name: "Signing key".into(),
+            key_for: None,
+            key_public: None,
+            key_private: Some("secret".into()),
+            key_apikey: None,
+            key_token: None,
+            description: String::new(),
+            expires_at: None,
+            organization_id: None,
+            key_prefix: None,
+            key_hash: None,
+            scopes: Vec::new(),
+            algorithm: None,
+            iroh_identity: false,
+            key_dek: None,
+            has_secrets: false,
+        },
+    )
+    .await?;
+
+    let mut stolen = key.clone();
+    stolen.name = "Stolen".into();
+    stolen.created_by_user_id = grace.id.clone();
+    assert!(matches!(
+        Key::update(grace.clone(), key.id.clone(), stolen.clone()).await,
+        Err(AppError::Forbidden(_))
+    ));
+    assert!(matches!(
+        Key::delete(grace.clone(), key.id.clone()).await,
+        Err(AppError::Forbidden(_))
+    ));
+    assert_eq!(Key::get_by_id(key.id.clone()).await?.name, "Signing key");
+
+    // The owner may edit, but cannot hand the key to someone else
+    let updated = Key::update(ada.clone(), key.id.clone(), stolen).await?;
+    assert_eq!(updated.name, "Stolen");
+    assert_eq!(updated.created_by_user_id, ada.id);
+    assert!(Key::delete(ada.clone(), key.id.clone()).await?);
+
+    ada.delete_user().await?;
+    grace.delete_user().await?;
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_organization_key_is_shared_with_members() -> Result<(), AppError> {
+    use crate::organization::models::Organization;
+
+    let ada = AdapterUser::create_test_user().await?;
+    let grace = AdapterUser::create_test_user().await?;
+    let organization = Organization::create(&ada, "Analytical Engines".into()).await?;
+    Membership::add(organization.id.clone(), grace.id.clone(), Role::Member).await?;
+
+    let mut key = test_key(&ada);
+    key.organization_id = Some(organization.id.clone());
+
+    assert!(key.authorize(&grace, Action::Read).await.is_ok());
+    assert!(matches!(
+        key.authorize(&grace, Action::Delete).await,
+        Err(AppError::Forbidden(_))
+    ));
+
+    ada.delete_user().await?;
+    grace.delete_user().await?;
+    Ok(())
+}
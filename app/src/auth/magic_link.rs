@@ -0,0 +1,201 @@
+use leptos::prelude::*;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{
+        account::AdapterAccount,
+        session::AdapterSession,
+        token::{CreateVerificationToken, VerificationToken},
+        user::{AdapterUser, CreateUserData},
+    },
+    db::settings::get_env,
+    mail::{mailer, Email, MailTransport},
+    theme::Theme,
+    AppError,
+};
+
+#[cfg(feature = "ssr")]
+use axum::{
+    extract::Query,
+    http::header::SET_COOKIE,
+    response::{IntoResponse, Redirect, Response},
+};
+
+/// How long a sign-in link stays valid.
+pub const MAGIC_LINK_TTL_MINUTES: i64 = 15;
+const MAX_EMAIL_LEN: usize = 254;
+
+/// Trims and lowercases an email address, or returns `None` if it does not look like one.
+pub fn normalize_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    if email.len() > MAX_EMAIL_LEN || email.chars().any(char::is_whitespace) {
+        return None;
+    }
+
+    let (local, domain) = email.split_once('@')?;
+    let valid = !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.');
+    valid.then_some(email)
+}
+
+/// The link mailed to the user, pointing at `/auth/verify` on `base_url`.
+pub fn magic_link_url(base_url: &str, email: &str, token: &str) -> String {
+    format!(
+        "{}/auth/verify?email={}&token={}",
+        base_url.trim_end_matches('/'),
+        urlencoding::encode(email),
+        urlencoding::encode(token)
+    )
+}
+
+#[cfg(feature = "ssr")]
+fn magic_link_email(to: &str, url: &str) -> Email {
+    Email {
+        to: to.to_string(),
+        subject: "Your sign-in link".to_string(),
+        text: format!(
+            "Use this link to sign in. It expires in {} minutes and works once.\n\n{}\n\nIf you did not ask for it, you can ignore this email.",
+            MAGIC_LINK_TTL_MINUTES, url
+        ),
+        html: Some(format!(
+            r#"<p>Use this link to sign in. It expires in {} minutes and works once.</p><p><a href="{}">Sign in</a></p><p>If you did not ask for it, you can ignore this email.</p>"#,
+            MAGIC_LINK_TTL_MINUTES, url
+        )),
+    }
+}
+
+/// Creates a verification token for `email` and mails the sign-in link through `transport`.
+#[cfg(feature = "ssr")]
+pub async fn send_magic_link(
+    email: &str,
+    base_url: &str,
+    transport: &impl MailTransport,
+) -> Result<(), AppError> {
+    let email = normalize_email(email)
+        .ok_or_else(|| AppError::ErrorReason("Enter a valid email address".into()))?;
+
+    let token = VerificationToken::create_verification_token(CreateVerificationToken {
+        identifier: email.clone(),
+        expires: chrono::Utc::now() + chrono::Duration::minutes(MAGIC_LINK_TTL_MINUTES),
+    })
+    .await?;
+
+    let url = magic_link_url(base_url, &email, &token.token);
+    transport.send(&magic_link_email(&email, &url)).await
+}
+
+/// Consumes a sign-in link and starts a session, creating the user on first sign-in unless
+/// they already signed up with a password.
+#[cfg(feature = "ssr")]
+pub async fn verify_magic_link(email: &str, token: String) -> Result<AdapterSession, AppError> {
+    let email =
+        normalize_email(email).ok_or_else(|| AppError::AuthError("Invalid sign-in link".into()))?;
+    VerificationToken::use_verification_token(email.clone(), token).await?;
+
+    let user = match AdapterAccount::find("email", &email).await? {
+        Some(account) => AdapterUser::get_user(account.user_id).await?,
+        // Someone who signed up with a password gets the link added to that account
+        None => match AdapterAccount::find("credentials", &email).await? {
+            Some(account) => {
+                AdapterAccount::link_email(account.user_id.clone(), &email).await?;
+                AdapterUser::get_user(account.user_id).await?
+            }
+            None => {
+                let name = email.split('@').next().unwrap_or_default().to_string();
+                let user = AdapterUser::create_user(CreateUserData {
+                    name,
+                    image: None,
+                    theme: Theme::default(),
+                })
+                .await?;
+                AdapterAccount::link_email(user.id.clone(), &email).await?;
+                user
+            }
+        },
+    };
+
+    let user = user.set_verified_email().await?;
+    user.new_session().await
+}
+
+/// Mails a sign-in link. Succeeds whether or not an account exists for the address, so the
+/// form cannot be used to find out who has signed up.
+#[server]
+pub async fn request_magic_link(email: String) -> Result<(), ServerFnError> {
+    let base_url = get_env("AUTH_URL").map_err(|e| ServerFnError::new(e.to_string()))?;
+    send_magic_link(&email, &base_url, mailer()).await?;
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, serde::Deserialize)]
+struct VerifyParams {
+    email: String,
+    token: String,
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn verify_handler(Query(params): Query<VerifyParams>) -> Response {
+    match verify_magic_link(&params.email, params.token).await {
+        Ok(session) => {
+            let cookie = session.build_session_cookie().to_string();
+            ([(SET_COOKIE, cookie)], Redirect::to(session.landing_path())).into_response()
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "Sign-in link rejected");
+            Redirect::to("/login?error=invalid_link").into_response()
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_normalize_email() {
+    assert_eq!(
+        normalize_email("  Ada@Example.COM "),
+        Some("ada@example.com".to_string())
+    );
+    assert_eq!(normalize_email("ada@example"), None);
+    assert_eq!(normalize_email("@example.com"), None);
+    assert_eq!(normalize_email("ada@@example.com"), None);
+    assert_eq!(normalize_email("ada lovelace@example.com"), None);
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_magic_link_url_encodes_params() {
+    assert_eq!(
+        magic_link_url("http://localhost:3000/", "ada+test@example.com", "a b"),
+        "http://localhost:3000/auth/verify?email=ada%2Btest%40example.com&token=a%20b"
+    );
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_magic_link_signs_in_password_users() {
+    use crate::auth::credentials::register_with_password;
+
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    let registered = register_with_password("Ada", &email, "correct horse 42".into())
+        .await
+        .unwrap();
+
+    let token = VerificationToken::create_verification_token(CreateVerificationToken {
+        identifier: email.clone(),
+        expires: chrono::Utc::now() + chrono::Duration::minutes(MAGIC_LINK_TTL_MINUTES),
+    })
+    .await
+    .unwrap();
+    let session = verify_magic_link(&email, token.token).await.unwrap();
+
+    assert_eq!(session.user_id, registered.user_id);
+    let linked = AdapterAccount::find("email", &email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(linked.user_id, registered.user_id);
+}
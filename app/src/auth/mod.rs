@@ -19,5 +19,39 @@ pub mod storage_authed_trait;
 
 #[cfg(feature = "ssr")]
 pub use storage_authed_trait::StorageAuthed;
+pub mod account_data;
+pub mod api_keys;
+#[cfg(feature = "ssr")]
+pub mod cookies;
+pub mod credentials;
+#[cfg(feature = "ssr")]
+pub mod csrf;
+pub mod devices;
+#[cfg(feature = "ssr")]
+pub mod extract;
+pub mod key_vault;
+pub mod keypairs;
 pub mod keys;
+pub mod magic_link;
 pub mod navbar;
+pub mod oidc;
+pub mod passkeys;
+pub mod permissions;
+#[cfg(feature = "ssr")]
+pub mod rate_limit;
+pub mod totp;
+pub mod ui_totp;
+
+/// Routes for links that are opened from outside the app, nested under `/auth`.
+#[cfg(feature = "ssr")]
+pub fn auth_routes<S>() -> axum::Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    use axum::routing::get;
+
+    axum::Router::new()
+        .route("/verify", get(magic_link::verify_handler))
+        .route("/oidc/{provider}/login", get(oidc::login_handler))
+        .route("/oidc/{provider}/callback", get(oidc::callback_handler))
+}
@@ -1,5 +1,5 @@
-// use crate::organization::organization_selector::OrganizationSelector;
 use crate::auth::session::get_user;
+use crate::organization::organization_selector::OrganizationSelector;
 
 use crate::{
     components::{
@@ -18,7 +18,7 @@ pub fn Navbar() -> impl IntoView {
             <div class="px-4">
                 <div class="flex justify-between items-center h-16">
                     <div class="flex items-center space-x-4">
-                        // <OrganizationSelector />
+                        <OrganizationSelector />
                     </div>
 
                     <div class="flex items-center space-x-4">
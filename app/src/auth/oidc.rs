@@ -0,0 +1,627 @@
+//! Sign-in through OpenID Connect providers using the authorization code flow with PKCE.
+//!
+//! Providers are listed in `OIDC_PROVIDERS` (comma separated ids) and each one is configured
+//! with `OIDC_<ID>_ISSUER`, `OIDC_<ID>_CLIENT_ID`, and optionally `OIDC_<ID>_CLIENT_SECRET`,
+//! `OIDC_<ID>_NAME` and `OIDC_<ID>_SCOPES`. Endpoints are read from the issuer's discovery
+//! document, and the callback is `{AUTH_URL}/auth/oidc/{id}/callback`.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{
+        account::AdapterAccount,
+        session::AdapterSession,
+        user::{AdapterUser, CreateUserData},
+    },
+    db::settings::get_env,
+    db_init,
+    theme::Theme,
+    AppError, RecordId,
+};
+
+#[cfg(feature = "ssr")]
+use axum::{
+    extract::{Path, Query},
+    http::header::SET_COOKIE,
+    response::{IntoResponse, Redirect, Response},
+};
+
+#[cfg(feature = "ssr")]
+use crate::admin::models::impersonator_in;
+#[cfg(feature = "ssr")]
+use crate::auth::{
+    cookies::{expired_oidc_state_cookie, oidc_state_cookie, oidc_state_from},
+    extract::OptionalAuthUser,
+};
+
+#[cfg(feature = "ssr")]
+use axum_extra::extract::CookieJar;
+
+#[cfg(feature = "ssr")]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+
+/// How long the user has to finish signing in at the provider.
+pub const OIDC_STATE_TTL_MINUTES: i64 = 10;
+/// Access tokens this close to expiry are refreshed before use.
+pub const OIDC_REFRESH_MARGIN_SECONDS: i64 = 60;
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// A configured provider, as shown on the login form.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OidcProviderInfo {
+    pub id: String,
+    pub name: String,
+}
+
+impl OidcProviderInfo {
+    pub fn login_url(&self) -> String {
+        format!("/auth/oidc/{}/login", urlencoding::encode(&self.id))
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub id: String,
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scopes: String,
+    pub redirect_url: String,
+}
+
+#[cfg(feature = "ssr")]
+impl OidcProviderConfig {
+    pub fn from_env(id: &str, base_url: &str) -> Result<Self, AppError> {
+        let key = |name: &str| {
+            format!(
+                "OIDC_{}_{}",
+                id.to_uppercase()
+                    .replace(|c: char| !c.is_alphanumeric(), "_"),
+                name
+            )
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            name: get_env(&key("NAME")).unwrap_or_else(|_| id.to_string()),
+            issuer: get_env(&key("ISSUER"))?,
+            client_id: get_env(&key("CLIENT_ID"))?,
+            client_secret: get_env(&key("CLIENT_SECRET"))
+                .ok()
+                .filter(|s| !s.is_empty()),
+            scopes: get_env(&key("SCOPES")).unwrap_or_else(|_| DEFAULT_SCOPES.to_string()),
+            redirect_url: format!(
+                "{}/auth/oidc/{}/callback",
+                base_url.trim_end_matches('/'),
+                urlencoding::encode(id)
+            ),
+        })
+    }
+
+    /// Every provider in `OIDC_PROVIDERS`. Misconfigured providers are logged and left out.
+    pub fn all_from_env() -> Vec<Self> {
+        let (Ok(ids), Ok(base_url)) = (get_env("OIDC_PROVIDERS"), get_env("AUTH_URL")) else {
+            return Vec::new();
+        };
+
+        ids.split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| match Self::from_env(id, &base_url) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    tracing::error!(provider = id, error = %e, "OIDC provider is misconfigured");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn find(id: &str) -> Result<Self, AppError> {
+        Self::all_from_env()
+            .into_iter()
+            .find(|config| config.id == id)
+            .ok_or_else(|| AppError::NotFound(format!("Unknown sign-in provider {}", id)))
+    }
+}
+
+/// The parts of the provider's discovery document this flow uses.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcTokens {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: Option<i64>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub id_token: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcUserInfo {
+    pub sub: String,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+    pub picture: Option<String>,
+}
+
+/// Talks to one provider's endpoints.
+#[cfg(feature = "ssr")]
+pub struct OidcClient {
+    pub config: OidcProviderConfig,
+    pub metadata: OidcMetadata,
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "ssr")]
+impl OidcClient {
+    /// Fetches the discovery document and checks it belongs to the configured issuer.
+    pub async fn discover(config: OidcProviderConfig) -> Result<Self, AppError> {
+        let http = reqwest::Client::new();
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            config.issuer.trim_end_matches('/')
+        );
+
+        let metadata: OidcMetadata = http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AppError::Config(format!("Could not discover {}: {}", config.id, e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::Config(format!("Invalid discovery document: {}", e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != config.issuer.trim_end_matches('/') {
+            return Err(AppError::Config(format!(
+                "Discovery for {} returned issuer {}",
+                config.id, metadata.issuer
+            )));
+        }
+
+        Ok(Self {
+            config,
+            metadata,
+            http,
+        })
+    }
+
+    /// Where to send the browser to sign in.
+    pub fn authorize_url(&self, state: &str, code_challenge: &str) -> String {
+        let separator = if self.metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&code_challenge={}&code_challenge_method=S256",
+            self.metadata.authorization_endpoint,
+            separator,
+            urlencoding::encode(&self.config.client_id),
+            urlencoding::encode(&self.config.redirect_url),
+            urlencoding::encode(&self.config.scopes),
+            urlencoding::encode(state),
+            urlencoding::encode(code_challenge),
+        )
+    }
+
+    async fn token_request(&self, params: &[(&str, &str)]) -> Result<OidcTokens, AppError> {
+        let mut form = params.to_vec();
+        form.push(("client_id", &self.config.client_id));
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let response = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::AuthError(format!(
+                "Token request to {} failed with {}: {}",
+                self.config.id, status, body
+            )));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Trades the code from the callback for tokens, proving the login started here.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<OidcTokens, AppError> {
+        self.token_request(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("code_verifier", code_verifier),
+        ])
+        .await
+    }
+
+    pub async fn refresh(&self, refresh_token: &str) -> Result<OidcTokens, AppError> {
+        self.token_request(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await
+    }
+
+    /// Reads the signed-in user's claims. They come straight from the provider over TLS, so
+    /// the ID token's signature does not need checking here.
+    pub async fn userinfo(&self, access_token: &str) -> Result<OidcUserInfo, AppError> {
+        let endpoint = self.metadata.userinfo_endpoint.as_ref().ok_or_else(|| {
+            AppError::Config(format!("{} has no userinfo endpoint", self.config.id))
+        })?;
+
+        let response = self
+            .http
+            .get(endpoint)
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AppError::AuthError(format!(
+                "Userinfo request to {} failed with {}",
+                self.config.id,
+                response.status()
+            )));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// A random PKCE code verifier (43 URL-safe characters).
+#[cfg(feature = "ssr")]
+pub fn pkce_verifier() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// The S256 challenge sent with the authorization request for `verifier`.
+#[cfg(feature = "ssr")]
+pub fn pkce_challenge(verifier: &str) -> String {
+    use sha2::{Digest, Sha256};
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Unix time when tokens issued now stop working, or 0 if the provider did not say.
+#[cfg(feature = "ssr")]
+fn expires_at(tokens: &OidcTokens) -> i64 {
+    tokens
+        .expires_in
+        .map(|secs| chrono::Utc::now().timestamp() + secs)
+        .unwrap_or(0)
+}
+
+/// A login in progress, keyed by the `state` sent to the provider.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OidcLoginState {
+    provider: String,
+    code_verifier: String,
+    /// Set when a signed in user is linking another provider to their account.
+    link_user_id: Option<RecordId>,
+    expires: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(feature = "ssr")]
+impl OidcLoginState {
+    async fn create(
+        provider: &str,
+        code_verifier: String,
+        link_user_id: Option<RecordId>,
+    ) -> Result<String, AppError> {
+        let client = db_init().await?;
+        let state = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+
+        let _: Option<Self> = client
+            .create(("oidc_state", state.as_str()))
+            .content(Self {
+                provider: provider.to_string(),
+                code_verifier,
+                link_user_id,
+                expires: chrono::Utc::now() + chrono::Duration::minutes(OIDC_STATE_TTL_MINUTES),
+            })
+            .await?;
+        Ok(state)
+    }
+
+    /// Removes the state so a callback can only be used once.
+    async fn take(state: &str, provider: &str) -> Result<Self, AppError> {
+        let client = db_init().await?;
+        let login: Option<Self> = client.delete(("oidc_state", state)).await?;
+        let login = login.ok_or_else(|| AppError::AuthError("Unknown sign-in attempt".into()))?;
+
+        if login.provider != provider || login.expires < chrono::Utc::now() {
+            return Err(AppError::AuthError("Sign-in attempt expired".into()));
+        }
+        Ok(login)
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl AdapterAccount {
+    /// Adds a provider login to a user.
+    pub async fn link_oidc(
+        user_id: RecordId,
+        provider: &str,
+        subject: &str,
+        tokens: &OidcTokens,
+    ) -> Result<Self, AppError> {
+        use crate::auth::account::{AccountType, LinkAccountData};
+
+        let client = db_init().await?;
+        let data = LinkAccountData {
+            access_token: tokens.access_token.clone(),
+            account_type: AccountType::OAuth,
+            expires_at: expires_at(tokens),
+            provider_account_id: subject.to_string(),
+            provider: provider.to_string(),
+            refresh_token: tokens.refresh_token.clone(),
+            scope: tokens.scope.clone().unwrap_or_default(),
+            token_type: tokens.token_type.clone(),
+            user_id,
+            password_hash: None,
+        };
+
+        let account: Option<Self> = client.create("account").content(data).await?;
+        account.ok_or_else(|| AppError::AuthError("Could not link account".into()))
+    }
+
+    /// Stores newly issued tokens. Providers that do not rotate refresh tokens leave the
+    /// current one in place.
+    pub async fn update_tokens(&self, tokens: &OidcTokens) -> Result<Self, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query(
+                "UPDATE $account SET access_token = $access_token, expires_at = $expires_at, token_type = $token_type, refresh_token = $refresh_token, scope = $scope RETURN AFTER;",
+            )
+            .bind(("account", self.id.clone()))
+            .bind(("access_token", tokens.access_token.clone()))
+            .bind(("expires_at", expires_at(tokens)))
+            .bind(("token_type", tokens.token_type.clone()))
+            .bind((
+                "refresh_token",
+                tokens
+                    .refresh_token
+                    .clone()
+                    .or_else(|| self.refresh_token.clone()),
+            ))
+            .bind((
+                "scope",
+                tokens.scope.clone().unwrap_or_else(|| self.scope.clone()),
+            ))
+            .await?;
+
+        let account: Option<Self> = result.take(0)?;
+        account.ok_or_else(|| AppError::NotFound("Account not found".into()))
+    }
+
+    pub fn token_expires_soon(&self) -> bool {
+        self.expires_at != 0
+            && self.expires_at - OIDC_REFRESH_MARGIN_SECONDS < chrono::Utc::now().timestamp()
+    }
+}
+
+/// Returns a usable access token for a provider account, refreshing it first if it is about to
+/// expire.
+#[cfg(feature = "ssr")]
+pub async fn fresh_access_token(account: AdapterAccount) -> Result<String, AppError> {
+    if !account.token_expires_soon() {
+        return Ok(account.access_token);
+    }
+    let refresh_token = account.refresh_token.clone().ok_or_else(|| {
+        AppError::AuthError(format!("{} token expired, sign in again", account.provider))
+    })?;
+
+    let client = OidcClient::discover(OidcProviderConfig::find(&account.provider)?).await?;
+    let tokens = client.refresh(&refresh_token).await?;
+    Ok(account.update_tokens(&tokens).await?.access_token)
+}
+
+/// Finds the user behind a provider login, links it to `link_user_id` if given, or creates a
+/// new user. Accounts are never matched by email, since providers differ in how carefully they
+/// verify addresses.
+#[cfg(feature = "ssr")]
+pub async fn sign_in_with_oidc(
+    provider: &str,
+    info: &OidcUserInfo,
+    tokens: &OidcTokens,
+    link_user_id: Option<RecordId>,
+) -> Result<AdapterSession, AppError> {
+    let user = match AdapterAccount::find(provider, &info.sub).await? {
+        Some(account) => {
+            if link_user_id
+                .as_ref()
+                .is_some_and(|id| *id != account.user_id)
+            {
+                return Err(AppError::AuthError(
+                    "This login is already linked to another user".into(),
+                ));
+            }
+            account.update_tokens(tokens).await?;
+            AdapterUser::get_user(account.user_id).await?
+        }
+        None => {
+            let user = match link_user_id {
+                Some(user_id) => AdapterUser::get_user(user_id).await?,
+                None => {
+                    let name = info
+                        .name
+                        .clone()
+                        .or_else(|| info.preferred_username.clone())
+                        .or_else(|| {
+                            info.email
+                                .as_ref()
+                                .and_then(|e| e.split('@').next())
+                                .map(str::to_string)
+                        })
+                        .unwrap_or_else(|| provider.to_string());
+                    AdapterUser::create_user(CreateUserData {
+                        name,
+                        image: info.picture.clone(),
+                        theme: Theme::default(),
+                    })
+                    .await?
+                }
+            };
+            AdapterAccount::link_oidc(user.id.clone(), provider, &info.sub, tokens).await?;
+            user
+        }
+    };
+
+    user.new_session().await
+}
+
+#[server]
+pub async fn get_oidc_providers() -> Result<Vec<OidcProviderInfo>, ServerFnError> {
+    Ok(OidcProviderConfig::all_from_env()
+        .into_iter()
+        .map(|config| OidcProviderInfo {
+            id: config.id,
+            name: config.name,
+        })
+        .collect())
+}
+
+/// Starts a sign-in and returns the provider's authorization URL with the `state` it carries.
+#[cfg(feature = "ssr")]
+async fn start_login(
+    provider: &str,
+    user: Option<AdapterUser>,
+    jar: &CookieJar,
+) -> Result<(String, String), AppError> {
+    // Linking would give a superadmin acting as the user a lasting sign-in of their own
+    if impersonator_in(jar).await?.is_some() {
+        return Err(AppError::Forbidden(
+            "Stop impersonating to link a sign-in provider".into(),
+        ));
+    }
+    let client = OidcClient::discover(OidcProviderConfig::find(provider)?).await?;
+
+    // A signed in user starting a login is linking another provider
+    let link_user_id = user.map(|user| user.id);
+
+    let verifier = pkce_verifier();
+    let challenge = pkce_challenge(&verifier);
+    let state = OidcLoginState::create(provider, verifier, link_user_id).await?;
+    Ok((client.authorize_url(&state, &challenge), state))
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn login_handler(
+    Path(provider): Path<String>,
+    OptionalAuthUser(user): OptionalAuthUser,
+    jar: CookieJar,
+) -> Response {
+    match start_login(&provider, user, &jar).await {
+        // The callback only accepts a state this browser was given, so a sign-in link can't
+        // be finished in someone else's browser
+        Ok((url, state)) => {
+            let cookie = oidc_state_cookie(state, time::Duration::minutes(OIDC_STATE_TTL_MINUTES));
+            ([(SET_COOKIE, cookie.to_string())], Redirect::to(&url)).into_response()
+        }
+        Err(AppError::Forbidden(reason)) => {
+            tracing::warn!(provider, reason, "OIDC sign-in refused");
+            Redirect::to("/login?error=impersonating").into_response()
+        }
+        Err(e) => {
+            tracing::error!(provider, error = %e, "Could not start OIDC sign-in");
+            Redirect::to("/login?error=oidc_failed").into_response()
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+pub(crate) struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finishes a sign-in. `browser_state` is the state cookie set when this browser started it.
+#[cfg(feature = "ssr")]
+async fn finish_login(
+    provider: &str,
+    params: CallbackParams,
+    browser_state: Option<String>,
+) -> Result<AdapterSession, AppError> {
+    if let Some(error) = params.error {
+        return Err(AppError::AuthError(format!("Provider returned {}", error)));
+    }
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return Err(AppError::AuthError("Missing code or state".into()));
+    };
+    if browser_state.as_deref() != Some(state.as_str()) {
+        return Err(AppError::AuthError(
+            "Sign-in was started in another browser".into(),
+        ));
+    }
+
+    let login = OidcLoginState::take(&state, provider).await?;
+    let client = OidcClient::discover(OidcProviderConfig::find(provider)?).await?;
+    let tokens = client.exchange_code(&code, &login.code_verifier).await?;
+    let info = client.userinfo(&tokens.access_token).await?;
+
+    sign_in_with_oidc(provider, &info, &tokens, login.link_user_id).await
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn callback_handler(
+    Path(provider): Path<String>,
+    Query(params): Query<CallbackParams>,
+    jar: CookieJar,
+) -> Response {
+    let clear_state = (SET_COOKIE, expired_oidc_state_cookie().to_string());
+    match finish_login(&provider, params, oidc_state_from(&jar)).await {
+        Ok(session) => {
+            let cookie = session.build_session_cookie().to_string();
+            (
+                [clear_state, (SET_COOKIE, cookie)],
+                Redirect::to(session.landing_path()),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::warn!(provider, error = %e, "OIDC sign-in rejected");
+            ([clear_state], Redirect::to("/login?error=oidc_failed")).into_response()
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_pkce_challenge_matches_rfc_7636() {
+    // Appendix B of RFC 7636
+    assert_eq!(
+        pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFWFn9QZNdqK2_c6ZZ-rbx7WMbmUbS5g"
+    );
+    let verifier = pkce_verifier();
+    assert_eq!(verifier.len(), 43);
+    assert_ne!(verifier, pkce_verifier());
+}
@@ -0,0 +1,612 @@
+//! Passkey (WebAuthn) registration and sign-in. The browser half of each ceremony goes through
+//! `navigator.credentials` in the hydrate build; the server keeps the ceremony state between
+//! the start and finish calls in the `webauthn_ceremony` table.
+
+use leptos::prelude::*;
+use leptos::server_fn::codec::Json;
+use serde::{Deserialize, Serialize};
+use webauthn_rs_proto::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        account::AdapterAccount,
+        magic_link::normalize_email,
+        session::{get_user, set_session_cookie},
+        user::AdapterUser,
+    },
+    db::settings::get_env,
+    db_init, AppError,
+};
+use crate::{date_utils::format_date, Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+#[cfg(feature = "ssr")]
+use partial_struct::Partial;
+#[cfg(feature = "ssr")]
+use webauthn_rs::prelude::{
+    Passkey, PasskeyAuthentication, PasskeyRegistration, Url, Uuid, Webauthn, WebauthnBuilder,
+    WebauthnError,
+};
+
+/// How long the browser has to answer a challenge.
+pub const WEBAUTHN_CEREMONY_TTL_MINUTES: i64 = 5;
+const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+
+/// A passkey as listed in settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyInfo {
+    pub id: RecordId,
+    pub name: String,
+    pub created_at: Datetime,
+    pub last_used_at: Option<Datetime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyRegistrationChallenge {
+    pub ceremony_id: String,
+    pub options: CreationChallengeResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyLoginChallenge {
+    pub ceremony_id: String,
+    pub options: RequestChallengeResponse,
+}
+
+/// A registered passkey. The `Passkey` is stored serialized since it holds binary key material.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize, Partial)]
+#[partial("CreateWebauthnCredential", derive(Serialize, Deserialize), omit(id))]
+pub struct WebauthnCredential {
+    pub id: RecordId,
+    pub user_id: RecordId,
+    pub name: String,
+    /// WebAuthn user handle, shared by all of a user's passkeys.
+    pub user_handle: String,
+    /// Base64url credential id, used to find the passkey after a sign-in.
+    pub credential_id: String,
+    pub passkey: String,
+    pub created_at: Datetime,
+    pub last_used_at: Option<Datetime>,
+}
+
+#[cfg(feature = "ssr")]
+fn webauthn_error(error: WebauthnError) -> AppError {
+    AppError::AuthError(format!("Passkey check failed: {}", error))
+}
+
+/// The relying party is the host of `AUTH_URL`, so passkeys only work on that origin.
+#[cfg(feature = "ssr")]
+fn webauthn() -> Result<Webauthn, AppError> {
+    let base_url = get_env("AUTH_URL")?;
+    let origin = Url::parse(&base_url)
+        .map_err(|e| AppError::Config(format!("Invalid AUTH_URL {}: {}", base_url, e)))?;
+    let rp_id = origin
+        .host_str()
+        .ok_or_else(|| AppError::Config(format!("AUTH_URL {} has no host", base_url)))?
+        .to_string();
+
+    WebauthnBuilder::new(&rp_id, &origin)
+        .map(|builder| builder.rp_name("Netron"))
+        .and_then(|builder| builder.build())
+        .map_err(|e| AppError::Config(format!("Invalid WebAuthn settings: {}", e)))
+}
+
+#[cfg(feature = "ssr")]
+impl WebauthnCredential {
+    pub async fn for_user(user_id: RecordId) -> Result<Vec<Self>, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query(
+                "SELECT * FROM webauthn_credential WHERE user_id = $user_id ORDER BY created_at;",
+            )
+            .bind(("user_id", user_id))
+            .await?;
+        let credentials: Vec<Self> = result.take(0)?;
+        Ok(credentials)
+    }
+
+    pub async fn find_by_credential_id(credential_id: &str) -> Result<Option<Self>, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("SELECT * FROM ONLY webauthn_credential WHERE credential_id = $credential_id LIMIT 1;")
+            .bind(("credential_id", credential_id.to_string()))
+            .await?;
+        let credential: Option<Self> = result.take(0)?;
+        Ok(credential)
+    }
+
+    pub async fn create(
+        user_id: RecordId,
+        user_handle: String,
+        name: &str,
+        passkey: &Passkey,
+    ) -> Result<Self, AppError> {
+        let client = db_init().await?;
+        let name = match name.trim() {
+            "" => DEFAULT_PASSKEY_NAME.to_string(),
+            name => name.to_string(),
+        };
+
+        let data = CreateWebauthnCredential {
+            user_id,
+            name,
+            user_handle,
+            credential_id: URL_SAFE_NO_PAD.encode(passkey.cred_id()),
+            passkey: serde_json::to_string(passkey)?,
+            created_at: Datetime::from(chrono::Utc::now()),
+            last_used_at: None,
+        };
+
+        let credential: Option<Self> = client.create("webauthn_credential").content(data).await?;
+        credential.ok_or_else(|| AppError::AuthError("Could not save passkey".into()))
+    }
+
+    pub fn passkey(&self) -> Result<Passkey, AppError> {
+        Ok(serde_json::from_str(&self.passkey)?)
+    }
+
+    /// Saves the passkey's updated signature counter after a sign-in.
+    pub async fn record_use(&self, passkey: &Passkey) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("UPDATE $id SET passkey = $passkey, last_used_at = time::now();")
+            .bind(("id", self.id.clone()))
+            .bind(("passkey", serde_json::to_string(passkey)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes one of `user_id`'s passkeys.
+    pub async fn revoke(id: RecordId, user_id: RecordId) -> Result<(), AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("DELETE $id WHERE user_id = $user_id RETURN BEFORE;")
+            .bind(("id", id))
+            .bind(("user_id", user_id))
+            .await?;
+        let deleted: Vec<Self> = result.take(0)?;
+        if deleted.is_empty() {
+            return Err(AppError::NotFound("Passkey not found".into()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<WebauthnCredential> for PasskeyInfo {
+    fn from(credential: WebauthnCredential) -> Self {
+        Self {
+            id: credential.id,
+            name: credential.name,
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+        }
+    }
+}
+
+/// Ceremony state between the start and finish server calls.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WebauthnCeremony {
+    user_id: RecordId,
+    user_handle: String,
+    state: String,
+    expires: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(feature = "ssr")]
+impl WebauthnCeremony {
+    async fn create(
+        user_id: RecordId,
+        user_handle: String,
+        state: &impl Serialize,
+    ) -> Result<String, AppError> {
+        let client = db_init().await?;
+        let ceremony_id = uuid::Uuid::new_v4().simple().to_string();
+
+        let _: Option<Self> = client
+            .create(("webauthn_ceremony", ceremony_id.as_str()))
+            .content(Self {
+                user_id,
+                user_handle,
+                state: serde_json::to_string(state)?,
+                expires: chrono::Utc::now()
+                    + chrono::Duration::minutes(WEBAUTHN_CEREMONY_TTL_MINUTES),
+            })
+            .await?;
+        Ok(ceremony_id)
+    }
+
+    /// Removes the ceremony so each challenge is answered at most once.
+    async fn take(ceremony_id: &str) -> Result<Self, AppError> {
+        let client = db_init().await?;
+        let ceremony: Option<Self> = client.delete(("webauthn_ceremony", ceremony_id)).await?;
+        let ceremony = ceremony
+            .filter(|c| c.expires > chrono::Utc::now())
+            .ok_or_else(|| AppError::AuthError("Passkey request expired, try again".into()))?;
+        Ok(ceremony)
+    }
+}
+
+#[server]
+pub async fn start_passkey_registration() -> Result<PasskeyRegistrationChallenge, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let existing = WebauthnCredential::for_user(user.id.clone()).await?;
+
+    let user_handle = match existing.first() {
+        Some(credential) => credential.user_handle.clone(),
+        None => Uuid::new_v4().to_string(),
+    };
+    let handle = Uuid::parse_str(&user_handle)
+        .map_err(|e| AppError::GenericError(format!("Invalid passkey user handle: {}", e)))?;
+    // Stops the browser from registering the same authenticator twice
+    let exclude = existing
+        .iter()
+        .map(|credential| Ok(credential.passkey()?.cred_id().clone()))
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let (options, state) = webauthn()?
+        .start_passkey_registration(handle, &user.name, &user.name, Some(exclude))
+        .map_err(webauthn_error)?;
+    let ceremony_id = WebauthnCeremony::create(user.id, user_handle, &state).await?;
+
+    Ok(PasskeyRegistrationChallenge {
+        ceremony_id,
+        options,
+    })
+}
+
+// Credentials are nested binary structures, which the default url encoding cannot carry
+#[server(input = Json)]
+pub async fn finish_passkey_registration(
+    ceremony_id: String,
+    name: String,
+    credential: RegisterPublicKeyCredential,
+) -> Result<PasskeyInfo, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let ceremony = WebauthnCeremony::take(&ceremony_id).await?;
+    if ceremony.user_id != user.id {
+        return Err(AppError::AuthError("Passkey request belongs to another user".into()).into());
+    }
+
+    let state: PasskeyRegistration = serde_json::from_str(&ceremony.state)?;
+    let passkey = webauthn()?
+        .finish_passkey_registration(&credential, &state)
+        .map_err(webauthn_error)?;
+
+    let saved = WebauthnCredential::create(user.id, ceremony.user_handle, &name, &passkey).await?;
+    Ok(saved.into())
+}
+
+/// The user signing in with `email` and their passkeys, if they have any.
+#[cfg(feature = "ssr")]
+async fn passkeys_for_email(
+    email: &str,
+) -> Result<Option<(RecordId, Vec<WebauthnCredential>)>, AppError> {
+    let Some(email) = normalize_email(email) else {
+        return Ok(None);
+    };
+    let account = match AdapterAccount::find("email", &email).await? {
+        Some(account) => account,
+        None => match AdapterAccount::find("credentials", &email).await? {
+            Some(account) => account,
+            None => return Ok(None),
+        },
+    };
+    let credentials = WebauthnCredential::for_user(account.user_id.clone()).await?;
+    Ok((!credentials.is_empty()).then_some((account.user_id, credentials)))
+}
+
+/// A challenge for an address without passkeys. It names one made-up credential that stays the
+/// same for the address, so the answer looks like a real one and doesn't tell whether the
+/// address has an account. No ceremony is stored, so it can't be finished.
+#[cfg(feature = "ssr")]
+fn decoy_passkey_login(email: &str) -> Result<PasskeyLoginChallenge, AppError> {
+    use sha2::{Digest, Sha256};
+    use webauthn_rs_proto::AllowCredentials;
+
+    static DECOY_SALT: std::sync::LazyLock<[u8; 32]> = std::sync::LazyLock::new(rand::random);
+
+    let (mut options, _) = webauthn()?
+        .start_discoverable_authentication()
+        .map_err(webauthn_error)?;
+    let credential_id = Sha256::new()
+        .chain_update(*DECOY_SALT)
+        .chain_update(email.trim().to_lowercase())
+        .finalize()
+        .to_vec();
+    options.public_key.allow_credentials = vec![AllowCredentials {
+        type_: "public-key".to_string(),
+        id: credential_id.into(),
+        transports: None,
+    }];
+
+    Ok(PasskeyLoginChallenge {
+        ceremony_id: uuid::Uuid::new_v4().simple().to_string(),
+        options,
+    })
+}
+
+/// Starts a sign-in for the passkeys of the user with this email address. Addresses without
+/// passkeys get a challenge that can't be answered rather than an error.
+#[server]
+pub async fn start_passkey_login(email: String) -> Result<PasskeyLoginChallenge, ServerFnError> {
+    let Some((user_id, credentials)) = passkeys_for_email(&email).await? else {
+        return Ok(decoy_passkey_login(&email)?);
+    };
+
+    let user_handle = credentials[0].user_handle.clone();
+    let passkeys = credentials
+        .iter()
+        .map(WebauthnCredential::passkey)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (options, state) = webauthn()?
+        .start_passkey_authentication(&passkeys)
+        .map_err(webauthn_error)?;
+    let ceremony_id = WebauthnCeremony::create(user_id, user_handle, &state).await?;
+
+    Ok(PasskeyLoginChallenge {
+        ceremony_id,
+        options,
+    })
+}
+
+#[server(input = Json)]
+pub async fn finish_passkey_login(
+    ceremony_id: String,
+    credential: PublicKeyCredential,
+) -> Result<(), ServerFnError> {
+    let ceremony = WebauthnCeremony::take(&ceremony_id).await?;
+    let state: PasskeyAuthentication = serde_json::from_str(&ceremony.state)?;
+    let result = webauthn()?
+        .finish_passkey_authentication(&credential, &state)
+        .map_err(webauthn_error)?;
+
+    let stored =
+        WebauthnCredential::find_by_credential_id(&URL_SAFE_NO_PAD.encode(result.cred_id()))
+            .await?
+            .filter(|c| c.user_id == ceremony.user_id)
+            .ok_or_else(|| AppError::AuthError("Unknown passkey".into()))?;
+    let mut passkey = stored.passkey()?;
+    passkey.update_credential(&result);
+    stored.record_use(&passkey).await?;
+
+    let user = AdapterUser::get_user(ceremony.user_id).await?;
+    // The authenticator verified the user, which stands in for a second factor
+    let session = user.new_verified_session().await?;
+    set_session_cookie(&session);
+    Ok(())
+}
+
+#[server]
+pub async fn list_passkeys() -> Result<Vec<PasskeyInfo>, ServerFnError> {
+    let user = get_user().await?;
+    let credentials = WebauthnCredential::for_user(user.id).await?;
+    Ok(credentials.into_iter().map(PasskeyInfo::from).collect())
+}
+
+#[server]
+pub async fn revoke_passkey(id: RecordId) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    WebauthnCredential::revoke(id, user.id).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "ssr"))]
+fn js_error(error: wasm_bindgen::JsValue) -> String {
+    use wasm_bindgen::JsCast;
+
+    match error.dyn_ref::<web_sys::DomException>() {
+        // Cancelling the prompt and timing out both surface as NotAllowedError
+        Some(e) if e.name() == "NotAllowedError" => "The passkey prompt was cancelled".to_string(),
+        Some(e) => e.message(),
+        None => format!("{:?}", error),
+    }
+}
+
+/// Asks the browser's authenticator to create a credential for `options`.
+async fn browser_create_credential(
+    options: CreationChallengeResponse,
+) -> Result<RegisterPublicKeyCredential, String> {
+    #[cfg(not(feature = "ssr"))]
+    {
+        use wasm_bindgen::JsCast;
+
+        let window = web_sys::window().ok_or("No browser window")?;
+        let options: web_sys::CredentialCreationOptions = options.into();
+        let promise = window
+            .navigator()
+            .credentials()
+            .create_with_options(&options)
+            .map_err(js_error)?;
+        let credential = wasm_bindgen_futures::JsFuture::from(promise)
+            .await
+            .map_err(js_error)?
+            .dyn_into::<web_sys::PublicKeyCredential>()
+            .map_err(|_| "The browser did not return a passkey".to_string())?;
+        Ok(RegisterPublicKeyCredential::from(credential))
+    }
+    #[cfg(feature = "ssr")]
+    {
+        let _ = options;
+        Err("Passkeys are only available in the browser".to_string())
+    }
+}
+
+/// Asks the browser's authenticator to sign the challenge in `options`.
+async fn browser_get_credential(
+    options: RequestChallengeResponse,
+) -> Result<PublicKeyCredential, String> {
+    #[cfg(not(feature = "ssr"))]
+    {
+        use wasm_bindgen::JsCast;
+
+        let window = web_sys::window().ok_or("No browser window")?;
+        let options: web_sys::CredentialRequestOptions = options.into();
+        let promise = window
+            .navigator()
+            .credentials()
+            .get_with_options(&options)
+            .map_err(js_error)?;
+        let credential = wasm_bindgen_futures::JsFuture::from(promise)
+            .await
+            .map_err(js_error)?
+            .dyn_into::<web_sys::PublicKeyCredential>()
+            .map_err(|_| "The browser did not return a passkey".to_string())?;
+        Ok(PublicKeyCredential::from(credential))
+    }
+    #[cfg(feature = "ssr")]
+    {
+        let _ = options;
+        Err("Passkeys are only available in the browser".to_string())
+    }
+}
+
+/// Runs a full registration ceremony for the signed in user.
+pub async fn register_passkey(name: String) -> Result<PasskeyInfo, String> {
+    let challenge = start_passkey_registration()
+        .await
+        .map_err(|e| e.to_string())?;
+    let credential = browser_create_credential(challenge.options).await?;
+    finish_passkey_registration(challenge.ceremony_id, name, credential)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Runs a full sign-in ceremony. On success the session cookie is set.
+pub async fn login_with_passkey(email: String) -> Result<(), String> {
+    let challenge = start_passkey_login(email)
+        .await
+        .map_err(|e| e.to_string())?;
+    let credential = browser_get_credential(challenge.options).await?;
+    finish_passkey_login(challenge.ceremony_id, credential)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[component]
+pub fn PasskeyItem(passkey: PasskeyInfo, on_revoke: Callback<RecordId>) -> impl IntoView {
+    let id = passkey.id.clone();
+
+    view! {
+        <div class="px-4 py-3 hover:bg-neutral-50 dark:hover:bg-neutral-700">
+            <div class="flex items-center justify-between">
+                <div class="flex-1">
+                    <h3 class="text-sm font-medium text-neutral-900 dark:text-neutral-100">{passkey.name.clone()}</h3>
+                    <div class="flex items-center gap-4 mt-2 text-xs text-neutral-600 dark:text-neutral-400">
+                        <span>"Added: "{format_date(&passkey.created_at)}</span>
+                        <span>
+                            "Last used: "
+                            {passkey.last_used_at.as_ref().map(format_date).unwrap_or_else(|| "never".to_string())}
+                        </span>
+                    </div>
+                </div>
+                <button
+                    class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 text-sm font-medium"
+                    on:click=move |_| on_revoke.run(id.clone())
+                >
+                    "Revoke"
+                </button>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+pub fn PasskeysControl() -> impl IntoView {
+    let passkeys = Resource::new(|| (), |_| list_passkeys());
+    let revoke = ServerAction::<RevokePasskey>::new();
+    let (name, set_name) = signal(String::new());
+    let (registering, set_registering) = signal(false);
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| {
+        if let Some(result) = revoke.value().get() {
+            set_error_message.set(result.err().map(|e| e.to_string()));
+            passkeys.refetch();
+        }
+    });
+
+    let on_revoke = Callback::new(move |id: RecordId| {
+        revoke.dispatch(RevokePasskey { id });
+    });
+
+    // The browser prompt future is not Send, so it runs on the local task queue
+    let add = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        set_registering.set(true);
+        set_error_message.set(None);
+        let name = name.get_untracked();
+        leptos::task::spawn_local(async move {
+            match register_passkey(name).await {
+                Ok(_) => {
+                    set_name.set(String::new());
+                    passkeys.refetch();
+                }
+                Err(e) => set_error_message.set(Some(e)),
+            }
+            set_registering.set(false);
+        });
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-4">"Passkeys"</h2>
+            <p class="text-neutral-600 dark:text-neutral-400 mb-6">
+                "Sign in with your fingerprint, face or security key instead of a password."
+            </p>
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match passkeys.get() {
+                    Some(Ok(list)) if list.is_empty() => {
+                        view! { <p class="text-neutral-500 dark:text-neutral-400">"No passkeys yet."</p> }.into_any()
+                    }
+                    Some(Ok(list)) => {
+                        view! {
+                            <div class="divide-y divide-neutral-200 dark:divide-neutral-700">
+                                {list
+                                    .into_iter()
+                                    .map(|passkey| view! { <PasskeyItem passkey=passkey on_revoke=on_revoke /> })
+                                    .collect_view()}
+                            </div>
+                        }.into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! { <p class="text-red-600 dark:text-red-400">"Error loading passkeys: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            <form on:submit=add class="flex gap-2 mt-4">
+                <input
+                    type="text"
+                    class="flex-1 px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+                    placeholder="Name, e.g. Work laptop"
+                    prop:value=move || name.get()
+                    on:input=move |e| set_name.set(event_target_value(&e))
+                    disabled=move || registering.get()
+                />
+                <button
+                    type="submit"
+                    class="px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed"
+                    disabled=move || registering.get()
+                >
+                    {move || if registering.get() { "Waiting for device..." } else { "Add passkey" }}
+                </button>
+            </form>
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
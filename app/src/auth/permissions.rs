@@ -0,0 +1,89 @@
+//! Who may read, change or delete a stored record. Every user holds at most one [`Role`] on a
+//! record: superadmins hold it everywhere, the user in `created_by_user_id` owns it, and team
+//! roles come from [`Permission::team_role`]. Each record type picks the role an [`Action`]
+//! needs through [`Permission::required_role`].
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+use crate::{auth::user::AdapterUser, AppError};
+
+#[cfg(feature = "ssr")]
+use surrealdb::RecordId;
+
+/// Roles from least to most privileged, so that `role >= required` grants access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Shares a team with the owner.
+    Member,
+    /// Created the record.
+    Owner,
+    /// Manages the team the record belongs to.
+    Admin,
+    Superadmin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Read,
+    Update,
+    Delete,
+}
+
+#[cfg(feature = "ssr")]
+#[allow(async_fn_in_trait)]
+pub trait Permission {
+    /// The user that created the record.
+    fn owner_id(&self) -> Option<&RecordId>;
+
+    /// The role `user` holds on this record through a team, if any.
+    async fn team_role(&self, _user: &AdapterUser) -> Result<Option<Role>, AppError> {
+        Ok(None)
+    }
+
+    /// The least privileged role allowed to perform `action`. By default team members may read,
+    /// and only owners and above may change or delete.
+    fn required_role(action: Action) -> Role {
+        match action {
+            Action::Read => Role::Member,
+            Action::Update | Action::Delete => Role::Owner,
+        }
+    }
+
+    /// The highest role `user` holds on this record.
+    async fn role_of(&self, user: &AdapterUser) -> Result<Option<Role>, AppError> {
+        if user.superadmin == Some(true) {
+            return Ok(Some(Role::Superadmin));
+        }
+        let team_role = self.team_role(user).await?;
+        let owner_role = (self.owner_id() == Some(&user.id)).then_some(Role::Owner);
+        Ok(team_role.max(owner_role))
+    }
+
+    /// Fails with [`AppError::Forbidden`] unless `user` may perform `action`.
+    async fn authorize(&self, user: &AdapterUser, action: Action) -> Result<(), AppError> {
+        let verb = match action {
+            Action::Read => "read",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        };
+        match self.role_of(user).await? {
+            Some(role) if role >= Self::required_role(action) => Ok(()),
+            _ => Err(AppError::Forbidden(format!(
+                "Not allowed to {} this record",
+                verb
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_role_order() {
+    assert!(Role::Member < Role::Owner);
+    assert!(Role::Owner < Role::Admin);
+    assert!(Role::Admin < Role::Superadmin);
+    assert_eq!(Some(Role::Admin).max(Some(Role::Owner)), Some(Role::Admin));
+    assert_eq!(None.max(Some(Role::Member)), Some(Role::Member));
+}
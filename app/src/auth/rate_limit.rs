@@ -0,0 +1,89 @@
+//! In-memory limits on repeated failures, such as wrong two-factor codes. Counts live in this
+//! server process only, which matches how the app is deployed today.
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use crate::AppError;
+
+/// Allows `max_failures` failures per key within a sliding `window`.
+pub struct RateLimiter {
+    max_failures: usize,
+    window: Duration,
+    failures: DashMap<String, Vec<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_failures: usize, window: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            failures: DashMap::new(),
+        }
+    }
+
+    /// Returns how long `key` has to wait, or `None` if it may try again now.
+    pub fn retry_after(&self, key: &str) -> Option<Duration> {
+        self.retry_after_at(key, Instant::now())
+    }
+
+    fn retry_after_at(&self, key: &str, now: Instant) -> Option<Duration> {
+        let mut entry = self.failures.get_mut(key)?;
+        entry.retain(|at| now.duration_since(*at) < self.window);
+        if entry.len() < self.max_failures {
+            return None;
+        }
+        entry
+            .first()
+            .map(|oldest| self.window.saturating_sub(now.duration_since(*oldest)))
+    }
+
+    /// Fails with a message telling the user when to retry once `key` is over the limit.
+    pub fn check(&self, key: &str) -> Result<(), AppError> {
+        match self.retry_after(key) {
+            Some(wait) => Err(AppError::AuthError(format!(
+                "Too many attempts, try again in {} minutes",
+                wait.as_secs().div_ceil(60).max(1)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, key: &str) {
+        self.record_failure_at(key, Instant::now());
+    }
+
+    fn record_failure_at(&self, key: &str, at: Instant) {
+        self.failures.entry(key.to_string()).or_default().push(at);
+    }
+
+    /// Forgets the failures of `key`, after a successful attempt.
+    pub fn reset(&self, key: &str) {
+        self.failures.remove(key);
+    }
+}
+
+#[test]
+fn test_rate_limiter_window() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    limiter.record_failure_at("ada", start);
+    assert_eq!(limiter.retry_after_at("ada", start), None);
+    limiter.record_failure_at("ada", start + Duration::from_secs(10));
+    assert_eq!(
+        limiter.retry_after_at("ada", start + Duration::from_secs(10)),
+        Some(Duration::from_secs(50))
+    );
+    assert_eq!(limiter.retry_after_at("grace", start), None);
+
+    // The first failure ages out of the window
+    assert_eq!(
+        limiter.retry_after_at("ada", start + Duration::from_secs(61)),
+        None
+    );
+
+    limiter.reset("ada");
+    assert!(limiter.check("ada").is_ok());
+}
@@ -12,12 +12,27 @@ use crate::db::db_init;
 #[cfg(feature = "ssr")]
 use crate::AppError;
 
+#[cfg(feature = "ssr")]
+use crate::auth::cookies::{
+    expired_session_cookie, session_cookie, session_cookie_name, session_token_from,
+};
+
 #[cfg(feature = "ssr")]
 use surrealdb::{Datetime, RecordId};
 
 #[cfg(not(feature = "ssr"))]
 use crate::{Datetime, RecordId};
 
+/// How long a session stays valid without activity. Every request pushes the expiry forward.
+pub const SESSION_TTL_DAYS: i64 = 30;
+
+/// Activity is recorded, and the expiry renewed, at most this often per session.
+pub const SESSION_TOUCH_INTERVAL_MINUTES: i64 = 5;
+
+/// How often expired sessions and one-time tokens are deleted.
+#[cfg(feature = "ssr")]
+pub const SESSION_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize, Partial)]
 #[partial("CreateSessionData", derive(Serialize, Deserialize), omit(id))]
 #[partial("UpdateSessionData", derive(Serialize, Deserialize), omit(id, user_id))]
@@ -26,6 +41,27 @@ pub struct AdapterSession {
     pub session_token: String,
     pub user_id: RecordId,
     pub expires: Datetime,
+    /// Set while a user with two-factor authentication still has to enter a code. Pending
+    /// sessions do not authenticate requests.
+    #[serde(default)]
+    pub mfa_pending: bool,
+    /// The browser that created or last used the session.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// The address the session was last used from.
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<Datetime>,
+    #[serde(default)]
+    pub last_seen_at: Option<Datetime>,
+    /// The organization this session works in. `None` is the user's personal space.
+    #[serde(default)]
+    pub organization_id: Option<RecordId>,
+    /// The superadmin acting as this user. Impersonation sessions keep their short expiry
+    /// instead of sliding forward with activity.
+    #[serde(default)]
+    pub impersonator_id: Option<RecordId>,
 }
 
 #[cfg(feature = "ssr")]
@@ -34,7 +70,7 @@ impl AdapterSession {
         let client = db_init().await?;
 
         let mut result = client
-            .query("SELECT * FROM ONLY session WHERE session_token = $session_token LIMIT 1;")
+            .query("SELECT * FROM ONLY session WHERE session_token = $session_token AND expires > time::now() LIMIT 1;")
             .bind(("session_token", session_token))
             .await?;
 
@@ -56,19 +92,46 @@ impl AdapterSession {
         Ok(session)
     }
 
-    pub fn build_session_cookie(&self) -> axum_extra::extract::cookie::Cookie<'_> {
-        use axum_extra::extract::cookie::Cookie;
-        use time::Duration;
+    /// Where to send the browser after signing in with this session.
+    pub fn landing_path(&self) -> &'static str {
+        if self.mfa_pending {
+            "/login/2fa"
+        } else {
+            "/"
+        }
+    }
+
+    /// The session for `session_token` if it is still waiting for its second factor.
+    pub async fn pending_mfa(session_token: String) -> Result<Option<AdapterSession>, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("SELECT * FROM ONLY session WHERE session_token = $session_token AND mfa_pending = true AND expires > time::now() LIMIT 1;")
+            .bind(("session_token", session_token))
+            .await?;
+        let session: Option<AdapterSession> = result.take(0)?;
+        Ok(session)
+    }
 
-        let cookie: Cookie<'_> = Cookie::build(("session_token", self.session_token.clone()))
-            .path("/")
-            .secure(false) // use only over HTTPS
-            .http_only(true) // JS can't read the cookie
-            .same_site(leptos_use::SameSite::Lax)
-            .max_age(Duration::days(365))
-            .build();
+    /// Marks the second factor as done and gives the session its full lifetime.
+    pub async fn complete_mfa(&self) -> Result<AdapterSession, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("UPDATE $id SET mfa_pending = false, expires = $expires RETURN AFTER;")
+            .bind(("id", self.id.clone()))
+            .bind((
+                "expires",
+                Datetime::from(chrono::Utc::now() + chrono::Duration::days(SESSION_TTL_DAYS)),
+            ))
+            .await?;
+        let session: Option<AdapterSession> = result.take(0)?;
+        session.ok_or_else(|| AppError::AuthError("Session not found".into()))
+    }
 
-        cookie
+    pub fn build_session_cookie(&self) -> axum_extra::extract::cookie::Cookie<'_> {
+        session_cookie(
+            self.session_token.clone(),
+            time::Duration::days(SESSION_TTL_DAYS),
+        )
     }
 
     // pub async fn get_session_and_user(
@@ -93,15 +156,104 @@ impl AdapterSession {
     ) -> Result<Option<AdapterSession>, AppError> {
         let client = db_init().await?;
 
-        let result = client
-            .query("UPDATE session SET expires = $expires WHERE session_token = $session_token;")
+        let mut result = client
+            .query("UPDATE session SET expires = $expires WHERE session_token = $session_token RETURN AFTER;")
             .bind(("expires", data.expires))
             .bind(("session_token", data.session_token))
             .await?;
 
-        println!("update_session: {:?}", result);
+        let updated: Vec<AdapterSession> = result.take(0)?;
+        Ok(updated.into_iter().next())
+    }
 
-        Ok(None)
+    /// Records activity on a live session and pushes its expiry a full lifetime ahead. Returns
+    /// `None` for unknown, expired or pending sessions.
+    pub async fn touch(
+        session_token: String,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> Result<Option<AdapterSession>, AppError> {
+        let client = db_init().await?;
+
+        let mut result = client
+            .query(
+                "UPDATE session SET last_seen_at = time::now(), expires = IF impersonator_id = NONE THEN $expires ELSE expires END, user_agent = $user_agent ?? user_agent, ip = $ip ?? ip \
+                 WHERE session_token = $session_token AND expires > time::now() AND mfa_pending != true RETURN AFTER;",
+            )
+            .bind(("session_token", session_token))
+            .bind((
+                "expires",
+                Datetime::from(chrono::Utc::now() + chrono::Duration::days(SESSION_TTL_DAYS)),
+            ))
+            .bind(("user_agent", user_agent))
+            .bind(("ip", ip))
+            .await?;
+
+        let updated: Vec<AdapterSession> = result.take(0)?;
+        Ok(updated.into_iter().next())
+    }
+
+    pub async fn set_organization(
+        session_token: String,
+        organization_id: Option<RecordId>,
+    ) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("UPDATE session SET organization_id = $organization_id WHERE session_token = $session_token;")
+            .bind(("session_token", session_token))
+            .bind(("organization_id", organization_id))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Live sessions of a user, most recently used first.
+    pub async fn for_user(user_id: RecordId) -> Result<Vec<AdapterSession>, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("SELECT * FROM session WHERE user_id = $user_id AND expires > time::now() AND mfa_pending != true ORDER BY last_seen_at DESC;")
+            .bind(("user_id", user_id))
+            .await?;
+        let sessions: Vec<AdapterSession> = result.take(0)?;
+        Ok(sessions)
+    }
+
+    /// Deletes one session of `user_id`. Fails if the session belongs to someone else.
+    pub async fn revoke(id: RecordId, user_id: RecordId) -> Result<(), AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("DELETE session WHERE id = $id AND user_id = $user_id RETURN BEFORE;")
+            .bind(("id", id))
+            .bind(("user_id", user_id))
+            .await?;
+        let deleted: Vec<AdapterSession> = result.take(0)?;
+        if deleted.is_empty() {
+            return Err(AppError::NotFound("Session not found".into()));
+        }
+        Ok(())
+    }
+
+    /// Deletes every session of `user_id` except the one with `keep_token`.
+    pub async fn revoke_others(user_id: RecordId, keep_token: String) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("DELETE session WHERE user_id = $user_id AND session_token != $session_token;")
+            .bind(("user_id", user_id))
+            .bind(("session_token", keep_token))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Deletes every session of `user_id`.
+    pub async fn revoke_all(user_id: RecordId) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("DELETE session WHERE user_id = $user_id;")
+            .bind(("user_id", user_id))
+            .await?
+            .check()?;
+        Ok(())
     }
 
     pub async fn delete_session(session_token: String) -> Result<Option<AdapterSession>, AppError> {
@@ -116,59 +268,171 @@ impl AdapterSession {
     }
 }
 
-#[server]
-pub async fn get_session() -> Result<String, ServerFnError> {
-    use crate::auth::user::AdapterUser;
+/// Sends the session cookie with the current server function response.
+#[cfg(feature = "ssr")]
+pub fn set_session_cookie(session: &AdapterSession) {
+    use http::header::HeaderValue;
+    use leptos_axum::ResponseOptions;
+
+    if let Some(resp) = use_context::<ResponseOptions>() {
+        if let Ok(value) = HeaderValue::from_str(&session.build_session_cookie().to_string()) {
+            resp.insert_header(axum::http::header::SET_COOKIE, value);
+        }
+    }
+}
+
+/// The session token sent with the current server function request.
+#[cfg(feature = "ssr")]
+pub async fn current_session_token() -> Result<String, ServerFnError> {
     let cookie_jar = leptos_axum::extract::<axum_extra::extract::CookieJar>().await?;
-    let csrf_cookie = cookie_jar
+    session_token_from(&cookie_jar).ok_or(ServerFnError::new("Not logged in."))
+}
+
+/// When each session was last touched by this server process, so that most requests skip the
+/// database write.
+#[cfg(feature = "ssr")]
+static LAST_TOUCH: std::sync::LazyLock<dashmap::DashMap<String, std::time::Instant>> =
+    std::sync::LazyLock::new(dashmap::DashMap::new);
+
+#[cfg(feature = "ssr")]
+fn touch_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(SESSION_TOUCH_INTERVAL_MINUTES as u64 * 60)
+}
+
+/// Claims the next touch of `session_token` if the last one is old enough.
+#[cfg(feature = "ssr")]
+fn touch_due(session_token: &str) -> bool {
+    let now = std::time::Instant::now();
+    match LAST_TOUCH.get(session_token) {
+        Some(last) if now.duration_since(*last) < touch_interval() => false,
+        _ => {
+            LAST_TOUCH.insert(session_token.to_string(), now);
+            true
+        }
+    }
+}
+
+/// The client address, preferring the first `X-Forwarded-For` hop set by a reverse proxy.
+/// Only shown to the user, never trusted for access decisions.
+#[cfg(feature = "ssr")]
+fn client_ip(request: &axum::extract::Request) -> Option<String> {
+    request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty())
+        .or_else(|| {
+            request
+                .extensions()
+                .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+                .map(|info| info.0.ip().to_string())
+        })
+}
+
+/// Middleware that records activity on the request's session and slides its expiry forward,
+/// re-sending the cookie with the new lifetime.
+#[cfg(feature = "ssr")]
+pub async fn session_activity(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::http::header::{HeaderValue, SET_COOKIE, USER_AGENT};
+
+    let token = session_token_from(&axum_extra::extract::CookieJar::from_headers(
+        request.headers(),
+    ))
+    .filter(|token| touch_due(token));
+    let Some(token) = token else {
+        return next.run(request).await;
+    };
+
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(512).collect::<String>());
+    let touched = AdapterSession::touch(token, user_agent, client_ip(&request)).await;
+
+    let mut response = next.run(request).await;
+    // Signing in or out sets its own cookie, which must win
+    let cookie_prefix = format!("{}=", session_cookie_name());
+    let sets_session = response
+        .headers()
+        .get_all(SET_COOKIE)
         .iter()
-        .filter(|cookie| cookie.name().contains("session_token"))
-        .next()
-        .ok_or(ServerFnError::new("Not logged in."))?;
-    let user = AdapterUser::get_user_from_session(csrf_cookie.value().to_string()).await?;
+        .any(|value| value.as_bytes().starts_with(cookie_prefix.as_bytes()));
+    match touched {
+        Ok(Some(session)) if !sets_session => {
+            if let Ok(value) = HeaderValue::from_str(&session.build_session_cookie().to_string()) {
+                response.headers_mut().append(SET_COOKIE, value);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Could not record session activity: {}", e),
+    }
+    response
+}
+
+/// Deletes expired sessions and one-time sign-in state.
+#[cfg(feature = "ssr")]
+pub async fn sweep_expired() -> Result<(), AppError> {
+    let client = db_init().await?;
+    client
+        .query(
+            "DELETE session WHERE expires < time::now();
+             DELETE verificationToken WHERE <datetime> expires < time::now();
+             DELETE oidc_state WHERE <datetime> expires < time::now();
+             DELETE webauthn_ceremony WHERE <datetime> expires < time::now();",
+        )
+        .await?
+        .check()?;
+
+    let interval = touch_interval();
+    LAST_TOUCH.retain(|_, last| last.elapsed() < interval);
+    Ok(())
+}
+
+/// Runs [`sweep_expired`] every [`SESSION_SWEEP_INTERVAL`] for the life of the server.
+#[cfg(feature = "ssr")]
+pub fn spawn_session_sweeper() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep_expired().await {
+                tracing::error!("Failed to delete expired sessions: {}", e);
+            }
+        }
+    });
+}
+
+#[server]
+pub async fn get_session() -> Result<String, ServerFnError> {
+    let user = crate::auth::extract::auth_user().await?;
     Ok(user.name)
 }
 
 #[server]
 pub async fn get_user() -> Result<crate::auth::user::AdapterUser, ServerFnError> {
-    let cookie_jar = leptos_axum::extract::<axum_extra::extract::CookieJar>().await?;
-    let csrf_cookie = cookie_jar
-        .iter()
-        .filter(|cookie| cookie.name().contains("session_token"))
-        .next()
-        .ok_or(ServerFnError::new("Not logged in."))?;
-    let user = AdapterUser::get_user_from_session(csrf_cookie.value().to_string()).await?;
-    Ok(user)
+    crate::auth::extract::auth_user().await
 }
 
 #[server]
 pub async fn logout() -> Result<(), ServerFnError> {
-    use axum_extra::extract::cookie::Cookie;
     use http::header::HeaderValue;
     use leptos_axum::ResponseOptions;
-    use time::Duration;
 
     let cookie_jar = leptos_axum::extract::<axum_extra::extract::CookieJar>().await?;
 
     // Find and delete the session from database
-    if let Some(session_cookie) = cookie_jar
-        .iter()
-        .find(|cookie| cookie.name().contains("session_token"))
-    {
-        let _ = AdapterSession::delete_session(session_cookie.value().to_string()).await;
-    }
-
-    // Create the cookie to overwrite the existing session token
-    // This will effectively clear the session on the client side
-    // by setting an empty session token with a past expiration date
-    let cookie = Cookie::build(("session_token", "".to_string()))
-        .path("/")
-        .secure(false) // use only over HTTPS
-        .http_only(true) // JS can't read the cookie
-        .same_site(leptos_use::SameSite::Lax)
-        .max_age(Duration::MICROSECOND)
-        .expires(time::OffsetDateTime::now_utc() - time::Duration::days(1))
-        .build();
+    if let Some(session_token) = session_token_from(&cookie_jar) {
+        let _ = AdapterSession::delete_session(session_token).await;
+    }
+
+    // Overwrite the session cookie with an empty one that has already expired
+    let cookie = expired_session_cookie();
 
     // Set the cookie via ResponseOptions
     if let Some(resp) = use_context::<ResponseOptions>() {
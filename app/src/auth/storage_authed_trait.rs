@@ -5,7 +5,10 @@ use crate::AppError;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "ssr")]
-use crate::auth::user::AdapterUser;
+use crate::auth::{
+    permissions::{Action, Permission},
+    user::AdapterUser,
+};
 
 #[cfg(feature = "ssr")]
 use surrealdb::RecordId;
@@ -16,7 +19,7 @@ pub trait StorageAuthed<NoId, WithId>
 where
     Self: Clone + Serialize + for<'de> Deserialize<'de> + 'static,
     NoId: Clone + Sync + Send + Serialize + 'static,
-    WithId: Clone + Sync + Send + Serialize + for<'de> Deserialize<'de> + 'static,
+    WithId: Clone + Sync + Send + Serialize + for<'de> Deserialize<'de> + Permission + 'static,
 {
     const TABLE_NAME: &str;
 
@@ -57,6 +60,13 @@ where
         }
     }
 
+    /// The record with `id`, if `user` may read it.
+    async fn get_by_id_for(user: AdapterUser, id: RecordId) -> Result<WithId, AppError> {
+        let item = Self::get_by_id(id).await?;
+        item.authorize(&user, Action::Read).await?;
+        Ok(item)
+    }
+
     async fn get_many() -> Result<Vec<WithId>, AppError> {
         let db = crate::db_init().await?;
 
@@ -80,26 +90,27 @@ where
         Ok(items)
     }
 
-    async fn update(user: AdapterUser, _id: RecordId, content: WithId) -> Result<WithId, AppError> {
-        // todo look up user permissions;
+    /// Updates the record with `id` if `user` may. The owner stays the same whatever `content`
+    /// says, and a `content` id other than `id` is rejected by the database.
+    async fn update(user: AdapterUser, id: RecordId, content: WithId) -> Result<WithId, AppError> {
+        let existing = Self::get_by_id(id.clone()).await?;
+        existing.authorize(&user, Action::Update).await?;
 
         let db = crate::db_init().await?;
 
-        let query = format!(
-            r#"
-            LET $record = UPDATE $content.id MERGE $content;
-            UPDATE $record SET created_by_user_id = $user_id, created_at = time::now(), updated_at = time::now();
-            SELECT * from $record;
-            "#
-        );
+        let query = r#"
+            UPDATE ONLY $id MERGE $content RETURN NONE;
+            UPDATE ONLY $id SET created_by_user_id = $owner_id, updated_at = time::now() RETURN AFTER;
+        "#;
 
         let mut response = db
             .query(query)
+            .bind(("id", id))
             .bind(("content", content))
-            .bind(("user_id", user.id))
+            .bind(("owner_id", existing.owner_id().cloned()))
             .await?;
 
-        let updated_item: Option<WithId> = response.take(2)?;
+        let updated_item: Option<WithId> = response.take(1)?;
 
         match updated_item {
             Some(item) => Ok(item),
@@ -123,8 +134,9 @@ where
         }
     }
 
-    async fn delete(_user: AdapterUser, id: RecordId) -> Result<bool, AppError> {
-        // todo look up user permissions;
+    async fn delete(user: AdapterUser, id: RecordId) -> Result<bool, AppError> {
+        let existing = Self::get_by_id(id.clone()).await?;
+        existing.authorize(&user, Action::Delete).await?;
 
         let db = crate::db_init().await?;
         let deleted: Option<WithId> = db.delete(id).await?;
@@ -0,0 +1,406 @@
+//! Time-based one-time passwords (RFC 6238) as a second factor, with one-time recovery codes
+//! for when the authenticator app is lost.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+use std::{sync::LazyLock, time::Duration};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    admin::models::forbid_impersonation,
+    auth::{
+        rate_limit::RateLimiter,
+        session::{get_user, AdapterSession},
+        user::AdapterUser,
+    },
+    db_init, AppError, Datetime, RecordId,
+};
+
+#[cfg(feature = "ssr")]
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// Lifetime of a session that is waiting for its second factor.
+pub const MFA_PENDING_TTL_MINUTES: i64 = 10;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const TOTP_ISSUER: &str = "Netron";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_LEN: usize = 16;
+/// Lowercase letters and digits without the easily confused 0, 1, i, l and o.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Wrong codes allowed per user before they have to wait.
+#[cfg(feature = "ssr")]
+static CODE_ATTEMPTS: LazyLock<RateLimiter> =
+    LazyLock::new(|| RateLimiter::new(5, Duration::from_secs(15 * 60)));
+
+/// What an authenticator app needs to start generating codes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    /// Base32 secret, for typing in by hand.
+    pub secret: String,
+    /// `otpauth://` URL, shown as a QR code.
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: usize,
+}
+
+/// A user's authenticator. `enabled_at` stays empty until the user proves they can generate
+/// codes, and `last_used_step` stops a code from being used twice.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpFactor {
+    pub id: RecordId,
+    pub user_id: RecordId,
+    pub secret: String,
+    pub enabled_at: Option<Datetime>,
+    pub last_used_step: Option<i64>,
+}
+
+#[cfg(feature = "ssr")]
+fn build_totp(secret: &str, account_name: &str) -> Result<TOTP, AppError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| AppError::GenericError(format!("Invalid TOTP secret: {:?}", e)))?;
+    // otpauth labels use ':' to separate issuer and account
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        account_name.replace(':', " "),
+    )
+    .map_err(|e| AppError::GenericError(format!("Invalid TOTP settings: {:?}", e)))
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The time step `code` belongs to, allowing one step of clock drift either way.
+#[cfg(feature = "ssr")]
+fn matching_step(totp: &TOTP, code: &str, unix_time: u64) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    let current = unix_time / TOTP_STEP_SECONDS;
+    [current.saturating_sub(1), current, current + 1]
+        .into_iter()
+        .find(|step| {
+            constant_time_eq(
+                totp.generate(step * TOTP_STEP_SECONDS).as_bytes(),
+                code.as_bytes(),
+            )
+        })
+}
+
+/// Random recovery codes, formatted as `xxxx-xxxx-xxxx-xxxx`.
+#[cfg(feature = "ssr")]
+fn generate_recovery_codes() -> Vec<String> {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: Vec<char> = (0..RECOVERY_CODE_LEN)
+                .map(|_| {
+                    RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            chars
+                .chunks(4)
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Recovery codes carry about 80 bits of randomness, so a plain SHA-256 is enough to keep them
+/// unreadable at rest.
+#[cfg(feature = "ssr")]
+fn hash_recovery_code(code: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(feature = "ssr")]
+impl TotpFactor {
+    pub async fn for_user(user_id: RecordId) -> Result<Option<Self>, AppError> {
+        let client = db_init().await?;
+        let mut result = client
+            .query("SELECT * FROM ONLY totp_factor WHERE user_id = $user_id LIMIT 1;")
+            .bind(("user_id", user_id))
+            .await?;
+        let factor: Option<Self> = result.take(0)?;
+        Ok(factor)
+    }
+
+    pub async fn enabled_for(user_id: RecordId) -> Result<bool, AppError> {
+        Ok(Self::for_user(user_id)
+            .await?
+            .is_some_and(|factor| factor.enabled_at.is_some()))
+    }
+
+    /// Replaces any unconfirmed factor with a new secret.
+    async fn start(user_id: RecordId) -> Result<Self, AppError> {
+        let client = db_init().await?;
+        let secret = Secret::Raw(rand::random::<[u8; TOTP_SECRET_BYTES]>().to_vec())
+            .to_encoded()
+            .to_string();
+
+        let mut result = client
+            .query("DELETE totp_factor WHERE user_id = $user_id AND enabled_at = NONE;")
+            .query("CREATE ONLY totp_factor CONTENT { user_id: $user_id, secret: $secret, enabled_at: NONE, last_used_step: NONE };")
+            .bind(("user_id", user_id))
+            .bind(("secret", secret))
+            .await?;
+        let factor: Option<Self> = result.take(1)?;
+        factor.ok_or_else(|| AppError::AuthError("Could not start two-factor setup".into()))
+    }
+
+    /// Checks a code and records its time step so it cannot be replayed.
+    async fn verify_code(&self, account_name: &str, code: &str) -> Result<bool, AppError> {
+        let totp = build_totp(&self.secret, account_name)?;
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let Some(step) = matching_step(&totp, code, now) else {
+            return Ok(false);
+        };
+        let step = step as i64;
+        if self.last_used_step.is_some_and(|last| step <= last) {
+            return Ok(false);
+        }
+
+        // The condition makes two concurrent uses of one code race for a single update
+        let client = db_init().await?;
+        let mut result = client
+            .query("UPDATE $id SET last_used_step = $step WHERE last_used_step = NONE OR last_used_step < $step RETURN AFTER;")
+            .bind(("id", self.id.clone()))
+            .bind(("step", step))
+            .await?;
+        let updated: Vec<Self> = result.take(0)?;
+        Ok(!updated.is_empty())
+    }
+
+    async fn enable(&self) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("UPDATE $id SET enabled_at = time::now();")
+            .bind(("id", self.id.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(user_id: RecordId) -> Result<(), AppError> {
+        let client = db_init().await?;
+        client
+            .query("DELETE totp_factor WHERE user_id = $user_id; DELETE recovery_code WHERE user_id = $user_id;")
+            .bind(("user_id", user_id))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+struct RecoveryCode {
+    #[allow(dead_code)]
+    id: RecordId,
+}
+
+/// Replaces a user's recovery codes, returning the new ones in plain text for the only time.
+#[cfg(feature = "ssr")]
+async fn replace_recovery_codes(user_id: RecordId) -> Result<Vec<String>, AppError> {
+    let client = db_init().await?;
+    let codes = generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    client
+        .query("DELETE recovery_code WHERE user_id = $user_id;")
+        .query("FOR $hash IN $hashes { CREATE recovery_code CONTENT { user_id: $user_id, code_hash: $hash, used_at: NONE }; };")
+        .bind(("user_id", user_id))
+        .bind(("hashes", hashes))
+        .await?;
+    Ok(codes)
+}
+
+/// Marks a recovery code as used, returning whether it was valid and unused.
+#[cfg(feature = "ssr")]
+async fn use_recovery_code(user_id: RecordId, code: &str) -> Result<bool, AppError> {
+    let client = db_init().await?;
+    let mut result = client
+        .query("UPDATE recovery_code SET used_at = time::now() WHERE user_id = $user_id AND code_hash = $code_hash AND used_at = NONE RETURN AFTER;")
+        .bind(("user_id", user_id))
+        .bind(("code_hash", hash_recovery_code(code)))
+        .await?;
+    let used: Vec<RecoveryCode> = result.take(0)?;
+    Ok(!used.is_empty())
+}
+
+#[cfg(feature = "ssr")]
+async fn recovery_codes_left(user_id: RecordId) -> Result<usize, AppError> {
+    let client = db_init().await?;
+    let mut result = client
+        .query("count(SELECT id FROM recovery_code WHERE user_id = $user_id AND used_at = NONE);")
+        .bind(("user_id", user_id))
+        .await?;
+    let count: Option<usize> = result.take(0)?;
+    Ok(count.unwrap_or(0))
+}
+
+/// Checks an authenticator or recovery code for an enabled factor, counting failures against
+/// the user's rate limit.
+#[cfg(feature = "ssr")]
+async fn check_second_factor(user: &AdapterUser, code: &str) -> Result<(), AppError> {
+    let key = user.id.to_string();
+    CODE_ATTEMPTS.check(&key)?;
+
+    let factor = TotpFactor::for_user(user.id.clone())
+        .await?
+        .filter(|factor| factor.enabled_at.is_some())
+        .ok_or_else(|| AppError::AuthError("Two-factor authentication is not enabled".into()))?;
+
+    let valid = factor.verify_code(&user.name, code).await?
+        || use_recovery_code(user.id.clone(), code).await?;
+    if !valid {
+        CODE_ATTEMPTS.record_failure(&key);
+        return Err(AppError::AuthError("That code is not valid".into()));
+    }
+    CODE_ATTEMPTS.reset(&key);
+    Ok(())
+}
+
+#[server]
+pub async fn get_totp_status() -> Result<TotpStatus, ServerFnError> {
+    let user = get_user().await?;
+    if !TotpFactor::enabled_for(user.id.clone()).await? {
+        return Ok(TotpStatus::default());
+    }
+    Ok(TotpStatus {
+        enabled: true,
+        recovery_codes_left: recovery_codes_left(user.id).await?,
+    })
+}
+
+#[server]
+pub async fn start_totp_enrollment() -> Result<TotpEnrollment, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    if TotpFactor::enabled_for(user.id.clone()).await? {
+        return Err(
+            AppError::ErrorReason("Two-factor authentication is already enabled".into()).into(),
+        );
+    }
+
+    let factor = TotpFactor::start(user.id).await?;
+    let totp = build_totp(&factor.secret, &user.name)?;
+    Ok(TotpEnrollment {
+        secret: factor.secret,
+        otpauth_url: totp.get_url(),
+    })
+}
+
+/// Turns two-factor authentication on once the user enters a code from their app, returning
+/// their recovery codes.
+#[server]
+pub async fn confirm_totp_enrollment(code: String) -> Result<Vec<String>, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    let key = user.id.to_string();
+    CODE_ATTEMPTS.check(&key)?;
+
+    let factor = TotpFactor::for_user(user.id.clone())
+        .await?
+        .filter(|factor| factor.enabled_at.is_none())
+        .ok_or_else(|| AppError::ErrorReason("Start two-factor setup first".into()))?;
+
+    if !factor.verify_code(&user.name, &code).await? {
+        CODE_ATTEMPTS.record_failure(&key);
+        return Err(AppError::AuthError("That code is not valid".into()).into());
+    }
+    CODE_ATTEMPTS.reset(&key);
+
+    factor.enable().await?;
+    Ok(replace_recovery_codes(user.id).await?)
+}
+
+#[server]
+pub async fn regenerate_recovery_codes(code: String) -> Result<Vec<String>, ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    check_second_factor(&user, &code).await?;
+    Ok(replace_recovery_codes(user.id).await?)
+}
+
+#[server]
+pub async fn disable_totp(code: String) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    forbid_impersonation().await?;
+    check_second_factor(&user, &code).await?;
+    TotpFactor::remove(user.id).await?;
+    Ok(())
+}
+
+/// Upgrades the pending session in the cookie after a valid authenticator or recovery code.
+#[server]
+pub async fn verify_second_factor(code: String) -> Result<(), ServerFnError> {
+    let cookie_jar = leptos_axum::extract::<axum_extra::extract::CookieJar>().await?;
+    let token = crate::auth::cookies::session_token_from(&cookie_jar)
+        .ok_or_else(|| ServerFnError::new("Not logged in."))?;
+
+    let session = AdapterSession::pending_mfa(token)
+        .await?
+        .ok_or_else(|| AppError::AuthError("Sign in again to continue".into()))?;
+
+    let user = AdapterUser::get_user(session.user_id.clone()).await?;
+    check_second_factor(&user, &code).await?;
+    session.complete_mfa().await?;
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_matching_step_rfc_6238() {
+    let secret = Secret::Raw(b"12345678901234567890".to_vec())
+        .to_encoded()
+        .to_string();
+    let totp = build_totp(&secret, "ada@example.com").unwrap();
+
+    // RFC 6238 appendix B gives 94287082 at T=59; six digits keep the last six
+    assert_eq!(totp.generate(59), "287082");
+    assert_eq!(matching_step(&totp, "287082", 59), Some(1));
+    assert_eq!(matching_step(&totp, "287 082", 89), Some(1));
+    assert_eq!(matching_step(&totp, "287082", 59 + 90), None);
+    assert_eq!(matching_step(&totp, "000000", 59), None);
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_recovery_codes() {
+    let codes = generate_recovery_codes();
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+    assert!(codes
+        .iter()
+        .all(|code| code.len() == 19 && code.matches('-').count() == 3));
+
+    let code = &codes[0];
+    assert_eq!(
+        hash_recovery_code(code),
+        hash_recovery_code(&code.replace('-', "").to_uppercase())
+    );
+    assert_ne!(hash_recovery_code(code), hash_recovery_code(&codes[1]));
+}
@@ -1,19 +1,41 @@
 use crate::components::{
     input::{FormField, Input, InputType},
-    Seperator,
+    Seperator, SubmitButton, TabButton, TabNavGroup,
 };
 
-use leptos::{prelude::*, reactive::spawn_local};
+use leptos::prelude::*;
+use leptos_router::hooks::use_query_map;
 
-use serde::{Deserialize, Serialize};
+use crate::auth::credentials::{LoginPassword, RegisterPassword, PASSWORD_MIN_LEN};
+use crate::auth::magic_link::RequestMagicLink;
+use crate::auth::oidc::get_oidc_providers;
+use crate::auth::passkeys::login_with_passkey;
 
-use crate::auth::user::AdapterUser;
-
-#[cfg(feature = "ssr")]
-use crate::auth::user::CreateUserData;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoginMethod {
+    EmailLink,
+    Password,
+    Passkey,
+}
 
 #[component]
 pub fn LoginForm() -> impl IntoView {
+    let (method, set_method) = signal(LoginMethod::EmailLink);
+
+    // `/auth/verify` and the OIDC callback redirect back here when sign-in fails
+    let query = use_query_map();
+    let link_error = move || {
+        query
+            .with(|q| q.get("error"))
+            .map(|error| match error.as_str() {
+                "oidc_failed" => {
+                    "Signing in with that provider did not work. Try again or use another method."
+                }
+                "impersonating" => "Stop impersonating before linking a sign-in provider.",
+                _ => "That sign-in link is invalid or has expired. Request a new one below.",
+            })
+    };
+
     view! {
         <div class="">
 
@@ -24,7 +46,292 @@ pub fn LoginForm() -> impl IntoView {
                 </div>
 
                 <Seperator />
+
+                {move || link_error().map(|msg| {
+                    view! { <p class="mt-4 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+                })}
+
+                <TabNavGroup>
+                    <TabButton
+                        active=move || method.get() == LoginMethod::EmailLink
+                        on:click=move |_| set_method.set(LoginMethod::EmailLink)
+                    >
+                        "Email link"
+                    </TabButton>
+                    <TabButton
+                        active=move || method.get() == LoginMethod::Password
+                        on:click=move |_| set_method.set(LoginMethod::Password)
+                    >
+                        "Password"
+                    </TabButton>
+                    <TabButton
+                        active=move || method.get() == LoginMethod::Passkey
+                        on:click=move |_| set_method.set(LoginMethod::Passkey)
+                    >
+                        "Passkey"
+                    </TabButton>
+                </TabNavGroup>
+
+                {move || match method.get() {
+                    LoginMethod::EmailLink => view! { <MagicLinkForm /> }.into_any(),
+                    LoginMethod::Password => view! { <PasswordForm /> }.into_any(),
+                    LoginMethod::Passkey => view! { <PasskeyForm /> }.into_any(),
+                }}
+
+                <ProviderButtons />
             </div>
         </div>
     }
 }
+
+/// Full page load so the navbar and everything else pick up the new session cookie.
+pub(crate) fn reload_to(path: &str) {
+    #[cfg(not(feature = "ssr"))]
+    if let Some(window) = web_sys::window() {
+        let _ = window.location().set_href(path);
+    }
+    #[cfg(feature = "ssr")]
+    let _ = path;
+}
+
+/// One link per configured OIDC provider. Renders nothing when none are set up.
+#[component]
+fn ProviderButtons() -> impl IntoView {
+    let providers = Resource::new(|| (), |_| get_oidc_providers());
+
+    view! {
+        <Suspense>
+            {move || {
+                let providers = providers.get().and_then(|r| r.ok()).unwrap_or_default();
+                (!providers.is_empty()).then(|| {
+                    view! {
+                        <div class="mt-6 flex flex-col gap-2">
+                            <Seperator />
+                            {providers
+                                .into_iter()
+                                .map(|provider| {
+                                    view! {
+                                        <a
+                                            href=provider.login_url()
+                                            rel="external"
+                                            class="w-full px-4 py-2 text-center text-sm font-medium border border-neutral-300 dark:border-neutral-600 rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                                        >
+                                            {format!("Continue with {}", provider.name)}
+                                        </a>
+                                    }
+                                })
+                                .collect_view()}
+                        </div>
+                    }
+                })
+            }}
+        </Suspense>
+    }
+}
+
+#[component]
+fn MagicLinkForm() -> impl IntoView {
+    let email = RwSignal::new(String::new());
+    let request_link = ServerAction::<RequestMagicLink>::new();
+    let (submitting, set_submitting) = signal(false);
+
+    Effect::new(move |_| {
+        set_submitting.set(request_link.pending().get());
+    });
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        request_link.dispatch(RequestMagicLink { email: email.get() });
+    };
+
+    view! {
+        {move || match request_link.value().get() {
+            Some(Ok(())) => {
+                view! {
+                    <div class="mt-6 text-center">
+                        <h2 class="text-lg font-semibold text-neutral-800 dark:text-neutral-100">"Check your email"</h2>
+                        <p class="mt-2 text-sm text-neutral-600 dark:text-neutral-400">
+                            "We sent a sign-in link to " <span class="font-medium">{email.get_untracked()}</span> "."
+                        </p>
+                    </div>
+                }
+                    .into_any()
+            }
+            result => {
+                let error = result.and_then(|r| r.err()).map(|e| e.to_string());
+                view! {
+                    <form on:submit=submit class="mt-6 flex flex-col gap-4">
+                        <FormField label="Email" label_for="login-email">
+                            <Input
+                                id="login-email"
+                                name="email"
+                                r#type=InputType::Email
+                                placeholder="you@example.com"
+                                value=email
+                                required=true
+                                autocomplete="email"
+                            />
+                        </FormField>
+                        {error.map(|msg| {
+                            view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> }
+                        })}
+                        <SubmitButton text="Email me a sign-in link" is_submitting=submitting />
+                    </form>
+                }
+                    .into_any()
+            }
+        }}
+    }
+}
+
+#[component]
+fn PasswordForm() -> impl IntoView {
+    let (registering, set_registering) = signal(false);
+    let name = RwSignal::new(String::new());
+    let email = RwSignal::new(String::new());
+    let password = RwSignal::new(String::new());
+
+    let login = ServerAction::<LoginPassword>::new();
+    let register = ServerAction::<RegisterPassword>::new();
+    let (submitting, set_submitting) = signal(false);
+
+    Effect::new(move |_| {
+        set_submitting.set(login.pending().get() || register.pending().get());
+    });
+
+    Effect::new(move |_| {
+        for result in [login.value().get(), register.value().get()] {
+            if let Some(Ok(path)) = result {
+                reload_to(&path);
+            }
+        }
+    });
+
+    let error = move || {
+        let result = if registering.get() {
+            register.value().get()
+        } else {
+            login.value().get()
+        };
+        result.and_then(|r| r.err()).map(|e| e.to_string())
+    };
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        if registering.get() {
+            register.dispatch(RegisterPassword {
+                name: name.get(),
+                email: email.get(),
+                password: password.get(),
+            });
+        } else {
+            login.dispatch(LoginPassword {
+                email: email.get(),
+                password: password.get(),
+            });
+        }
+    };
+
+    view! {
+        <form on:submit=submit class="mt-6 flex flex-col gap-4">
+            {move || registering.get().then(|| {
+                view! {
+                    <FormField label="Name" label_for="login-name">
+                        <Input id="login-name" name="name" value=name required=true autocomplete="name" />
+                    </FormField>
+                }
+            })}
+            <FormField label="Email" label_for="login-password-email">
+                <Input
+                    id="login-password-email"
+                    name="email"
+                    r#type=InputType::Email
+                    placeholder="you@example.com"
+                    value=email
+                    required=true
+                    autocomplete="email"
+                />
+            </FormField>
+            {move || {
+                let help = if registering.get() {
+                    format!("At least {} characters, mixing letters with numbers or symbols.", PASSWORD_MIN_LEN)
+                } else {
+                    String::new()
+                };
+                let autocomplete = if registering.get() { "new-password" } else { "current-password" };
+                view! {
+                    <FormField label="Password" label_for="login-password" help_text=help>
+                        <Input
+                            id="login-password"
+                            name="password"
+                            r#type=InputType::Password
+                            value=password
+                            required=true
+                            autocomplete=autocomplete
+                        />
+                    </FormField>
+                }
+            }}
+            {move || error().map(|msg| {
+                view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+            {move || {
+                let text = if registering.get() { "Create account" } else { "Sign in" };
+                view! { <SubmitButton text=text is_submitting=submitting /> }
+            }}
+            <button
+                type="button"
+                class="text-sm text-blue-600 dark:text-blue-400 hover:underline"
+                on:click=move |_| set_registering.update(|r| *r = !*r)
+            >
+                {move || if registering.get() {
+                    "Already have an account? Sign in"
+                } else {
+                    "New here? Create an account"
+                }}
+            </button>
+        </form>
+    }
+}
+
+#[component]
+fn PasskeyForm() -> impl IntoView {
+    let email = RwSignal::new(String::new());
+    let (submitting, set_submitting) = signal(false);
+    let (error, set_error) = signal(Option::<String>::None);
+
+    // The browser prompt future is not Send, so it runs on the local task queue
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        set_submitting.set(true);
+        set_error.set(None);
+        let email = email.get_untracked();
+        leptos::task::spawn_local(async move {
+            match login_with_passkey(email).await {
+                Ok(()) => reload_to("/"),
+                Err(e) => set_error.set(Some(e)),
+            }
+            set_submitting.set(false);
+        });
+    };
+
+    view! {
+        <form on:submit=submit class="mt-6 flex flex-col gap-4">
+            <FormField label="Email" label_for="login-passkey-email">
+                <Input
+                    id="login-passkey-email"
+                    name="email"
+                    r#type=InputType::Email
+                    placeholder="you@example.com"
+                    value=email
+                    required=true
+                    autocomplete="email webauthn"
+                />
+            </FormField>
+            {move || error.get().map(|msg| {
+                view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+            <SubmitButton text="Sign in with passkey" is_submitting=submitting />
+        </form>
+    }
+}
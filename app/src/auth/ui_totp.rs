@@ -0,0 +1,251 @@
+use leptos::prelude::*;
+
+use crate::auth::totp::{
+    get_totp_status, ConfirmTotpEnrollment, DisableTotp, RegenerateRecoveryCodes,
+    StartTotpEnrollment, TotpEnrollment, VerifySecondFactor,
+};
+use crate::auth::ui_auth::reload_to;
+use crate::components::{
+    input::{FormField, Input},
+    qrcode::QRCode,
+    Seperator, SubmitButton,
+};
+
+const INPUT_CLASS: &str = "w-full px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-700 text-neutral-900 dark:text-white rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500";
+const PRIMARY_BUTTON_CLASS: &str = "px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed";
+
+/// Recovery codes, shown once right after they are generated.
+#[component]
+fn RecoveryCodes(codes: Vec<String>, on_done: Callback<()>) -> impl IntoView {
+    view! {
+        <div class="mt-4 p-4 rounded-md bg-neutral-100 dark:bg-neutral-700">
+            <p class="text-sm font-medium mb-2">
+                "Save these recovery codes somewhere safe. Each one signs you in once if you lose your authenticator, and they will not be shown again."
+            </p>
+            <ul class="grid grid-cols-2 gap-1 font-mono text-sm">
+                {codes.into_iter().map(|code| view! { <li>{code}</li> }).collect_view()}
+            </ul>
+            <button type="button" class=format!("{} mt-4", PRIMARY_BUTTON_CLASS) on:click=move |_| on_done.run(())>
+                "I have saved my codes"
+            </button>
+        </div>
+    }
+}
+
+/// Two-factor setup and removal, shown in settings.
+#[component]
+pub fn TotpControl() -> impl IntoView {
+    let status = Resource::new(|| (), |_| get_totp_status());
+    let start = ServerAction::<StartTotpEnrollment>::new();
+    let confirm = ServerAction::<ConfirmTotpEnrollment>::new();
+    let disable = ServerAction::<DisableTotp>::new();
+    let regenerate = ServerAction::<RegenerateRecoveryCodes>::new();
+
+    let (enrollment, set_enrollment) = signal(Option::<TotpEnrollment>::None);
+    let (recovery_codes, set_recovery_codes) = signal(Option::<Vec<String>>::None);
+    let (code, set_code) = signal(String::new());
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    Effect::new(move |_| match start.value().get() {
+        Some(Ok(started)) => set_enrollment.set(Some(started)),
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    Effect::new(move |_| {
+        for result in [confirm.value().get(), regenerate.value().get()] {
+            match result {
+                Some(Ok(codes)) => {
+                    set_enrollment.set(None);
+                    set_code.set(String::new());
+                    set_error_message.set(None);
+                    set_recovery_codes.set(Some(codes));
+                    status.refetch();
+                }
+                Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+                None => {}
+            }
+        }
+    });
+
+    Effect::new(move |_| match disable.value().get() {
+        Some(Ok(())) => {
+            set_code.set(String::new());
+            set_error_message.set(None);
+            status.refetch();
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    let code_input = move || {
+        view! {
+            <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                class=INPUT_CLASS
+                placeholder="123456"
+                prop:value=move || code.get()
+                on:input=move |e| set_code.set(event_target_value(&e))
+            />
+        }
+    };
+
+    let confirm_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        confirm.dispatch(ConfirmTotpEnrollment { code: code.get() });
+    };
+    let disable_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        disable.dispatch(DisableTotp { code: code.get() });
+    };
+    let regenerate_click = move |_| {
+        regenerate.dispatch(RegenerateRecoveryCodes { code: code.get() });
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-4">"Two-factor authentication"</h2>
+            {move || match recovery_codes.get() {
+                Some(codes) => {
+                    view! {
+                        <RecoveryCodes codes=codes on_done=Callback::new(move |_| set_recovery_codes.set(None)) />
+                    }
+                        .into_any()
+                }
+                None => {
+                    view! {
+                        <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                            {move || match (status.get(), enrollment.get()) {
+                                (_, Some(setup)) => {
+                                    view! {
+                                        <p class="text-neutral-600 dark:text-neutral-400 mb-4">
+                                            "Scan this code with your authenticator app, then enter the code it shows."
+                                        </p>
+                                        <QRCode input=setup.otpauth_url.clone() />
+                                        <p class="mt-2 text-xs text-neutral-500 dark:text-neutral-400">
+                                            "Or enter this key by hand: " <span class="font-mono">{setup.secret.clone()}</span>
+                                        </p>
+                                        <form on:submit=confirm_submit class="flex gap-2 mt-4">
+                                            {code_input}
+                                            <button type="submit" class=PRIMARY_BUTTON_CLASS disabled=move || confirm.pending().get()>
+                                                "Turn on"
+                                            </button>
+                                        </form>
+                                    }
+                                        .into_any()
+                                }
+                                (Some(Ok(current)), None) if current.enabled => {
+                                    view! {
+                                        <p class="text-neutral-600 dark:text-neutral-400 mb-4">
+                                            "Two-factor authentication is on. "
+                                            {format!("{} recovery codes left.", current.recovery_codes_left)}
+                                        </p>
+                                        <form on:submit=disable_submit class="flex gap-2">
+                                            {code_input}
+                                            <button
+                                                type="button"
+                                                class="px-4 py-2 bg-neutral-200 dark:bg-neutral-700 rounded-md hover:bg-neutral-300 dark:hover:bg-neutral-600"
+                                                on:click=regenerate_click
+                                            >
+                                                "New recovery codes"
+                                            </button>
+                                            <button
+                                                type="submit"
+                                                class="px-4 py-2 bg-red-600 text-white rounded-md hover:bg-red-700 disabled:opacity-50"
+                                                disabled=move || disable.pending().get()
+                                            >
+                                                "Turn off"
+                                            </button>
+                                        </form>
+                                    }
+                                        .into_any()
+                                }
+                                (Some(Ok(_)), None) => {
+                                    view! {
+                                        <p class="text-neutral-600 dark:text-neutral-400 mb-4">
+                                            "Ask for a code from an authenticator app when you sign in."
+                                        </p>
+                                        <button
+                                            class=PRIMARY_BUTTON_CLASS
+                                            disabled=move || start.pending().get()
+                                            on:click=move |_| {
+                                                start.dispatch(StartTotpEnrollment {});
+                                            }
+                                        >
+                                            "Set up"
+                                        </button>
+                                    }
+                                        .into_any()
+                                }
+                                (Some(Err(e)), None) => {
+                                    view! { <p class="text-red-600 dark:text-red-400">{e.to_string()}</p> }.into_any()
+                                }
+                                (None, None) => view! { <div></div> }.into_any(),
+                            }}
+                        </Suspense>
+                    }
+                        .into_any()
+                }
+            }}
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+/// Second sign-in step for users with two-factor authentication.
+#[component]
+pub fn SecondFactorForm() -> impl IntoView {
+    let code = RwSignal::new(String::new());
+    let verify = ServerAction::<VerifySecondFactor>::new();
+    let (submitting, set_submitting) = signal(false);
+
+    Effect::new(move |_| {
+        set_submitting.set(verify.pending().get());
+    });
+
+    Effect::new(move |_| {
+        if matches!(verify.value().get(), Some(Ok(()))) {
+            reload_to("/");
+        }
+    });
+
+    let error = move || {
+        verify
+            .value()
+            .get()
+            .and_then(|r| r.err())
+            .map(|e| e.to_string())
+    };
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        verify.dispatch(VerifySecondFactor { code: code.get() });
+    };
+
+    view! {
+        <div class="bg-white dark:bg-black rounded-lg shadow-2xl p-8 mx-8 w-full max-w-md mx-auto">
+            <div class="text-center mb-8">
+                <h1 class="text-3xl font-bold text-neutral-800 dark:text-neutral-100 mb-2">"Two-factor authentication"</h1>
+                <small class="text-neutral-500 dark:text-neutral-400">
+                    "Enter the code from your authenticator app, or one of your recovery codes."
+                </small>
+            </div>
+
+            <Seperator />
+
+            <form on:submit=submit class="mt-6 flex flex-col gap-4">
+                <FormField label="Code" label_for="login-2fa-code">
+                    <Input id="login-2fa-code" name="code" value=code required=true autocomplete="one-time-code" />
+                </FormField>
+                {move || error().map(|msg| {
+                    view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> }
+                })}
+                <SubmitButton text="Verify" is_submitting=submitting />
+            </form>
+        </div>
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "ssr")]
 use crate::auth::{
-    session::{AdapterSession, CreateSessionData},
+    session::{AdapterSession, CreateSessionData, SESSION_TTL_DAYS},
     token::{CreateVerificationToken, VerificationToken},
     // wallet::Wallet,
 };
@@ -22,12 +22,12 @@ use crate::theme::Theme;
 #[partial(
     "CreateUserData",
     derive(Serialize, Deserialize, Clone),
-    omit(id, superadmin)
+    omit(id, superadmin, disabled)
 )]
 #[partial(
     "UpdateUserData",
     derive(Debug, Serialize, Deserialize, Clone),
-    omit(superadmin)
+    omit(superadmin, disabled)
 )]
 pub struct AdapterUser {
     pub id: RecordId,
@@ -36,11 +36,18 @@ pub struct AdapterUser {
     pub superadmin: Option<bool>,
     #[serde(default)]
     pub theme: Theme,
+    /// Set by a superadmin. Disabled users can't sign in, and their sessions and API keys stop
+    /// working.
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 #[cfg(feature = "ssr")]
 use crate::db::db_init;
 
+/// Shown as the author of chat messages whose account was deleted.
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
 #[cfg(feature = "ssr")]
 impl AdapterUser {
     pub async fn create_user(user_data: CreateUserData) -> Result<Self, AppError> {
@@ -87,15 +94,17 @@ impl AdapterUser {
     }
 
     pub async fn get_user_by_account(
-        provider_account_id: RecordId,
+        provider: &str,
+        provider_account_id: &str,
     ) -> Result<Option<AdapterUser>, AppError> {
         let client = db_init().await?;
 
         let mut result = client
             .query(
-                "SELECT * FROM ONLY account WHERE providerAccountId = $providerAccountId LIMIT 1;",
+                "(SELECT user_id FROM ONLY account WHERE provider = $provider AND provider_account_id = $provider_account_id LIMIT 1 FETCH user_id).user_id;",
             )
-            .bind(("providerAccountId", provider_account_id))
+            .bind(("provider", provider.to_string()))
+            .bind(("provider_account_id", provider_account_id.to_string()))
             .await?;
 
         let user: Option<Self> = result.take(0)?;
@@ -117,18 +126,18 @@ impl AdapterUser {
         let client = db_seperate_connection().await?;
 
         let mut result = client
-            .query("(SELECT user_id from ONLY session where session_token = $session_token LIMIT 1 FETCH user_id).user_id;")
+            .query("(SELECT user_id from ONLY session where session_token = $session_token AND mfa_pending != true AND expires > time::now() LIMIT 1 FETCH user_id).user_id;")
             .bind(("session_token", session_token))
             .await?;
 
         let user: Option<Self> = result.take(0)?;
 
-        if let Some(user) = user {
-            Ok(user)
-        } else {
-            Err(AppError::AuthError(
+        match user {
+            Some(user) if !user.disabled => Ok(user),
+            Some(_) => Err(AppError::AuthError("Account disabled".into())),
+            None => Err(AppError::AuthError(
                 "User not found for session_token".into(),
-            ))
+            )),
         }
     }
 
@@ -189,23 +198,171 @@ impl AdapterUser {
         Ok(user)
     }
 
+    /// Deletes the user with everything that is only theirs: sign-in methods, sessions,
+    /// personal keys, direct messages and organizations nobody else is in. Keys they added to
+    /// shared organizations pass to an admin there. Their room messages stay for the other
+    /// participants but no longer name them. Fails while the user is the last admin of an
+    /// organization that has other members.
     pub async fn delete_user(&self) -> Result<(), AppError> {
+        use crate::auth::permissions::Role;
+        use crate::organization::{
+            invites::invite_identifier_prefix,
+            models::{Membership, Organization},
+        };
+
+        let mut sole_organizations = Vec::new();
+        for (organization, role) in Organization::for_user(self).await? {
+            let members = Membership::members(organization.id.clone()).await?;
+            let admins = members.iter().filter(|m| m.role >= Role::Admin).count();
+            if members.len() <= 1 {
+                sole_organizations.push(organization.id);
+            } else if role >= Role::Admin && admins <= 1 {
+                return Err(AppError::ErrorReason(format!(
+                    "Make another member an admin of {} before deleting the account",
+                    organization.name
+                )));
+            }
+        }
+        let invite_prefixes: Vec<String> = sole_organizations
+            .iter()
+            .map(invite_identifier_prefix)
+            .collect();
+
         let client = db_init().await?;
-        let _: Option<AdapterUser> = client.delete(&self.id).await?;
-        // delete all related data?
+        client
+            .query(
+                r#"
+                BEGIN TRANSACTION;
+                LET $emails = (SELECT VALUE provider_account_id FROM account WHERE user_id = $user_id);
+                LET $rooms = (SELECT VALUE id FROM chat_room WHERE organization_id IN $organizations);
+                LET $conversations = (SELECT VALUE id FROM dm_conversation WHERE participants CONTAINS $user_id);
+                DELETE chat_event WHERE room_id IN $rooms OR room_id IN $conversations;
+                DELETE chat_room_member WHERE room_id IN $rooms OR user_id = $user_id;
+                DELETE chat_read_marker WHERE room_id IN $rooms OR room_id IN $conversations OR user_id = $user_id;
+                DELETE chat_room WHERE id IN $rooms;
+                DELETE dm_conversation WHERE id IN $conversations;
+                FOR $prefix IN $invite_prefixes {
+                    DELETE verificationToken WHERE string::starts_with(identifier, $prefix);
+                };
+                DELETE key WHERE organization_id IN $organizations OR (created_by_user_id = $user_id AND organization_id = NONE);
+                FOR $key IN (SELECT id, organization_id FROM key WHERE created_by_user_id = $user_id) {
+                    LET $admin = (SELECT VALUE user_id FROM membership WHERE organization_id = $key.organization_id AND role IN ['admin', 'superadmin'] AND user_id != $user_id LIMIT 1)[0];
+                    IF $admin = NONE {
+                        DELETE $key.id;
+                    } ELSE {
+                        UPDATE $key.id SET created_by_user_id = $admin, iroh_identity = false;
+                    };
+                };
+                DELETE membership WHERE organization_id IN $organizations OR user_id = $user_id;
+                DELETE organization WHERE id IN $organizations;
+                DELETE verificationToken WHERE identifier IN $emails;
+                DELETE session WHERE user_id = $user_id OR impersonator_id = $user_id;
+                DELETE account WHERE user_id = $user_id;
+                DELETE webauthn_credential WHERE user_id = $user_id;
+                DELETE webauthn_ceremony WHERE user_id = $user_id;
+                DELETE totp_factor WHERE user_id = $user_id;
+                DELETE recovery_code WHERE user_id = $user_id;
+                UPDATE chat_event SET user_id = NONE, username = $deleted_name WHERE user_id = $user_id;
+                UPDATE chat_room SET created_by_user_id = NONE WHERE created_by_user_id = $user_id;
+                DELETE $user_id;
+                COMMIT TRANSACTION;
+                "#,
+            )
+            .bind(("user_id", self.id.clone()))
+            .bind(("organizations", sole_organizations))
+            .bind(("invite_prefixes", invite_prefixes))
+            .bind(("deleted_name", DELETED_USER_NAME))
+            .await?
+            .check()?;
         Ok(())
     }
 
+    /// Starts a session after a first factor. Users with two-factor authentication get a
+    /// short-lived pending session until they enter a code.
     pub async fn new_session(&self) -> Result<AdapterSession, AppError> {
+        use crate::auth::totp::{TotpFactor, MFA_PENDING_TTL_MINUTES};
+
+        self.check_enabled()?;
+        if TotpFactor::enabled_for(self.id.clone()).await? {
+            let session_data = CreateSessionData {
+                user_id: self.id.clone(),
+                session_token: uuid::Uuid::new_v4().to_string(),
+                expires: Datetime::from(
+                    Utc::now() + chrono::Duration::minutes(MFA_PENDING_TTL_MINUTES),
+                ),
+                mfa_pending: true,
+                user_agent: None,
+                ip: None,
+                created_at: Some(Datetime::from(Utc::now())),
+                last_seen_at: None,
+                organization_id: None,
+                impersonator_id: None,
+            };
+            return AdapterSession::create_session(session_data).await;
+        }
+        self.new_verified_session().await
+    }
+
+    /// Starts a full session, for sign-ins that already verified the user, such as passkeys.
+    pub async fn new_verified_session(&self) -> Result<AdapterSession, AppError> {
+        self.check_enabled()?;
         let session_data = CreateSessionData {
             user_id: self.id.clone(),
             session_token: uuid::Uuid::new_v4().to_string(),
-            expires: Datetime::from(Utc::now() + chrono::Duration::days(365)),
+            expires: Datetime::from(Utc::now() + chrono::Duration::days(SESSION_TTL_DAYS)),
+            mfa_pending: false,
+            user_agent: None,
+            ip: None,
+            created_at: Some(Datetime::from(Utc::now())),
+            last_seen_at: None,
+            organization_id: None,
+            impersonator_id: None,
         };
 
         AdapterSession::create_session(session_data).await
     }
 
+    /// Fails with [`AppError::Forbidden`] if a superadmin disabled the user.
+    pub fn check_enabled(&self) -> Result<(), AppError> {
+        if self.disabled {
+            Err(AppError::Forbidden("This account has been disabled".into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Disables or re-enables the user. Disabling also signs them out everywhere.
+    pub async fn set_disabled(&self, disabled: bool) -> Result<Self, AppError> {
+        let client = db_init().await?;
+
+        let mut query = client
+            .query(
+                "UPDATE $userid SET disabled = $disabled RETURN AFTER;
+                 IF $disabled { DELETE session WHERE user_id = $userid };",
+            )
+            .bind(("userid", self.id.clone()))
+            .bind(("disabled", disabled))
+            .await?;
+
+        let user: Option<Self> = query.take(0)?;
+        let user = user.ok_or_else(|| AppError::AuthError("User not found".into()))?;
+        Ok(user)
+    }
+
+    pub async fn set_superadmin(&self, superadmin: bool) -> Result<Self, AppError> {
+        let client = db_init().await?;
+
+        let mut query = client
+            .query("UPDATE $userid SET superadmin = $superadmin RETURN AFTER;")
+            .bind(("userid", self.id.clone()))
+            .bind(("superadmin", superadmin))
+            .await?;
+
+        let user: Option<Self> = query.take(0)?;
+        let user = user.ok_or_else(|| AppError::AuthError("User not found".into()))?;
+        Ok(user)
+    }
+
     pub async fn get_all_users() -> Result<Vec<Self>, AppError> {
         let client = db_init().await?;
         let users: Vec<Self> = client.select("user").await?;
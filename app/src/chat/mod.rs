@@ -1,6 +1,13 @@
 pub mod models;
+pub mod presence;
+pub mod receipts;
+pub mod search;
 pub mod shared;
 pub mod ui_chat;
+pub mod ui_message;
+pub mod ui_presence;
+pub mod ui_rooms;
+pub mod ui_search;
 
 #[cfg(feature = "ssr")]
 pub mod websocket;
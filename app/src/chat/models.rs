@@ -1,11 +1,12 @@
 use crate::auth::{session::get_user, user::AdapterUser};
 
 #[cfg(feature = "ssr")]
-use crate::{db_init, AppError};
+use crate::{auth::permissions::Role, db_init, organization::models::Membership, AppError};
 
 #[cfg(feature = "ssr")]
 use chrono::Utc;
 
+use crate::chat::shared::{ChatMessage, WsMessage};
 use crate::{Datetime, RecordId};
 use leptos::prelude::*;
 use partial_struct::Partial;
@@ -16,57 +17,774 @@ pub enum ChatEventType {
     Message,
     UserJoined,
     UserLeft,
+    /// `message` holds the new text of the `target_id` message.
+    MessageEdited,
+    MessageDeleted,
+    /// `message` holds the emoji. Reacting twice with the same emoji removes the reaction.
+    Reaction,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Partial)]
-#[partial(
-    "CreateChatEvent",
-    derive(Serialize, Deserialize, Clone),
-    omit(id, username)
-)]
+#[partial("CreateChatEvent", derive(Serialize, Deserialize, Clone), omit(id))]
 pub struct ChatEventDb {
     pub id: RecordId,
     pub user_id: Option<RecordId>,
     pub username: String,
+    /// Room the event belongs to. Events written before rooms existed have no room and are
+    /// shown in the default room.
+    #[serde(default)]
+    pub room_id: Option<RecordId>,
     pub event_type: ChatEventType,
     pub message: Option<String>,
     pub timestamp: Datetime,
+    /// The `chat_event` an edit, delete or reaction applies to.
+    #[serde(default)]
+    pub target_id: Option<RecordId>,
+}
+
+impl ChatEventDb {
+    pub fn into_ws_message(self) -> WsMessage {
+        let room_id = self
+            .room_id
+            .unwrap_or_else(crate::chat::shared::default_room_id);
+        let event_id = self.target_id.unwrap_or_else(|| self.id.clone());
+
+        match self.event_type {
+            ChatEventType::Message => WsMessage::Message(ChatMessage {
+                id: Some(self.id),
+                user_id: self
+                    .user_id
+                    .unwrap_or_else(|| RecordId::from(("user", "anonymous"))),
+                username: self.username,
+                room_id,
+                message: self.message.unwrap_or_default(),
+                timestamp: self.timestamp.to_string(),
+                edited: false,
+                reactions: Vec::new(),
+            }),
+            ChatEventType::UserJoined => WsMessage::UserJoined {
+                username: self.username,
+                room_id,
+            },
+            ChatEventType::UserLeft => WsMessage::UserLeft {
+                username: self.username,
+                room_id,
+            },
+            ChatEventType::MessageEdited => WsMessage::MessageEdited {
+                event_id,
+                room_id,
+                message: self.message.unwrap_or_default(),
+            },
+            ChatEventType::MessageDeleted => WsMessage::MessageDeleted { event_id, room_id },
+            ChatEventType::Reaction => WsMessage::Reaction {
+                event_id,
+                room_id,
+                user_id: self
+                    .user_id
+                    .unwrap_or_else(|| RecordId::from(("user", "anonymous"))),
+                emoji: self.message.unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Turns a chronological list of events into the messages to display, with edits,
+    /// deletes and reactions applied to the messages they target.
+    pub fn fold_history(events: Vec<Self>) -> Vec<WsMessage> {
+        let mut msgs = Vec::new();
+        for event in events {
+            let msg = event.into_ws_message();
+            if !msg.apply_update(&mut msgs) {
+                msgs.push(msg);
+            }
+        }
+        msgs
+    }
+}
+
+/// One page of chat history, oldest message first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatHistoryPage {
+    /// Messages of the page followed by the edits, deletes and reactions that apply to them.
+    pub events: Vec<ChatEventDb>,
+    /// Whether older messages exist before this page. For a page loaded with `since`, whether
+    /// more events followed than fit in it.
+    pub has_more: bool,
+    /// Pass as `before` to load the page preceding this one.
+    pub next_before: Option<RecordId>,
+}
+
+pub const CHAT_HISTORY_PAGE_SIZE: usize = 50;
+pub const CHAT_HISTORY_MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Partial)]
+#[partial("CreateChatRoom", derive(Serialize, Deserialize, Clone), omit(id))]
+pub struct ChatRoom {
+    pub id: RecordId,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by_user_id: Option<RecordId>,
+    pub created_at: Datetime,
+    /// Rooms of an organization are only open to its members. Rooms without one are public.
+    #[serde(default)]
+    pub organization_id: Option<RecordId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Partial)]
+#[partial(
+    "CreateChatRoomMember",
+    derive(Serialize, Deserialize, Clone),
+    omit(id)
+)]
+pub struct ChatRoomMember {
+    pub id: RecordId,
+    pub room_id: RecordId,
+    pub user_id: RecordId,
+    pub joined_at: Datetime,
+}
+
+pub const CHAT_ROOM_NAME_MAX_LEN: usize = 64;
+
+#[cfg(feature = "ssr")]
+impl ChatRoom {
+    pub async fn get(id: RecordId) -> Result<Self, AppError> {
+        if id.table() != "chat_room" {
+            return Err(AppError::NotFound("Invalid chat room ID".into()));
+        }
+
+        let db = db_init().await?;
+        let room: Option<Self> = db.select(id).await?;
+        room.ok_or_else(|| AppError::NotFound("Chat room not found".into()))
+    }
+
+    pub async fn get_all() -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM chat_room ORDER BY created_at ASC;")
+            .await?;
+        let rooms: Vec<Self> = result.take(0)?;
+        Ok(rooms)
+    }
+
+    /// Public rooms plus those of the organization the user is working in.
+    pub async fn listed_for(organization_id: Option<RecordId>) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM chat_room WHERE organization_id IS NONE OR organization_id = $organization_id ORDER BY created_at ASC;")
+            .bind(("organization_id", organization_id))
+            .await?;
+        let rooms: Vec<Self> = result.take(0)?;
+        Ok(rooms)
+    }
+
+    /// Every room `user` may read: public rooms and those of all their organizations.
+    pub async fn visible_to(user: &AdapterUser) -> Result<Vec<Self>, AppError> {
+        if user.is_super_admin().unwrap_or(false) {
+            return Self::get_all().await;
+        }
+
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM chat_room WHERE organization_id IS NONE OR organization_id IN (SELECT VALUE organization_id FROM membership WHERE user_id = $user_id) ORDER BY created_at ASC;")
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let rooms: Vec<Self> = result.take(0)?;
+        Ok(rooms)
+    }
+
+    /// Fails with [`AppError::Forbidden`] if the room belongs to an organization `user` is not
+    /// a member of. Anonymous users only get into public rooms.
+    pub async fn check_access(&self, user: Option<&AdapterUser>) -> Result<(), AppError> {
+        let Some(organization_id) = &self.organization_id else {
+            return Ok(());
+        };
+        let Some(user) = user else {
+            return Err(AppError::Forbidden("Sign in to join this room".into()));
+        };
+        if user.is_super_admin().unwrap_or(false) {
+            return Ok(());
+        }
+        Membership::require(organization_id.clone(), user, Role::Member).await?;
+        Ok(())
+    }
+
+    /// Like [`ChatRoom::check_access`] for a room id. Fails with [`AppError::NotFound`] for
+    /// rooms that were never stored, except the default room before the schema seeded it.
+    pub async fn check_access_to(room_id: RecordId, user: &AdapterUser) -> Result<(), AppError> {
+        match Self::get(room_id.clone()).await {
+            Ok(room) => room.check_access(Some(user)).await,
+            Err(AppError::NotFound(_)) if room_id == crate::chat::shared::default_room_id() => {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn create(
+        user: &AdapterUser,
+        name: String,
+        description: Option<String>,
+        organization_id: Option<RecordId>,
+    ) -> Result<Self, AppError> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::ErrorReason("Room name is required".into()));
+        }
+        if name.chars().count() > CHAT_ROOM_NAME_MAX_LEN {
+            return Err(AppError::ErrorReason(format!(
+                "Room name must be at most {} characters",
+                CHAT_ROOM_NAME_MAX_LEN
+            )));
+        }
+
+        let db = db_init().await?;
+        let content = CreateChatRoom {
+            name,
+            description: description.filter(|d| !d.trim().is_empty()),
+            created_by_user_id: Some(user.id.clone()),
+            created_at: Datetime::from(Utc::now()),
+            organization_id,
+        };
+
+        let created: Option<Self> = db.create("chat_room").content(content).await.map_err(|e| {
+            // Names are unique per organization; say so without naming anything in others
+            if e.to_string().contains("chat_room_org_name_index") {
+                AppError::ErrorReason("A room with this name already exists".into())
+            } else {
+                e.into()
+            }
+        })?;
+        let created = created.ok_or_else(|| AppError::new("Failed to create chat room"))?;
+
+        created.add_member(user).await?;
+        Ok(created)
+    }
+
+    pub async fn add_member(&self, user: &AdapterUser) -> Result<(), AppError> {
+        let db = db_init().await?;
+        let member = CreateChatRoomMember {
+            room_id: self.id.clone(),
+            user_id: user.id.clone(),
+            joined_at: Datetime::from(Utc::now()),
+        };
+
+        // The unique (room_id, user_id) index makes re-joining a no-op.
+        db.query("INSERT IGNORE INTO chat_room_member $member;")
+            .bind(("member", member))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_member(&self, user: &AdapterUser) -> Result<(), AppError> {
+        let db = db_init().await?;
+        db.query("DELETE chat_room_member WHERE room_id = $room_id AND user_id = $user_id;")
+            .bind(("room_id", self.id.clone()))
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn joined_by(user: &AdapterUser) -> Result<Vec<RecordId>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT VALUE room_id FROM chat_room_member WHERE user_id = $user_id;")
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let rooms: Vec<RecordId> = result.take(0)?;
+        Ok(rooms)
+    }
 }
 
 #[server]
-pub async fn get_chat_history(limit: Option<usize>) -> Result<Vec<ChatEventDb>, ServerFnError> {
-    // Verify user is authenticated
+pub async fn get_chat_rooms() -> Result<Vec<ChatRoom>, ServerFnError> {
+    use crate::organization::models::current_organization_id;
+
     let _user = get_user().await?;
+    let rooms = ChatRoom::listed_for(current_organization_id().await?).await?;
+    Ok(rooms)
+}
 
-    let db = db_init().await?;
-    let limit = limit.unwrap_or(100);
+#[server]
+pub async fn get_joined_chat_rooms() -> Result<Vec<RecordId>, ServerFnError> {
+    let user = get_user().await?;
+    let rooms = ChatRoom::joined_by(&user).await?;
+    Ok(rooms)
+}
+
+/// Creates a room in the organization the session works in, or a public room outside one.
+#[server]
+pub async fn create_chat_room(
+    name: String,
+    description: Option<String>,
+) -> Result<ChatRoom, ServerFnError> {
+    use crate::organization::models::current_organization_id;
+
+    let user = get_user().await?;
+    let organization_id = current_organization_id().await?;
+    let room = ChatRoom::create(&user, name, description, organization_id).await?;
+    Ok(room)
+}
+
+#[cfg(feature = "ssr")]
+impl ChatEventDb {
+    pub async fn get(id: RecordId) -> Result<Self, AppError> {
+        if id.table() != "chat_event" {
+            return Err(AppError::NotFound("Invalid chat event ID".into()));
+        }
+
+        let db = db_init().await?;
+        let event: Option<Self> = db.select(id).await?;
+        event.ok_or_else(|| AppError::NotFound("Message not found".into()))
+    }
 
-    let query = r#"SELECT * FROM chat_event WHERE event_type in ["Message"] ORDER BY timestamp DESC LIMIT $limit;"#;
-    let mut result = db.query(query).bind(("limit", limit)).await?;
+    /// Loads a message that is about to be edited, deleted or reacted to. Fails if the event
+    /// is not a message or the message has been deleted.
+    pub async fn get_live_message(id: RecordId) -> Result<Self, AppError> {
+        let event = Self::get(id).await?;
+        if !matches!(event.event_type, ChatEventType::Message) {
+            return Err(AppError::ErrorReason("Only messages can be changed".into()));
+        }
 
-    let events: Vec<ChatEventDb> = result.take(0)?;
+        let db = db_init().await?;
+        let mut result = db
+            .query(
+                "SELECT count() FROM chat_event WHERE target_id = $id AND event_type = 'MessageDeleted' GROUP ALL;",
+            )
+            .bind(("id", event.id.clone()))
+            .await?;
+        let deleted: Option<usize> = result.take((0, "count"))?;
+        if deleted.unwrap_or(0) > 0 {
+            return Err(AppError::NotFound("Message was deleted".into()));
+        }
 
-    // Return in chronological order
-    let mut events = events;
-    events.reverse();
+        Ok(event)
+    }
+
+    /// Authors may edit and delete their own messages; superadmins may change any message.
+    pub fn can_modify(&self, user: &AdapterUser) -> bool {
+        self.user_id.as_ref() == Some(&user.id) || user.is_super_admin().unwrap_or(false)
+    }
+
+    /// Messages posted to a room or DM conversation before the `before` message (or the
+    /// latest ones without a cursor), oldest first.
+    pub async fn page_in_room(
+        room_id: RecordId,
+        before: Option<RecordId>,
+        limit: usize,
+    ) -> Result<ChatHistoryPage, AppError> {
+        let db = db_init().await?;
+        let default_room = crate::chat::shared::default_room_id();
+        let include_legacy = room_id == default_room;
+        let limit = limit.clamp(1, CHAT_HISTORY_MAX_PAGE_SIZE);
+
+        // The cursor must be a message of this room; its timestamp bounds the page
+        let cursor = match before {
+            Some(before) => {
+                let event = Self::get(before).await?;
+                if event
+                    .room_id
+                    .clone()
+                    .unwrap_or_else(|| default_room.clone())
+                    != room_id
+                {
+                    return Err(AppError::ErrorReason("Cursor is from another room".into()));
+                }
+                Some(event)
+            }
+            None => None,
+        };
+
+        // Ties on timestamp are broken by id so no message is skipped or repeated
+        let query = r#"
+            SELECT * FROM chat_event
+            WHERE event_type in ["Message"]
+                AND (room_id = $room_id OR ($include_legacy AND room_id IS NONE))
+                AND ($before_ts IS NONE OR timestamp < $before_ts
+                    OR (timestamp = $before_ts AND id < $before_id))
+            ORDER BY timestamp DESC, id DESC LIMIT $limit;
+        "#;
+        let mut result = db
+            .query(query)
+            .bind(("room_id", room_id))
+            .bind(("include_legacy", include_legacy))
+            .bind(("before_ts", cursor.as_ref().map(|e| e.timestamp.clone())))
+            .bind(("before_id", cursor.map(|e| e.id)))
+            .bind(("limit", limit + 1))
+            .await?;
+
+        let mut events: Vec<Self> = result.take(0)?;
+        let has_more = events.len() > limit;
+        events.truncate(limit);
+
+        // Return in chronological order
+        events.reverse();
+        let next_before = has_more
+            .then(|| events.first().map(|e| e.id.clone()))
+            .flatten();
+
+        Ok(ChatHistoryPage {
+            events: Self::with_updates(events).await?,
+            has_more,
+            next_before,
+        })
+    }
+
+    /// Messages, edits, deletes and reactions recorded after `since`, oldest first. Used to
+    /// catch up after a dropped connection.
+    pub async fn since_in_room(
+        room_id: RecordId,
+        since: Datetime,
+        limit: usize,
+    ) -> Result<ChatHistoryPage, AppError> {
+        let db = db_init().await?;
+        let include_legacy = room_id == crate::chat::shared::default_room_id();
+        let limit = limit.clamp(1, CHAT_HISTORY_MAX_PAGE_SIZE);
+
+        let query = r#"
+            SELECT * FROM chat_event
+            WHERE event_type IN ["Message", "MessageEdited", "MessageDeleted", "Reaction"]
+                AND (room_id = $room_id OR ($include_legacy AND room_id IS NONE))
+                AND timestamp > $since
+            ORDER BY timestamp ASC, id ASC LIMIT $limit;
+        "#;
+        let mut result = db
+            .query(query)
+            .bind(("room_id", room_id))
+            .bind(("include_legacy", include_legacy))
+            .bind(("since", since))
+            .bind(("limit", limit + 1))
+            .await?;
+
+        let mut events: Vec<Self> = result.take(0)?;
+        let has_more = events.len() > limit;
+        events.truncate(limit);
+
+        Ok(ChatHistoryPage {
+            events,
+            has_more,
+            next_before: None,
+        })
+    }
+
+    /// Messages surrounding `anchor`: up to half a page before it, then the anchor and the
+    /// messages after it. Used to show a search hit in context.
+    pub async fn page_around(anchor: Self, limit: usize) -> Result<ChatHistoryPage, AppError> {
+        let room_id = anchor
+            .room_id
+            .clone()
+            .unwrap_or_else(crate::chat::shared::default_room_id);
+        let include_legacy = room_id == crate::chat::shared::default_room_id();
+        let half = (limit.clamp(2, CHAT_HISTORY_MAX_PAGE_SIZE) / 2).max(1);
+
+        let older = Self::page_in_room(room_id.clone(), Some(anchor.id.clone()), half).await?;
+
+        let db = db_init().await?;
+        let query = r#"
+            SELECT * FROM chat_event
+            WHERE event_type in ["Message"]
+                AND (room_id = $room_id OR ($include_legacy AND room_id IS NONE))
+                AND (timestamp > $anchor_ts OR (timestamp = $anchor_ts AND id >= $anchor_id))
+            ORDER BY timestamp ASC, id ASC LIMIT $limit;
+        "#;
+        let mut result = db
+            .query(query)
+            .bind(("room_id", room_id))
+            .bind(("include_legacy", include_legacy))
+            .bind(("anchor_ts", anchor.timestamp))
+            .bind(("anchor_id", anchor.id))
+            .bind(("limit", half))
+            .await?;
+        let newer: Vec<Self> = result.take(0)?;
+
+        let mut events = older.events;
+        events.extend(Self::with_updates(newer).await?);
+
+        Ok(ChatHistoryPage {
+            events,
+            has_more: older.has_more,
+            next_before: older.next_before,
+        })
+    }
 
-    Ok(events)
+    /// Appends the edits, deletes and reactions that target `events`, oldest first.
+    async fn with_updates(mut events: Vec<Self>) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let ids: Vec<RecordId> = events.iter().map(|e| e.id.clone()).collect();
+        let mut result = db
+            .query("SELECT * FROM chat_event WHERE target_id IN $ids ORDER BY timestamp ASC;")
+            .bind(("ids", ids))
+            .await?;
+        let updates: Vec<Self> = result.take(0)?;
+        events.extend(updates);
+        Ok(events)
+    }
+
+    /// Checks `user` may read the room or DM conversation this event was posted to.
+    pub async fn check_readable_by(&self, user: &AdapterUser) -> Result<(), AppError> {
+        let room_id = self
+            .room_id
+            .clone()
+            .unwrap_or_else(crate::chat::shared::default_room_id);
+        if DmConversation::is_dm(&room_id) {
+            DmConversation::get_for_user(room_id, user).await?;
+        } else if room_id.table() != "chat_room" {
+            return Err(AppError::NotFound("Not a chat room".into()));
+        } else {
+            ChatRoom::check_access_to(room_id, user).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A page of a room's history. Pass `before` to page back from a message, or `since` to get
+/// everything recorded after a point in time instead.
+#[server]
+pub async fn get_chat_history(
+    room_id: RecordId,
+    before: Option<RecordId>,
+    since: Option<Datetime>,
+    limit: Option<usize>,
+) -> Result<ChatHistoryPage, ServerFnError> {
+    let user = get_user().await?;
+
+    if room_id.table() != "chat_room" {
+        return Err(ServerFnError::new("Not a chat room"));
+    }
+    ChatRoom::check_access_to(room_id.clone(), &user).await?;
+
+    let limit = limit.unwrap_or(CHAT_HISTORY_PAGE_SIZE);
+    let page = match since {
+        Some(since) => ChatEventDb::since_in_room(room_id, since, limit).await?,
+        None => ChatEventDb::page_in_room(room_id, before, limit).await?,
+    };
+    Ok(page)
+}
+
+/// A page of history centred on one message, e.g. a search hit.
+#[server]
+pub async fn get_chat_context(
+    event_id: RecordId,
+    limit: Option<usize>,
+) -> Result<ChatHistoryPage, ServerFnError> {
+    let user = get_user().await?;
+    let anchor = ChatEventDb::get(event_id).await?;
+    anchor.check_readable_by(&user).await?;
+
+    let page = ChatEventDb::page_around(anchor, limit.unwrap_or(CHAT_HISTORY_PAGE_SIZE)).await?;
+    Ok(page)
+}
+
+/// Public profile of a chat participant, without account details.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatUser {
+    pub id: RecordId,
+    pub name: String,
+    pub image: Option<String>,
+}
+
+impl From<AdapterUser> for ChatUser {
+    fn from(user: AdapterUser) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            image: user.image,
+        }
+    }
+}
+
+pub const DM_CONVERSATION_TABLE: &str = "dm_conversation";
+
+/// A private conversation between two users. Its messages are `chat_event`s whose `room_id`
+/// is the conversation id.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DmConversation {
+    pub id: RecordId,
+    pub participants: Vec<RecordId>,
+    pub created_at: Datetime,
+    pub last_message_at: Option<Datetime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DmConversationSummary {
+    pub conversation: DmConversation,
+    pub other: ChatUser,
+}
+
+impl DmConversation {
+    pub fn is_dm(room_id: &RecordId) -> bool {
+        room_id.table() == DM_CONVERSATION_TABLE
+    }
+
+    pub fn includes(&self, user_id: &RecordId) -> bool {
+        self.participants.contains(user_id)
+    }
+
+    pub fn other_participant(&self, user_id: &RecordId) -> Option<&RecordId> {
+        self.participants
+            .iter()
+            .find(|p| *p != user_id)
+            .or_else(|| self.participants.first())
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl DmConversation {
+    /// Conversation ids are derived from the sorted participant ids, so both users resolve to
+    /// the same record without a lookup.
+    pub fn id_for(a: &RecordId, b: &RecordId) -> RecordId {
+        let mut ids = [a.to_string(), b.to_string()];
+        ids.sort();
+        RecordId::from((DM_CONVERSATION_TABLE, ids.join("|")))
+    }
+
+    pub async fn get(id: RecordId) -> Result<Self, AppError> {
+        if !Self::is_dm(&id) {
+            return Err(AppError::NotFound("Invalid conversation ID".into()));
+        }
+
+        let db = db_init().await?;
+        let conversation: Option<Self> = db.select(id).await?;
+        conversation.ok_or_else(|| AppError::NotFound("Conversation not found".into()))
+    }
+
+    /// Loads a conversation and checks that `user` takes part in it.
+    pub async fn get_for_user(id: RecordId, user: &AdapterUser) -> Result<Self, AppError> {
+        let conversation = Self::get(id).await?;
+        if !conversation.includes(&user.id) {
+            return Err(AppError::AuthError(
+                "Not a participant in this conversation".into(),
+            ));
+        }
+        Ok(conversation)
+    }
+
+    pub async fn get_or_create(a: &RecordId, b: &RecordId) -> Result<Self, AppError> {
+        let db = db_init().await?;
+        let id = Self::id_for(a, b);
+
+        let mut participants = vec![a.clone(), b.clone()];
+        participants.sort_by_key(|p| p.to_string());
+        participants.dedup();
+
+        let mut result = db
+            .query(
+                r#"
+                INSERT IGNORE INTO dm_conversation { id: $id, participants: $participants, created_at: time::now() };
+                SELECT * FROM ONLY $id;
+                "#,
+            )
+            .bind(("id", id))
+            .bind(("participants", participants))
+            .await?;
+
+        let conversation: Option<Self> = result.take(1)?;
+        conversation.ok_or_else(|| AppError::new("Failed to open conversation"))
+    }
+
+    pub async fn for_user(user: &AdapterUser) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query(
+                "SELECT * FROM dm_conversation WHERE participants CONTAINS $user_id ORDER BY last_message_at DESC;",
+            )
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let conversations: Vec<Self> = result.take(0)?;
+        Ok(conversations)
+    }
+
+    pub async fn touch(&self) -> Result<(), AppError> {
+        let db = db_init().await?;
+        db.query("UPDATE $id SET last_message_at = time::now();")
+            .bind(("id", self.id.clone()))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn summary_for(self, user: &AdapterUser) -> Result<DmConversationSummary, AppError> {
+        let other_id = self
+            .other_participant(&user.id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound("Conversation has no participants".into()))?;
+        let other = AdapterUser::get_user(other_id).await?;
+        Ok(DmConversationSummary {
+            conversation: self,
+            other: other.into(),
+        })
+    }
+}
+
+#[server]
+pub async fn get_chat_users() -> Result<Vec<ChatUser>, ServerFnError> {
+    let user = get_user().await?;
+    let users = AdapterUser::get_all_users().await?;
+    Ok(users
+        .into_iter()
+        .filter(|u| u.id != user.id)
+        .map(ChatUser::from)
+        .collect())
+}
+
+#[server]
+pub async fn get_dm_conversations() -> Result<Vec<DmConversationSummary>, ServerFnError> {
+    let user = get_user().await?;
+    let mut summaries = Vec::new();
+    for conversation in DmConversation::for_user(&user).await? {
+        match conversation.summary_for(&user).await {
+            Ok(summary) => summaries.push(summary),
+            Err(e) => tracing::warn!("Skipping conversation: {}", e),
+        }
+    }
+    Ok(summaries)
+}
+
+#[server]
+pub async fn open_dm_conversation(
+    user_id: RecordId,
+) -> Result<DmConversationSummary, ServerFnError> {
+    let user = get_user().await?;
+    // Make sure the other user exists before creating a conversation with them
+    let other = AdapterUser::get_user(user_id).await?;
+    let conversation = DmConversation::get_or_create(&user.id, &other.id).await?;
+    let summary = conversation.summary_for(&user).await?;
+    Ok(summary)
+}
+
+#[server]
+pub async fn get_dm_history(
+    conversation_id: RecordId,
+    before: Option<RecordId>,
+    since: Option<Datetime>,
+    limit: Option<usize>,
+) -> Result<ChatHistoryPage, ServerFnError> {
+    let user = get_user().await?;
+    let conversation = DmConversation::get_for_user(conversation_id, &user).await?;
+    let limit = limit.unwrap_or(CHAT_HISTORY_PAGE_SIZE);
+    let page = match since {
+        Some(since) => ChatEventDb::since_in_room(conversation.id, since, limit).await?,
+        None => ChatEventDb::page_in_room(conversation.id, before, limit).await?,
+    };
+    Ok(page)
 }
 
 #[cfg(feature = "ssr")]
 pub async fn save_chat_event(
     user_id: Option<RecordId>,
     username: String,
+    room_id: Option<RecordId>,
     event_type: ChatEventType,
     message: Option<String>,
+    target_id: Option<RecordId>,
 ) -> Result<ChatEventDb, AppError> {
     let db = db_init().await?;
 
     let event_data = CreateChatEvent {
         user_id,
+        username,
+        room_id,
         event_type,
         message,
         timestamp: Datetime::from(Utc::now()),
+        target_id,
     };
 
     let created: Option<ChatEventDb> = db.create("chat_event").content(event_data).await?;
@@ -86,3 +804,101 @@ pub async fn get_user_info(user_id: RecordId) -> Result<Option<AdapterUser>, Ser
         Err(_) => Ok(None),
     }
 }
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_dm_conversation_id_is_symmetric() {
+    let alice = RecordId::from(("user", "alice"));
+    let bob = RecordId::from(("user", "bob"));
+
+    let id = DmConversation::id_for(&alice, &bob);
+    assert_eq!(id, DmConversation::id_for(&bob, &alice));
+    assert!(DmConversation::is_dm(&id));
+    assert_ne!(id, DmConversation::id_for(&alice, &alice));
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_fold_history_applies_updates() {
+    let room_id = crate::chat::shared::default_room_id();
+    let alice = RecordId::from(("user", "alice"));
+    let bob = RecordId::from(("user", "bob"));
+    let event =
+        |id: &str, event_type: ChatEventType, message: &str, target: Option<&str>| ChatEventDb {
+            id: RecordId::from(("chat_event", id)),
+            user_id: Some(if id.starts_with('b') {
+                bob.clone()
+            } else {
+                alice.clone()
+            }),
+            username: "alice".into(),
+            room_id: Some(room_id.clone()),
+            event_type,
+            message: Some(message.into()),
+            timestamp: Datetime::from(Utc::now()),
+            target_id: target.map(|t| RecordId::from(("chat_event", t))),
+        };
+
+    let msgs = ChatEventDb::fold_history(vec![
+        event("a1", ChatEventType::Message, "first", None),
+        event("a2", ChatEventType::Message, "second", None),
+        event("a3", ChatEventType::MessageEdited, "first!", Some("a1")),
+        event("a4", ChatEventType::MessageDeleted, "", Some("a2")),
+        event("a5", ChatEventType::Reaction, "👍", Some("a1")),
+        event("b1", ChatEventType::Reaction, "👍", Some("a1")),
+        event("a6", ChatEventType::Reaction, "👍", Some("a1")),
+    ]);
+
+    assert_eq!(msgs.len(), 1);
+    match &msgs[0] {
+        WsMessage::Message(chat) => {
+            assert_eq!(chat.message, "first!");
+            assert!(chat.edited);
+            assert_eq!(chat.reactions.len(), 1);
+            assert_eq!(chat.reactions[0].user_ids, vec![bob.clone()]);
+        }
+        other => panic!("unexpected message: {other:?}"),
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_unknown_rooms_are_not_found() {
+    let user = AdapterUser::create_test_user().await.unwrap();
+    let made_up = RecordId::from(("chat_room", uuid::Uuid::new_v4().to_string()));
+
+    assert!(matches!(
+        ChatRoom::check_access_to(made_up, &user).await,
+        Err(AppError::NotFound(_))
+    ));
+    assert!(
+        ChatRoom::check_access_to(crate::chat::shared::default_room_id(), &user)
+            .await
+            .is_ok()
+    );
+}
+
+#[cfg(feature = "ssr")]
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn test_room_names_are_unique_per_organization() {
+    use crate::organization::models::Organization;
+
+    crate::db::db_schema().await.unwrap();
+    let user = AdapterUser::create_test_user().await.unwrap();
+    let first = Organization::create(&user, "First".into()).await.unwrap();
+    let second = Organization::create(&user, "Second".into()).await.unwrap();
+    let name = format!("dev-{}", uuid::Uuid::new_v4());
+
+    ChatRoom::create(&user, name.clone(), None, Some(first.id.clone()))
+        .await
+        .unwrap();
+    ChatRoom::create(&user, name.clone(), None, Some(second.id))
+        .await
+        .unwrap();
+    assert!(matches!(
+        ChatRoom::create(&user, name, None, Some(first.id)).await,
+        Err(AppError::ErrorReason(_))
+    ));
+}
@@ -0,0 +1,68 @@
+use leptos::prelude::*;
+
+use crate::chat::models::ChatUser;
+use crate::RecordId;
+
+#[cfg(feature = "ssr")]
+use crate::chat::websocket::ChatState;
+
+/// How often the client refreshes its typing indicator while the user keeps typing. Must be
+/// shorter than the server's expiry so the indicator does not flicker.
+pub const TYPING_REFRESH_MS: f64 = 3000.0;
+
+/// Someone typing in a room, as tracked by the client from `WsMessage::Typing` events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatTypingUser {
+    pub room_id: RecordId,
+    pub user_id: Option<RecordId>,
+    pub username: String,
+}
+
+impl ChatTypingUser {
+    /// Whether this is the same person, matching anonymous users by name.
+    pub fn is_same(&self, room_id: &RecordId, user_id: &Option<RecordId>, username: &str) -> bool {
+        &self.room_id == room_id
+            && match (&self.user_id, user_id) {
+                (Some(a), Some(b)) => a == b,
+                (None, None) => self.username == username,
+                _ => false,
+            }
+    }
+}
+
+/// Text shown above the chat input, e.g. "Ada and Bob are typing...".
+pub fn typing_label(names: &[String]) -> Option<String> {
+    match names {
+        [] => None,
+        [one] => Some(format!("{} is typing...", one)),
+        [one, two] => Some(format!("{} and {} are typing...", one, two)),
+        _ => Some("Several people are typing...".to_string()),
+    }
+}
+
+/// Signed in users with an open chat socket.
+#[server]
+pub async fn get_online_users() -> Result<Vec<ChatUser>, ServerFnError> {
+    crate::auth::session::get_user().await?;
+    let state = use_context::<ChatState>()
+        .ok_or_else(|| ServerFnError::new("Chat is not running on this server"))?;
+    Ok(state.online_users())
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_typing_label() {
+    assert_eq!(typing_label(&[]), None);
+    assert_eq!(
+        typing_label(&["Ada".into()]).as_deref(),
+        Some("Ada is typing...")
+    );
+    assert_eq!(
+        typing_label(&["Ada".into(), "Bob".into()]).as_deref(),
+        Some("Ada and Bob are typing...")
+    );
+    assert_eq!(
+        typing_label(&["Ada".into(), "Bob".into(), "Cy".into()]).as_deref(),
+        Some("Several people are typing...")
+    );
+}
@@ -0,0 +1,191 @@
+use leptos::prelude::*;
+use partial_struct::Partial;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::models::ChatUser;
+use crate::{Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{session::get_user, user::AdapterUser},
+    chat::models::{ChatEventDb, ChatRoom, DmConversation},
+    db_init, AppError,
+};
+
+#[cfg(feature = "ssr")]
+use chrono::Utc;
+
+pub const CHAT_READ_MARKER_TABLE: &str = "chat_read_marker";
+
+/// The last message a user has read in a room or DM conversation.
+#[derive(Debug, Clone, Serialize, Deserialize, Partial)]
+#[partial(
+    "CreateChatReadMarker",
+    derive(Serialize, Deserialize, Clone),
+    omit(id)
+)]
+pub struct ChatReadMarker {
+    pub id: RecordId,
+    pub user_id: RecordId,
+    pub room_id: RecordId,
+    pub event_id: RecordId,
+    /// Timestamp of `event_id`, used to count the messages after it.
+    pub event_timestamp: Datetime,
+    pub read_at: Datetime,
+}
+
+/// A read marker with the reader's public profile, as shown under messages.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatReadReceipt {
+    pub user: ChatUser,
+    pub room_id: RecordId,
+    pub event_id: RecordId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatUnreadCount {
+    pub room_id: RecordId,
+    pub count: usize,
+}
+
+/// Total unread chat messages for the signed in user, shared between the sidebar badge and
+/// `ChatApp`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChatUnreadTotal(pub RwSignal<usize>);
+
+#[cfg(feature = "ssr")]
+impl ChatReadMarker {
+    pub fn id_for(user_id: &RecordId, room_id: &RecordId) -> RecordId {
+        RecordId::from((
+            CHAT_READ_MARKER_TABLE,
+            format!("{}|{}", user_id, room_id).as_str(),
+        ))
+    }
+
+    /// Moves the user's marker in the event's room forward to `event`. Markers never move
+    /// back, so a stale receipt from another tab is ignored.
+    pub async fn mark_read(user: &AdapterUser, event: &ChatEventDb) -> Result<Self, AppError> {
+        let room_id = event
+            .room_id
+            .clone()
+            .unwrap_or_else(crate::chat::shared::default_room_id);
+        let id = Self::id_for(&user.id, &room_id);
+
+        let db = db_init().await?;
+        let existing: Option<Self> = db.select(id.clone()).await?;
+        if let Some(existing) = existing {
+            if existing.event_timestamp >= event.timestamp {
+                return Ok(existing);
+            }
+        }
+
+        let content = CreateChatReadMarker {
+            user_id: user.id.clone(),
+            room_id,
+            event_id: event.id.clone(),
+            event_timestamp: event.timestamp.clone(),
+            read_at: Datetime::from(Utc::now()),
+        };
+        let marker: Option<Self> = db.upsert(id).content(content).await?;
+        marker.ok_or_else(|| AppError::new("Failed to save read marker"))
+    }
+
+    pub async fn for_room(room_id: RecordId) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM chat_read_marker WHERE room_id = $room_id;")
+            .bind(("room_id", room_id))
+            .await?;
+        let markers: Vec<Self> = result.take(0)?;
+        Ok(markers)
+    }
+
+    pub async fn for_user(user: &AdapterUser) -> Result<Vec<Self>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT * FROM chat_read_marker WHERE user_id = $user_id;")
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let markers: Vec<Self> = result.take(0)?;
+        Ok(markers)
+    }
+}
+
+/// Unread messages from other users in each joined room and DM conversation.
+#[cfg(feature = "ssr")]
+pub async fn unread_counts(user: &AdapterUser) -> Result<Vec<ChatUnreadCount>, AppError> {
+    let mut room_ids = ChatRoom::joined_by(user).await?;
+    room_ids.extend(
+        DmConversation::for_user(user)
+            .await?
+            .into_iter()
+            .map(|c| c.id),
+    );
+
+    let markers = ChatReadMarker::for_user(user).await?;
+    let db = db_init().await?;
+    let default_room = crate::chat::shared::default_room_id();
+
+    let mut counts = Vec::new();
+    for room_id in room_ids {
+        let since = markers
+            .iter()
+            .find(|m| m.room_id == room_id)
+            .map(|m| m.event_timestamp.clone());
+
+        let mut result = db
+            .query(
+                r#"
+                SELECT count() FROM chat_event
+                WHERE event_type = "Message"
+                    AND (room_id = $room_id OR ($include_legacy AND room_id IS NONE))
+                    AND ($since IS NONE OR timestamp > $since)
+                    AND user_id != $user_id
+                GROUP ALL;
+                "#,
+            )
+            .bind(("room_id", room_id.clone()))
+            .bind(("include_legacy", room_id == default_room))
+            .bind(("since", since))
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let count: Option<usize> = result.take((0, "count"))?;
+
+        if let Some(count) = count.filter(|c| *c > 0) {
+            counts.push(ChatUnreadCount { room_id, count });
+        }
+    }
+
+    Ok(counts)
+}
+
+#[server]
+pub async fn get_unread_counts() -> Result<Vec<ChatUnreadCount>, ServerFnError> {
+    let user = get_user().await?;
+    let counts = unread_counts(&user).await?;
+    Ok(counts)
+}
+
+#[server]
+pub async fn get_read_receipts(room_id: RecordId) -> Result<Vec<ChatReadReceipt>, ServerFnError> {
+    let user = get_user().await?;
+    if DmConversation::is_dm(&room_id) {
+        DmConversation::get_for_user(room_id.clone(), &user).await?;
+    } else if room_id.table() != "chat_room" {
+        return Err(ServerFnError::new("Not a chat room"));
+    } else {
+        ChatRoom::check_access_to(room_id.clone(), &user).await?;
+    }
+
+    let mut receipts = Vec::new();
+    for marker in ChatReadMarker::for_room(room_id).await? {
+        if let Ok(reader) = AdapterUser::get_user(marker.user_id).await {
+            receipts.push(ChatReadReceipt {
+                user: reader.into(),
+                room_id: marker.room_id,
+                event_id: marker.event_id,
+            });
+        }
+    }
+    Ok(receipts)
+}
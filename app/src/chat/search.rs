@@ -0,0 +1,277 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{session::get_user, user::AdapterUser},
+    chat::models::{ChatRoom, DmConversation},
+    db_init, AppError,
+};
+
+pub const CHAT_SEARCH_LIMIT: usize = 50;
+pub const CHAT_SEARCH_QUERY_MAX_LEN: usize = 200;
+/// Characters of context kept on either side of the first highlighted term.
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+
+// Control characters never typed into chat, used to find highlights in SurrealDB's output
+#[cfg(feature = "ssr")]
+const HIGHLIGHT_OPEN: char = '\u{2}';
+#[cfg(feature = "ssr")]
+const HIGHLIGHT_CLOSE: char = '\u{3}';
+
+/// A run of snippet text, highlighted if it matched the query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSearchHit {
+    /// The message to jump to. Hits on an edit point at the edited message.
+    pub event_id: RecordId,
+    pub room_id: RecordId,
+    /// Room name or the other DM participant's name.
+    pub room_label: String,
+    pub user_id: Option<RecordId>,
+    pub username: String,
+    pub snippet: Vec<SnippetPart>,
+    pub timestamp: Datetime,
+}
+
+/// Splits text marked with `open`/`close` into parts, trimmed to the context around the
+/// first highlight.
+pub fn snippet_parts(marked: &str, open: char, close: char) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut highlighted = false;
+
+    for c in marked.chars() {
+        if c == open || c == close {
+            if !current.is_empty() {
+                parts.push(SnippetPart {
+                    text: std::mem::take(&mut current),
+                    highlighted,
+                });
+            }
+            highlighted = c == open;
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        parts.push(SnippetPart {
+            text: current,
+            highlighted,
+        });
+    }
+
+    let Some(first) = parts.iter().position(|p| p.highlighted) else {
+        return parts;
+    };
+
+    // Keep the tail of the text before the first match and the head of the text after it
+    if first > 0 {
+        let before = &mut parts[first - 1];
+        let count = before.text.chars().count();
+        if count > SNIPPET_CONTEXT_CHARS {
+            let tail: String = before
+                .text
+                .chars()
+                .skip(count - SNIPPET_CONTEXT_CHARS)
+                .collect();
+            before.text = format!("…{}", tail);
+        }
+        parts.drain(..first - 1);
+    }
+
+    // After the drain the first match is at index 0 or 1
+    let first = usize::from(!parts[0].highlighted);
+    let mut remaining = SNIPPET_CONTEXT_CHARS;
+    let mut keep = parts.len();
+    for (index, part) in parts.iter_mut().enumerate().skip(first + 1) {
+        if part.highlighted {
+            continue;
+        }
+        let count = part.text.chars().count();
+        if count > remaining {
+            part.text = format!("{}…", part.text.chars().take(remaining).collect::<String>());
+            keep = index + 1;
+            break;
+        }
+        remaining -= count;
+    }
+    parts.truncate(keep);
+    parts
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+struct SearchRow {
+    id: RecordId,
+    target_id: Option<RecordId>,
+    room_id: Option<RecordId>,
+    user_id: Option<RecordId>,
+    username: String,
+    timestamp: Datetime,
+    highlighted: Option<String>,
+}
+
+/// Filters for `search_chat`. Dates are inclusive of `from` and exclusive of `to`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatSearchFilter {
+    pub room_id: Option<RecordId>,
+    pub author_id: Option<RecordId>,
+    pub from: Option<Datetime>,
+    pub to: Option<Datetime>,
+}
+
+#[cfg(feature = "ssr")]
+pub async fn search_messages(
+    user: &AdapterUser,
+    query: &str,
+    filter: ChatSearchFilter,
+    limit: usize,
+) -> Result<Vec<ChatSearchHit>, AppError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    if query.chars().count() > CHAT_SEARCH_QUERY_MAX_LEN {
+        return Err(AppError::ErrorReason("Search query is too long".into()));
+    }
+
+    let conversations = DmConversation::for_user(user).await?;
+    let rooms = ChatRoom::visible_to(user).await?;
+    if let Some(room_id) = &filter.room_id {
+        if DmConversation::is_dm(room_id) {
+            if !conversations.iter().any(|c| &c.id == room_id) {
+                return Err(AppError::AuthError("Not a participant".into()));
+            }
+        } else if room_id.table() != "chat_room" {
+            return Err(AppError::NotFound("Not a chat room".into()));
+        } else {
+            ChatRoom::check_access_to(room_id.clone(), user).await?;
+        }
+    }
+
+    let include_legacy = filter
+        .room_id
+        .as_ref()
+        .is_none_or(|room_id| *room_id == crate::chat::shared::default_room_id());
+    let dm_ids: Vec<RecordId> = conversations.iter().map(|c| c.id.clone()).collect();
+    let room_ids: Vec<RecordId> = rooms.iter().map(|room| room.id.clone()).collect();
+
+    // Searches original messages and edits; hits on deleted messages are dropped
+    let sql = r#"
+        LET $deleted = SELECT VALUE target_id FROM chat_event WHERE event_type = "MessageDeleted";
+        SELECT id, target_id, room_id, user_id, username, timestamp,
+            search::highlight($open, $close, 1) AS highlighted,
+            search::score(1) AS score
+        FROM chat_event
+        WHERE message @1@ $query
+            AND event_type IN ["Message", "MessageEdited"]
+            AND (target_id ?? id) NOT IN $deleted
+            AND ($room_id IS NONE OR room_id = $room_id OR ($include_legacy AND room_id IS NONE))
+            AND (room_id IS NONE OR room_id IN $room_ids OR room_id IN $dm_ids)
+            AND ($author_id IS NONE OR user_id = $author_id)
+            AND ($from IS NONE OR timestamp >= $from)
+            AND ($to IS NONE OR timestamp < $to)
+        ORDER BY score DESC LIMIT $limit;
+    "#;
+
+    let db = db_init().await?;
+    let mut result = db
+        .query(sql)
+        .bind(("query", query.to_string()))
+        .bind(("open", HIGHLIGHT_OPEN.to_string()))
+        .bind(("close", HIGHLIGHT_CLOSE.to_string()))
+        .bind(("room_id", filter.room_id))
+        .bind(("include_legacy", include_legacy))
+        .bind(("room_ids", room_ids))
+        .bind(("dm_ids", dm_ids))
+        .bind(("author_id", filter.author_id))
+        .bind(("from", filter.from))
+        .bind(("to", filter.to))
+        .bind(("limit", limit.clamp(1, CHAT_SEARCH_LIMIT)))
+        .await?;
+    let rows: Vec<SearchRow> = result.take(1)?;
+
+    let mut labels: std::collections::HashMap<RecordId, String> = rooms
+        .into_iter()
+        .map(|room| (room.id, format!("#{}", room.name)))
+        .collect();
+    for conversation in conversations {
+        let id = conversation.id.clone();
+        if let Ok(summary) = conversation.summary_for(user).await {
+            labels.insert(id, format!("@{}", summary.other.name));
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let hits = rows
+        .into_iter()
+        .filter_map(|row| {
+            let event_id = row.target_id.unwrap_or(row.id);
+            // An edit and its original can both match; show the message once
+            if !seen.insert(event_id.clone()) {
+                return None;
+            }
+            let room_id = row
+                .room_id
+                .unwrap_or_else(crate::chat::shared::default_room_id);
+            Some(ChatSearchHit {
+                event_id,
+                room_label: labels.get(&room_id).cloned().unwrap_or_default(),
+                room_id,
+                user_id: row.user_id,
+                username: row.username,
+                snippet: snippet_parts(
+                    &row.highlighted.unwrap_or_default(),
+                    HIGHLIGHT_OPEN,
+                    HIGHLIGHT_CLOSE,
+                ),
+                timestamp: row.timestamp,
+            })
+        })
+        .collect();
+
+    Ok(hits)
+}
+
+#[server]
+pub async fn search_chat(
+    query: String,
+    filter: ChatSearchFilter,
+) -> Result<Vec<ChatSearchHit>, ServerFnError> {
+    let user = get_user().await?;
+    let hits = search_messages(&user, &query, filter, CHAT_SEARCH_LIMIT).await?;
+    Ok(hits)
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_snippet_parts_splits_and_trims() {
+    let marked = format!(
+        "{}the {}deploy{} went fine{}",
+        "x".repeat(100),
+        HIGHLIGHT_OPEN,
+        HIGHLIGHT_CLOSE,
+        "y".repeat(200)
+    );
+    let parts = snippet_parts(&marked, HIGHLIGHT_OPEN, HIGHLIGHT_CLOSE);
+
+    assert_eq!(parts.len(), 3);
+    assert!(parts[0].text.starts_with('…'));
+    assert_eq!(parts[0].text.chars().count(), SNIPPET_CONTEXT_CHARS + 1);
+    assert_eq!(
+        parts[1],
+        SnippetPart {
+            text: "deploy".into(),
+            highlighted: true
+        }
+    );
+    assert!(parts[2].text.ends_with('…'));
+}
@@ -1,18 +1,298 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::chat::models::ChatUser;
+use crate::chat::receipts::ChatReadReceipt;
 use crate::RecordId;
 
+/// Key of the room every connection joins on connect.
+pub const DEFAULT_ROOM: &str = "general";
+
+/// Version of the websocket protocol spoken by this build.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest client protocol version the server still understands.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+pub fn default_room_id() -> RecordId {
+    RecordId::from(("chat_room", DEFAULT_ROOM))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
+    /// The persisted `chat_event` id, if the server stored the message.
+    #[serde(default)]
+    pub id: Option<RecordId>,
     pub user_id: RecordId,
     pub username: String,
+    pub room_id: RecordId,
     pub message: String,
     pub timestamp: String,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default)]
+    pub reactions: Vec<ChatReaction>,
 }
 
+/// Users who reacted to a message with one emoji.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatReaction {
+    pub emoji: String,
+    pub user_ids: Vec<RecordId>,
+}
+
+/// Emoji offered by the reaction picker.
+pub const REACTION_EMOJI: [&str; 6] = ["👍", "❤️", "😂", "🎉", "😮", "👀"];
+pub const REACTION_MAX_LEN: usize = 16;
+
+impl ChatMessage {
+    /// When the message was sent. Live messages carry RFC 3339 timestamps, while history
+    /// loaded from the database may wrap them as `d'...'`.
+    pub fn sent_at(&self) -> Option<DateTime<Utc>> {
+        let raw = self
+            .timestamp
+            .trim()
+            .trim_start_matches("d'")
+            .trim_end_matches('\'');
+        DateTime::parse_from_rfc3339(raw)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    pub fn toggle_reaction(&mut self, user_id: &RecordId, emoji: &str) {
+        match self.reactions.iter().position(|r| r.emoji == emoji) {
+            Some(index) => {
+                let reaction = &mut self.reactions[index];
+                if let Some(user_index) = reaction.user_ids.iter().position(|u| u == user_id) {
+                    reaction.user_ids.remove(user_index);
+                    if reaction.user_ids.is_empty() {
+                        self.reactions.remove(index);
+                    }
+                } else {
+                    reaction.user_ids.push(user_id.clone());
+                }
+            }
+            None => self.reactions.push(ChatReaction {
+                emoji: emoji.to_string(),
+                user_ids: vec![user_id.clone()],
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WsErrorCode {
+    /// The frame was not valid JSON or not a known `ClientWsMessage`.
+    MalformedFrame,
+    UnsupportedVersion,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    /// The request is understood but this server does not handle it yet.
+    Unsupported,
+    Internal,
+}
+
+/// Frames sent from the server to clients.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WsMessage {
-    UserJoined { username: String },
-    UserLeft { username: String },
+    UserJoined {
+        username: String,
+        room_id: RecordId,
+    },
+    UserLeft {
+        username: String,
+        room_id: RecordId,
+    },
     Message(ChatMessage),
+    MessageEdited {
+        event_id: RecordId,
+        room_id: RecordId,
+        message: String,
+    },
+    MessageDeleted {
+        event_id: RecordId,
+        room_id: RecordId,
+    },
+    /// Toggles `user_id`'s `emoji` reaction on a message.
+    Reaction {
+        event_id: RecordId,
+        room_id: RecordId,
+        user_id: RecordId,
+        emoji: String,
+    },
+    /// A user read up to a message.
+    ReadReceipt(ChatReadReceipt),
+    /// A user started or stopped typing. The server sends `typing: false` itself when a
+    /// client stops refreshing the indicator.
+    Typing {
+        room_id: RecordId,
+        user_id: Option<RecordId>,
+        username: String,
+        typing: bool,
+    },
+    /// Everyone currently online, sent whenever someone connects or disconnects.
+    Presence {
+        online: Vec<ChatUser>,
+    },
+    /// Reply to `ClientWsMessage::Hello` with the version both sides will speak.
+    Welcome {
+        protocol_version: u32,
+    },
+    Pong {
+        nonce: Option<u64>,
+    },
+    /// Confirms a `ClientWsMessage::Send` was accepted.
+    Ack {
+        client_msg_id: String,
+        event_id: Option<RecordId>,
+    },
+    /// Sent only to the socket whose frame could not be handled.
+    Error {
+        code: WsErrorCode,
+        message: String,
+        /// The id of the refused `ClientWsMessage::Send`, so the client can drop it.
+        #[serde(default)]
+        client_msg_id: Option<String>,
+    },
+}
+
+impl WsMessage {
+    pub fn room_id(&self) -> Option<&RecordId> {
+        match self {
+            WsMessage::UserJoined { room_id, .. } | WsMessage::UserLeft { room_id, .. } => {
+                Some(room_id)
+            }
+            WsMessage::Message(msg) => Some(&msg.room_id),
+            WsMessage::MessageEdited { room_id, .. }
+            | WsMessage::MessageDeleted { room_id, .. }
+            | WsMessage::Reaction { room_id, .. } => Some(room_id),
+            WsMessage::ReadReceipt(receipt) => Some(&receipt.room_id),
+            WsMessage::Typing { room_id, .. } => Some(room_id),
+            WsMessage::Presence { .. }
+            | WsMessage::Welcome { .. }
+            | WsMessage::Pong { .. }
+            | WsMessage::Ack { .. }
+            | WsMessage::Error { .. } => None,
+        }
+    }
+
+    /// Applies an edit, delete or reaction to the message it targets in `msgs`. Returns false
+    /// for frames that are not updates, which callers append instead.
+    pub fn apply_update(&self, msgs: &mut Vec<WsMessage>) -> bool {
+        let target = match self {
+            WsMessage::MessageEdited { event_id, .. }
+            | WsMessage::MessageDeleted { event_id, .. }
+            | WsMessage::Reaction { event_id, .. } => event_id,
+            _ => return false,
+        };
+
+        let position = msgs.iter().position(
+            |m| matches!(m, WsMessage::Message(chat) if chat.id.as_ref() == Some(target)),
+        );
+        let Some(position) = position else {
+            return true;
+        };
+
+        match self {
+            WsMessage::MessageDeleted { .. } => {
+                msgs.remove(position);
+            }
+            WsMessage::MessageEdited { message, .. } => {
+                if let WsMessage::Message(chat) = &mut msgs[position] {
+                    chat.message = message.clone();
+                    chat.edited = true;
+                }
+            }
+            WsMessage::Reaction { user_id, emoji, .. } => {
+                if let WsMessage::Message(chat) = &mut msgs[position] {
+                    chat.toggle_reaction(user_id, emoji);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn error(code: WsErrorCode, message: impl ToString) -> Self {
+        WsMessage::Error {
+            code,
+            message: message.to_string(),
+            client_msg_id: None,
+        }
+    }
+}
+
+/// Frames sent from clients to the server, as JSON text frames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientWsMessage {
+    /// Optional first frame announcing the client's protocol version. Clients that skip it
+    /// are assumed to speak `PROTOCOL_VERSION`.
+    Hello {
+        protocol_version: u32,
+    },
+    /// Subscribe to a room, in addition to the rooms already joined.
+    JoinRoom {
+        room_id: RecordId,
+    },
+    /// Stop receiving a room's messages.
+    LeaveRoom {
+        room_id: RecordId,
+    },
+    /// Post to a room or DM conversation. `client_msg_id` is echoed back in an `Ack`, and a
+    /// frame resent with the same id is acknowledged again instead of posted twice.
+    Send {
+        room_id: RecordId,
+        message: String,
+        #[serde(default)]
+        client_msg_id: Option<String>,
+    },
+    Edit {
+        event_id: RecordId,
+        message: String,
+    },
+    Delete {
+        event_id: RecordId,
+    },
+    /// Adds the emoji reaction, or removes it if this user already reacted with it.
+    React {
+        event_id: RecordId,
+        emoji: String,
+    },
+    /// Sent while the user types, at most every few seconds, and with `typing: false` when
+    /// they clear the input.
+    Typing {
+        room_id: RecordId,
+        typing: bool,
+    },
+    /// Marks everything up to `event_id` in the room as read.
+    ReadReceipt {
+        room_id: RecordId,
+        event_id: RecordId,
+    },
+    Ping {
+        #[serde(default)]
+        nonce: Option<u64>,
+    },
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_sent_at_parses_live_and_stored_timestamps() {
+    let mut message = ChatMessage {
+        id: None,
+        user_id: RecordId::from(("user", "ada")),
+        username: "Ada".into(),
+        room_id: default_room_id(),
+        message: "hi".into(),
+        timestamp: "2024-05-01T10:00:00+00:00".into(),
+        edited: false,
+        reactions: Vec::new(),
+    };
+    let live = message.sent_at().expect("live timestamp");
+
+    message.timestamp = "d'2024-05-01T10:00:00Z'".into();
+    assert_eq!(message.sent_at(), Some(live));
+
+    message.timestamp = "yesterday".into();
+    assert_eq!(message.sent_at(), None);
 }
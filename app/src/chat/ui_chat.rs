@@ -2,18 +2,118 @@ use crate::components::{Tooltip, UserAvatar};
 use crate::date_utils::{format_time_iso, TimeFormatVariant};
 
 use leptos::prelude::*;
+use phosphor_leptos::{Icon, MAGNIFYING_GLASS};
+use std::collections::HashMap;
 
-use crate::chat::models::{get_chat_history, get_user_info, ChatEventType};
-use crate::chat::shared::{ChatMessage, WsMessage};
+use crate::auth::session::get_user;
+use crate::chat::models::{
+    get_chat_context, get_chat_history, get_dm_history, get_user_info, ChatEventDb,
+    ChatHistoryPage, ChatUser, DmConversation, CHAT_HISTORY_PAGE_SIZE,
+};
+use crate::chat::presence::{get_online_users, typing_label, ChatTypingUser};
+use crate::chat::receipts::{
+    get_read_receipts, get_unread_counts, ChatReadReceipt, ChatUnreadTotal,
+};
+use crate::chat::search::ChatSearchHit;
+use crate::chat::shared::{default_room_id, ClientWsMessage, WsMessage, DEFAULT_ROOM};
+use crate::chat::ui_message::{ChatMessageContent, SeenBy};
+use crate::chat::ui_presence::OnlineUserList;
+use crate::chat::ui_rooms::{ChatRoomList, DirectMessageList};
+use crate::chat::ui_search::ChatSearchPanel;
 
 #[cfg(not(feature = "ssr"))]
 use {
+    crate::chat::presence::TYPING_REFRESH_MS,
+    crate::chat::shared::PROTOCOL_VERSION,
+    leptos::task::spawn_local,
+    std::collections::HashSet,
+    std::time::Duration,
     wasm_bindgen::prelude::*,
     web_sys::js_sys,
     web_sys::{MessageEvent, WebSocket},
 };
 
-use crate::RecordId;
+use crate::{Datetime, RecordId};
+
+/// Sends a frame over the chat socket stored on `window.chat_ws`.
+#[cfg(not(feature = "ssr"))]
+fn send_ws_message(msg: &ClientWsMessage) -> bool {
+    let Ok(frame) = serde_json::to_string(msg) else {
+        return false;
+    };
+
+    let window = web_sys::window().expect("window");
+    if let Ok(ws_value) = js_sys::Reflect::get(&window, &JsValue::from_str("chat_ws")) {
+        if let Ok(ws) = ws_value.dyn_into::<WebSocket>() {
+            if ws.ready_state() == WebSocket::OPEN {
+                return ws.send_with_str(&frame).is_ok();
+            }
+        }
+    }
+    false
+}
+
+/// Delay before the first reconnect attempt; doubled after each failed attempt.
+#[cfg(not(feature = "ssr"))]
+const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+#[cfg(not(feature = "ssr"))]
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff for the `attempt`th reconnect, starting at 0.
+#[cfg(not(feature = "ssr"))]
+fn reconnect_delay(attempt: u32) -> Duration {
+    RECONNECT_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+/// A random id for an outgoing message, which the server echoes back in its `Ack`.
+#[cfg(not(feature = "ssr"))]
+fn new_client_msg_id() -> String {
+    format!(
+        "{:x}-{:08x}",
+        js_sys::Date::now() as u64,
+        (js_sys::Math::random() * f64::from(u32::MAX)) as u32
+    )
+}
+
+/// Appends a message unless one with the same id is already shown, e.g. when the replay
+/// after a reconnect overlaps with live delivery.
+#[cfg(not(feature = "ssr"))]
+fn push_unique(msgs: &mut Vec<WsMessage>, msg: WsMessage) {
+    if let WsMessage::Message(chat) = &msg {
+        let duplicate = chat.id.is_some()
+            && msgs
+                .iter()
+                .any(|m| matches!(m, WsMessage::Message(shown) if shown.id == chat.id));
+        if duplicate {
+            return;
+        }
+    }
+    msgs.push(msg);
+}
+
+/// Distance from the top of the message list, in pixels, at which older history is loaded.
+const LOAD_OLDER_THRESHOLD_PX: i32 = 48;
+
+/// DOM id of a rendered message, used to scroll search hits into view.
+fn message_element_id(event_id: &RecordId) -> String {
+    format!("chat-msg-{}", event_id)
+}
+
+/// Loads a page of history for a room or DM conversation, either before a message or
+/// everything after `since`.
+async fn load_history_page(
+    room_id: RecordId,
+    before: Option<RecordId>,
+    since: Option<Datetime>,
+) -> Result<ChatHistoryPage, ServerFnError> {
+    if DmConversation::is_dm(&room_id) {
+        get_dm_history(room_id, before, since, Some(CHAT_HISTORY_PAGE_SIZE)).await
+    } else {
+        get_chat_history(room_id, before, since, Some(CHAT_HISTORY_PAGE_SIZE)).await
+    }
+}
 
 #[component]
 pub fn ChatApp() -> impl IntoView {
@@ -22,37 +122,371 @@ pub fn ChatApp() -> impl IntoView {
     let (input_value, set_input_value) = signal(String::new());
     #[allow(unused)]
     let (connected, set_connected) = signal(false);
-    let (new_message_count, _set_new_message_count) = signal(0usize);
+    // Unread messages per room or conversation id, excluding the one being viewed
+    let unread = RwSignal::new(HashMap::<String, usize>::new());
+    // Read markers of everyone in the room being viewed
+    let receipts = RwSignal::new(Vec::<ChatReadReceipt>::new());
+    let (active_room, set_active_room) = signal(default_room_id());
+    let (active_label, set_active_label) = signal(DEFAULT_ROOM.to_string());
+    #[allow(unused)]
+    let (dm_refresh, set_dm_refresh) = signal(0usize);
+    #[allow(unused)]
+    let (ws_error, set_ws_error) = signal(Option::<String>::None);
     let messages_container_ref = NodeRef::<leptos::html::Div>::new();
+    // Cursor for the page before the oldest loaded message, if there is one
+    let (older_cursor, set_older_cursor) = signal(Option::<RecordId>::None);
+    // Scroll height before older messages were prepended, so the view can stay in place
+    #[allow(unused)]
+    let preserve_scroll = StoredValue::new(Option::<i32>::None);
+    // Message opened from search; history is loaded around it instead of at the latest page
+    let (anchor, set_anchor) = signal(Option::<RecordId>::None);
+    // Anchor still to be scrolled into view once it has rendered
+    #[allow(unused)]
+    let pending_scroll = StoredValue::new(Option::<RecordId>::None);
+    let (search_open, set_search_open) = signal(false);
+    #[allow(unused)]
+    let (online, set_online) = signal(Vec::<ChatUser>::new());
+    // Other people typing, in any room; filtered to the active room when shown
+    let typing_users = RwSignal::new(Vec::<ChatTypingUser>::new());
+    // Messages the server hasn't acknowledged yet, in the order they were typed. Each new
+    // socket gets all of them again until their `Ack` arrives.
+    let outbox = RwSignal::new(Vec::<ClientWsMessage>::new());
+    // Bumped to open a new socket after the previous one closed
+    #[allow(unused)]
+    let (reconnect_tick, set_reconnect_tick) = signal(0u32);
+    #[allow(unused)]
+    let (reconnecting, set_reconnecting) = signal(false);
+
+    let is_dm = move || DmConversation::is_dm(&active_room.get());
+
+    let current_user = Resource::new(|| (), |_| get_user());
+    let current_user_id = move || current_user.get().and_then(|u| u.ok()).map(|u| u.id);
+    let is_superadmin = move || {
+        current_user
+            .get()
+            .and_then(|u| u.ok())
+            .is_some_and(|u| u.superadmin == Some(true))
+    };
+
+    let unread_counts = Resource::new(|| (), |_| get_unread_counts());
+    Effect::new(move |_| {
+        if let Some(Ok(counts)) = unread_counts.get() {
+            let active = active_room.get_untracked();
+            unread.set(
+                counts
+                    .into_iter()
+                    .filter(|c| c.room_id != active)
+                    .map(|c| (c.room_id.to_string(), c.count))
+                    .collect(),
+            );
+        }
+    });
 
-    // Load chat history
-    let chat_history = Resource::new(|| (), |_| async move { get_chat_history(Some(50)).await });
+    // Keep the sidebar badge in sync
+    let unread_total = use_context::<ChatUnreadTotal>();
+    Effect::new(move |_| {
+        let total = unread.with(|counts| counts.values().sum());
+        if let Some(unread_total) = unread_total {
+            unread_total.0.set(total);
+        }
+    });
+    let other_unread = move || unread.with(|counts| counts.values().sum::<usize>());
+
+    let online_users = Resource::new(|| (), |_| get_online_users());
+    Effect::new(move |_| {
+        if let Some(Ok(users)) = online_users.get() {
+            set_online.set(users);
+        }
+    });
+
+    let typing_text = move || {
+        let room_id = active_room.get();
+        let me = current_user_id();
+        let names: Vec<String> = typing_users.with(|all| {
+            all.iter()
+                .filter(|t| t.room_id == room_id && (t.user_id.is_none() || t.user_id != me))
+                .map(|t| t.username.clone())
+                .collect()
+        });
+        typing_label(&names)
+    };
 
-    // Convert loaded history to WsMessage format
+    let room_receipts = Resource::new(move || active_room.get(), get_read_receipts);
     Effect::new(move |_| {
-        if let Some(Ok(history)) = chat_history.get() {
-            let ws_messages: Vec<WsMessage> = history
-                .into_iter()
-                .map(|event| match event.event_type {
-                    ChatEventType::Message => WsMessage::Message(ChatMessage {
-                        user_id: event.user_id.unwrap_or_else(|| {
-                            // Fallback for anonymous users (shouldn't happen now)
-
-                            RecordId::from(("user", "anonymous"))
-                        }),
-                        username: event.username,
-                        message: event.message.unwrap_or_default(),
-                        timestamp: event.timestamp.to_string(),
-                    }),
-                    ChatEventType::UserJoined => WsMessage::UserJoined {
-                        username: event.username,
-                    },
-                    ChatEventType::UserLeft => WsMessage::UserLeft {
-                        username: event.username,
-                    },
+        if let Some(Ok(loaded)) = room_receipts.get() {
+            receipts.set(loaded);
+        }
+    });
+
+    // Last message a read receipt was sent for, so each is sent once
+    #[cfg(not(feature = "ssr"))]
+    let last_read_sent = StoredValue::new(Option::<RecordId>::None);
+
+    // Marks the newest loaded message of the room being viewed as read
+    #[cfg(not(feature = "ssr"))]
+    let mark_active_read = move || {
+        let room_id = active_room.get_untracked();
+        unread.update(|counts| {
+            counts.remove(&room_id.to_string());
+        });
+
+        let newest = messages.with_untracked(|msgs| {
+            msgs.iter().rev().find_map(|m| match m {
+                WsMessage::Message(chat) if chat.room_id == room_id => chat.id.clone(),
+                _ => None,
+            })
+        });
+        if let Some(event_id) = newest {
+            if last_read_sent.get_value().as_ref() != Some(&event_id)
+                && send_ws_message(&ClientWsMessage::ReadReceipt {
+                    room_id,
+                    event_id: event_id.clone(),
                 })
-                .collect();
-            set_messages.set(ws_messages);
+            {
+                last_read_sent.set_value(Some(event_id));
+            }
+        }
+    };
+
+    // When the last typing indicator was sent, in milliseconds since the epoch
+    #[cfg(not(feature = "ssr"))]
+    let last_typing_sent = StoredValue::new(0.0f64);
+
+    let on_input = move |ev: leptos::ev::Event| {
+        let value = event_target_value(&ev);
+        #[cfg(not(feature = "ssr"))]
+        {
+            let room_id = active_room.get_untracked();
+            let now = js_sys::Date::now();
+            if value.is_empty() {
+                if last_typing_sent.get_value() > 0.0 {
+                    send_ws_message(&ClientWsMessage::Typing {
+                        room_id,
+                        typing: false,
+                    });
+                    last_typing_sent.set_value(0.0);
+                }
+            } else if now - last_typing_sent.get_value() > TYPING_REFRESH_MS
+                && send_ws_message(&ClientWsMessage::Typing {
+                    room_id,
+                    typing: true,
+                })
+            {
+                last_typing_sent.set_value(now);
+            }
+        }
+        set_input_value.set(value);
+    };
+
+    let send_frame = Callback::new(move |#[allow(unused_variables)] msg: ClientWsMessage| {
+        #[cfg(not(feature = "ssr"))]
+        send_ws_message(&msg);
+    });
+
+    // Load chat history for the room or conversation being viewed
+    let chat_history = Resource::new(
+        move || (active_room.get(), anchor.get()),
+        |(room_id, anchor)| async move {
+            match anchor {
+                Some(event_id) => get_chat_context(event_id, Some(CHAT_HISTORY_PAGE_SIZE)).await,
+                None => load_history_page(room_id, None, None).await,
+            }
+        },
+    );
+
+    // Replace the room's messages with the loaded history
+    Effect::new(move |_| {
+        if let Some(Ok(page)) = chat_history.get() {
+            let room_id = active_room.get_untracked();
+            set_older_cursor.set(page.next_before);
+            set_messages.update(|msgs| {
+                msgs.retain(|m| m.room_id() != Some(&room_id));
+                msgs.extend(ChatEventDb::fold_history(page.events));
+            });
+            #[cfg(not(feature = "ssr"))]
+            mark_active_read();
+        }
+    });
+
+    // Failed reconnects since the socket was last open, for the backoff delay
+    #[cfg(not(feature = "ssr"))]
+    let reconnect_attempts = StoredValue::new(0u32);
+    #[cfg(not(feature = "ssr"))]
+    let has_connected = StoredValue::new(false);
+
+    // Ids of outbox messages already written to the current socket
+    #[cfg(not(feature = "ssr"))]
+    let in_flight = StoredValue::new(HashSet::<String>::new());
+
+    #[cfg(not(feature = "ssr"))]
+    let flush_outbox = move || {
+        outbox.with_untracked(|queue| {
+            for frame in queue {
+                let ClientWsMessage::Send {
+                    client_msg_id: Some(id),
+                    ..
+                } = frame
+                else {
+                    continue;
+                };
+                if in_flight.with_value(|sent| sent.contains(id)) {
+                    continue;
+                }
+                if !send_ws_message(frame) {
+                    break;
+                }
+                in_flight.update_value(|sent| {
+                    sent.insert(id.clone());
+                });
+            }
+        });
+    };
+
+    // Drops a message from the outbox once the server acknowledged or refused it
+    #[cfg(not(feature = "ssr"))]
+    let settle_outbox = move |id: &str| {
+        in_flight.update_value(|sent| {
+            sent.remove(id);
+        });
+        outbox.update(|queue| {
+            queue.retain(|frame| match frame {
+                ClientWsMessage::Send { client_msg_id, .. } => client_msg_id.as_deref() != Some(id),
+                _ => true,
+            })
+        });
+    };
+
+    // Catches up on what happened while the socket was closed: events in the room being
+    // viewed since its newest message, plus counts, receipts and presence
+    #[cfg(not(feature = "ssr"))]
+    let replay_missed = move || {
+        unread_counts.refetch();
+        online_users.refetch();
+        room_receipts.refetch();
+        set_dm_refresh.update(|n| *n += 1);
+
+        let room_id = active_room.get_untracked();
+        let since = messages.with_untracked(|msgs| {
+            msgs.iter().rev().find_map(|m| match m {
+                WsMessage::Message(chat) if chat.room_id == room_id => chat.sent_at(),
+                _ => None,
+            })
+        });
+        let Some(since) = since else {
+            chat_history.refetch();
+            return;
+        };
+
+        spawn_local(async move {
+            match load_history_page(room_id.clone(), None, Some(Datetime::from(since))).await {
+                Ok(page) if !page.has_more => {
+                    if room_id != active_room.get_untracked() {
+                        return;
+                    }
+                    set_messages.update(|msgs| {
+                        for event in page.events {
+                            let msg = event.into_ws_message();
+                            if !msg.apply_update(msgs) {
+                                push_unique(msgs, msg);
+                            }
+                        }
+                    });
+                    mark_active_read();
+                }
+                // Too much was missed to patch in, so start again from the latest page
+                _ => chat_history.refetch(),
+            }
+        });
+    };
+
+    let load_older = Action::new(move |(room_id, before): &(RecordId, RecordId)| {
+        let room_id = room_id.clone();
+        let before = before.clone();
+        async move {
+            (
+                room_id.clone(),
+                load_history_page(room_id, Some(before), None).await,
+            )
+        }
+    });
+
+    // Prepend older pages, unless the user switched rooms while it loaded
+    Effect::new(move |_| {
+        if let Some((room_id, Ok(page))) = load_older.value().get() {
+            if room_id != active_room.get_untracked() {
+                return;
+            }
+            set_older_cursor.set(page.next_before);
+            set_messages.update(|msgs| {
+                let older = ChatEventDb::fold_history(page.events);
+                msgs.splice(0..0, older);
+            });
+        }
+    });
+
+    let on_messages_scroll = move |_| {
+        let Some(container) = messages_container_ref.get_untracked() else {
+            return;
+        };
+        if container.scroll_top() > LOAD_OLDER_THRESHOLD_PX || load_older.pending().get_untracked()
+        {
+            return;
+        }
+        if let Some(before) = older_cursor.get_untracked() {
+            preserve_scroll.set_value(Some(container.scroll_height()));
+            load_older.dispatch((active_room.get_untracked(), before));
+        }
+    };
+
+    let room_messages = move || {
+        let room_id = active_room.get();
+        messages
+            .get()
+            .into_iter()
+            .filter(|m| m.room_id() == Some(&room_id))
+            .collect::<Vec<_>>()
+    };
+
+    let select_room = Callback::new(move |(room_id, name): (RecordId, String)| {
+        #[cfg(not(feature = "ssr"))]
+        send_ws_message(&ClientWsMessage::JoinRoom {
+            room_id: room_id.clone(),
+        });
+        set_anchor.set(None);
+        set_active_label.set(name);
+        set_active_room.set(room_id);
+    });
+
+    // DMs are routed to the user's sockets by the server, so there is nothing to join
+    let select_dm = Callback::new(move |(conversation_id, name): (RecordId, String)| {
+        set_anchor.set(None);
+        set_active_label.set(name);
+        set_active_room.set(conversation_id);
+    });
+
+    let jump_to_hit = Callback::new(move |hit: ChatSearchHit| {
+        #[cfg(not(feature = "ssr"))]
+        if !DmConversation::is_dm(&hit.room_id) {
+            send_ws_message(&ClientWsMessage::JoinRoom {
+                room_id: hit.room_id.clone(),
+            });
+        }
+        pending_scroll.set_value(Some(hit.event_id.clone()));
+        set_anchor.set(Some(hit.event_id));
+        set_active_label.set(hit.room_label.trim_start_matches(['#', '@']).to_string());
+        set_active_room.set(hit.room_id);
+    });
+
+    let leave_room = Callback::new(move |room_id: RecordId| {
+        #[cfg(not(feature = "ssr"))]
+        send_ws_message(&ClientWsMessage::LeaveRoom {
+            room_id: room_id.clone(),
+        });
+        set_messages.update(|msgs| msgs.retain(|m| m.room_id() != Some(&room_id)));
+        if active_room.get_untracked() == room_id {
+            set_anchor.set(None);
+            set_active_label.set(DEFAULT_ROOM.to_string());
+            set_active_room.set(default_room_id());
         }
     });
 
@@ -61,6 +495,9 @@ pub fn ChatApp() -> impl IntoView {
         Effect::new(move |_| {
             use leptos::logging::log;
 
+            // Runs again, opening a new socket, each time a reconnect is scheduled
+            reconnect_tick.track();
+
             let window = web_sys::window().expect("window");
             let location = window.location();
             let protocol = location.protocol().expect("protocol");
@@ -78,6 +515,25 @@ pub fn ChatApp() -> impl IntoView {
                     let onopen = Closure::wrap(Box::new(move || {
                         log!("WebSocket connected");
                         set_connected.set(true);
+                        set_reconnecting.set(false);
+                        reconnect_attempts.set_value(0);
+                        send_ws_message(&ClientWsMessage::Hello {
+                            protocol_version: PROTOCOL_VERSION,
+                        });
+                        // The server puts every socket in the default room; rejoin the room
+                        // being viewed so it keeps receiving messages.
+                        let room_id = active_room.get_untracked();
+                        if room_id != default_room_id() && !DmConversation::is_dm(&room_id) {
+                            send_ws_message(&ClientWsMessage::JoinRoom { room_id });
+                        }
+                        if has_connected.get_value() {
+                            replay_missed();
+                        }
+                        has_connected.set_value(true);
+                        // Whatever the old socket didn't get acked is sent again
+                        in_flight.update_value(|sent| sent.clear());
+                        flush_outbox();
+                        mark_active_read();
                     }) as Box<dyn Fn()>);
                     ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
                     onopen.forget();
@@ -86,9 +542,97 @@ pub fn ChatApp() -> impl IntoView {
                         if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
                             let msg_str: String = txt.into();
                             if let Ok(ws_msg) = serde_json::from_str::<WsMessage>(&msg_str) {
-                                // Increment new message count for visual indication
-                                if let WsMessage::Message(_) = &ws_msg {
-                                    _set_new_message_count.update(|count| *count += 1);
+                                match &ws_msg {
+                                    WsMessage::Message(chat_msg) => {
+                                        if DmConversation::is_dm(&chat_msg.room_id) {
+                                            set_dm_refresh.update(|n| *n += 1);
+                                        }
+                                        let from_me = current_user
+                                            .get_untracked()
+                                            .and_then(|u| u.ok())
+                                            .is_some_and(|u| u.id == chat_msg.user_id);
+                                        // A message ends its author's typing indicator
+                                        typing_users.update(|all| {
+                                            all.retain(|t| {
+                                                !t.is_same(
+                                                    &chat_msg.room_id,
+                                                    &Some(chat_msg.user_id.clone()),
+                                                    &chat_msg.username,
+                                                )
+                                            })
+                                        });
+                                        if chat_msg.room_id == active_room.get_untracked() {
+                                            set_messages
+                                                .update(|msgs| push_unique(msgs, ws_msg.clone()));
+                                            mark_active_read();
+                                            return;
+                                        }
+                                        if !from_me {
+                                            unread.update(|counts| {
+                                                *counts
+                                                    .entry(chat_msg.room_id.to_string())
+                                                    .or_default() += 1;
+                                            });
+                                        }
+                                    }
+                                    WsMessage::ReadReceipt(receipt) => {
+                                        receipts.update(|all| {
+                                            all.retain(|r| {
+                                                !(r.user.id == receipt.user.id
+                                                    && r.room_id == receipt.room_id)
+                                            });
+                                            all.push(receipt.clone());
+                                        });
+                                        return;
+                                    }
+                                    WsMessage::Typing {
+                                        room_id,
+                                        user_id,
+                                        username,
+                                        typing,
+                                    } => {
+                                        typing_users.update(|all| {
+                                            all.retain(|t| !t.is_same(room_id, user_id, username));
+                                            if *typing {
+                                                all.push(ChatTypingUser {
+                                                    room_id: room_id.clone(),
+                                                    user_id: user_id.clone(),
+                                                    username: username.clone(),
+                                                });
+                                            }
+                                        });
+                                        return;
+                                    }
+                                    WsMessage::Presence { online } => {
+                                        set_online.set(online.clone());
+                                        return;
+                                    }
+                                    WsMessage::Error {
+                                        code,
+                                        message,
+                                        client_msg_id,
+                                    } => {
+                                        log!("Chat error {:?}: {}", code, message);
+                                        if let Some(id) = client_msg_id {
+                                            settle_outbox(id);
+                                        }
+                                        set_ws_error.set(Some(message.clone()));
+                                        return;
+                                    }
+                                    WsMessage::MessageEdited { .. }
+                                    | WsMessage::MessageDeleted { .. }
+                                    | WsMessage::Reaction { .. } => {
+                                        set_messages.update(|msgs| {
+                                            ws_msg.apply_update(msgs);
+                                        });
+                                        return;
+                                    }
+                                    WsMessage::Ack { client_msg_id, .. } => {
+                                        settle_outbox(client_msg_id);
+                                        return;
+                                    }
+                                    WsMessage::Welcome { .. } | WsMessage::Pong { .. } => return,
+                                    WsMessage::UserJoined { .. } | WsMessage::UserLeft { .. } => {}
                                 }
                                 set_messages.update(|msgs| msgs.push(ws_msg));
                             }
@@ -99,8 +643,16 @@ pub fn ChatApp() -> impl IntoView {
                     onmessage.forget();
 
                     let onclose = Closure::wrap(Box::new(move || {
-                        log!("WebSocket disconnected");
                         set_connected.set(false);
+                        set_reconnecting.set(true);
+
+                        // Jitter spreads out reconnects when a server restart drops everyone
+                        let attempt = reconnect_attempts.get_value();
+                        reconnect_attempts.set_value(attempt.saturating_add(1));
+                        let jitter = Duration::from_millis((js_sys::Math::random() * 250.0) as u64);
+                        let delay = reconnect_delay(attempt) + jitter;
+                        log!("WebSocket disconnected, reconnecting in {:?}", delay);
+                        set_timeout(move || set_reconnect_tick.update(|n| *n += 1), delay);
                     }) as Box<dyn Fn()>);
                     ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
                     onclose.forget();
@@ -116,10 +668,34 @@ pub fn ChatApp() -> impl IntoView {
             }
         });
 
-        // Auto-scroll to bottom when new messages arrive
+        // Auto-scroll to bottom when new messages arrive, or keep the view in place when
+        // older messages were prepended
         Effect::new(move |_| {
             let _ = messages.get(); // Subscribe to message changes
             if let Some(container) = messages_container_ref.get() {
+                if let Some(prev_height) = preserve_scroll
+                    .try_update_value(|prev| prev.take())
+                    .flatten()
+                {
+                    container.set_scroll_top(container.scroll_height() - prev_height);
+                    return;
+                }
+
+                // Bring a search hit into view once its element exists
+                if let Some(event_id) = pending_scroll.get_value() {
+                    let document = web_sys::window()
+                        .expect("window")
+                        .document()
+                        .expect("document");
+                    if let Some(element) =
+                        document.get_element_by_id(&message_element_id(&event_id))
+                    {
+                        element.scroll_into_view();
+                        pending_scroll.set_value(None);
+                    }
+                    return;
+                }
+
                 // Scroll to bottom immediately
                 container.set_scroll_top(container.scroll_height());
             }
@@ -136,34 +712,82 @@ pub fn ChatApp() -> impl IntoView {
 
         #[cfg(not(feature = "ssr"))]
         {
-            let window = web_sys::window().expect("window");
-            if let Ok(ws_value) = js_sys::Reflect::get(&window, &JsValue::from_str("chat_ws")) {
-                if let Ok(ws) = ws_value.dyn_into::<WebSocket>() {
-                    if ws.ready_state() == WebSocket::OPEN {
-                        let _ = ws.send_with_str(&msg);
-                        set_input_value.set(String::new());
-                        // Reset new message count when user sends a message
-                        _set_new_message_count.set(0);
-                    }
-                }
-            }
+            // Queue first so messages typed while offline go out in order after reconnecting
+            outbox.update(|queue| {
+                queue.push(ClientWsMessage::Send {
+                    room_id: active_room.get_untracked(),
+                    message: msg,
+                    client_msg_id: Some(new_client_msg_id()),
+                })
+            });
+            flush_outbox();
+            // The server clears the typing indicator when the message arrives
+            last_typing_sent.set_value(0.0);
         }
+        set_ws_error.set(None);
+        set_input_value.set(String::new());
+    };
+
+    let pending_messages = move || {
+        let room_id = active_room.get();
+        outbox.with(|queue| {
+            queue
+                .iter()
+                .filter_map(|frame| match frame {
+                    ClientWsMessage::Send {
+                        room_id: to,
+                        message,
+                        ..
+                    } if *to == room_id => Some(message.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
     };
 
     view! {
-        <div class="flex flex-col h-full bg-white dark:bg-neutral-950">
+        <div class="flex flex-row h-full bg-white dark:bg-neutral-950">
+        <aside class="w-48 shrink-0 border-r border-neutral-200 dark:border-neutral-800 p-2 flex flex-col gap-4 overflow-y-auto">
+            <ChatRoomList active_room=active_room on_select=select_room on_leave=leave_room />
+            <DirectMessageList active_room=active_room refresh=dm_refresh on_select=select_dm />
+            <OnlineUserList online=online />
+        </aside>
+        <div class="flex flex-col flex-1 min-w-0 h-full">
             <div class="">
                 <div class="">
                     <div class="flex items-center justify-between mt-2">
-                        <div class="flex items-center">
+                        <div class="flex items-center gap-2 px-2">
                             <div class={move || if connected.get() { "w-5 h-5 bg-green-500 rounded-full" } else { "w-5 h-5 bg-red-500 rounded-full" }}></div>
+                            <h2 class="font-semibold text-neutral-900 dark:text-neutral-100">
+                                {move || if is_dm() { format!("@{}", active_label.get()) } else { format!("#{}", active_label.get()) }}
+                            </h2>
+                            {move || reconnecting.get().then(|| {
+                                view! { <span class="text-xs text-neutral-500 dark:text-neutral-400">"Reconnecting..."</span> }
+                            })}
+                            {move || anchor.get().map(|_| {
+                                view! {
+                                    <button
+                                        class="text-xs text-blue-600 dark:text-blue-400 hover:underline"
+                                        on:click=move |_| set_anchor.set(None)
+                                    >
+                                        "Jump to latest"
+                                    </button>
+                                }
+                            })}
                         </div>
+                        <button
+                            class="ml-auto mr-2 p-1 rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                            title="Search messages"
+                            on:click=move |_| set_search_open.update(|open| *open = !*open)
+                        >
+                            <Icon icon=MAGNIFYING_GLASS size="18px" />
+                        </button>
                         {move || {
-                            let count = new_message_count.get();
+                            let count = other_unread();
                             if count > 0 {
                                 view! {
                                     <div class="bg-blue-500 text-white text-xs px-2 py-1 rounded-full animate-pulse">
-                                        {if count == 1 { "New message".to_string() } else { format!("{} new messages", count) }}
+                                        {if count == 1 { "1 unread elsewhere".to_string() } else { format!("{} unread elsewhere", count) }}
                                     </div>
                                 }.into_any()
                             } else {
@@ -177,25 +801,34 @@ pub fn ChatApp() -> impl IntoView {
             <div
                 class="flex-1 overflow-y-auto p-4 flex flex-col justify-end"
                 node_ref=messages_container_ref
+                on:scroll=on_messages_scroll
             >
+                {move || load_older.pending().get().then(|| {
+                    view! { <p class="text-center text-xs text-neutral-500">"Loading earlier messages..."</p> }
+                })}
                 <div class="max-w-4xl mx-auto space-y-3 flex flex-col justify-end min-h-full">
                     <For
-                        each=move || messages.get()
+                        each=room_messages
                         key=|msg| match msg {
-                            WsMessage::UserJoined { username } => format!("join_{}", username),
-                            WsMessage::UserLeft { username } => format!("leave_{}", username),
-                            WsMessage::Message(m) => format!("msg_{}_{}", m.username, m.timestamp),
+                            WsMessage::UserJoined { username, room_id } => format!("join_{}_{}", room_id, username),
+                            WsMessage::UserLeft { username, room_id } => format!("leave_{}_{}", room_id, username),
+                            // Include the content so edited messages and reactions re-render
+                            WsMessage::Message(m) => format!(
+                                "msg_{}_{}_{}_{}_{:?}",
+                                m.room_id, m.username, m.timestamp, m.message, m.reactions
+                            ),
+                            other => format!("{:?}", other),
                         }
                         children=move |msg| {
                             match msg {
-                                WsMessage::UserJoined { username } => {
+                                WsMessage::UserJoined { username, .. } => {
                                     view! {
                                         <div class="text-center text-sm text-neutral-500 dark:text-neutral-400">
                                             <span class="font-medium">{username}</span> " joined the chat"
                                         </div>
                                     }.into_any()
                                 },
-                                WsMessage::UserLeft { username } => {
+                                WsMessage::UserLeft { username, .. } => {
                                     view! {
                                         <div class="text-center text-sm text-neutral-500 dark:text-neutral-400">
                                             <span class="font-medium">{username}</span> " left the chat"
@@ -205,9 +838,26 @@ pub fn ChatApp() -> impl IntoView {
                                 WsMessage::Message(chat_msg) => {
                                     let user_id = chat_msg.user_id.clone();
                                     let username = chat_msg.username.clone();
-                                    let message = chat_msg.message.clone();
                                     let timestamp = chat_msg.timestamp.clone();
                                     let fallback_username = username.clone();
+                                    let viewer_id = current_user_id();
+                                    let element_id = chat_msg.id.as_ref().map(message_element_id);
+                                    let is_anchor = chat_msg.id.is_some() && chat_msg.id == anchor.get_untracked();
+                                    let seen_event_id = chat_msg.id.clone();
+                                    let seen_by = Signal::derive(move || {
+                                        let Some(event_id) = seen_event_id.as_ref() else {
+                                            return Vec::new();
+                                        };
+                                        let me = current_user_id();
+                                        receipts.with(|all| {
+                                            all.iter()
+                                                .filter(|r| &r.event_id == event_id && Some(&r.user.id) != me.as_ref())
+                                                .map(|r| r.user.clone())
+                                                .collect()
+                                        })
+                                    });
+                                    let can_modify = is_superadmin()
+                                        || viewer_id.as_ref() == Some(&chat_msg.user_id);
 
                                     let user_resource = Resource::new(
                                         move || user_id.clone(),
@@ -215,7 +865,14 @@ pub fn ChatApp() -> impl IntoView {
                                     );
 
                                     view! {
-                                        <div class="p-1 animate-in slide-in-from-bottom-2 duration-300">
+                                        <div
+                                            id=element_id
+                                            class=if is_anchor {
+                                                "p-1 rounded-md bg-yellow-50 dark:bg-yellow-950 ring-1 ring-yellow-300 dark:ring-yellow-700"
+                                            } else {
+                                                "p-1 animate-in slide-in-from-bottom-2 duration-300"
+                                            }
+                                        >
                                             <div class="flex items-start space-x-3">
                                                 <Suspense fallback=move || {
                                                     view! {
@@ -248,11 +905,17 @@ pub fn ChatApp() -> impl IntoView {
                                                 <div class="flex-1 min-w-0">
                                                     <div class="flex items-baseline">
 
-                                                          <p class="text-neutral-700 dark:text-neutral-300 w-full">
-                                                            <span class="font-semibold text-neutral-900 dark:text-neutral-100 mr-2 ">
-                                                                {username.clone()}
-                                                            </span>: {message.clone()}
-                                                        </p>
+                                                          <div class="flex gap-2 text-neutral-700 dark:text-neutral-300 w-full">
+                                                            <span class="font-semibold text-neutral-900 dark:text-neutral-100">
+                                                                {username.clone()}:
+                                                            </span>
+                                                            <ChatMessageContent
+                                                                message=chat_msg
+                                                                current_user_id=viewer_id
+                                                                can_modify=can_modify
+                                                                on_send=send_frame
+                                                            />
+                                                        </div>
                                                         <span class="text-xs text-neutral-500 dark:text-neutral-400">
                                                             {
 
@@ -265,33 +928,62 @@ pub fn ChatApp() -> impl IntoView {
 
                                                         </span>
                                                     </div>
-
+                                                    <SeenBy readers=seen_by />
                                                 </div>
                                             </div>
                                         </div>
                                     }.into_any()
                                 }
+                                // Protocol replies are never stored in the message list
+                                WsMessage::MessageEdited { .. }
+                                | WsMessage::MessageDeleted { .. }
+                                | WsMessage::Reaction { .. }
+                                | WsMessage::ReadReceipt(_)
+                                | WsMessage::Typing { .. }
+                                | WsMessage::Presence { .. }
+                                | WsMessage::Welcome { .. }
+                                | WsMessage::Pong { .. }
+                                | WsMessage::Ack { .. }
+                                | WsMessage::Error { .. } => {
+                                    view! { <div></div> }.into_any()
+                                }
                             }
                         }
                     />
+                    {move || {
+                        pending_messages()
+                            .into_iter()
+                            .map(|text| {
+                                view! {
+                                    <div class="p-1 text-neutral-500 dark:text-neutral-400" title="Sent once the connection is back">
+                                        {text} <span class="ml-1 text-xs italic">"(waiting to send)"</span>
+                                    </div>
+                                }
+                            })
+                            .collect_view()
+                    }}
                 </div>
             </div>
 
             <div class="bg-white dark:bg-neutral-800 border-t border-neutral-200 dark:border-neutral-700 p-4">
                 <div class="max-w-4xl mx-auto">
+                    <p class="h-4 mb-1 text-xs italic text-neutral-500 dark:text-neutral-400">
+                        {typing_text}
+                    </p>
+                    {move || ws_error.get().map(|msg| {
+                        view! { <p class="mb-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+                    })}
                     <form on:submit=send_message class="flex gap-2">
                         <input
                             type="text"
                             class="flex-1 px-4 py-2 border border-neutral-300 dark:border-neutral-600 rounded-lg bg-white dark:bg-neutral-900 text-neutral-900 dark:text-neutral-100 focus:outline-none focus:ring-2 focus:ring-blue-500 dark:focus:ring-blue-400"
-                            placeholder="Type a message..."
+                            placeholder=move || if is_dm() { format!("Message {}...", active_label.get()) } else { "Type a message...".to_string() }
                             prop:value=move || input_value.get()
-                            on:input=move |ev| set_input_value.set(event_target_value(&ev))
-                            disabled=move || !connected.get()
+                            on:input=on_input
                         />
                         <button
                             type="submit"
-                            class="px-6 py-2 bg-blue-600 hover:bg-blue-700 disabled:bg-neutral-400 text-white font-medium rounded-lg transition-colors duration-200 disabled:cursor-not-allowed"
-                            disabled=move || !connected.get()
+                            class="px-6 py-2 bg-blue-600 hover:bg-blue-700 text-white font-medium rounded-lg transition-colors duration-200"
                         >
                             "Send"
                         </button>
@@ -299,5 +991,17 @@ pub fn ChatApp() -> impl IntoView {
                 </div>
             </div>
         </div>
+        {move || search_open.get().then(|| {
+            view! {
+                <aside class="w-72 shrink-0 border-l border-neutral-200 dark:border-neutral-800 p-2">
+                    <ChatSearchPanel
+                        active_room=active_room
+                        on_jump=jump_to_hit
+                        on_close=Callback::new(move |_| set_search_open.set(false))
+                    />
+                </aside>
+            }
+        })}
+        </div>
     }
 }
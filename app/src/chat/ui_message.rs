@@ -0,0 +1,207 @@
+use leptos::prelude::*;
+use phosphor_leptos::{Icon, PENCIL_SIMPLE, SMILEY, TRASH};
+
+use crate::chat::models::ChatUser;
+use crate::chat::shared::{ChatMessage, ClientWsMessage, REACTION_EMOJI};
+use crate::components::UserAvatar;
+use crate::RecordId;
+
+/// Most readers shown as avatars before the rest are summarised as "+N".
+const SEEN_BY_MAX_AVATARS: usize = 5;
+
+/// Text, reactions and edit/delete/react controls of one chat message.
+#[component]
+pub fn ChatMessageContent(
+    message: ChatMessage,
+    /// Id of the signed in user, used to highlight their own reactions.
+    current_user_id: Option<RecordId>,
+    /// Whether the signed in user may edit and delete this message.
+    can_modify: bool,
+    on_send: Callback<ClientWsMessage>,
+) -> impl IntoView {
+    let (editing, set_editing) = signal(false);
+    let (edit_value, set_edit_value) = signal(message.message.clone());
+    let (picker_open, set_picker_open) = signal(false);
+
+    // Messages that were never stored cannot be referenced by edits or reactions
+    let event_id = StoredValue::new(message.id.clone());
+
+    let react = move |emoji: String| {
+        if let Some(event_id) = event_id.get_value() {
+            on_send.run(ClientWsMessage::React { event_id, emoji });
+        }
+        set_picker_open.set(false);
+    };
+
+    let submit_edit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let text = edit_value.get();
+        if text.trim().is_empty() {
+            return;
+        }
+        if let Some(event_id) = event_id.get_value() {
+            on_send.run(ClientWsMessage::Edit {
+                event_id,
+                message: text,
+            });
+        }
+        set_editing.set(false);
+    };
+
+    let delete = move |_| {
+        if let Some(event_id) = event_id.get_value() {
+            on_send.run(ClientWsMessage::Delete { event_id });
+        }
+    };
+
+    let reactions = message
+        .reactions
+        .iter()
+        .map(|reaction| {
+            let emoji = reaction.emoji.clone();
+            let mine = current_user_id
+                .as_ref()
+                .is_some_and(|id| reaction.user_ids.contains(id));
+            let class = if mine {
+                "px-1.5 py-0.5 text-xs rounded-full border border-blue-400 bg-blue-50 dark:bg-blue-950"
+            } else {
+                "px-1.5 py-0.5 text-xs rounded-full border border-neutral-300 dark:border-neutral-600"
+            };
+            view! {
+                <button class=class on:click=move |_| react(emoji.clone())>
+                    {reaction.emoji.clone()} " " {reaction.user_ids.len()}
+                </button>
+            }
+        })
+        .collect_view();
+
+    let has_id = message.id.is_some();
+    let text = message.message.clone();
+    let edited = message.edited;
+
+    view! {
+        <div class="group flex flex-col gap-1 w-full">
+            {move || {
+                if editing.get() {
+                    view! {
+                        <form on:submit=submit_edit class="flex gap-2">
+                            <input
+                                type="text"
+                                class="flex-1 px-2 py-1 text-sm border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                                prop:value=move || edit_value.get()
+                                on:input=move |ev| set_edit_value.set(event_target_value(&ev))
+                            />
+                            <button type="submit" class="text-sm text-blue-600 dark:text-blue-400">"Save"</button>
+                            <button
+                                type="button"
+                                class="text-sm text-neutral-500"
+                                on:click=move |_| set_editing.set(false)
+                            >
+                                "Cancel"
+                            </button>
+                        </form>
+                    }
+                        .into_any()
+                } else {
+                    view! {
+                        <span>
+                            {text.clone()}
+                            {edited.then(|| view! { <span class="ml-1 text-xs text-neutral-500">"(edited)"</span> })}
+                        </span>
+                    }
+                        .into_any()
+                }
+            }}
+            <div class="flex flex-wrap items-center gap-1">
+                {reactions}
+                {has_id.then(|| {
+                    view! {
+                        <div class="flex items-center gap-1 opacity-0 group-hover:opacity-100">
+                            <button
+                                class="p-1 text-neutral-500 hover:text-neutral-900 dark:hover:text-neutral-100"
+                                title="React"
+                                on:click=move |_| set_picker_open.update(|open| *open = !*open)
+                            >
+                                <Icon icon=SMILEY size="14px" />
+                            </button>
+                            {can_modify.then(|| {
+                                view! {
+                                    <button
+                                        class="p-1 text-neutral-500 hover:text-neutral-900 dark:hover:text-neutral-100"
+                                        title="Edit"
+                                        on:click=move |_| set_editing.set(true)
+                                    >
+                                        <Icon icon=PENCIL_SIMPLE size="14px" />
+                                    </button>
+                                    <button
+                                        class="p-1 text-neutral-500 hover:text-red-600 dark:hover:text-red-400"
+                                        title="Delete"
+                                        on:click=delete
+                                    >
+                                        <Icon icon=TRASH size="14px" />
+                                    </button>
+                                }
+                            })}
+                        </div>
+                    }
+                })}
+                {move || {
+                    picker_open.get().then(|| {
+                        view! {
+                            <div class="flex gap-1 px-1 rounded-md bg-neutral-100 dark:bg-neutral-800">
+                                {REACTION_EMOJI
+                                    .iter()
+                                    .map(|emoji| {
+                                        let emoji = emoji.to_string();
+                                        view! {
+                                            <button class="px-1" on:click={
+                                                let emoji = emoji.clone();
+                                                move |_| react(emoji.clone())
+                                            }>
+                                                {emoji}
+                                            </button>
+                                        }
+                                    })
+                                    .collect_view()}
+                            </div>
+                        }
+                    })
+                }}
+            </div>
+        </div>
+    }
+}
+
+/// Avatars of the users whose last read message is this one.
+#[component]
+pub fn SeenBy(readers: Signal<Vec<ChatUser>>) -> impl IntoView {
+    move || {
+        let readers = readers.get();
+        if readers.is_empty() {
+            return view! { <div></div> }.into_any();
+        }
+
+        let names = readers
+            .iter()
+            .map(|r| r.name.clone())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let overflow = readers.len().saturating_sub(SEEN_BY_MAX_AVATARS);
+
+        view! {
+            <div class="flex items-center justify-end gap-0.5 mt-1" title=format!("Seen by {}", names)>
+                {readers
+                    .into_iter()
+                    .take(SEEN_BY_MAX_AVATARS)
+                    .map(|reader| {
+                        view! { <UserAvatar name=Some(reader.name) image=reader.image size="sm" /> }
+                    })
+                    .collect_view()}
+                {(overflow > 0).then(|| {
+                    view! { <span class="text-xs text-neutral-500">{format!("+{}", overflow)}</span> }
+                })}
+            </div>
+        }
+            .into_any()
+    }
+}
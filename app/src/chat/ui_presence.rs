@@ -0,0 +1,33 @@
+use leptos::prelude::*;
+
+use crate::chat::models::ChatUser;
+use crate::components::UserAvatar;
+
+/// Signed in users who currently have chat open, kept live by `WsMessage::Presence` events.
+#[component]
+pub fn OnlineUserList(online: ReadSignal<Vec<ChatUser>>) -> impl IntoView {
+    view! {
+        <div class="flex flex-col gap-2">
+            <h2 class="px-2 text-xs font-semibold uppercase tracking-wide text-neutral-500 dark:text-neutral-400">
+                {move || format!("Online — {}", online.with(|users| users.len()))}
+            </h2>
+            <ul class="flex flex-col gap-1">
+                <For
+                    each=move || online.get()
+                    key=|user| user.id.to_string()
+                    children=move |user| {
+                        view! {
+                            <li class="flex items-center gap-2 px-2 py-1 text-sm">
+                                <span class="relative">
+                                    <UserAvatar name=Some(user.name.clone()) image=user.image.clone() size="sm" />
+                                    <span class="absolute -bottom-0.5 -right-0.5 w-2 h-2 rounded-full bg-green-500 ring-1 ring-white dark:ring-neutral-950"></span>
+                                </span>
+                                <span class="truncate">{user.name.clone()}</span>
+                            </li>
+                        }
+                    }
+                />
+            </ul>
+        </div>
+    }
+}
@@ -0,0 +1,246 @@
+use leptos::prelude::*;
+use phosphor_leptos::{Icon, HASH, PLUS, SIGN_OUT};
+
+use crate::chat::models::{
+    create_chat_room, get_chat_rooms, get_chat_users, get_dm_conversations, open_dm_conversation,
+    ChatRoom, DmConversationSummary,
+};
+use crate::chat::shared::default_room_id;
+use crate::components::UserAvatar;
+use crate::RecordId;
+
+#[component]
+pub fn ChatRoomList(
+    active_room: ReadSignal<RecordId>,
+    /// Called with the room id and its display name.
+    on_select: Callback<(RecordId, String)>,
+    on_leave: Callback<RecordId>,
+) -> impl IntoView {
+    let rooms = Resource::new(|| (), |_| get_chat_rooms());
+    let (new_room_name, set_new_room_name) = signal(String::new());
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+
+    let create_action = Action::new(move |name: &String| {
+        let name = name.clone();
+        async move { create_chat_room(name, None).await }
+    });
+
+    Effect::new(move |_| {
+        if let Some(result) = create_action.value().get() {
+            match result {
+                Ok(room) => {
+                    set_new_room_name.set(String::new());
+                    set_error_message.set(None);
+                    rooms.refetch();
+                    on_select.run((room.id, room.name));
+                }
+                Err(e) => set_error_message.set(Some(e.to_string())),
+            }
+        }
+    });
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let name = new_room_name.get();
+        if name.trim().is_empty() {
+            return;
+        }
+        create_action.dispatch(name);
+    };
+
+    let room_row = move |room: ChatRoom| {
+        let room_id = room.id.clone();
+        let select = (room.id.clone(), room.name.clone());
+        let leave_id = room.id.clone();
+        let is_default = room.id == default_room_id();
+        let is_active = move || active_room.get() == room_id;
+
+        view! {
+            <li class="group flex items-center gap-1">
+                <button
+                    class=move || {
+                        if is_active() {
+                            "flex-1 flex items-center gap-1 px-2 py-1 rounded-md text-left text-sm bg-neutral-200 dark:bg-neutral-800 font-medium"
+                        } else {
+                            "flex-1 flex items-center gap-1 px-2 py-1 rounded-md text-left text-sm hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                        }
+                    }
+                    title=room.description.clone().unwrap_or_default()
+                    on:click=move |_| on_select.run(select.clone())
+                >
+                    <Icon icon=HASH size="14px" />
+                    <span class="truncate">{room.name.clone()}</span>
+                </button>
+                {(!is_default).then(|| {
+                    view! {
+                        <button
+                            class="opacity-0 group-hover:opacity-100 p-1 text-neutral-500 hover:text-red-600 dark:hover:text-red-400"
+                            title="Leave room"
+                            on:click=move |_| on_leave.run(leave_id.clone())
+                        >
+                            <Icon icon=SIGN_OUT size="14px" />
+                        </button>
+                    }
+                })}
+            </li>
+        }
+    };
+
+    view! {
+        <div class="flex flex-col gap-2">
+            <h2 class="px-2 text-xs font-semibold uppercase tracking-wide text-neutral-500 dark:text-neutral-400">
+                "Rooms"
+            </h2>
+            <Suspense fallback=move || {
+                view! { <div class="h-4 mx-2 bg-neutral-200 dark:bg-neutral-700 rounded animate-pulse"></div> }
+            }>
+                {move || match rooms.get() {
+                    Some(Ok(rooms)) => {
+                        view! {
+                            <ul class="flex flex-col gap-1">
+                                {rooms.into_iter().map(room_row).collect_view()}
+                            </ul>
+                        }
+                            .into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! {
+                            <p class="px-2 text-xs text-red-600 dark:text-red-400">{e.to_string()}</p>
+                        }
+                            .into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+
+            <form on:submit=submit class="flex items-center gap-1 mt-2">
+                <input
+                    type="text"
+                    class="flex-1 min-w-0 px-2 py-1 text-sm border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                    placeholder="New room"
+                    prop:value=move || new_room_name.get()
+                    on:input=move |ev| set_new_room_name.set(event_target_value(&ev))
+                />
+                <button
+                    type="submit"
+                    class="p-1 rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                    title="Create room"
+                >
+                    <Icon icon=PLUS size="16px" />
+                </button>
+            </form>
+            {move || error_message.get().map(|msg| {
+                view! { <p class="px-2 text-xs text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+#[component]
+pub fn DirectMessageList(
+    active_room: ReadSignal<RecordId>,
+    /// Bumped by the parent when a message arrives for a conversation, so new DMs show up.
+    refresh: ReadSignal<usize>,
+    /// Called with the conversation id and the other participant's name.
+    on_select: Callback<(RecordId, String)>,
+) -> impl IntoView {
+    let conversations = Resource::new(move || refresh.get(), |_| get_dm_conversations());
+    let users = Resource::new(|| (), |_| get_chat_users());
+
+    let open_action = Action::new(move |user_id: &RecordId| {
+        let user_id = user_id.clone();
+        async move { open_dm_conversation(user_id).await }
+    });
+
+    Effect::new(move |_| {
+        if let Some(Ok(summary)) = open_action.value().get() {
+            conversations.refetch();
+            on_select.run((summary.conversation.id, summary.other.name));
+        }
+    });
+
+    let conversation_row = move |summary: DmConversationSummary| {
+        let conversation_id = summary.conversation.id.clone();
+        let select = (summary.conversation.id.clone(), summary.other.name.clone());
+        let is_active = move || active_room.get() == conversation_id;
+
+        view! {
+            <li>
+                <button
+                    class=move || {
+                        if is_active() {
+                            "w-full flex items-center gap-2 px-2 py-1 rounded-md text-left text-sm bg-neutral-200 dark:bg-neutral-800 font-medium"
+                        } else {
+                            "w-full flex items-center gap-2 px-2 py-1 rounded-md text-left text-sm hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                        }
+                    }
+                    on:click=move |_| on_select.run(select.clone())
+                >
+                    <UserAvatar name=Some(summary.other.name.clone()) image=summary.other.image.clone() size="sm" />
+                    <span class="truncate">{summary.other.name.clone()}</span>
+                </button>
+            </li>
+        }
+    };
+
+    view! {
+        <div class="flex flex-col gap-2">
+            <h2 class="px-2 text-xs font-semibold uppercase tracking-wide text-neutral-500 dark:text-neutral-400">
+                "Direct messages"
+            </h2>
+            <Suspense fallback=move || view! { <div></div> }>
+                {move || match conversations.get() {
+                    Some(Ok(conversations)) => {
+                        view! {
+                            <ul class="flex flex-col gap-1">
+                                {conversations.into_iter().map(conversation_row).collect_view()}
+                            </ul>
+                        }
+                            .into_any()
+                    }
+                    Some(Err(e)) => {
+                        view! {
+                            <p class="px-2 text-xs text-red-600 dark:text-red-400">{e.to_string()}</p>
+                        }
+                            .into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+            <Suspense fallback=move || view! { <div></div> }>
+                {move || {
+                    users
+                        .get()
+                        .and_then(|users| users.ok())
+                        .map(|users| {
+                            let options = StoredValue::new(users);
+                            view! {
+                                <select
+                                    class="mx-2 px-2 py-1 text-sm border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                                    on:change=move |ev| {
+                                        let value = event_target_value(&ev);
+                                        let selected = options
+                                            .get_value()
+                                            .into_iter()
+                                            .find(|u| u.id.to_string() == value);
+                                        if let Some(user) = selected {
+                                            open_action.dispatch(user.id);
+                                        }
+                                    }
+                                >
+                                    <option value="" selected=true>"New message..."</option>
+                                    {options
+                                        .get_value()
+                                        .into_iter()
+                                        .map(|user| {
+                                            view! { <option value=user.id.to_string()>{user.name}</option> }
+                                        })
+                                        .collect_view()}
+                                </select>
+                            }
+                        })
+                }}
+            </Suspense>
+        </div>
+    }
+}
@@ -0,0 +1,197 @@
+use chrono::{NaiveDate, Utc};
+use leptos::prelude::*;
+use phosphor_leptos::{Icon, MAGNIFYING_GLASS, X};
+
+use crate::chat::models::get_chat_users;
+use crate::chat::search::{search_chat, ChatSearchFilter, ChatSearchHit};
+use crate::date_utils::{format_time_iso, TimeFormatVariant};
+use crate::{Datetime, RecordId};
+
+/// Midnight UTC of a `YYYY-MM-DD` date input value, shifted by `days`.
+fn parse_date_input(value: &str, days: i64) -> Option<Datetime> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let date = date.checked_add_signed(chrono::Duration::days(days))?;
+    let datetime = date
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Utc)
+        .single()?;
+    Some(Datetime::from(datetime))
+}
+
+#[component]
+pub fn ChatSearchPanel(
+    active_room: ReadSignal<RecordId>,
+    /// Called when a result is clicked, to show the message in its room.
+    on_jump: Callback<ChatSearchHit>,
+    on_close: Callback<()>,
+) -> impl IntoView {
+    let (query, set_query) = signal(String::new());
+    let (this_room_only, set_this_room_only) = signal(false);
+    let (author, set_author) = signal(String::new());
+    let (from, set_from) = signal(String::new());
+    let (to, set_to) = signal(String::new());
+
+    let users = Resource::new(|| (), |_| get_chat_users());
+
+    let search_action = Action::new(move |(query, filter): &(String, ChatSearchFilter)| {
+        let query = query.clone();
+        let filter = filter.clone();
+        async move { search_chat(query, filter).await }
+    });
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let text = query.get();
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let author = author.get();
+        let filter = ChatSearchFilter {
+            room_id: this_room_only.get().then(|| active_room.get()),
+            author_id: users
+                .get()
+                .and_then(|users| users.ok())
+                .and_then(|users| users.into_iter().find(|u| u.id.to_string() == author))
+                .map(|u| u.id),
+            from: parse_date_input(&from.get(), 0),
+            // The end date is inclusive in the form, so search up to the next midnight
+            to: parse_date_input(&to.get(), 1),
+        };
+        search_action.dispatch((text, filter));
+    };
+
+    let hit_row = move |hit: ChatSearchHit| {
+        let time_display = format_time_iso(hit.timestamp.to_string(), TimeFormatVariant::Ago);
+        let snippet = hit
+            .snippet
+            .iter()
+            .map(|part| {
+                if part.highlighted {
+                    view! { <mark class="bg-yellow-200 dark:bg-yellow-700 rounded-sm">{part.text.clone()}</mark> }
+                        .into_any()
+                } else {
+                    view! { <span>{part.text.clone()}</span> }.into_any()
+                }
+            })
+            .collect_view();
+        let room_label = hit.room_label.clone();
+        let username = hit.username.clone();
+
+        view! {
+            <li>
+                <button
+                    class="w-full flex flex-col gap-1 p-2 rounded-md text-left text-sm hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                    on:click=move |_| on_jump.run(hit.clone())
+                >
+                    <div class="flex items-center justify-between gap-2 text-xs text-neutral-500 dark:text-neutral-400">
+                        <span class="truncate">{room_label} " · " {username}</span>
+                        <span class="whitespace-nowrap">{time_display.1}</span>
+                    </div>
+                    <p class="text-neutral-700 dark:text-neutral-300 break-words">{snippet}</p>
+                </button>
+            </li>
+        }
+    };
+
+    view! {
+        <div class="flex flex-col gap-2 h-full">
+            <div class="flex items-center justify-between">
+                <h2 class="text-xs font-semibold uppercase tracking-wide text-neutral-500 dark:text-neutral-400">
+                    "Search"
+                </h2>
+                <button
+                    class="p-1 rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-800"
+                    title="Close search"
+                    on:click=move |_| on_close.run(())
+                >
+                    <Icon icon=X size="14px" />
+                </button>
+            </div>
+            <form on:submit=submit class="flex flex-col gap-2 text-sm">
+                <div class="flex items-center gap-1">
+                    <input
+                        type="search"
+                        class="flex-1 min-w-0 px-2 py-1 border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                        placeholder="Search messages"
+                        prop:value=move || query.get()
+                        on:input=move |ev| set_query.set(event_target_value(&ev))
+                    />
+                    <button type="submit" class="p-1 rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-800" title="Search">
+                        <Icon icon=MAGNIFYING_GLASS size="16px" />
+                    </button>
+                </div>
+                <label class="flex items-center gap-2 text-neutral-600 dark:text-neutral-400">
+                    <input
+                        type="checkbox"
+                        prop:checked=move || this_room_only.get()
+                        on:change=move |ev| set_this_room_only.set(event_target_checked(&ev))
+                    />
+                    "This conversation only"
+                </label>
+                <Suspense fallback=move || view! { <div></div> }>
+                    {move || {
+                        users
+                            .get()
+                            .and_then(|users| users.ok())
+                            .map(|users| {
+                                view! {
+                                    <select
+                                        class="px-2 py-1 border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                                        on:change=move |ev| set_author.set(event_target_value(&ev))
+                                    >
+                                        <option value="">"Anyone"</option>
+                                        {users
+                                            .into_iter()
+                                            .map(|user| {
+                                                view! { <option value=user.id.to_string()>{user.name}</option> }
+                                            })
+                                            .collect_view()}
+                                    </select>
+                                }
+                            })
+                    }}
+                </Suspense>
+                <div class="flex items-center gap-1">
+                    <input
+                        type="date"
+                        class="flex-1 min-w-0 px-1 py-1 border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                        title="From"
+                        on:change=move |ev| set_from.set(event_target_value(&ev))
+                    />
+                    <input
+                        type="date"
+                        class="flex-1 min-w-0 px-1 py-1 border border-neutral-300 dark:border-neutral-600 rounded-md bg-white dark:bg-neutral-900"
+                        title="To"
+                        on:change=move |ev| set_to.set(event_target_value(&ev))
+                    />
+                </div>
+            </form>
+            <div class="flex-1 overflow-y-auto">
+                {move || {
+                    if search_action.pending().get() {
+                        return view! { <p class="text-xs text-neutral-500">"Searching..."</p> }.into_any();
+                    }
+                    match search_action.value().get() {
+                        Some(Ok(hits)) if hits.is_empty() => {
+                            view! { <p class="text-xs text-neutral-500">"No messages found"</p> }.into_any()
+                        }
+                        Some(Ok(hits)) => {
+                            view! {
+                                <ul class="flex flex-col gap-1">
+                                    {hits.into_iter().map(hit_row).collect_view()}
+                                </ul>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => {
+                            view! { <p class="text-xs text-red-600 dark:text-red-400">{e.to_string()}</p> }
+                                .into_any()
+                        }
+                        None => view! { <div></div> }.into_any(),
+                    }
+                }}
+            </div>
+        </div>
+    }
+}
@@ -1,33 +1,225 @@
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::extract::State;
 use axum::response::IntoResponse;
-use axum_extra::extract::CookieJar;
 use dashmap::DashMap;
 use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
-use crate::auth::user::AdapterUser;
+use crate::auth::{extract::OptionalAuthUser, user::AdapterUser};
+use crate::RecordId;
 
-use super::models::{save_chat_event, ChatEventType};
-use super::shared::{ChatMessage, WsMessage};
+use super::models::{
+    save_chat_event, ChatEventDb, ChatEventType, ChatRoom, ChatUser, DmConversation,
+};
+use super::receipts::{ChatReadMarker, ChatReadReceipt};
+use super::shared::{
+    default_room_id, ChatMessage, ClientWsMessage, WsErrorCode, WsMessage, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION, REACTION_MAX_LEN,
+};
 
-type Clients = Arc<DashMap<String, String>>;
-type Broadcaster = Arc<broadcast::Sender<String>>;
+const ROOM_CHANNEL_CAPACITY: usize = 1000;
+/// How long a typing indicator lasts without being refreshed by the client.
+pub const TYPING_EXPIRY: Duration = Duration::from_secs(6);
+/// How long a `client_msg_id` is remembered, so a client resending unacknowledged messages
+/// after reconnecting doesn't post them twice.
+const SENT_ID_TTL: Duration = Duration::from_secs(10 * 60);
 
-#[derive(Clone)]
+/// Outgoing side of one open socket. A user with several tabs open has several handles.
+#[derive(Clone, Debug)]
+struct ClientHandle {
+    connection_id: u64,
+    sender: mpsc::UnboundedSender<String>,
+}
+
+/// Open sockets keyed by client id (the user record id, or a generated id for anonymous users).
+type Clients = Arc<DashMap<String, Vec<ClientHandle>>>;
+type Rooms = Arc<DashMap<String, broadcast::Sender<String>>>;
+
+#[derive(Clone, Debug)]
 pub struct ChatState {
     clients: Clients,
-    broadcaster: Broadcaster,
+    rooms: Rooms,
+    /// Signed in users with at least one open socket, keyed by user id.
+    presence: Arc<DashMap<String, ChatUser>>,
+    /// Recently accepted `client_msg_id`s, keyed by user and message id, with the event each
+    /// one created.
+    sent: Arc<DashMap<String, (Option<RecordId>, Instant)>>,
+    next_connection_id: Arc<AtomicU64>,
+    persist: bool,
+}
+
+impl Default for ChatState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChatState {
     pub fn new() -> Self {
-        let (tx, _) = broadcast::channel(1000);
         Self {
             clients: Arc::new(DashMap::new()),
-            broadcaster: Arc::new(tx),
+            rooms: Arc::new(DashMap::new()),
+            presence: Arc::new(DashMap::new()),
+            sent: Arc::new(DashMap::new()),
+            next_connection_id: Arc::new(AtomicU64::new(1)),
+            persist: true,
+        }
+    }
+
+    /// Chat state that only relays messages between sockets and never touches the database.
+    /// Used by tests that spin up the router without a SurrealDB instance.
+    pub fn in_memory() -> Self {
+        Self {
+            persist: false,
+            ..Self::new()
+        }
+    }
+
+    /// Broadcast channel for a room, created the first time anyone joins it.
+    fn room_sender(&self, room_id: &RecordId) -> broadcast::Sender<String> {
+        self.rooms
+            .entry(room_id.to_string())
+            .or_insert_with(|| broadcast::channel(ROOM_CHANNEL_CAPACITY).0)
+            .clone()
+    }
+
+    fn register(&self, client_id: &str, sender: mpsc::UnboundedSender<String>) -> u64 {
+        let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        self.clients
+            .entry(client_id.to_string())
+            .or_default()
+            .push(ClientHandle {
+                connection_id,
+                sender,
+            });
+        connection_id
+    }
+
+    /// Removes a socket. Returns true if that was the user's last one and they went offline.
+    fn unregister(&self, client_id: &str, connection_id: u64) -> bool {
+        if let Some(mut handles) = self.clients.get_mut(client_id) {
+            handles.retain(|h| h.connection_id != connection_id);
+        }
+        self.clients
+            .remove_if(client_id, |_, handles| handles.is_empty());
+
+        !self.clients.contains_key(client_id) && self.presence.remove(client_id).is_some()
+    }
+
+    /// Marks a signed in user online. Returns true if they were not online before.
+    fn set_online(&self, user: &AdapterUser) -> bool {
+        self.presence
+            .insert(user.id.to_string(), ChatUser::from(user.clone()))
+            .is_none()
+    }
+
+    /// Signed in users with an open socket, one entry per user however many tabs they have.
+    pub fn online_users(&self) -> Vec<ChatUser> {
+        let mut users: Vec<ChatUser> = self.presence.iter().map(|u| u.value().clone()).collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        users
+    }
+
+    fn send_to_all(&self, msg: &WsMessage) {
+        let Ok(json) = serde_json::to_string(msg) else {
+            return;
+        };
+        for handles in self.clients.iter() {
+            for handle in handles.value() {
+                let _ = handle.sender.send(json.clone());
+            }
+        }
+    }
+
+    /// The event a message with this `client_msg_id` already created, if it was sent before.
+    fn already_sent(&self, key: &str) -> Option<Option<RecordId>> {
+        self.sent
+            .get(key)
+            .filter(|sent| sent.1.elapsed() < SENT_ID_TTL)
+            .map(|sent| sent.0.clone())
+    }
+
+    fn remember_sent(&self, key: String, event_id: Option<RecordId>) {
+        self.sent.retain(|_, sent| sent.1.elapsed() < SENT_ID_TTL);
+        self.sent.insert(key, (event_id, Instant::now()));
+    }
+
+    fn publish_presence(&self) {
+        self.send_to_all(&WsMessage::Presence {
+            online: self.online_users(),
+        });
+    }
+
+    /// Delivers a message to every socket the user has open. Returns how many sockets got it.
+    fn send_to_user(&self, user_id: &RecordId, msg: &WsMessage) -> usize {
+        let Ok(json) = serde_json::to_string(msg) else {
+            return 0;
+        };
+
+        self.clients
+            .get(&user_id.to_string())
+            .map(|handles| {
+                handles
+                    .iter()
+                    .filter(|h| h.sender.send(json.clone()).is_ok())
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    fn broadcast(&self, room_id: &RecordId, msg: &WsMessage) {
+        if let Ok(json) = serde_json::to_string(msg) {
+            let _ = self.room_sender(room_id).send(json);
+        }
+    }
+
+    /// Sends to everyone in the room, or to both participants of a DM conversation.
+    fn deliver(&self, room_id: &RecordId, conversation: Option<&DmConversation>, msg: &WsMessage) {
+        match conversation {
+            Some(conversation) => {
+                for participant in &conversation.participants {
+                    self.send_to_user(participant, msg);
+                }
+            }
+            None => self.broadcast(room_id, msg),
+        }
+    }
+
+    /// Persists a chat event, returning it when stored. Failures are logged and do not stop
+    /// the message from being relayed.
+    async fn record_event(
+        &self,
+        user_id: Option<RecordId>,
+        username: String,
+        room_id: RecordId,
+        event_type: ChatEventType,
+        message: Option<String>,
+    ) -> Option<ChatEventDb> {
+        if !self.persist {
+            return None;
+        }
+
+        match save_chat_event(
+            user_id,
+            username,
+            Some(room_id),
+            event_type.clone(),
+            message,
+            None,
+        )
+        .await
+        {
+            Ok(event) => Some(event),
+            Err(e) => {
+                warn!("Failed to save {:?} event: {}", event_type, e);
+                None
+            }
         }
     }
 }
@@ -35,27 +227,606 @@ impl ChatState {
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<ChatState>,
-    jar: CookieJar,
+    OptionalAuthUser(user): OptionalAuthUser,
 ) -> impl IntoResponse {
-    // Try to get user from session
-    let user_result = async {
-        let session_cookie = jar
-            .iter()
-            .find(|cookie| cookie.name().contains("session_token"))
-            .ok_or("No session token found")?;
-
-        AdapterUser::get_user_from_session(session_cookie.value().to_string())
+    ws.on_upgrade(move |socket| handle_socket(socket, state, user))
+}
+
+/// A request from one socket that could not be handled; sent back to that socket only.
+struct WsError {
+    code: WsErrorCode,
+    message: String,
+    client_msg_id: Option<String>,
+}
+
+impl WsError {
+    fn new(code: WsErrorCode, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            client_msg_id: None,
+        }
+    }
+}
+
+impl From<WsError> for WsMessage {
+    fn from(error: WsError) -> Self {
+        WsMessage::Error {
+            code: error.code,
+            message: error.message,
+            client_msg_id: error.client_msg_id,
+        }
+    }
+}
+
+type WsResult = Result<(), WsError>;
+
+struct TypingState {
+    room_id: RecordId,
+    conversation: Option<DmConversation>,
+    expiry: JoinHandle<()>,
+}
+
+/// Per-socket state: which rooms this connection listens to and where outgoing frames go.
+struct ChatConnection {
+    state: ChatState,
+    client_id: String,
+    connection_id: u64,
+    user: Option<AdapterUser>,
+    username: String,
+    outbound: mpsc::UnboundedSender<String>,
+    joined: HashMap<String, (RecordId, JoinHandle<()>)>,
+    /// Rooms this socket is typing in, with the timer that expires the indicator.
+    typing: HashMap<String, TypingState>,
+    protocol_version: u32,
+}
+
+impl ChatConnection {
+    fn user_id(&self) -> Option<RecordId> {
+        self.user.as_ref().map(|u| u.id.clone())
+    }
+
+    fn require_user(&self) -> Result<AdapterUser, WsError> {
+        self.user
+            .clone()
+            .ok_or_else(|| WsError::new(WsErrorCode::Unauthorized, "Sign in to do that"))
+    }
+
+    /// Sends a frame to this socket only.
+    fn reply(&self, msg: &WsMessage) {
+        if let Ok(json) = serde_json::to_string(msg) {
+            let _ = self.outbound.send(json);
+        }
+    }
+
+    fn hello(&mut self, protocol_version: u32) -> WsResult {
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+            return Err(WsError::new(
+                WsErrorCode::UnsupportedVersion,
+                format!(
+                    "Protocol version {} is not supported, expected {}..={}",
+                    protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+                ),
+            ));
+        }
+
+        self.protocol_version = protocol_version;
+        self.reply(&WsMessage::Welcome { protocol_version });
+        Ok(())
+    }
+
+    async fn join_room(&mut self, room_id: RecordId) -> WsResult {
+        if room_id.table() != "chat_room" {
+            return Err(WsError::new(
+                WsErrorCode::NotFound,
+                format!("{} is not a chat room", room_id),
+            ));
+        }
+
+        if self.joined.contains_key(&room_id.to_string()) {
+            return Ok(());
+        }
+
+        if self.state.persist {
+            let room = ChatRoom::get(room_id.clone())
+                .await
+                .map_err(|e| WsError::new(WsErrorCode::NotFound, e))?;
+            room.check_access(self.user.as_ref())
+                .await
+                .map_err(|e| WsError::new(WsErrorCode::Forbidden, e))?;
+            if let Some(user) = &self.user {
+                if let Err(e) = room.add_member(user).await {
+                    warn!("Failed to save membership for {}: {}", room_id, e);
+                }
+            }
+        }
+
+        let mut rx = self.state.room_sender(&room_id).subscribe();
+        let outbound = self.outbound.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        if outbound.send(msg).is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Chat connection lagged, skipped {} messages", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        self.joined
+            .insert(room_id.to_string(), (room_id.clone(), forwarder));
+
+        self.state
+            .record_event(
+                self.user_id(),
+                self.username.clone(),
+                room_id.clone(),
+                ChatEventType::UserJoined,
+                None,
+            )
+            .await;
+
+        self.state.broadcast(
+            &room_id,
+            &WsMessage::UserJoined {
+                username: self.username.clone(),
+                room_id,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stops listening to a room. When `forget` is set the persisted membership is removed
+    /// too, which is what an explicit leave does; disconnects keep the membership.
+    async fn leave_room(&mut self, room_id: RecordId, forget: bool) {
+        let Some((_, forwarder)) = self.joined.remove(&room_id.to_string()) else {
+            return;
+        };
+        forwarder.abort();
+
+        if forget && self.state.persist {
+            if let Some(user) = &self.user {
+                if let Ok(room) = ChatRoom::get(room_id.clone()).await {
+                    if let Err(e) = room.remove_member(user).await {
+                        warn!("Failed to remove membership for {}: {}", room_id, e);
+                    }
+                }
+            }
+        }
+
+        self.state
+            .record_event(
+                self.user_id(),
+                self.username.clone(),
+                room_id.clone(),
+                ChatEventType::UserLeft,
+                None,
+            )
+            .await;
+
+        self.state.broadcast(
+            &room_id,
+            &WsMessage::UserLeft {
+                username: self.username.clone(),
+                room_id,
+            },
+        );
+    }
+
+    async fn leave_all(&mut self) {
+        let rooms: Vec<RecordId> = self.joined.values().map(|(id, _)| id.clone()).collect();
+        for room_id in rooms {
+            self.leave_room(room_id, false).await;
+        }
+    }
+
+    async fn send_message(
+        &mut self,
+        room_id: RecordId,
+        text: String,
+        client_msg_id: Option<String>,
+    ) -> WsResult {
+        let Some(client_msg_id) = client_msg_id else {
+            self.post_message(room_id, text).await?;
+            return Ok(());
+        };
+
+        // A resend after a reconnect whose first attempt got through but was never acked.
+        // Anonymous sockets get a new client id each time, so only the user id is in the key.
+        let user_key = self.user_id().map(|id| id.to_string()).unwrap_or_default();
+        let key = format!("{}|{}", user_key, client_msg_id);
+        if let Some(event_id) = self.state.already_sent(&key) {
+            self.reply(&WsMessage::Ack {
+                client_msg_id,
+                event_id,
+            });
+            return Ok(());
+        }
+
+        match self.post_message(room_id, text).await {
+            Ok(event_id) => {
+                self.state.remember_sent(key, event_id.clone());
+                self.reply(&WsMessage::Ack {
+                    client_msg_id,
+                    event_id,
+                });
+                Ok(())
+            }
+            Err(error) => Err(WsError {
+                client_msg_id: Some(client_msg_id),
+                ..error
+            }),
+        }
+    }
+
+    async fn post_message(
+        &mut self,
+        room_id: RecordId,
+        text: String,
+    ) -> Result<Option<RecordId>, WsError> {
+        if text.trim().is_empty() {
+            return Err(WsError::new(
+                WsErrorCode::MalformedFrame,
+                "Message is empty",
+            ));
+        }
+
+        let event_id = if DmConversation::is_dm(&room_id) {
+            self.send_direct(room_id.clone(), text).await?
+        } else {
+            self.send_to_room(room_id.clone(), text).await?
+        };
+        self.stop_typing(&room_id);
+        Ok(event_id)
+    }
+
+    async fn send_to_room(
+        &mut self,
+        room_id: RecordId,
+        text: String,
+    ) -> Result<Option<RecordId>, WsError> {
+        if !self.joined.contains_key(&room_id.to_string()) {
+            return Err(WsError::new(
+                WsErrorCode::Forbidden,
+                format!("Join {} before posting to it", room_id),
+            ));
+        }
+
+        // Save message to database
+        let event = self
+            .state
+            .record_event(
+                self.user_id(),
+                self.username.clone(),
+                room_id.clone(),
+                ChatEventType::Message,
+                Some(text.clone()),
+            )
+            .await;
+        let event_id = event.map(|e| e.id);
+
+        let chat_msg = ChatMessage {
+            id: event_id.clone(),
+            user_id: self
+                .user_id()
+                .unwrap_or_else(|| RecordId::from(("user", "anonymous"))),
+            username: self.username.clone(),
+            room_id: room_id.clone(),
+            message: text,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            edited: false,
+            reactions: Vec::new(),
+        };
+        self.state
+            .broadcast(&room_id, &WsMessage::Message(chat_msg));
+        Ok(event_id)
+    }
+
+    /// Posts to a DM conversation and delivers it to every open socket of both participants.
+    async fn send_direct(
+        &mut self,
+        conversation_id: RecordId,
+        text: String,
+    ) -> Result<Option<RecordId>, WsError> {
+        let user = self.require_user()?;
+
+        if !self.state.persist {
+            return Err(WsError::new(
+                WsErrorCode::Unsupported,
+                "Direct messages need a database",
+            ));
+        }
+
+        let conversation = DmConversation::get_for_user(conversation_id, &user)
             .await
-            .map_err(|e| format!("Failed to get user: {}", e))
+            .map_err(|e| WsError::new(WsErrorCode::Forbidden, e))?;
+
+        let event = self
+            .state
+            .record_event(
+                Some(user.id.clone()),
+                self.username.clone(),
+                conversation.id.clone(),
+                ChatEventType::Message,
+                Some(text.clone()),
+            )
+            .await;
+        if let Err(e) = conversation.touch().await {
+            warn!("Failed to update conversation {}: {}", conversation.id, e);
+        }
+        let event_id = event.map(|e| e.id);
+
+        let ws_msg = WsMessage::Message(ChatMessage {
+            id: event_id.clone(),
+            user_id: user.id.clone(),
+            username: self.username.clone(),
+            room_id: conversation.id.clone(),
+            message: text,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            edited: false,
+            reactions: Vec::new(),
+        });
+
+        for participant in &conversation.participants {
+            self.state.send_to_user(participant, &ws_msg);
+        }
+        Ok(event_id)
     }
-    .await;
 
-    ws.on_upgrade(move |socket| handle_socket(socket, state, user_result.ok()))
+    /// Checks this socket may see `room_id` and returns the DM conversation when it is one.
+    async fn authorize_room(&self, room_id: &RecordId) -> Result<Option<DmConversation>, WsError> {
+        if DmConversation::is_dm(room_id) {
+            let user = self.require_user()?;
+            let conversation = DmConversation::get_for_user(room_id.clone(), &user)
+                .await
+                .map_err(|e| WsError::new(WsErrorCode::Forbidden, e))?;
+            return Ok(Some(conversation));
+        }
+
+        if !self.joined.contains_key(&room_id.to_string()) {
+            return Err(WsError::new(
+                WsErrorCode::Forbidden,
+                format!("Join {} first", room_id),
+            ));
+        }
+        Ok(None)
+    }
+
+    /// Records an edit, delete or reaction against `event_id` and delivers it to the message's
+    /// room. Edits and deletes are limited to the author and superadmins.
+    async fn update_message(
+        &mut self,
+        event_id: RecordId,
+        event_type: ChatEventType,
+        message: Option<String>,
+    ) -> WsResult {
+        let user = self.require_user()?;
+
+        if !self.state.persist {
+            return Err(WsError::new(
+                WsErrorCode::Unsupported,
+                "Changing messages needs a database",
+            ));
+        }
+
+        let target = ChatEventDb::get_live_message(event_id)
+            .await
+            .map_err(|e| WsError::new(WsErrorCode::NotFound, e))?;
+        let room_id = target.room_id.clone().unwrap_or_else(default_room_id);
+        let conversation = self.authorize_room(&room_id).await?;
+
+        if !matches!(event_type, ChatEventType::Reaction) && !target.can_modify(&user) {
+            return Err(WsError::new(
+                WsErrorCode::Forbidden,
+                "Only the author can change this message",
+            ));
+        }
+
+        let event = save_chat_event(
+            Some(user.id.clone()),
+            self.username.clone(),
+            Some(room_id.clone()),
+            event_type,
+            message,
+            Some(target.id),
+        )
+        .await
+        .map_err(|e| WsError::new(WsErrorCode::Internal, e))?;
+
+        let msg = event.into_ws_message();
+        self.state.deliver(&room_id, conversation.as_ref(), &msg);
+        Ok(())
+    }
+
+    /// Moves the user's read marker to `event_id` and tells the room who has seen it.
+    async fn mark_read(&mut self, room_id: RecordId, event_id: RecordId) -> WsResult {
+        let user = self.require_user()?;
+
+        if !self.state.persist {
+            return Err(WsError::new(
+                WsErrorCode::Unsupported,
+                "Read receipts need a database",
+            ));
+        }
+
+        let conversation = self.authorize_room(&room_id).await?;
+        let event = ChatEventDb::get(event_id)
+            .await
+            .map_err(|e| WsError::new(WsErrorCode::NotFound, e))?;
+        if event.room_id.clone().unwrap_or_else(default_room_id) != room_id {
+            return Err(WsError::new(
+                WsErrorCode::NotFound,
+                "Message is not in this room",
+            ));
+        }
+
+        let marker = ChatReadMarker::mark_read(&user, &event)
+            .await
+            .map_err(|e| WsError::new(WsErrorCode::Internal, e))?;
+
+        let receipt = WsMessage::ReadReceipt(ChatReadReceipt {
+            user: user.into(),
+            room_id: marker.room_id,
+            event_id: marker.event_id,
+        });
+        self.state
+            .deliver(&room_id, conversation.as_ref(), &receipt);
+        Ok(())
+    }
+
+    fn typing_message(&self, room_id: RecordId, typing: bool) -> WsMessage {
+        WsMessage::Typing {
+            room_id,
+            user_id: self.user_id(),
+            username: self.username.clone(),
+            typing,
+        }
+    }
+
+    /// Starts or refreshes this user's typing indicator in a room. The server ends it after
+    /// `TYPING_EXPIRY` unless the client sends another `Typing` frame first.
+    async fn set_typing(&mut self, room_id: RecordId, typing: bool) -> WsResult {
+        if !typing {
+            self.stop_typing(&room_id);
+            return Ok(());
+        }
+
+        let conversation = self.authorize_room(&room_id).await?;
+        let refreshed = match self.take_typing(&room_id) {
+            Some(previous) => {
+                previous.expiry.abort();
+                true
+            }
+            None => false,
+        };
+        if !refreshed {
+            self.state.deliver(
+                &room_id,
+                conversation.as_ref(),
+                &self.typing_message(room_id.clone(), true),
+            );
+        }
+
+        let state = self.state.clone();
+        let expired_room = room_id.clone();
+        let expired_conversation = conversation.clone();
+        let stopped = self.typing_message(room_id.clone(), false);
+        let expiry = tokio::spawn(async move {
+            tokio::time::sleep(TYPING_EXPIRY).await;
+            state.deliver(&expired_room, expired_conversation.as_ref(), &stopped);
+        });
+
+        self.typing.insert(
+            room_id.to_string(),
+            TypingState {
+                room_id,
+                conversation,
+                expiry,
+            },
+        );
+        Ok(())
+    }
+
+    /// Removes the room's typing indicator if it is still showing. One whose timer ran out was
+    /// already ended for everyone, so it is dropped instead.
+    fn take_typing(&mut self, room_id: &RecordId) -> Option<TypingState> {
+        self.typing
+            .remove(&room_id.to_string())
+            .filter(|typing| !typing.expiry.is_finished())
+    }
+
+    /// Ends a typing indicator early, e.g. when the message is sent or the socket closes.
+    fn stop_typing(&mut self, room_id: &RecordId) {
+        let Some(typing) = self.take_typing(room_id) else {
+            return;
+        };
+        typing.expiry.abort();
+        self.state.deliver(
+            &typing.room_id,
+            typing.conversation.as_ref(),
+            &self.typing_message(typing.room_id.clone(), false),
+        );
+    }
+
+    fn stop_all_typing(&mut self) {
+        let rooms: Vec<RecordId> = self.typing.values().map(|t| t.room_id.clone()).collect();
+        for room_id in rooms {
+            self.stop_typing(&room_id);
+        }
+    }
+
+    async fn handle_client_message(&mut self, msg: ClientWsMessage) -> WsResult {
+        match msg {
+            ClientWsMessage::Hello { protocol_version } => self.hello(protocol_version),
+            ClientWsMessage::JoinRoom { room_id } => self.join_room(room_id).await,
+            ClientWsMessage::LeaveRoom { room_id } => {
+                self.leave_room(room_id, true).await;
+                Ok(())
+            }
+            ClientWsMessage::Send {
+                room_id,
+                message,
+                client_msg_id,
+            } => self.send_message(room_id, message, client_msg_id).await,
+            ClientWsMessage::Ping { nonce } => {
+                self.reply(&WsMessage::Pong { nonce });
+                Ok(())
+            }
+            ClientWsMessage::Edit { event_id, message } => {
+                if message.trim().is_empty() {
+                    return Err(WsError::new(
+                        WsErrorCode::MalformedFrame,
+                        "Message is empty",
+                    ));
+                }
+                self.update_message(event_id, ChatEventType::MessageEdited, Some(message))
+                    .await
+            }
+            ClientWsMessage::Delete { event_id } => {
+                self.update_message(event_id, ChatEventType::MessageDeleted, None)
+                    .await
+            }
+            ClientWsMessage::React { event_id, emoji } => {
+                let emoji = emoji.trim().to_string();
+                if emoji.is_empty() || emoji.chars().count() > REACTION_MAX_LEN {
+                    return Err(WsError::new(
+                        WsErrorCode::MalformedFrame,
+                        "Invalid reaction",
+                    ));
+                }
+                self.update_message(event_id, ChatEventType::Reaction, Some(emoji))
+                    .await
+            }
+            ClientWsMessage::ReadReceipt { room_id, event_id } => {
+                self.mark_read(room_id, event_id).await
+            }
+            ClientWsMessage::Typing { room_id, typing } => self.set_typing(room_id, typing).await,
+        }
+    }
+
+    async fn handle_text(&mut self, text: &str) {
+        let result = match serde_json::from_str::<ClientWsMessage>(text) {
+            Ok(msg) => self.handle_client_message(msg).await,
+            Err(e) => Err(WsError::new(
+                WsErrorCode::MalformedFrame,
+                format!("Could not parse frame: {}", e),
+            )),
+        };
+
+        if let Err(error) = result {
+            self.reply(&error.into());
+        }
+    }
 }
 
 async fn handle_socket(socket: WebSocket, state: ChatState, user: Option<AdapterUser>) {
     let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.broadcaster.subscribe();
+    let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<String>();
 
     let (client_id, username) = match &user {
         Some(user_data) => {
@@ -73,96 +844,68 @@ async fn handle_socket(socket: WebSocket, state: ChatState, user: Option<Adapter
         }
     };
 
-    state.clients.insert(client_id.clone(), username.clone());
-
-    // Save join event to database
-    let user_id_for_save = user.as_ref().map(|u| u.id.clone());
-    if let Err(e) = save_chat_event(
-        user_id_for_save,
-        username.clone(),
-        ChatEventType::UserJoined,
-        None,
-    )
-    .await
-    {
-        warn!("Failed to save join event: {}", e);
-    }
-
-    let join_msg = WsMessage::UserJoined {
-        username: username.clone(),
-    };
-    let _ = state
-        .broadcaster
-        .send(serde_json::to_string(&join_msg).unwrap());
+    let connection_id = state.register(&client_id, outbound.clone());
 
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
+        while let Some(msg) = outbound_rx.recv().await {
             if sender.send(Message::Text(msg.into())).await.is_err() {
                 break;
             }
         }
     });
 
-    let state_clone = state.clone();
-    let username_clone = username.clone();
-    let user_id_clone = user.as_ref().map(|u| u.id.clone());
-    let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            if let Message::Text(text) = msg {
-                // Save message to database
-                if let Err(e) = save_chat_event(
-                    user_id_clone.clone(),
-                    username_clone.clone(),
-                    ChatEventType::Message,
-                    Some(text.to_string()),
-                )
-                .await
-                {
-                    warn!("Failed to save chat message: {}", e);
-                }
+    let mut connection = ChatConnection {
+        state: state.clone(),
+        client_id,
+        connection_id,
+        user,
+        username,
+        outbound,
+        joined: HashMap::new(),
+        typing: HashMap::new(),
+        protocol_version: PROTOCOL_VERSION,
+    };
 
-                let chat_msg = ChatMessage {
-                    user_id: user_id_clone.clone().unwrap_or_else(|| {
-                        use surrealdb::RecordId;
-                        RecordId::from(("user", "anonymous"))
-                    }),
-                    username: username_clone.clone(),
-                    message: text.to_string(),
-                    timestamp: chrono::Utc::now().to_rfc3339(),
-                };
-                let ws_msg = WsMessage::Message(chat_msg);
-                if let Ok(json) = serde_json::to_string(&ws_msg) {
-                    let _ = state_clone.broadcaster.send(json);
-                }
-            }
-        }
-    });
+    // Everyone hears about a user's first socket; later tabs only need the current list
+    let came_online = connection
+        .user
+        .as_ref()
+        .is_some_and(|user| state.set_online(user));
+    if came_online {
+        state.publish_presence();
+    } else {
+        connection.reply(&WsMessage::Presence {
+            online: state.online_users(),
+        });
+    }
 
-    tokio::select! {
-        _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort(),
+    if let Err(e) = connection.join_room(default_room_id()).await {
+        warn!("Could not join the default room: {}", e.message);
+    }
+
+    loop {
+        tokio::select! {
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Text(text))) => connection.handle_text(text.as_str()).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = &mut send_task => break,
+        }
     }
 
-    info!("Client {} disconnected", &client_id);
-    state.clients.remove(&client_id);
+    send_task.abort();
 
-    // Save leave event to database
-    let user_id_for_leave = user.as_ref().map(|u| u.id.clone());
-    if let Err(e) = save_chat_event(
-        user_id_for_leave,
-        username.clone(),
-        ChatEventType::UserLeft,
-        None,
-    )
-    .await
-    {
-        warn!("Failed to save leave event: {}", e);
+    info!(
+        "Client {} (protocol v{}) disconnected",
+        &connection.client_id, connection.protocol_version
+    );
+    connection.stop_all_typing();
+    if state.unregister(&connection.client_id, connection.connection_id) {
+        state.publish_presence();
     }
 
-    let leave_msg = WsMessage::UserLeft { username };
-    let _ = state
-        .broadcaster
-        .send(serde_json::to_string(&leave_msg).unwrap());
+    connection.leave_all().await;
 }
 
 pub fn chat_routes() -> axum::Router<ChatState> {
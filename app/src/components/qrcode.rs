@@ -1,14 +1,19 @@
 use leptos::prelude::*;
 
+/// Renders `input` as an inline SVG QR code. It is drawn locally so tickets and two-factor
+/// secrets are never sent to a third-party service.
 #[component]
 pub fn QRCode(input: String) -> impl IntoView {
-    let encoded = urlencoding::encode(&input);
-    let src = format!(
-        "https://api.qrserver.com/v1/create-qr-code/?data={}&size=600x600",
-        encoded
-    );
+    let svg = qrcode::QrCode::new(input.as_bytes())
+        .map(|code| {
+            code.render::<qrcode::render::svg::Color>()
+                .min_dimensions(200, 200)
+                .quiet_zone(true)
+                .build()
+        })
+        .unwrap_or_default();
 
     view! {
-        <img src=src alt="QR Code" />
+        <div class="inline-block bg-white p-2 rounded" role="img" aria-label="QR Code" inner_html=svg></div>
     }
 }
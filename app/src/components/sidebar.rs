@@ -12,6 +12,24 @@ pub struct NavBarLink {
     pub icon: ButtonIcon,
     pub icon_hover: Option<ButtonIcon>,
     pub url: String,
+    /// Count shown as a badge on the link, hidden while zero.
+    pub badge: Option<Signal<usize>>,
+}
+
+#[component]
+fn LinkBadge(badge: Option<Signal<usize>>) -> impl IntoView {
+    move || {
+        badge
+            .map(|badge| badge.get())
+            .filter(|count| *count > 0)
+            .map(|count| {
+                view! {
+                    <span class="absolute -top-1 -right-1 min-w-4 h-4 px-1 rounded-full bg-red-500 text-white text-[10px] leading-4 text-center pointer-events-none">
+                        {if count > 99 { "99+".to_string() } else { count.to_string() }}
+                    </span>
+                }
+            })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -89,13 +107,16 @@ pub fn SideBar(links: Vec<SidebarItem>) -> impl IntoView {
                                         match item {
                                             SidebarItem::Link(link) => view! {
                                                 <Tooltip label=link.name.clone() align=Align::Right>
-                                                    <Button
-                                                        icon=link.icon.clone()
-                                                        state=MaybeProp::from(if is_active { BtnState::Active } else { BtnState::Default })
-                                                        href=link.url.clone()
-                                                        variant=BtnVariant::Square
-                                                        on_click=Callback::new(move |_| set_is_mobile_open.set(false))
-                                                    />
+                                                    <div class="relative">
+                                                        <Button
+                                                            icon=link.icon.clone()
+                                                            state=MaybeProp::from(if is_active { BtnState::Active } else { BtnState::Default })
+                                                            href=link.url.clone()
+                                                            variant=BtnVariant::Square
+                                                            on_click=Callback::new(move |_| set_is_mobile_open.set(false))
+                                                        />
+                                                        <LinkBadge badge=link.badge />
+                                                    </div>
                                                 </Tooltip>
                                             }.into_any(),
                                             SidebarItem::Divider => view! {
@@ -131,7 +152,7 @@ pub fn SideBar(links: Vec<SidebarItem>) -> impl IntoView {
 
                                 match item {
                                     SidebarItem::Link(link) => view! {
-
+                                        <div class="relative w-full">
                                             <Button
                                                 icon=link.icon.clone()
                                                 state=if is_active { BtnState::Active } else { BtnState::Default }
@@ -142,6 +163,8 @@ pub fn SideBar(links: Vec<SidebarItem>) -> impl IntoView {
                                             >
                                                 {link.name.clone()}
                                             </Button>
+                                            <LinkBadge badge=link.badge />
+                                        </div>
 
                                     }.into_any(),
                                     SidebarItem::Divider => view! {
@@ -115,6 +115,33 @@ pub async fn db_schema() -> Result<(), AppError> {
     let schema = r#"
         remove field if exists email on table user;
         REMOVE INDEX if exists user_email_index ON TABLE user;
+
+        DEFINE INDEX IF NOT EXISTS account_provider_index ON TABLE account COLUMNS provider, provider_account_id UNIQUE;
+        DEFINE INDEX IF NOT EXISTS verification_token_identifier_index ON TABLE verificationToken COLUMNS identifier;
+        DEFINE INDEX IF NOT EXISTS webauthn_credential_user_index ON TABLE webauthn_credential COLUMNS user_id;
+        DEFINE INDEX IF NOT EXISTS totp_factor_user_index ON TABLE totp_factor COLUMNS user_id UNIQUE;
+        DEFINE INDEX IF NOT EXISTS recovery_code_user_index ON TABLE recovery_code COLUMNS user_id, code_hash;
+        DEFINE INDEX IF NOT EXISTS session_token_index ON TABLE session COLUMNS session_token UNIQUE;
+        DEFINE INDEX IF NOT EXISTS session_user_index ON TABLE session COLUMNS user_id;
+        DEFINE INDEX IF NOT EXISTS webauthn_credential_id_index ON TABLE webauthn_credential COLUMNS credential_id UNIQUE;
+
+        DEFINE INDEX IF NOT EXISTS membership_org_user_index ON TABLE membership COLUMNS organization_id, user_id UNIQUE;
+        DEFINE INDEX IF NOT EXISTS membership_user_index ON TABLE membership COLUMNS user_id;
+        DEFINE INDEX IF NOT EXISTS key_organization_index ON TABLE key COLUMNS organization_id;
+        DEFINE INDEX IF NOT EXISTS key_prefix_index ON TABLE key COLUMNS key_prefix;
+        DEFINE INDEX IF NOT EXISTS admin_audit_created_index ON TABLE admin_audit COLUMNS created_at;
+
+        REMOVE INDEX IF EXISTS chat_room_name_index ON TABLE chat_room;
+        DEFINE INDEX IF NOT EXISTS chat_room_org_name_index ON TABLE chat_room COLUMNS organization_id, name UNIQUE;
+        DEFINE INDEX IF NOT EXISTS chat_room_organization_index ON TABLE chat_room COLUMNS organization_id;
+        DEFINE INDEX IF NOT EXISTS chat_room_member_index ON TABLE chat_room_member COLUMNS room_id, user_id UNIQUE;
+        DEFINE INDEX IF NOT EXISTS chat_event_room_index ON TABLE chat_event COLUMNS room_id, timestamp;
+        DEFINE INDEX IF NOT EXISTS chat_event_target_index ON TABLE chat_event COLUMNS target_id;
+        DEFINE INDEX IF NOT EXISTS chat_read_marker_room_index ON TABLE chat_read_marker COLUMNS room_id;
+        DEFINE INDEX IF NOT EXISTS chat_read_marker_user_index ON TABLE chat_read_marker COLUMNS user_id;
+        DEFINE ANALYZER IF NOT EXISTS chat_analyzer TOKENIZERS blank, class, punct FILTERS lowercase, ascii, snowball(english);
+        DEFINE INDEX IF NOT EXISTS chat_event_message_search ON TABLE chat_event COLUMNS message SEARCH ANALYZER chat_analyzer BM25 HIGHLIGHTS;
+        INSERT IGNORE INTO chat_room { id: chat_room:general, name: "general", description: "Everyone", created_at: time::now() };
     "#;
 
     let _ = db.query(schema).await;
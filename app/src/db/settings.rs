@@ -11,6 +11,11 @@ pub struct Settings {
     pub surrealdb_ns: String,
     pub surrealdb_user: String,
     pub surrealdb_pass: String,
+    /// Base64 of the 32 byte master key wrapping the data keys of stored key material.
+    pub key_encryption_key: Option<String>,
+    /// Master keys replaced by `key_encryption_key`, still read until their data keys are
+    /// re-wrapped.
+    pub key_encryption_previous_keys: Vec<String>,
 }
 
 #[cfg(feature = "ssr")]
@@ -34,5 +39,18 @@ pub fn get_settings() -> Settings {
         surrealdb_ns: get_env("SURREALDB_NS").unwrap(),
         surrealdb_user: get_env("SURREALDB_USER").unwrap(),
         surrealdb_pass: get_env("SURREALDB_PASS").unwrap(),
+        // An empty value, as in a fresh copy of .env.sample, counts as not set
+        key_encryption_key: get_env("KEY_ENCRYPTION_KEY")
+            .ok()
+            .filter(|key| !key.trim().is_empty()),
+        key_encryption_previous_keys: get_env("KEY_ENCRYPTION_PREVIOUS_KEYS")
+            .map(|keys| {
+                keys.split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
     }
 }
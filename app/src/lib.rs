@@ -1,11 +1,16 @@
 pub mod p2p;
 use crate::{
+    chat::{
+        receipts::{get_unread_counts, ChatUnreadTotal},
+        ChatApp,
+    },
     components::{
         button::ButtonIcon,
         sidebar::{NavBarLink, SideBar, SidebarItem},
     },
     navbar::Navbar,
-    screens::HomeScreen,
+    organization::ui_organization::InviteScreen,
+    screens::{AdminScreen, DevicesScreen, HomeScreen, SettingsScreen},
     theme::ThemeProvider,
 };
 use backend::*;
@@ -15,7 +20,8 @@ use leptos_router::{
     components::{Route, Router, Routes},
     path,
 };
-use phosphor_leptos::{CUBE, GEAR, PLANET, SHARE_NETWORK};
+use phosphor_leptos::{CHAT_CIRCLE, CUBE, GEAR, PLANET, SHARE_NETWORK};
+pub mod admin;
 pub mod apperror;
 pub mod auth;
 pub mod chat;
@@ -23,9 +29,12 @@ pub mod colors;
 pub mod components;
 pub mod date_utils;
 pub mod navbar;
+pub mod organization;
 pub mod theme;
 pub use apperror::AppError;
 pub mod db;
+#[cfg(feature = "ssr")]
+pub mod mail;
 pub mod screens;
 
 pub mod surrealtypes;
@@ -47,24 +56,45 @@ pub const LOGO: &str = "
 
 #[component]
 pub fn App() -> impl IntoView {
+    // Unread chat messages for the sidebar badge; `ChatApp` keeps it current while open
+    let chat_unread = RwSignal::new(0usize);
+    provide_context(ChatUnreadTotal(chat_unread));
+    Effect::new(move |_| {
+        leptos::task::spawn_local(async move {
+            if let Ok(counts) = get_unread_counts().await {
+                chat_unread.set(counts.iter().map(|c| c.count).sum());
+            }
+        });
+    });
+
     let links = vec![
         SidebarItem::Link(NavBarLink {
             name: "Dashboard".to_string(),
             icon: ButtonIcon::Icon(CUBE),
             icon_hover: None,
             url: "/".to_string(),
+            badge: None,
         }),
         SidebarItem::Link(NavBarLink {
             name: "Global".to_string(),
             icon: ButtonIcon::Icon(PLANET),
             icon_hover: None,
             url: "/global".to_string(),
+            badge: None,
+        }),
+        SidebarItem::Link(NavBarLink {
+            name: "Chat".to_string(),
+            icon: ButtonIcon::Icon(CHAT_CIRCLE),
+            icon_hover: None,
+            url: "/chat".to_string(),
+            badge: Some(chat_unread.into()),
         }),
         SidebarItem::Link(NavBarLink {
             name: "iroh".to_string(),
             icon: ButtonIcon::Icon(SHARE_NETWORK),
             icon_hover: None,
             url: "/iroh".to_string(),
+            badge: None,
         }),
         SidebarItem::Gap,
         SidebarItem::Divider,
@@ -73,6 +103,7 @@ pub fn App() -> impl IntoView {
             icon: ButtonIcon::Icon(GEAR),
             icon_hover: None,
             url: "/settings".to_string(),
+            badge: None,
         }),
     ];
 
@@ -103,6 +134,13 @@ pub fn App() -> impl IntoView {
 
                                          <Routes fallback=|| "Page not found.".into_view()>
                                             <Route path=path!("/") view=HomeScreen />
+                                            <Route path=path!("/chat") view=ChatApp />
+                                            <Route path=path!("/login") view=auth::ui_auth::LoginForm />
+                                            <Route path=path!("/login/2fa") view=auth::ui_totp::SecondFactorForm />
+                                            <Route path=path!("/settings") view=SettingsScreen />
+                                            <Route path=path!("/settings/devices") view=DevicesScreen />
+                                            <Route path=path!("/invite") view=InviteScreen />
+                                            <Route path=path!("/admin") view=AdminScreen />
                                             <Route path=path!("/iroh") view=p2p::iroh_ui::IrohTest />
                                         </Routes>
                                     </div>
@@ -0,0 +1,257 @@
+//! Outgoing email. The transport is picked from `MAIL_TRANSPORT` at startup: `smtp` for real
+//! delivery, `file` to write `.eml` files for local development and tests, or `log` (the
+//! default) to print messages to the server log.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::db::settings::get_env;
+use crate::AppError;
+
+const DEFAULT_FROM: &str = "Netron <no-reply@localhost>";
+const DEFAULT_MAIL_DIR: &str = "target/mail";
+const DEFAULT_SMTP_PORT: u16 = 587;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// `text` with the characters that are special in HTML replaced by entities, for putting
+/// user-provided values into email bodies.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub trait MailTransport: Send + Sync {
+    fn send(&self, email: &Email) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, AppError> {
+    address
+        .parse()
+        .map_err(|e| AppError::ErrorReason(format!("Invalid email address {}: {}", address, e)))
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, AppError> {
+    let builder = Message::builder()
+        .from(from.clone())
+        .to(parse_mailbox(&email.to)?)
+        .subject(email.subject.clone());
+
+    let message = match &email.html {
+        Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+            email.text.clone(),
+            html.clone(),
+        )),
+        None => builder.singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(email.text.clone()),
+        ),
+    };
+    message.map_err(|e| AppError::GenericError(format!("Could not build email: {}", e)))
+}
+
+/// Delivers through an SMTP relay using STARTTLS.
+pub struct SmtpMailTransport {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, AppError> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+            .map_err(|e| AppError::Config(format!("Invalid SMTP host {}: {}", host, e)))?
+            .port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            from: parse_mailbox(from)?,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl MailTransport for SmtpMailTransport {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let message = build_message(&self.from, email)?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::GenericError(format!("Could not send email: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Writes each message to its own `.eml` file in a directory.
+pub struct FileMailTransport {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl FileMailTransport {
+    pub fn new(dir: impl Into<PathBuf>, from: &str) -> Result<Self, AppError> {
+        Ok(Self {
+            from: parse_mailbox(from)?,
+            dir: dir.into(),
+        })
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+}
+
+impl MailTransport for FileMailTransport {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let message = build_message(&self.from, email)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, message.formatted())?;
+        tracing::info!(to = %email.to, path = %path.display(), "Wrote email to file");
+        Ok(())
+    }
+}
+
+/// Prints messages to the server log instead of sending them.
+pub struct LogMailTransport;
+
+impl MailTransport for LogMailTransport {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        tracing::info!(
+            to = %email.to,
+            subject = %email.subject,
+            "Email not sent, MAIL_TRANSPORT is log:\n{}",
+            email.text
+        );
+        Ok(())
+    }
+}
+
+/// The transport configured for this server.
+pub enum Mailer {
+    Smtp(SmtpMailTransport),
+    File(FileMailTransport),
+    Log(LogMailTransport),
+}
+
+impl Mailer {
+    pub fn from_env() -> Result<Self, AppError> {
+        let from = get_env("MAIL_FROM").unwrap_or_else(|_| DEFAULT_FROM.to_string());
+
+        match get_env("MAIL_TRANSPORT").as_deref() {
+            Ok("smtp") => {
+                let host = get_env("SMTP_HOST")?;
+                let port = match get_env("SMTP_PORT") {
+                    Ok(port) => port
+                        .parse()
+                        .map_err(|_| AppError::Config(format!("Invalid SMTP_PORT {}", port)))?,
+                    Err(_) => DEFAULT_SMTP_PORT,
+                };
+                let credentials = get_env("SMTP_USERNAME")
+                    .ok()
+                    .zip(get_env("SMTP_PASSWORD").ok());
+                Ok(Self::Smtp(SmtpMailTransport::new(
+                    &host,
+                    port,
+                    credentials,
+                    &from,
+                )?))
+            }
+            Ok("file") => {
+                let dir = get_env("MAIL_DIR").unwrap_or_else(|_| DEFAULT_MAIL_DIR.to_string());
+                Ok(Self::File(FileMailTransport::new(dir, &from)?))
+            }
+            Ok("log") | Err(_) => Ok(Self::Log(LogMailTransport)),
+            Ok(other) => Err(AppError::Config(format!(
+                "Unknown MAIL_TRANSPORT {}, expected smtp, file or log",
+                other
+            ))),
+        }
+    }
+}
+
+impl MailTransport for Mailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        match self {
+            Mailer::Smtp(transport) => transport.send(email).await,
+            Mailer::File(transport) => transport.send(email).await,
+            Mailer::Log(transport) => transport.send(email).await,
+        }
+    }
+}
+
+static MAILER: OnceLock<Mailer> = OnceLock::new();
+
+/// The server's mailer, configured from the environment on first use. A bad configuration
+/// falls back to logging so sign-in links still show up somewhere.
+pub fn mailer() -> &'static Mailer {
+    MAILER.get_or_init(|| {
+        dotenvy::dotenv().ok();
+        Mailer::from_env().unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Mail is misconfigured, logging emails instead");
+            Mailer::Log(LogMailTransport)
+        })
+    })
+}
+
+#[tokio::test]
+async fn test_file_transport_writes_eml() {
+    let dir = std::env::temp_dir().join(format!("netron-mail-{}", uuid::Uuid::new_v4()));
+    let transport = FileMailTransport::new(&dir, DEFAULT_FROM).unwrap();
+
+    transport
+        .send(&Email {
+            to: "ada@example.com".into(),
+            subject: "Hello".into(),
+            text: "Plain body".into(),
+            html: None,
+        })
+        .await
+        .unwrap();
+
+    let files: Vec<_> = std::fs::read_dir(transport.dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let written = std::fs::read_to_string(&files[0]).unwrap();
+    assert!(written.contains("To: ada@example.com"));
+    assert!(written.contains("Subject: Hello"));
+    assert!(written.contains("Plain body"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -1,8 +1,10 @@
 use crate::{
+    admin::ui_admin::ImpersonationBanner,
     auth::session::get_user,
     components::{
         AvatarButton, Dropdown, DropdownHeader, DropdownItem, DropdownMenu, DropdownSide,
     },
+    organization::organization_selector::OrganizationSelector,
     theme::ThemeToggle,
 };
 use leptos::prelude::*;
@@ -16,7 +18,8 @@ pub fn Navbar() -> impl IntoView {
             <div class="px-4">
                 <div class="flex justify-between items-center h-16">
                     <div class="flex items-center space-x-4">
-                        // <OrganizationSelector />
+                        <OrganizationSelector />
+                        <ImpersonationBanner />
                     </div>
 
                     <div class="flex items-center space-x-4">
@@ -52,10 +55,12 @@ pub fn Navbar() -> impl IntoView {
                                         }
                                         Some(Err(_)) => {
                                             view! {
-                                                <button
-                                                    class="w-8 h-8 bg-neutral-400 rounded-full hidden"
-                                                    disabled=true
-                                                ></button>
+                                                <a
+                                                    href="/login"
+                                                    class="px-3 py-1.5 text-sm font-medium rounded-md hover:bg-neutral-100 dark:hover:bg-neutral-700"
+                                                >
+                                                    "Sign in"
+                                                </a>
                                             }
                                                 .into_any()
                                         }
@@ -79,6 +84,7 @@ pub fn Navbar() -> impl IntoView {
                                     {move || {
                                         match user_resource.get() {
                                             Some(Ok(user)) => {
+                                                let superadmin = user.superadmin == Some(true);
                                                 view! {
                                                     <DropdownHeader>
                                                         {user.name}
@@ -86,6 +92,11 @@ pub fn Navbar() -> impl IntoView {
                                                     <DropdownItem href="/settings">
                                                         "Settings"
                                                     </DropdownItem>
+                                                    {superadmin.then(|| view! {
+                                                        <DropdownItem href="/admin">
+                                                            "Admin"
+                                                        </DropdownItem>
+                                                    })}
                                                     <DropdownItem href="/logout">
                                                         "Logout"
                                                     </DropdownItem>
@@ -0,0 +1,245 @@
+//! Invitations to join an organization by email. An invitation is a `VerificationToken` whose
+//! identifier names the organization and the address, so it is mailed, expires and is spent
+//! like a sign-in link.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::RecordId;
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{
+        magic_link::normalize_email,
+        permissions::Role,
+        session::get_user,
+        token::{CreateVerificationToken, VerificationToken},
+        user::AdapterUser,
+    },
+    db::settings::get_env,
+    db_init,
+    mail::{escape_html, mailer, Email, MailTransport},
+    organization::models::{Membership, Organization},
+    AppError,
+};
+
+/// How long an invitation can be accepted.
+pub const INVITE_TTL_DAYS: i64 = 7;
+
+/// An invitation that has not been accepted yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteInfo {
+    pub email: String,
+    pub expires: String,
+}
+
+/// The `verificationToken` identifier of an invitation to the organization with `key`.
+pub fn invite_identifier(organization_key: &str, email: &str) -> String {
+    format!("invite:{}:{}", organization_key, email)
+}
+
+/// The link mailed to the invitee, pointing at the `/invite` page on `base_url`.
+pub fn invite_url(base_url: &str, organization_key: &str, email: &str, token: &str) -> String {
+    format!(
+        "{}/invite?organization={}&email={}&token={}",
+        base_url.trim_end_matches('/'),
+        urlencoding::encode(organization_key),
+        urlencoding::encode(email),
+        urlencoding::encode(token)
+    )
+}
+
+/// The start of the identifier of every invitation to an organization.
+#[cfg(feature = "ssr")]
+pub fn invite_identifier_prefix(organization_id: &RecordId) -> String {
+    invite_identifier(&organization_key(organization_id), "")
+}
+
+/// The key part of an organization id, as used in invitation links.
+#[cfg(feature = "ssr")]
+fn organization_key(organization_id: &RecordId) -> String {
+    let id = organization_id.to_string();
+    id.split_once(':')
+        .map(|(_, key)| key.to_string())
+        .unwrap_or(id)
+}
+
+#[cfg(feature = "ssr")]
+fn invite_email(to: &str, inviter: &AdapterUser, organization: &Organization, url: &str) -> Email {
+    Email {
+        to: to.to_string(),
+        subject: format!("Join {} on Netron", organization.name),
+        text: format!(
+            "{} invited you to join {}. The invitation expires in {} days.\n\n{}\n\nIf you were not expecting it, you can ignore this email.",
+            inviter.name, organization.name, INVITE_TTL_DAYS, url
+        ),
+        html: Some(format!(
+            r#"<p>{} invited you to join {}. The invitation expires in {} days.</p><p><a href="{}">Accept the invitation</a></p><p>If you were not expecting it, you can ignore this email.</p>"#,
+            escape_html(&inviter.name),
+            escape_html(&organization.name),
+            INVITE_TTL_DAYS,
+            escape_html(url)
+        )),
+    }
+}
+
+/// Creates an invitation for `email` and mails it through `transport`.
+#[cfg(feature = "ssr")]
+pub async fn send_invite(
+    organization: &Organization,
+    inviter: &AdapterUser,
+    email: &str,
+    base_url: &str,
+    transport: &impl MailTransport,
+) -> Result<(), AppError> {
+    let email = normalize_email(email)
+        .ok_or_else(|| AppError::ErrorReason("Enter a valid email address".into()))?;
+    let key = organization_key(&organization.id);
+
+    let token = VerificationToken::create_verification_token(CreateVerificationToken {
+        identifier: invite_identifier(&key, &email),
+        expires: chrono::Utc::now() + chrono::Duration::days(INVITE_TTL_DAYS),
+    })
+    .await?;
+
+    let url = invite_url(base_url, &key, &email, &token.token);
+    transport
+        .send(&invite_email(&email, inviter, organization, &url))
+        .await
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+struct InviteRow {
+    identifier: String,
+    expires: String,
+}
+
+/// Pending invitations to an organization, newest last.
+#[cfg(feature = "ssr")]
+pub async fn pending_invites(organization_id: &RecordId) -> Result<Vec<InviteInfo>, AppError> {
+    let prefix = invite_identifier_prefix(organization_id);
+    let db = db_init().await?;
+    let mut result = db
+        .query("SELECT identifier, expires FROM verificationToken WHERE string::starts_with(identifier, $prefix) AND <datetime> expires > time::now() ORDER BY expires ASC;")
+        .bind(("prefix", prefix.clone()))
+        .await?;
+    let rows: Vec<InviteRow> = result.take(0)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| InviteInfo {
+            email: row.identifier.trim_start_matches(&prefix).to_string(),
+            expires: row.expires,
+        })
+        .collect())
+}
+
+#[server]
+pub async fn invite_member(organization_id: RecordId, email: String) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    Membership::require(organization_id.clone(), &user, Role::Admin).await?;
+    let organization = Organization::get(organization_id).await?;
+    let base_url = get_env("AUTH_URL")?;
+    send_invite(&organization, &user, &email, &base_url, mailer()).await?;
+    Ok(())
+}
+
+#[server]
+pub async fn get_pending_invites(
+    organization_id: RecordId,
+) -> Result<Vec<InviteInfo>, ServerFnError> {
+    let user = get_user().await?;
+    Membership::require(organization_id.clone(), &user, Role::Admin).await?;
+    let invites = pending_invites(&organization_id).await?;
+    Ok(invites)
+}
+
+#[server]
+pub async fn revoke_invite(organization_id: RecordId, email: String) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    Membership::require(organization_id.clone(), &user, Role::Admin).await?;
+    let email = normalize_email(&email)
+        .ok_or_else(|| AppError::ErrorReason("Enter a valid email address".into()))?;
+
+    let db = db_init().await?;
+    db.query("DELETE verificationToken WHERE identifier = $identifier;")
+        .bind((
+            "identifier",
+            invite_identifier(&organization_key(&organization_id), &email),
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Spends an invitation and adds the signed-in user to the organization, which the session
+/// then switches to. The token proves access to the invited mailbox, so the user may be signed
+/// in with any account.
+#[server]
+pub async fn accept_invite(
+    organization: String,
+    email: String,
+    token: String,
+) -> Result<RecordId, ServerFnError> {
+    use crate::organization::models::switch_organization;
+
+    let user = get_user().await?;
+    let email = normalize_email(&email)
+        .ok_or_else(|| AppError::AuthError("Invalid invitation link".into()))?;
+    VerificationToken::use_verification_token(invite_identifier(&organization, &email), token)
+        .await?;
+
+    let organization_id = RecordId::from(("organization", organization.as_str()));
+    Organization::get(organization_id.clone()).await?;
+    Membership::add(organization_id.clone(), user.id, Role::Member).await?;
+    switch_organization(Some(organization_id.clone())).await?;
+    Ok(organization_id)
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_invite_url() {
+    assert_eq!(
+        invite_url(
+            "http://localhost:3000/",
+            "acme",
+            "ada+team@example.com",
+            "t-1"
+        ),
+        "http://localhost:3000/invite?organization=acme&email=ada%2Bteam%40example.com&token=t-1"
+    );
+    assert_eq!(
+        invite_identifier("acme", "ada@example.com"),
+        "invite:acme:ada@example.com"
+    );
+}
+
+#[cfg(feature = "ssr")]
+#[test]
+fn test_invite_email_escapes_names() {
+    let inviter = AdapterUser {
+        id: RecordId::from(("user", "ada")),
+        name: "Ada <b>".into(),
+        image: None,
+        superadmin: None,
+        theme: crate::theme::Theme::System,
+        disabled: false,
+    };
+    let organization = Organization {
+        id: RecordId::from(("organization", "acme")),
+        name: r#"<a href="https://evil.example">Acme</a>"#.into(),
+        created_by_user_id: inviter.id.clone(),
+        created_at: crate::Datetime::from(chrono::Utc::now()),
+    };
+
+    let email = invite_email(
+        "grace@example.com",
+        &inviter,
+        &organization,
+        "http://localhost:3000/invite?organization=acme&token=t-1",
+    );
+    let html = email.html.unwrap();
+    assert!(!html.contains("evil.example\""));
+    assert!(html.contains("&lt;a href=&quot;https://evil.example&quot;&gt;Acme&lt;/a&gt;"));
+    assert!(html.contains("Ada &lt;b&gt;"));
+    assert!(html.contains(r#"href="http://localhost:3000/invite?organization=acme&amp;token=t-1""#));
+}
@@ -0,0 +1,4 @@
+pub mod invites;
+pub mod models;
+pub mod organization_selector;
+pub mod ui_organization;
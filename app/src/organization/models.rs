@@ -0,0 +1,320 @@
+use leptos::prelude::*;
+use partial_struct::Partial;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::permissions::Role;
+use crate::{Datetime, RecordId};
+
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{
+        cookies::session_token_from,
+        session::{get_user, AdapterSession},
+        user::AdapterUser,
+    },
+    db_init, AppError,
+};
+
+#[cfg(feature = "ssr")]
+use chrono::Utc;
+
+pub const ORGANIZATION_NAME_MAX_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Partial)]
+#[partial(
+    "CreateOrganizationData",
+    derive(Serialize, Deserialize, Clone),
+    omit(id)
+)]
+pub struct Organization {
+    pub id: RecordId,
+    pub name: String,
+    pub created_by_user_id: RecordId,
+    pub created_at: Datetime,
+}
+
+/// A user's place in an organization. Members share the organization's keys and chat rooms;
+/// admins also manage members and invitations.
+#[derive(Debug, Clone, Serialize, Deserialize, Partial)]
+#[partial("CreateMembership", derive(Serialize, Deserialize, Clone), omit(id))]
+pub struct Membership {
+    pub id: RecordId,
+    pub organization_id: RecordId,
+    pub user_id: RecordId,
+    pub role: Role,
+    pub created_at: Datetime,
+}
+
+/// An organization as listed for one of its members.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OrganizationSummary {
+    pub id: RecordId,
+    pub name: String,
+    pub role: Role,
+    /// Whether this is the organization the session is working in.
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberInfo {
+    pub user_id: RecordId,
+    pub name: String,
+    pub image: Option<String>,
+    pub role: Role,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+struct MembershipRow {
+    organization: Organization,
+    role: Role,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Deserialize)]
+struct MemberRow {
+    user: AdapterUser,
+    role: Role,
+}
+
+#[cfg(feature = "ssr")]
+impl Organization {
+    pub async fn get(id: RecordId) -> Result<Self, AppError> {
+        if id.table() != "organization" {
+            return Err(AppError::NotFound("Invalid organization ID".into()));
+        }
+
+        let db = db_init().await?;
+        let organization: Option<Self> = db.select(id).await?;
+        organization.ok_or_else(|| AppError::NotFound("Organization not found".into()))
+    }
+
+    /// Creates an organization with `user` as its first admin.
+    pub async fn create(user: &AdapterUser, name: String) -> Result<Self, AppError> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::ErrorReason(
+                "Organization name is required".into(),
+            ));
+        }
+        if name.chars().count() > ORGANIZATION_NAME_MAX_LEN {
+            return Err(AppError::ErrorReason(format!(
+                "Organization name must be at most {} characters",
+                ORGANIZATION_NAME_MAX_LEN
+            )));
+        }
+
+        let db = db_init().await?;
+        let content = CreateOrganizationData {
+            name,
+            created_by_user_id: user.id.clone(),
+            created_at: Datetime::from(Utc::now()),
+        };
+        let created: Option<Self> = db.create("organization").content(content).await?;
+        let created = created.ok_or_else(|| AppError::new("Failed to create organization"))?;
+
+        Membership::add(created.id.clone(), user.id.clone(), Role::Admin).await?;
+        Ok(created)
+    }
+
+    /// The organizations `user` belongs to, with their role in each.
+    pub async fn for_user(user: &AdapterUser) -> Result<Vec<(Self, Role)>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT organization_id AS organization, role FROM membership WHERE user_id = $user_id FETCH organization;")
+            .bind(("user_id", user.id.clone()))
+            .await?;
+        let rows: Vec<MembershipRow> = result.take(0)?;
+        let mut organizations: Vec<(Self, Role)> = rows
+            .into_iter()
+            .map(|row| (row.organization, row.role))
+            .collect();
+        organizations.sort_by(|a, b| a.0.name.to_lowercase().cmp(&b.0.name.to_lowercase()));
+        Ok(organizations)
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl Membership {
+    /// The role `user_id` holds in `organization_id`, or `None` if they are not a member.
+    pub async fn role_of(
+        organization_id: RecordId,
+        user_id: RecordId,
+    ) -> Result<Option<Role>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT VALUE role FROM ONLY membership WHERE organization_id = $organization_id AND user_id = $user_id LIMIT 1;")
+            .bind(("organization_id", organization_id))
+            .bind(("user_id", user_id))
+            .await?;
+        let role: Option<Role> = result.take(0)?;
+        Ok(role)
+    }
+
+    /// Fails with [`AppError::Forbidden`] unless `user` holds at least `role` in the organization.
+    pub async fn require(
+        organization_id: RecordId,
+        user: &AdapterUser,
+        role: Role,
+    ) -> Result<Role, AppError> {
+        match Self::role_of(organization_id, user.id.clone()).await? {
+            Some(held) if held >= role => Ok(held),
+            Some(_) => Err(AppError::Forbidden(
+                "Only organization admins can do that".into(),
+            )),
+            None => Err(AppError::Forbidden(
+                "Not a member of this organization".into(),
+            )),
+        }
+    }
+
+    /// Adds a member. Existing members keep their role.
+    pub async fn add(
+        organization_id: RecordId,
+        user_id: RecordId,
+        role: Role,
+    ) -> Result<(), AppError> {
+        let db = db_init().await?;
+        let membership = CreateMembership {
+            organization_id,
+            user_id,
+            role,
+            created_at: Datetime::from(Utc::now()),
+        };
+
+        // The unique (organization_id, user_id) index makes re-adding a no-op
+        db.query("INSERT IGNORE INTO membership $membership;")
+            .bind(("membership", membership))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn members(organization_id: RecordId) -> Result<Vec<MemberInfo>, AppError> {
+        let db = db_init().await?;
+        let mut result = db
+            .query("SELECT user_id AS user, role, created_at FROM membership WHERE organization_id = $organization_id ORDER BY created_at ASC FETCH user;")
+            .bind(("organization_id", organization_id))
+            .await?;
+        let rows: Vec<MemberRow> = result.take(0)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| MemberInfo {
+                user_id: row.user.id,
+                name: row.user.name,
+                image: row.user.image,
+                role: row.role,
+            })
+            .collect())
+    }
+
+    /// Removes a member, unless they are the organization's last admin.
+    pub async fn remove(organization_id: RecordId, user_id: RecordId) -> Result<(), AppError> {
+        let members = Self::members(organization_id.clone()).await?;
+        let admins = members.iter().filter(|m| m.role >= Role::Admin).count();
+        let removing_admin = members
+            .iter()
+            .any(|m| m.user_id == user_id && m.role >= Role::Admin);
+        if removing_admin && admins <= 1 {
+            return Err(AppError::ErrorReason(
+                "An organization needs at least one admin".into(),
+            ));
+        }
+
+        let db = db_init().await?;
+        db.query(
+            "DELETE membership WHERE organization_id = $organization_id AND user_id = $user_id;",
+        )
+        .bind(("organization_id", organization_id))
+        .bind(("user_id", user_id))
+        .await?;
+        Ok(())
+    }
+}
+
+/// The organization the current session works in, if the user is still a member of it.
+#[cfg(feature = "ssr")]
+pub async fn current_organization_id() -> Result<Option<RecordId>, ServerFnError> {
+    let user = get_user().await?;
+    let cookie_jar = leptos_axum::extract::<axum_extra::extract::CookieJar>().await?;
+    let Some(token) = session_token_from(&cookie_jar) else {
+        return Ok(None);
+    };
+
+    let session = AdapterSession::from_string(token).await?;
+    match session.organization_id {
+        Some(organization_id)
+            if Membership::role_of(organization_id.clone(), user.id)
+                .await?
+                .is_some() =>
+        {
+            Ok(Some(organization_id))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[server]
+pub async fn get_my_organizations() -> Result<Vec<OrganizationSummary>, ServerFnError> {
+    let user = get_user().await?;
+    let current = current_organization_id().await?;
+    let organizations = Organization::for_user(&user).await?;
+    Ok(organizations
+        .into_iter()
+        .map(|(organization, role)| OrganizationSummary {
+            current: current.as_ref() == Some(&organization.id),
+            id: organization.id,
+            name: organization.name,
+            role,
+        })
+        .collect())
+}
+
+/// Creates an organization and switches the session to it.
+#[server]
+pub async fn create_organization(name: String) -> Result<Organization, ServerFnError> {
+    let user = get_user().await?;
+    let organization = Organization::create(&user, name).await?;
+    switch_organization(Some(organization.id.clone())).await?;
+    Ok(organization)
+}
+
+/// Works in `organization_id` from now on in this session, or in the personal space for `None`.
+#[server]
+pub async fn switch_organization(organization_id: Option<RecordId>) -> Result<(), ServerFnError> {
+    use crate::auth::session::current_session_token;
+
+    let user = get_user().await?;
+    if let Some(organization_id) = &organization_id {
+        Membership::require(organization_id.clone(), &user, Role::Member).await?;
+    }
+    let token = current_session_token().await?;
+    AdapterSession::set_organization(token, organization_id).await?;
+    Ok(())
+}
+
+#[server]
+pub async fn get_organization_members(
+    organization_id: RecordId,
+) -> Result<Vec<MemberInfo>, ServerFnError> {
+    let user = get_user().await?;
+    Membership::require(organization_id.clone(), &user, Role::Member).await?;
+    let members = Membership::members(organization_id).await?;
+    Ok(members)
+}
+
+/// Removes a member. Admins may remove anyone; members may only leave.
+#[server]
+pub async fn remove_organization_member(
+    organization_id: RecordId,
+    user_id: RecordId,
+) -> Result<(), ServerFnError> {
+    let user = get_user().await?;
+    let required = if user_id == user.id {
+        Role::Member
+    } else {
+        Role::Admin
+    };
+    Membership::require(organization_id.clone(), &user, required).await?;
+    Membership::remove(organization_id, user_id).await?;
+    Ok(())
+}
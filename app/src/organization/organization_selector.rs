@@ -0,0 +1,61 @@
+use leptos::prelude::*;
+
+use crate::organization::models::{get_my_organizations, SwitchOrganization};
+
+/// Switches the organization the session works in. Hidden for users without organizations.
+#[component]
+pub fn OrganizationSelector() -> impl IntoView {
+    let organizations = Resource::new(|| (), |_| get_my_organizations());
+    let switch = ServerAction::<SwitchOrganization>::new();
+
+    // Everything on the page may be scoped to the organization, so start over
+    Effect::new(move |_| {
+        if matches!(switch.value().get(), Some(Ok(()))) {
+            #[cfg(not(feature = "ssr"))]
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().reload();
+            }
+        }
+    });
+
+    view! {
+        <Suspense fallback=|| ()>
+            {move || match organizations.get() {
+                Some(Ok(list)) if !list.is_empty() => {
+                    let options = list.clone();
+                    let on_change = move |ev: leptos::ev::Event| {
+                        let value = event_target_value(&ev);
+                        let organization_id = options
+                            .iter()
+                            .find(|org| org.id.to_string() == value)
+                            .map(|org| org.id.clone());
+                        switch.dispatch(SwitchOrganization { organization_id });
+                    };
+                    view! {
+                        <select
+                            class="px-3 py-2 border border-neutral-300 dark:border-neutral-600 bg-white dark:bg-neutral-800 text-neutral-900 dark:text-white rounded-md text-sm"
+                            disabled=move || switch.pending().get()
+                            on:change=on_change
+                        >
+                            <option value="" selected=!list.iter().any(|org| org.current)>
+                                "Personal"
+                            </option>
+                            {list
+                                .iter()
+                                .map(|org| {
+                                    view! {
+                                        <option value=org.id.to_string() selected=org.current>
+                                            {org.name.clone()}
+                                        </option>
+                                    }
+                                })
+                                .collect_view()}
+                        </select>
+                    }
+                        .into_any()
+                }
+                _ => ().into_any(),
+            }}
+        </Suspense>
+    }
+}
@@ -0,0 +1,342 @@
+use leptos::prelude::*;
+use leptos_router::hooks::use_query_map;
+
+use crate::auth::{permissions::Role, session::get_user, ui_auth::reload_to, AuthCheck};
+use crate::components::{
+    input::{FormField, Input, InputType},
+    Seperator, SubmitButton,
+};
+use crate::organization::invites::{get_pending_invites, AcceptInvite, InviteMember, RevokeInvite};
+use crate::organization::models::{
+    get_my_organizations, get_organization_members, CreateOrganization, OrganizationSummary,
+    RemoveOrganizationMember, ORGANIZATION_NAME_MAX_LEN,
+};
+use crate::RecordId;
+
+const PRIMARY_BUTTON_CLASS: &str = "px-4 py-2 bg-blue-600 text-white rounded-md hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed";
+
+fn role_label(role: Role) -> &'static str {
+    match role {
+        Role::Member | Role::Owner => "Member",
+        Role::Admin => "Admin",
+        Role::Superadmin => "Superadmin",
+    }
+}
+
+/// Members of the current organization. Admins also invite and remove members here.
+#[component]
+fn OrganizationMembers(organization: OrganizationSummary) -> impl IntoView {
+    let organization_id = organization.id.clone();
+    let is_admin = organization.role >= Role::Admin;
+
+    let user = Resource::new(|| (), |_| get_user());
+    let members = Resource::new(
+        {
+            let organization_id = organization_id.clone();
+            move || organization_id.clone()
+        },
+        get_organization_members,
+    );
+    let invites = Resource::new(
+        {
+            let organization_id = organization_id.clone();
+            move || organization_id.clone()
+        },
+        move |organization_id| async move {
+            if is_admin {
+                get_pending_invites(organization_id).await
+            } else {
+                Ok(Vec::new())
+            }
+        },
+    );
+
+    let remove = ServerAction::<RemoveOrganizationMember>::new();
+    let invite = ServerAction::<InviteMember>::new();
+    let revoke = ServerAction::<RevokeInvite>::new();
+    let email = RwSignal::new(String::new());
+    let (submitting, set_submitting) = signal(false);
+    let (error_message, set_error_message) = signal(Option::<String>::None);
+    let leaving = StoredValue::new(false);
+
+    Effect::new(move |_| {
+        set_submitting.set(invite.pending().get());
+    });
+
+    Effect::new(move |_| match remove.value().get() {
+        Some(Ok(())) => {
+            // Leaving drops the session back to the personal space
+            if leaving.get_value() {
+                reload_to("/settings");
+            } else {
+                set_error_message.set(None);
+                members.refetch();
+            }
+        }
+        Some(Err(e)) => set_error_message.set(Some(e.to_string())),
+        None => {}
+    });
+
+    Effect::new(move |_| {
+        for result in [invite.value().get(), revoke.value().get()] {
+            if let Some(result) = result {
+                set_error_message.set(result.err().map(|e| e.to_string()));
+                invites.refetch();
+            }
+        }
+    });
+
+    Effect::new(move |_| {
+        if matches!(invite.value().get(), Some(Ok(()))) {
+            email.set(String::new());
+        }
+    });
+
+    let submit_invite = {
+        let organization_id = organization_id.clone();
+        move |ev: leptos::ev::SubmitEvent| {
+            ev.prevent_default();
+            invite.dispatch(InviteMember {
+                organization_id: organization_id.clone(),
+                email: email.get(),
+            });
+        }
+    };
+
+    let remove_member = {
+        let organization_id = organization_id.clone();
+        Callback::new(move |(user_id, is_self): (RecordId, bool)| {
+            leaving.set_value(is_self);
+            remove.dispatch(RemoveOrganizationMember {
+                organization_id: organization_id.clone(),
+                user_id,
+            });
+        })
+    };
+
+    let revoke_invite = Callback::new(move |email: String| {
+        revoke.dispatch(RevokeInvite {
+            organization_id: organization_id.clone(),
+            email,
+        });
+    });
+
+    view! {
+        <div class="mt-4">
+            <h3 class="text-lg font-semibold mb-2">{organization.name.clone()}" members"</h3>
+            <Suspense fallback=move || view! { <div class="h-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || {
+                    let current_user_id = user.get().and_then(|r| r.ok()).map(|user| user.id);
+                    match members.get() {
+                        Some(Ok(list)) => {
+                            view! {
+                                <div class="divide-y divide-neutral-200 dark:divide-neutral-700">
+                                    {list
+                                        .into_iter()
+                                        .map(|member| {
+                                            let user_id = member.user_id.clone();
+                                            let is_self = current_user_id.as_ref() == Some(&member.user_id);
+                                            view! {
+                                                <div class="flex items-center justify-between px-4 py-3">
+                                                    <div class="text-sm">
+                                                        <span class="font-medium text-neutral-900 dark:text-neutral-100">{member.name.clone()}</span>
+                                                        <span class="ml-2 px-2 py-0.5 text-xs rounded bg-neutral-100 text-neutral-700 dark:bg-neutral-700 dark:text-neutral-300">
+                                                            {role_label(member.role)}
+                                                        </span>
+                                                    </div>
+                                                    {(is_self || is_admin).then(|| view! {
+                                                        <button
+                                                            class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 text-sm font-medium"
+                                                            on:click=move |_| remove_member.run((user_id.clone(), is_self))
+                                                        >
+                                                            {if is_self { "Leave" } else { "Remove" }}
+                                                        </button>
+                                                    })}
+                                                </div>
+                                            }
+                                        })
+                                        .collect_view()}
+                                </div>
+                            }.into_any()
+                        }
+                        Some(Err(e)) => {
+                            view! { <p class="text-red-600 dark:text-red-400">"Error loading members: " {e.to_string()}</p> }.into_any()
+                        }
+                        None => view! { <div></div> }.into_any(),
+                    }
+                }}
+            </Suspense>
+
+            {is_admin.then(|| view! {
+                <form on:submit=submit_invite class="mt-4 flex items-end gap-2">
+                    <FormField label="Invite by email" label_for="organization-invite-email" class="flex-1">
+                        <Input id="organization-invite-email" name="email" r#type=InputType::Email value=email required=true />
+                    </FormField>
+                    <SubmitButton text="Invite" is_submitting=submitting />
+                </form>
+                <Suspense>
+                    {move || {
+                        let pending = invites.get().and_then(|r| r.ok()).unwrap_or_default();
+                        (!pending.is_empty()).then(|| view! {
+                            <h4 class="mt-4 mb-2 text-sm font-medium text-neutral-700 dark:text-neutral-300">"Pending invitations"</h4>
+                            <div class="divide-y divide-neutral-200 dark:divide-neutral-700">
+                                {pending
+                                    .into_iter()
+                                    .map(|pending_invite| {
+                                        let email = pending_invite.email.clone();
+                                        view! {
+                                            <div class="flex items-center justify-between px-4 py-2 text-sm">
+                                                <span>{pending_invite.email}</span>
+                                                <button
+                                                    class="text-red-600 hover:text-red-800 dark:text-red-400 dark:hover:text-red-300 font-medium"
+                                                    on:click=move |_| revoke_invite.run(email.clone())
+                                                >
+                                                    "Revoke"
+                                                </button>
+                                            </div>
+                                        }
+                                    })
+                                    .collect_view()}
+                            </div>
+                        })
+                    }}
+                </Suspense>
+            })}
+
+            {move || error_message.get().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+/// Creating organizations and managing the one the session works in, shown in settings.
+#[component]
+pub fn OrganizationControl() -> impl IntoView {
+    let organizations = Resource::new(|| (), |_| get_my_organizations());
+    let create = ServerAction::<CreateOrganization>::new();
+    let name = RwSignal::new(String::new());
+    let (submitting, set_submitting) = signal(false);
+
+    Effect::new(move |_| {
+        set_submitting.set(create.pending().get());
+    });
+
+    // The session switched to the new organization, so everything on the page changes with it
+    Effect::new(move |_| {
+        if matches!(create.value().get(), Some(Ok(_))) {
+            reload_to("/settings");
+        }
+    });
+
+    let error = move || {
+        create
+            .value()
+            .get()
+            .and_then(|r| r.err())
+            .map(|e| e.to_string())
+    };
+
+    let submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        create.dispatch(CreateOrganization { name: name.get() });
+    };
+
+    view! {
+        <div class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow">
+            <h2 class="text-xl font-semibold mb-2">"Organization"</h2>
+            <p class="text-sm text-neutral-600 dark:text-neutral-400">
+                "Keys and chat rooms created while working in an organization are shared with its members. Switch organizations from the navigation bar."
+            </p>
+
+            <Suspense fallback=move || view! { <div class="h-4 mt-4 bg-neutral-200 dark:bg-neutral-700 rounded w-1/2 animate-pulse"></div> }>
+                {move || match organizations.get() {
+                    Some(Ok(list)) => match list.into_iter().find(|org| org.current) {
+                        Some(current) => view! { <OrganizationMembers organization=current /> }.into_any(),
+                        None => view! {
+                            <p class="mt-4 text-sm">"You are working in your personal space."</p>
+                        }.into_any(),
+                    },
+                    Some(Err(e)) => {
+                        view! { <p class="mt-4 text-red-600 dark:text-red-400">"Error loading organizations: " {e.to_string()}</p> }.into_any()
+                    }
+                    None => view! { <div></div> }.into_any(),
+                }}
+            </Suspense>
+
+            <form on:submit=submit class="mt-4 flex items-end gap-2">
+                <FormField label="New organization" label_for="organization-name" class="flex-1">
+                    <Input id="organization-name" name="name" value=name required=true maxlength={ORGANIZATION_NAME_MAX_LEN as i32} />
+                </FormField>
+                <button type="submit" class=PRIMARY_BUTTON_CLASS disabled=move || submitting.get()>
+                    "Create"
+                </button>
+            </form>
+            {move || error().map(|msg| {
+                view! { <p class="mt-2 text-sm text-red-600 dark:text-red-400">{msg}</p> }
+            })}
+        </div>
+    }
+}
+
+/// The page an invitation email links to. Accepting needs a signed-in account.
+#[component]
+pub fn InviteScreen() -> impl IntoView {
+    let query = use_query_map();
+    let accept = ServerAction::<AcceptInvite>::new();
+
+    Effect::new(move |_| {
+        if matches!(accept.value().get(), Some(Ok(_))) {
+            reload_to("/");
+        }
+    });
+
+    let error = move || {
+        accept
+            .value()
+            .get()
+            .and_then(|r| r.err())
+            .map(|e| e.to_string())
+    };
+
+    let on_accept = move |_| {
+        let (organization, email, token) = query.with_untracked(|q| {
+            (
+                q.get("organization").unwrap_or_default(),
+                q.get("email").unwrap_or_default(),
+                q.get("token").unwrap_or_default(),
+            )
+        });
+        accept.dispatch(AcceptInvite {
+            organization,
+            email,
+            token,
+        });
+    };
+
+    view! {
+        <AuthCheck unauthed=|| view! {
+            <p class="p-4">"Please " <a href="/login" class="text-blue-600 dark:text-blue-400 hover:underline">"sign in"</a> " and open the invitation link again to join the organization."</p>
+        }>
+            <div class="bg-white dark:bg-black rounded-lg shadow-2xl p-8 mx-8 w-full max-w-md mx-auto">
+                <div class="text-center mb-8">
+                    <h1 class="text-3xl font-bold text-neutral-800 dark:text-neutral-100 mb-2">"Organization invitation"</h1>
+                    <small class="text-neutral-500 dark:text-neutral-400">
+                        "You were invited to share keys and chat rooms with an organization."
+                    </small>
+                </div>
+
+                <Seperator />
+
+                <div class="mt-6 flex flex-col gap-4">
+                    {move || error().map(|msg| {
+                        view! { <p class="text-sm text-red-600 dark:text-red-400">{msg}</p> }
+                    })}
+                    <button class=PRIMARY_BUTTON_CLASS disabled=move || accept.pending().get() on:click=on_accept>
+                        "Join organization"
+                    </button>
+                </div>
+            </div>
+        </AuthCheck>
+    }
+}
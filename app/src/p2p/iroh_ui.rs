@@ -164,7 +164,14 @@ pub fn IrohTest() -> impl IntoView {
         {
             status.set("Initializing P2P node...".to_string());
             wasm_bindgen_futures::spawn_local(async move {
-                match crate::p2p::wasm_chat::ChatNode::spawn().await {
+                // Keep a stable node id when the user bound a key as their identity
+                let spawned = match crate::auth::keypairs::get_iroh_identity().await {
+                    Ok(Some(secret_key)) => {
+                        crate::p2p::wasm_chat::ChatNode::spawn_with_identity(secret_key).await
+                    }
+                    _ => crate::p2p::wasm_chat::ChatNode::spawn().await,
+                };
+                match spawned {
                     Ok(node) => {
                         let node_id = node.node_id();
                         status.set(format!("Node ready! ID: {:.8}...", node_id));
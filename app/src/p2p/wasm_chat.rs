@@ -32,6 +32,20 @@ impl ChatNode {
         Ok(Self(inner))
     }
 
+    /// Spawns a node with the base64 Ed25519 secret key the user bound as their iroh identity.
+    pub async fn spawn_with_identity(secret_key: String) -> Result<Self, JsError> {
+        let bytes: [u8; 32] = data_encoding::BASE64
+            .decode(secret_key.as_bytes())
+            .map_err(to_js_err)?
+            .try_into()
+            .map_err(|_| JsError::new("identity key must be 32 bytes"))?;
+        let secret_key = iroh::SecretKey::from_bytes(&bytes);
+        let inner = crate::p2p::iroh::ChatNode::spawn(Some(secret_key))
+            .await
+            .map_err(to_js_err)?;
+        Ok(Self(inner))
+    }
+
     pub fn node_id(&self) -> String {
         self.0.node_id().to_string()
     }
@@ -0,0 +1,30 @@
+use leptos::prelude::*;
+
+use crate::admin::ui_admin::{AdminAuditLog, AdminUsers, KeyEncryptionControl};
+use crate::auth::{session::get_user, AuthCheck};
+
+#[component]
+pub fn AdminScreen() -> impl IntoView {
+    let user = Resource::new(|| (), |_| get_user());
+
+    view! {
+        <AuthCheck unauthed=|| view! {
+            <p class="p-4">"Please " <a href="/login" class="text-blue-600 dark:text-blue-400 hover:underline">"sign in"</a> " to open the admin console."</p>
+        }>
+            <Suspense fallback=|| ()>
+                {move || user.get().map(|user| match user {
+                    Ok(user) if user.superadmin == Some(true) => view! {
+                        <div class="flex flex-col gap-4 p-4 max-w-5xl w-full mx-auto">
+                            <AdminUsers />
+                            <AdminAuditLog />
+                            <KeyEncryptionControl />
+                        </div>
+                    }.into_any(),
+                    _ => view! {
+                        <p class="p-4">"The admin console is for superadmins only."</p>
+                    }.into_any(),
+                })}
+            </Suspense>
+        </AuthCheck>
+    }
+}
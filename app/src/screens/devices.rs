@@ -0,0 +1,17 @@
+use leptos::prelude::*;
+
+use crate::auth::{devices::DevicesControl, AuthCheck};
+
+#[component]
+pub fn DevicesScreen() -> impl IntoView {
+    view! {
+        <AuthCheck unauthed=|| view! {
+            <p class="p-4">"Please " <a href="/login" class="text-blue-600 dark:text-blue-400 hover:underline">"sign in"</a> " to see your devices."</p>
+        }>
+            <div class="flex flex-col gap-4 p-4 max-w-3xl w-full mx-auto">
+                <a href="/settings" class="text-sm text-blue-600 dark:text-blue-400 hover:underline">"Back to settings"</a>
+                <DevicesControl />
+            </div>
+        </AuthCheck>
+    }
+}
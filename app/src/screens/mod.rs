@@ -1,6 +1,15 @@
+mod admin;
+
+mod devices;
+
 mod home;
 
 mod profile;
 
+mod settings;
+
+pub use admin::AdminScreen;
+pub use devices::DevicesScreen;
 pub use home::HomeScreen;
 pub use profile::ProfileScreen;
+pub use settings::SettingsScreen;
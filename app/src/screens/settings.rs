@@ -0,0 +1,31 @@
+use leptos::prelude::*;
+
+use crate::auth::{
+    account_data::AccountDataControl, keys::KeysControl, passkeys::PasskeysControl,
+    ui_totp::TotpControl, AuthCheck,
+};
+use crate::organization::ui_organization::OrganizationControl;
+
+#[component]
+pub fn SettingsScreen() -> impl IntoView {
+    view! {
+        <AuthCheck unauthed=|| view! {
+            <p class="p-4">"Please " <a href="/login" class="text-blue-600 dark:text-blue-400 hover:underline">"sign in"</a> " to manage your settings."</p>
+        }>
+            <div class="flex flex-col gap-4 p-4 max-w-3xl w-full mx-auto">
+                <a
+                    href="/settings/devices"
+                    class="p-4 bg-white dark:bg-neutral-800 rounded-lg shadow hover:bg-neutral-50 dark:hover:bg-neutral-700"
+                >
+                    <h2 class="text-xl font-semibold">"Your devices"</h2>
+                    <p class="text-neutral-600 dark:text-neutral-400">"See where you are signed in and sign out devices you no longer use."</p>
+                </a>
+                <OrganizationControl />
+                <TotpControl />
+                <PasskeysControl />
+                <KeysControl />
+                <AccountDataControl />
+            </div>
+        </AuthCheck>
+    }
+}
@@ -1,4 +1,13 @@
-use app::App;
+use app::{
+    auth::{
+        auth_routes,
+        csrf::{csrf_guard, CsrfConfig},
+        extract::resolve_auth,
+        session::{session_activity, spawn_session_sweeper},
+    },
+    chat::websocket::{chat_routes, ChatState},
+    App,
+};
 use axum::{extract::OriginalUri, http::Request, Router};
 use backend::fallback::file_and_error_handler;
 use leptos::prelude::*;
@@ -17,6 +26,7 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 pub struct ServerState {
     pub options: LeptosOptions,
     pub routes: Vec<leptos_axum::AxumRouteListing>,
+    pub chat: ChatState,
 }
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
@@ -79,9 +89,21 @@ async fn main() {
 		.with(tracing_subscriber::fmt::layer())
 		.init();
 
-    let _state = ServerState {
+    if let Err(e) = app::db::db_schema().await {
+        tracing::error!("Failed to apply database schema: {}", e);
+    }
+    // Seals keys stored in plaintext and moves them off previous master keys
+    match app::auth::key_vault::reencrypt_keys().await {
+        Ok(0) => {}
+        Ok(changed) => tracing::info!("Re-encrypted {} stored keys", changed),
+        Err(e) => tracing::warn!("Could not re-encrypt stored keys: {}", e),
+    }
+    spawn_session_sweeper();
+
+    let state = ServerState {
         options: leptos_options.clone(),
         routes: routes.clone(),
+        chat: ChatState::new(),
     };
 
     let cors = CorsLayer::new()
@@ -97,13 +119,33 @@ async fn main() {
                 .unwrap(),
         )
         .allow_origin(Any)
-        .allow_headers(vec![axum::http::header::CONTENT_TYPE]);
+        .allow_headers(vec![
+            axum::http::header::CONTENT_TYPE,
+            axum::http::header::AUTHORIZATION,
+        ]);
 
     let app = Router::new()
-        .leptos_routes(&leptos_options, routes, {
-            let leptos_options = leptos_options.clone();
-            move || shell(leptos_options.clone())
-        })
+        .nest("/api/chat", chat_routes().with_state(state.chat.clone()))
+        .nest("/auth", auth_routes())
+        .leptos_routes_with_context(
+            &leptos_options,
+            routes,
+            {
+                // Lets server functions such as `get_online_users` see who is connected
+                let chat = state.chat.clone();
+                move || provide_context(chat.clone())
+            },
+            {
+                let leptos_options = leptos_options.clone();
+                move || shell(leptos_options.clone())
+            },
+        )
+        .layer(axum::middleware::from_fn(session_activity))
+        .layer(axum::middleware::from_fn(resolve_auth))
+        .layer(axum::middleware::from_fn_with_state(
+            CsrfConfig::from_env(),
+            csrf_guard,
+        ))
         .layer(cors)
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
@@ -0,0 +1,127 @@
+use app::auth::{
+    account::AdapterAccount,
+    keys::{Key, KeyCreate},
+    permissions::Role,
+    session::AdapterSession,
+    user::{AdapterUser, DELETED_USER_NAME},
+    StorageAuthed,
+};
+use app::chat::{
+    models::{save_chat_event, ChatEventDb, ChatEventType, DmConversation},
+    shared::default_room_id,
+};
+use app::organization::models::{Membership, Organization};
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn deleting_a_user_removes_their_data_and_anonymises_messages() {
+    let user = AdapterUser::create_test_user().await.unwrap();
+    let email = format!("{}@example.com", user.id.key());
+    AdapterAccount::link_email(user.id.clone(), &email)
+        .await
+        .unwrap();
+    let session = user.new_verified_session().await.unwrap();
+    let organization = Organization::create(&user, "Solo".into()).await.unwrap();
+    let message = save_chat_event(
+        Some(user.id.clone()),
+        user.name.clone(),
+        Some(default_room_id()),
+        ChatEventType::Message,
+        Some("hello".into()),
+        None,
+    )
+    .await
+    .unwrap();
+
+    user.delete_user().await.unwrap();
+
+    assert!(AdapterUser::get_user(user.id.clone()).await.is_err());
+    assert!(AdapterSession::from_string(session.session_token)
+        .await
+        .is_err());
+    assert!(AdapterAccount::find("email", &email)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(Organization::get(organization.id).await.is_err());
+
+    let message = ChatEventDb::get(message.id).await.unwrap();
+    assert_eq!(message.user_id, None);
+    assert_eq!(message.username, DELETED_USER_NAME);
+    assert_eq!(message.message.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn the_last_admin_of_a_shared_organization_cannot_be_deleted() {
+    let admin = AdapterUser::create_test_user().await.unwrap();
+    let member = AdapterUser::create_test_user().await.unwrap();
+    let organization = Organization::create(&admin, "Shared".into()).await.unwrap();
+    Membership::add(organization.id.clone(), member.id.clone(), Role::Member)
+        .await
+        .unwrap();
+
+    assert!(admin.delete_user().await.is_err());
+    assert!(AdapterUser::get_user(admin.id.clone()).await.is_ok());
+
+    // The organization lives on with its remaining member
+    member.delete_user().await.unwrap();
+    admin.delete_user().await.unwrap();
+    assert!(Organization::get(organization.id).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn deleting_a_member_hands_over_shared_keys_and_removes_direct_messages() {
+    let admin = AdapterUser::create_test_user().await.unwrap();
+    let member = AdapterUser::create_test_user().await.unwrap();
+    let organization = Organization::create(&admin, "Shared".into()).await.unwrap();
+    Membership::add(organization.id.clone(), member.id.clone(), Role::Member)
+        .await
+        .unwrap();
+    let key = Key::create_by_user(
+        member.clone(),
+        KeyCreate {
+            name: "Deploy key".into(),
+            key_for: None,
+            key_public: None,
+            key_private: None,
+            key_apikey: None,
+            key_token: None,
+            description: String::new(),
+            expires_at: None,
+            organization_id: Some(organization.id.clone()),
+            key_prefix: None,
+            key_hash: None,
+            scopes: Vec::new(),
+            algorithm: None,
+            iroh_identity: false,
+            key_dek: None,
+            has_secrets: false,
+        },
+    )
+    .await
+    .unwrap();
+    let conversation = DmConversation::get_or_create(&admin.id, &member.id)
+        .await
+        .unwrap();
+    let message = save_chat_event(
+        Some(member.id.clone()),
+        member.name.clone(),
+        Some(conversation.id.clone()),
+        ChatEventType::Message,
+        Some("psst".into()),
+        None,
+    )
+    .await
+    .unwrap();
+
+    member.delete_user().await.unwrap();
+
+    // The organization keeps its key, now owned by its admin
+    let key = Key::get_by_id(key.id).await.unwrap();
+    assert_eq!(key.created_by_user_id, admin.id);
+    assert!(DmConversation::get(conversation.id).await.is_err());
+    assert!(ChatEventDb::get(message.id).await.is_err());
+    assert!(DmConversation::for_user(&admin).await.unwrap().is_empty());
+}
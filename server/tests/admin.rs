@@ -0,0 +1,32 @@
+use app::admin::models::{AdminAction, AuditEntry};
+use app::auth::{session::AdapterSession, user::AdapterUser};
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn disabling_a_user_signs_them_out_and_blocks_new_sessions() {
+    let admin = AdapterUser::create_test_user().await.unwrap();
+    let user = AdapterUser::create_test_user().await.unwrap();
+    let session = user.new_verified_session().await.unwrap();
+
+    let user = user.set_disabled(true).await.unwrap();
+    AuditEntry::record(&admin, AdminAction::Disable, Some(&user), None)
+        .await
+        .unwrap();
+
+    assert!(user.disabled);
+    assert!(AdapterSession::from_string(session.session_token.clone())
+        .await
+        .is_err());
+    assert!(user.new_verified_session().await.is_err());
+
+    let user = user.set_disabled(false).await.unwrap();
+    let session = user.new_verified_session().await.unwrap();
+    assert!(AdapterUser::get_user_from_session(session.session_token)
+        .await
+        .is_ok());
+
+    let recorded = AuditEntry::recent(0, 50).await.unwrap();
+    assert!(recorded.iter().any(|entry| entry.admin_id == admin.id
+        && entry.action == AdminAction::Disable
+        && entry.target_user_id.as_ref() == Some(&user.id)));
+}
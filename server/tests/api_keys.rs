@@ -0,0 +1,70 @@
+use app::auth::{
+    api_keys::{issue_api_key, ApiScope},
+    extract::{resolve_auth, AuthUser},
+    user::AdapterUser,
+};
+use axum::{routing::get, Router};
+use tokio::net::TcpListener;
+
+async fn whoami(AuthUser(user): AuthUser) -> String {
+    user.id.to_string()
+}
+
+/// Serves a handler that answers with the resolved user's id and returns the base URL.
+async fn spawn_app() -> String {
+    let app = Router::new()
+        .route("/whoami", get(whoami).post(whoami))
+        .layer(axum::middleware::from_fn(resolve_auth));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    url
+}
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn bearer_api_keys_authenticate_within_their_scopes() {
+    let url = spawn_app().await;
+    let user = AdapterUser::create_test_user().await.unwrap();
+    let issued = issue_api_key(
+        user.clone(),
+        "CI".into(),
+        String::new(),
+        vec![ApiScope::Read],
+        None,
+    )
+    .await
+    .unwrap();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/whoami", url))
+        .bearer_auth(&issued.secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), user.id.to_string());
+
+    // Read-only keys cannot change anything
+    let response = client
+        .post(format!("{}/whoami", url))
+        .bearer_auth(&issued.secret)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = client
+        .get(format!("{}/whoami", url))
+        .bearer_auth(format!("{}x", issued.secret))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    user.delete_user().await.unwrap();
+}
@@ -0,0 +1,330 @@
+use std::time::Duration;
+
+use app::chat::{
+    shared::{default_room_id, ClientWsMessage, WsErrorCode, WsMessage, PROTOCOL_VERSION},
+    websocket::{chat_routes, ChatState, TYPING_EXPIRY},
+};
+use app::RecordId;
+use axum::Router;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn spawn_chat_server() -> String {
+    let app = Router::new().nest(
+        "/api/chat",
+        chat_routes().with_state(ChatState::in_memory()),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    format!("ws://{}/api/chat/ws", addr)
+}
+
+async fn send_frame(client: &mut Client, msg: &ClientWsMessage) {
+    client
+        .send(Message::text(serde_json::to_string(msg).unwrap()))
+        .await
+        .unwrap();
+}
+
+async fn send_text(client: &mut Client, room_id: &RecordId, text: &str) {
+    let msg = ClientWsMessage::Send {
+        room_id: room_id.clone(),
+        message: text.to_string(),
+        client_msg_id: None,
+    };
+    send_frame(client, &msg).await;
+}
+
+/// Reads frames until one matches `pred`, failing the test if nothing arrives in time.
+async fn next_matching<F>(client: &mut Client, pred: F) -> WsMessage
+where
+    F: Fn(&WsMessage) -> bool,
+{
+    next_matching_within(client, Duration::from_secs(5), pred).await
+}
+
+async fn next_matching_within<F>(client: &mut Client, wait: Duration, pred: F) -> WsMessage
+where
+    F: Fn(&WsMessage) -> bool,
+{
+    tokio::time::timeout(wait, async {
+        while let Some(frame) = client.next().await {
+            if let Message::Text(text) = frame.unwrap() {
+                let msg: WsMessage = serde_json::from_str(text.as_str()).unwrap();
+                if pred(&msg) {
+                    return msg;
+                }
+            }
+        }
+        panic!("websocket closed before the expected message arrived");
+    })
+    .await
+    .expect("timed out waiting for websocket message")
+}
+
+#[tokio::test]
+async fn chat_messages_are_relayed_between_clients() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    let (mut bob, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut bob, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+    // alice sees bob arrive
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    send_text(&mut alice, &default_room_id(), "hello bob").await;
+
+    let received = next_matching(&mut bob, |m| matches!(m, WsMessage::Message(_))).await;
+    match received {
+        WsMessage::Message(chat) => assert_eq!(chat.message, "hello bob"),
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    send_text(&mut bob, &default_room_id(), "hi alice").await;
+
+    let received = next_matching(
+        &mut alice,
+        |m| matches!(m, WsMessage::Message(chat) if chat.message == "hi alice"),
+    )
+    .await;
+    assert!(matches!(received, WsMessage::Message(_)));
+
+    bob.close(None).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserLeft { .. })).await;
+}
+
+#[tokio::test]
+async fn room_messages_stay_in_their_room() {
+    let url = spawn_chat_server().await;
+    let dev_room = RecordId::from(("chat_room", "dev"));
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+    let (mut bob, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut bob, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    let join = ClientWsMessage::JoinRoom {
+        room_id: dev_room.clone(),
+    };
+    send_frame(&mut alice, &join).await;
+    next_matching(
+        &mut alice,
+        |m| matches!(m, WsMessage::UserJoined { room_id, .. } if *room_id == dev_room),
+    )
+    .await;
+
+    // alice now posts to dev, which bob never joined
+    send_text(&mut alice, &dev_room, "dev only").await;
+    let received = next_matching(&mut alice, |m| matches!(m, WsMessage::Message(_))).await;
+    assert_eq!(received.room_id(), Some(&dev_room));
+
+    send_text(&mut bob, &default_room_id(), "general chat").await;
+    match next_matching(&mut bob, |m| matches!(m, WsMessage::Message(_))).await {
+        WsMessage::Message(chat) => {
+            assert_eq!(chat.message, "general chat");
+            assert_eq!(chat.room_id, default_room_id());
+        }
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    // alice is still subscribed to general as well
+    next_matching(
+        &mut alice,
+        |m| matches!(m, WsMessage::Message(chat) if chat.message == "general chat"),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn protocol_errors_are_reported_to_the_sender() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    // Raw text frames are no longer accepted as chat messages
+    alice.send(Message::text("not json")).await.unwrap();
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Error { .. })).await {
+        WsMessage::Error { code, .. } => assert_eq!(code, WsErrorCode::MalformedFrame),
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    send_frame(
+        &mut alice,
+        &ClientWsMessage::Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+        },
+    )
+    .await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Error { .. })).await {
+        WsMessage::Error { code, .. } => assert_eq!(code, WsErrorCode::UnsupportedVersion),
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    // Posting to a room the socket never joined is refused
+    let dev_room = RecordId::from(("chat_room", "dev"));
+    send_text(&mut alice, &dev_room, "sneaky").await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Error { .. })).await {
+        WsMessage::Error { code, .. } => assert_eq!(code, WsErrorCode::Forbidden),
+        other => panic!("unexpected message: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn hello_ping_and_ack_round_trip() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    send_frame(
+        &mut alice,
+        &ClientWsMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+        },
+    )
+    .await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Welcome { .. })).await {
+        WsMessage::Welcome { protocol_version } => assert_eq!(protocol_version, PROTOCOL_VERSION),
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    send_frame(&mut alice, &ClientWsMessage::Ping { nonce: Some(7) }).await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Pong { .. })).await {
+        WsMessage::Pong { nonce } => assert_eq!(nonce, Some(7)),
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    let send = ClientWsMessage::Send {
+        room_id: default_room_id(),
+        message: "with ack".to_string(),
+        client_msg_id: Some("local-1".to_string()),
+    };
+    send_frame(&mut alice, &send).await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Ack { .. })).await {
+        WsMessage::Ack { client_msg_id, .. } => assert_eq!(client_msg_id, "local-1"),
+        other => panic!("unexpected message: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn typing_indicators_reach_the_room() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+    let (mut bob, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut bob, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    let typing = |room_id: RecordId, typing: bool| ClientWsMessage::Typing { room_id, typing };
+
+    send_frame(&mut alice, &typing(default_room_id(), true)).await;
+    match next_matching(&mut bob, |m| matches!(m, WsMessage::Typing { .. })).await {
+        WsMessage::Typing {
+            room_id, typing, ..
+        } => {
+            assert_eq!(room_id, default_room_id());
+            assert!(typing);
+        }
+        other => panic!("unexpected message: {:?}", other),
+    }
+
+    send_frame(&mut alice, &typing(default_room_id(), false)).await;
+    let stopped = next_matching(&mut bob, |m| matches!(m, WsMessage::Typing { .. })).await;
+    assert!(matches!(stopped, WsMessage::Typing { typing: false, .. }));
+}
+
+#[tokio::test]
+async fn typing_again_after_the_indicator_expired_shows_again() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+    let (mut bob, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut bob, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    let typing = ClientWsMessage::Typing {
+        room_id: default_room_id(),
+        typing: true,
+    };
+    send_frame(&mut alice, &typing).await;
+    next_matching(&mut bob, |m| {
+        matches!(m, WsMessage::Typing { typing: true, .. })
+    })
+    .await;
+
+    // alice pauses until the server ends the indicator, then types again
+    next_matching_within(&mut bob, TYPING_EXPIRY * 2, |m| {
+        matches!(m, WsMessage::Typing { typing: false, .. })
+    })
+    .await;
+    send_frame(&mut alice, &typing).await;
+    next_matching(&mut bob, |m| {
+        matches!(m, WsMessage::Typing { typing: true, .. })
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn resent_messages_are_acknowledged_but_posted_once() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+    let (mut bob, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut bob, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    // As after a reconnect that lost the first ack
+    let send = ClientWsMessage::Send {
+        room_id: default_room_id(),
+        message: "only once".to_string(),
+        client_msg_id: Some("local-1".to_string()),
+    };
+    for _ in 0..2 {
+        send_frame(&mut alice, &send).await;
+        match next_matching(&mut alice, |m| matches!(m, WsMessage::Ack { .. })).await {
+            WsMessage::Ack { client_msg_id, .. } => assert_eq!(client_msg_id, "local-1"),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+    send_text(&mut alice, &default_room_id(), "after").await;
+
+    let first = next_matching(&mut bob, |m| matches!(m, WsMessage::Message(_))).await;
+    assert!(matches!(first, WsMessage::Message(chat) if chat.message == "only once"));
+    let second = next_matching(&mut bob, |m| matches!(m, WsMessage::Message(_))).await;
+    assert!(matches!(second, WsMessage::Message(chat) if chat.message == "after"));
+}
+
+#[tokio::test]
+async fn refused_messages_name_their_client_msg_id() {
+    let url = spawn_chat_server().await;
+
+    let (mut alice, _) = connect_async(&url).await.unwrap();
+    next_matching(&mut alice, |m| matches!(m, WsMessage::UserJoined { .. })).await;
+
+    let send = ClientWsMessage::Send {
+        room_id: RecordId::from(("chat_room", "dev")),
+        message: "sneaky".to_string(),
+        client_msg_id: Some("local-2".to_string()),
+    };
+    send_frame(&mut alice, &send).await;
+    match next_matching(&mut alice, |m| matches!(m, WsMessage::Error { .. })).await {
+        WsMessage::Error {
+            code,
+            client_msg_id,
+            ..
+        } => {
+            assert_eq!(code, WsErrorCode::Forbidden);
+            assert_eq!(client_msg_id.as_deref(), Some("local-2"));
+        }
+        other => panic!("unexpected message: {other:?}"),
+    }
+}
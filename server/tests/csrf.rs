@@ -0,0 +1,112 @@
+use app::auth::{
+    cookies::session_cookie_name,
+    csrf::{csrf_guard, CsrfConfig},
+};
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Router,
+};
+use reqwest::header::{AUTHORIZATION, COOKIE, ORIGIN, REFERER};
+use tokio::net::TcpListener;
+
+const APP_ORIGIN: &str = "http://localhost:3000";
+
+/// Serves a stand-in server function behind the CSRF guard and returns its base URL.
+async fn spawn_app() -> String {
+    let app = Router::new()
+        .route("/api/update_profile", post(|| async { "updated" }))
+        .route("/", get(|| async { "home" }))
+        .layer(axum::middleware::from_fn_with_state(
+            CsrfConfig::new([APP_ORIGIN]),
+            csrf_guard,
+        ));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    url
+}
+
+async fn post_with(url: &str, header: reqwest::header::HeaderName, value: &str) -> StatusCode {
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/update_profile", url))
+        .header(header, value)
+        .send()
+        .await
+        .unwrap();
+    StatusCode::from_u16(response.status().as_u16()).unwrap()
+}
+
+#[tokio::test]
+async fn cross_origin_posts_are_rejected() {
+    let url = spawn_app().await;
+
+    assert_eq!(
+        post_with(&url, ORIGIN, "https://evil.example").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(post_with(&url, ORIGIN, "null").await, StatusCode::FORBIDDEN);
+    assert_eq!(
+        post_with(&url, REFERER, "https://evil.example/attack.html").await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn same_origin_and_trusted_posts_are_allowed() {
+    let url = spawn_app().await;
+
+    assert_eq!(post_with(&url, ORIGIN, APP_ORIGIN).await, StatusCode::OK);
+    // The origin the server is reached at, as for a deployment behind its own hostname
+    assert_eq!(post_with(&url, ORIGIN, &url).await, StatusCode::OK);
+    assert_eq!(
+        post_with(&url, REFERER, &format!("{}/settings", url)).await,
+        StatusCode::OK
+    );
+
+    // Non-browser clients send neither header
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/update_profile", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn safe_methods_are_not_checked() {
+    let url = spawn_app().await;
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header(ORIGIN, "https://evil.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn api_key_requests_from_other_origins_are_allowed_without_a_session_cookie() {
+    let url = spawn_app().await;
+    let post = |cookie: Option<String>| {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/api/update_profile", url))
+            .header(ORIGIN, "https://dashboard.example")
+            .header(AUTHORIZATION, "Bearer ntk_0123_secret");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        request.send()
+    };
+
+    let response = post(None).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A session cookie is ambient, so the origin still has to match
+    let session = format!("{}=abc", session_cookie_name());
+    let response = post(Some(session)).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
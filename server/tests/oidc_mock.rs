@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use app::auth::{
+    auth_routes,
+    cookies::session_cookie_name,
+    oidc::{pkce_challenge, pkce_verifier, OidcClient, OidcProviderConfig},
+    user::AdapterUser,
+};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::TcpListener;
+
+const CLIENT_ID: &str = "netron-test";
+const CLIENT_SECRET: &str = "shh";
+const REDIRECT_URL: &str = "http://localhost:3000/auth/oidc/mock/callback";
+
+/// A minimal OpenID provider that issues one code per authorization request and checks PKCE.
+#[derive(Clone, Default)]
+struct MockProvider {
+    issuer: Arc<Mutex<String>>,
+    /// Outstanding codes and the challenge they were issued for.
+    codes: Arc<Mutex<HashMap<String, String>>>,
+}
+
+#[derive(Deserialize)]
+struct AuthorizeParams {
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    code_challenge: String,
+    code_challenge_method: String,
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    client_id: String,
+    client_secret: Option<String>,
+    code: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+}
+
+async fn discovery(State(mock): State<MockProvider>) -> Json<serde_json::Value> {
+    let issuer = mock.issuer.lock().unwrap().clone();
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "userinfo_endpoint": format!("{}/userinfo", issuer),
+    }))
+}
+
+async fn authorize(
+    State(mock): State<MockProvider>,
+    Query(params): Query<AuthorizeParams>,
+) -> Response {
+    if params.client_id != CLIENT_ID || params.code_challenge_method != "S256" {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let code = format!("code-{}", mock.codes.lock().unwrap().len());
+    mock.codes
+        .lock()
+        .unwrap()
+        .insert(code.clone(), params.code_challenge);
+    Redirect::to(&format!(
+        "{}?code={}&state={}",
+        params.redirect_uri, code, params.state
+    ))
+    .into_response()
+}
+
+fn tokens(access_token: &str, refresh_token: &str) -> Response {
+    Json(json!({
+        "access_token": access_token,
+        "token_type": "Bearer",
+        "expires_in": 3600,
+        "refresh_token": refresh_token,
+        "scope": "openid email profile",
+    }))
+    .into_response()
+}
+
+async fn token(State(mock): State<MockProvider>, Form(form): Form<TokenForm>) -> Response {
+    let invalid = (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": "invalid_grant" })),
+    );
+    if form.client_id != CLIENT_ID || form.client_secret.as_deref() != Some(CLIENT_SECRET) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "invalid_client" })),
+        )
+            .into_response();
+    }
+
+    match form.grant_type.as_str() {
+        "authorization_code" => {
+            let challenge = form
+                .code
+                .and_then(|code| mock.codes.lock().unwrap().remove(&code));
+            match (challenge, form.code_verifier) {
+                (Some(challenge), Some(verifier)) if pkce_challenge(&verifier) == challenge => {
+                    tokens("access-1", "refresh-1")
+                }
+                _ => invalid.into_response(),
+            }
+        }
+        "refresh_token" if form.refresh_token.as_deref() == Some("refresh-1") => {
+            tokens("access-2", "refresh-2")
+        }
+        _ => invalid.into_response(),
+    }
+}
+
+async fn userinfo(headers: HeaderMap) -> Response {
+    match headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some("Bearer access-1") | Some("Bearer access-2") => Json(json!({
+            "sub": "user-123",
+            "name": "Ada Lovelace",
+            "email": "ada@example.com",
+        }))
+        .into_response(),
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+async fn spawn_mock_provider() -> String {
+    let mock = MockProvider::default();
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        // Serves the root issuer's document under another path, like a misconfigured tenant
+        .route("/tenant/.well-known/openid-configuration", get(discovery))
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .route("/userinfo", get(userinfo))
+        .with_state(mock.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    *mock.issuer.lock().unwrap() = issuer.clone();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    issuer
+}
+
+fn config(issuer: &str) -> OidcProviderConfig {
+    OidcProviderConfig {
+        id: "mock".into(),
+        name: "Mock".into(),
+        issuer: issuer.into(),
+        client_id: CLIENT_ID.into(),
+        client_secret: Some(CLIENT_SECRET.into()),
+        scopes: "openid email profile".into(),
+        redirect_url: REDIRECT_URL.into(),
+    }
+}
+
+/// Follows the authorization URL like a browser and returns the code and state from the
+/// redirect back to the app.
+async fn authorize_in_browser(url: &str) -> (String, String) {
+    let browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = browser.get(url).send().await.unwrap();
+    assert!(response.status().is_redirection());
+
+    let location = response.headers()[reqwest::header::LOCATION]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let query = location
+        .strip_prefix(&format!("{}?", REDIRECT_URL))
+        .expect("provider should redirect to the callback");
+    let params: HashMap<String, String> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    (params["code"].clone(), params["state"].clone())
+}
+
+#[tokio::test]
+async fn authorization_code_flow_with_pkce_and_refresh() {
+    let issuer = spawn_mock_provider().await;
+    let client = OidcClient::discover(config(&issuer)).await.unwrap();
+    assert_eq!(client.metadata.token_endpoint, format!("{}/token", issuer));
+
+    let verifier = pkce_verifier();
+    let url = client.authorize_url("state-abc", &pkce_challenge(&verifier));
+    let (code, state) = authorize_in_browser(&url).await;
+    assert_eq!(state, "state-abc");
+
+    let tokens = client.exchange_code(&code, &verifier).await.unwrap();
+    assert_eq!(tokens.access_token, "access-1");
+    assert_eq!(tokens.refresh_token.as_deref(), Some("refresh-1"));
+
+    let info = client.userinfo(&tokens.access_token).await.unwrap();
+    assert_eq!(info.sub, "user-123");
+    assert_eq!(info.email.as_deref(), Some("ada@example.com"));
+
+    let refreshed = client.refresh("refresh-1").await.unwrap();
+    assert_eq!(refreshed.access_token, "access-2");
+    assert!(client.userinfo(&refreshed.access_token).await.is_ok());
+}
+
+#[tokio::test]
+async fn code_exchange_rejects_wrong_verifier_and_reused_code() {
+    let issuer = spawn_mock_provider().await;
+    let client = OidcClient::discover(config(&issuer)).await.unwrap();
+
+    let verifier = pkce_verifier();
+    let url = client.authorize_url("state", &pkce_challenge(&verifier));
+    let (code, _) = authorize_in_browser(&url).await;
+
+    // The code is spent by the failed attempt, as a real provider would do
+    assert!(client.exchange_code(&code, &pkce_verifier()).await.is_err());
+    assert!(client.exchange_code(&code, &verifier).await.is_err());
+}
+
+#[tokio::test]
+async fn discovery_rejects_mismatched_issuer() {
+    let issuer = spawn_mock_provider().await;
+    let mut config = config(&issuer);
+    config.issuer = format!("{}/", issuer);
+    assert!(OidcClient::discover(config.clone()).await.is_ok());
+
+    config.issuer = format!("{}/tenant", issuer);
+    assert!(OidcClient::discover(config).await.is_err());
+}
+
+/// Serves the app's `/auth` routes and returns their base URL.
+async fn spawn_auth_routes() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, Router::new().nest("/auth", auth_routes()))
+            .await
+            .unwrap();
+    });
+    url
+}
+
+#[tokio::test]
+async fn callback_rejects_a_state_this_browser_was_not_given() {
+    let url = spawn_auth_routes().await;
+    let browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let callback = format!(
+        "{}/auth/oidc/mock/callback?code=code-0&state=state-abc",
+        url
+    );
+
+    for cookie in [None, Some("oidc_state=state-xyz")] {
+        let mut request = browser.get(&callback);
+        if let Some(cookie) = cookie {
+            request = request.header(reqwest::header::COOKIE, cookie);
+        }
+        let response = request.send().await.unwrap();
+        assert!(response.status().is_redirection());
+        assert_eq!(
+            response.headers()[reqwest::header::LOCATION],
+            "/login?error=oidc_failed"
+        );
+        // No session is handed out, and the state cookie is cleared
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect();
+        assert!(cookies.iter().all(|c| !c.contains("session_token")));
+        assert!(cookies.iter().any(|c| c.contains("oidc_state=;")));
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a running SurrealDB"]
+async fn impersonated_sessions_cannot_link_a_provider() {
+    let admin = AdapterUser::create_test_user().await.unwrap();
+    let target = AdapterUser::create_test_user().await.unwrap();
+    let session = target.new_verified_session().await.unwrap();
+    app::db_init()
+        .await
+        .unwrap()
+        .query("UPDATE session SET impersonator_id = $admin_id WHERE session_token = $token;")
+        .bind(("admin_id", admin.id.clone()))
+        .bind(("token", session.session_token.clone()))
+        .await
+        .unwrap();
+
+    let url = spawn_auth_routes().await;
+    let browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = browser
+        .get(format!("{}/auth/oidc/mock/login", url))
+        .header(
+            reqwest::header::COOKIE,
+            format!("{}={}", session_cookie_name(), session.session_token),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_redirection());
+    assert_eq!(
+        response.headers()[reqwest::header::LOCATION],
+        "/login?error=impersonating"
+    );
+    // No sign-in was started, so there is no state to finish it with
+    assert!(response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .all(|value| !value.to_str().unwrap().contains("oidc_state")));
+}